@@ -0,0 +1,405 @@
+//! Screen-reader friendly transcripts.
+//!
+//! While recording is enabled (see [`crate::Engine::record_interactions`]),
+//! every chunk written to the program and every chunk of its output is kept
+//! in order. [`crate::Engine::accessible_transcript`] turns that log into a
+//! linear, plain-text account of the session: what was typed, which keys
+//! were pressed, and what the program printed in response, without escape
+//! sequences, box drawing, or the echo of typed input.
+//!
+//! ```text
+//! Output:
+//!   $
+//! Command: echo "hello"
+//! Output:
+//!   "hello"
+//!   $
+//! Key: Ctrl+C
+//! ```
+
+use crate::commands::cursor_line;
+
+/// One chunk of traffic between the script and the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Interaction {
+    /// Bytes written to the program's stdin.
+    Input(Vec<u8>),
+    /// Bytes the program wrote to the terminal.
+    Output(Vec<u8>),
+}
+
+/// The ordered input and output of a run, recorded only when enabled.
+#[derive(Debug, Default)]
+pub(crate) struct InteractionLog {
+    enabled: bool,
+    entries: Vec<Interaction>,
+}
+
+impl InteractionLog {
+    pub(crate) fn enable(&mut self) {
+        self.enabled = true;
+    }
+
+    pub(crate) fn record_input(&mut self, data: &[u8]) {
+        if self.enabled {
+            self.entries.push(Interaction::Input(data.to_vec()));
+        }
+    }
+
+    /// Record program output, merging it into the previous entry when that
+    /// was output too.
+    pub(crate) fn record_output(&mut self, data: &[u8]) {
+        if !self.enabled {
+            return;
+        }
+        match self.entries.last_mut() {
+            Some(Interaction::Output(previous)) => previous.extend_from_slice(data),
+            _ => self.entries.push(Interaction::Output(data.to_vec())),
+        }
+    }
+
+    pub(crate) fn entries(&self) -> &[Interaction] {
+        &self.entries
+    }
+}
+
+/// A key that ends or edits the line being typed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Named(&'static str),
+    Ctrl(char),
+    Alt(char),
+}
+
+impl Key {
+    /// Keys that only move the cursor or delete within the line being typed.
+    fn edits_line(self) -> bool {
+        matches!(
+            self,
+            Key::Backspace | Key::Delete | Key::Left | Key::Right | Key::Home | Key::End
+        )
+    }
+
+    fn describe(self) -> String {
+        match self {
+            Key::Enter => "Enter".to_string(),
+            Key::Backspace => "Backspace".to_string(),
+            Key::Delete => "Delete".to_string(),
+            Key::Left => "Left".to_string(),
+            Key::Right => "Right".to_string(),
+            Key::Home => "Home".to_string(),
+            Key::End => "End".to_string(),
+            Key::Named(name) => name.to_string(),
+            Key::Ctrl(c) => format!("Ctrl+{}", c),
+            Key::Alt(c) => format!("Alt+{}", c),
+        }
+    }
+}
+
+/// What one chunk of input consists of, in order.
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    Key(Key),
+}
+
+/// Split input into printable characters and recognized keys. Bracketed
+/// paste markers are dropped so pasted text reads as typed text.
+fn tokenize(input: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(ch) = chars.next() {
+        let key = match ch {
+            '\r' | '\n' => Key::Enter,
+            '\x7f' | '\x08' => Key::Backspace,
+            '\t' => Key::Named("Tab"),
+            '\x1b' => match chars.peek() {
+                Some('[') | Some('O') => {
+                    chars.next();
+                    let mut sequence = String::new();
+                    for c in chars.by_ref() {
+                        sequence.push(c);
+                        if c.is_ascii_alphabetic() || c == '~' {
+                            break;
+                        }
+                    }
+                    match escape_key(&sequence) {
+                        Some(key) => key,
+                        None => continue,
+                    }
+                }
+                Some(&c) if !c.is_control() => {
+                    chars.next();
+                    Key::Alt(c)
+                }
+                _ => Key::Named("Escape"),
+            },
+            c if (c as u32) < 0x20 => Key::Ctrl((b'A' + c as u8 - 1) as char),
+            c if c.is_control() => continue,
+            c => {
+                tokens.push(Token::Char(c));
+                continue;
+            }
+        };
+        tokens.push(Token::Key(key));
+    }
+    tokens
+}
+
+/// The key sent as `ESC [ sequence` or `ESC O sequence`, or `None` for paste
+/// markers.
+fn escape_key(sequence: &str) -> Option<Key> {
+    let key = match sequence {
+        "200~" | "201~" => return None,
+        "A" => Key::Named("Up"),
+        "B" => Key::Named("Down"),
+        "C" => Key::Right,
+        "D" => Key::Left,
+        "H" | "1~" | "7~" => Key::Home,
+        "F" | "4~" | "8~" => Key::End,
+        "3~" => Key::Delete,
+        "2~" => Key::Named("Insert"),
+        "5~" => Key::Named("PageUp"),
+        "6~" => Key::Named("PageDown"),
+        "Z" => Key::Named("Shift+Tab"),
+        "P" => Key::Named("F1"),
+        "Q" => Key::Named("F2"),
+        "R" => Key::Named("F3"),
+        "S" => Key::Named("F4"),
+        _ => Key::Named("Escape sequence"),
+    };
+    Some(key)
+}
+
+/// Replace typographic punctuation with plain ASCII and drop box-drawing and
+/// block characters, which screen readers announce one by one.
+fn normalize_punctuation(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    for c in line.chars() {
+        match c {
+            '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{2032}' => out.push('\''),
+            '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{2033}' => out.push('"'),
+            '\u{2010}'..='\u{2015}' | '\u{2212}' => out.push('-'),
+            '\u{2026}' => out.push_str("..."),
+            '\u{2022}' | '\u{25CF}' | '\u{25E6}' => out.push('*'),
+            '\u{00A0}' | '\u{2000}'..='\u{200A}' => out.push(' '),
+            '\u{2500}'..='\u{259F}' => out.push(' '),
+            c => out.push(c),
+        }
+    }
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The lines of `output` as they would read on screen, normalized with
+/// [`normalize_punctuation`].
+fn screen_lines(output: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(output)
+        .split('\n')
+        .map(|line| normalize_punctuation(&render_line(line)))
+        .collect()
+}
+
+/// How `line`, written on its own, reads on a terminal wide enough for it.
+fn render_line(line: &str) -> String {
+    let cols = (line.chars().count() + 1).min(usize::from(u16::MAX));
+    let mut parser = vt100::Parser::new(1, cols as u16, 0);
+    parser.process(line.as_bytes());
+    cursor_line(parser.screen())
+}
+
+/// Builds the description entry by entry.
+#[derive(Default)]
+struct Describer {
+    lines: Vec<String>,
+    /// The line being typed and the cursor position within it.
+    typed: Vec<char>,
+    cursor: usize,
+    typing: bool,
+    /// The last submitted command (normalized), whose echo starts the next
+    /// output.
+    submitted: Option<String>,
+}
+
+impl Describer {
+    fn input(&mut self, data: &[u8]) {
+        for token in tokenize(&String::from_utf8_lossy(data)) {
+            match token {
+                Token::Char(c) => {
+                    self.typed.insert(self.cursor, c);
+                    self.cursor += 1;
+                    self.typing = true;
+                }
+                Token::Key(key) if key.edits_line() && self.typing => self.edit(key),
+                Token::Key(Key::Enter) => {
+                    let command = normalize_punctuation(&self.typed.iter().collect::<String>());
+                    if command.is_empty() {
+                        self.lines.push("Key: Enter".to_string());
+                    } else {
+                        self.lines.push(format!("Command: {}", command));
+                        self.submitted = Some(command);
+                    }
+                    self.end_typing();
+                }
+                Token::Key(key) => {
+                    self.flush_typed();
+                    self.lines.push(format!("Key: {}", key.describe()));
+                }
+            }
+        }
+    }
+
+    fn edit(&mut self, key: Key) {
+        match key {
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.typed.remove(self.cursor);
+            }
+            Key::Delete if self.cursor < self.typed.len() => {
+                self.typed.remove(self.cursor);
+            }
+            Key::Left => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right => self.cursor = (self.cursor + 1).min(self.typed.len()),
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.typed.len(),
+            _ => {}
+        }
+    }
+
+    /// Report text typed without pressing Enter, e.g. before Tab.
+    fn flush_typed(&mut self) {
+        if !self.typed.is_empty() {
+            let text = normalize_punctuation(&self.typed.iter().collect::<String>());
+            self.lines.push(format!("Typed: {}", text));
+        }
+        self.end_typing();
+    }
+
+    fn end_typing(&mut self) {
+        self.typed.clear();
+        self.cursor = 0;
+        self.typing = false;
+    }
+
+    /// Report program output. Output that arrives while a line is being typed
+    /// is its echo and is skipped, as is the echo of the last command.
+    fn output(&mut self, data: &[u8]) {
+        if self.typing {
+            return;
+        }
+        let mut lines = screen_lines(data);
+        if let Some(command) = self.submitted.take()
+            && let Some(first) = lines.first_mut()
+        {
+            match first.strip_prefix(&command) {
+                Some(rest) => *first = rest.trim_start().to_string(),
+                // The rest of the echo, when part of it came before Enter.
+                None if command.ends_with(first.as_str()) => first.clear(),
+                None => {}
+            }
+        }
+        lines.retain(|line| !line.is_empty());
+        if !lines.is_empty() {
+            self.lines.push("Output:".to_string());
+            self.lines
+                .extend(lines.into_iter().map(|line| format!("  {}", line)));
+        }
+    }
+}
+
+/// Describe the recorded session as plain text, one line per command, key,
+/// or line of output.
+pub(crate) fn describe(entries: &[Interaction]) -> String {
+    let mut describer = Describer::default();
+    for entry in entries {
+        match entry {
+            Interaction::Input(data) => describer.input(data),
+            Interaction::Output(data) => describer.output(data),
+        }
+    }
+    describer.flush_typed();
+    let mut text = describer.lines.join("\n");
+    text.push('\n');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(data: &str) -> Interaction {
+        Interaction::Input(data.as_bytes().to_vec())
+    }
+
+    fn output(data: &str) -> Interaction {
+        Interaction::Output(data.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_describe_typed_command() {
+        let entries = [
+            output("\x1b]0;user@host\x07\x1b[1;32m$\x1b[0m "),
+            input("e"),
+            output("e"),
+            input("c"),
+            output("c"),
+            input("hx"),
+            output("hx"),
+            input("\x7f"),
+            output("\x08 \x08"),
+            input("o hi\r"),
+            output("o hi\r\nhi\r\n$ "),
+            input("echo hi"),
+            input("\r"),
+            output("\r\nhi\r\n"),
+        ];
+        assert_eq!(
+            describe(&entries),
+            "Output:\n  $\nCommand: echo hi\nOutput:\n  hi\n  $\nCommand: echo hi\nOutput:\n  hi\n"
+        );
+    }
+
+    #[test]
+    fn test_describe_keys_and_line_edits() {
+        let entries = [
+            input("git sttus"),
+            input("\x1b[D\x1b[D\x1b[Da"),
+            input("\r"),
+            output("git status\r\nclean\r\n"),
+            input("ls al"),
+            input("\t"),
+            output("alpha\r\n"),
+            input("\x03"),
+            input("\x1b[200~pasted\x1b[201~"),
+        ];
+        assert_eq!(
+            describe(&entries),
+            "Command: git status\nOutput:\n  clean\nTyped: ls al\nKey: Tab\nOutput:\n  alpha\nKey: Ctrl+C\nTyped: pasted\n"
+        );
+    }
+
+    #[test]
+    fn test_screen_lines_drop_box_drawing() {
+        assert_eq!(
+            screen_lines("┌──────┐\r\n│ “hi” – ok… │\r\n└──────┘".as_bytes()),
+            vec!["", "\"hi\" - ok...", ""]
+        );
+    }
+
+    #[test]
+    fn test_log_records_only_when_enabled() {
+        let mut log = InteractionLog::default();
+        log.record_output(b"ignored");
+        log.enable();
+        log.record_output(b"a");
+        log.record_output(b"b");
+        log.record_input(b"c");
+        assert_eq!(log.entries(), &[output("ab"), input("c")]);
+    }
+}
@@ -0,0 +1,84 @@
+//! Removing ANSI escape sequences from program output.
+
+/// `text` without escape sequences, and for each remaining byte its offset
+/// in `text`.
+///
+/// Removes CSI sequences (`ESC [ ... final`, e.g. colors and cursor
+/// movement), OSC sequences (`ESC ] ... BEL` or `ESC ] ... ESC \`, e.g.
+/// window titles), other string sequences ended the same way (DCS `ESC P`,
+/// e.g. sixel images, and APC `ESC _`, e.g. kitty images), character set
+/// selections (`ESC ( B`), and other two-byte escapes. An unfinished sequence at the end of `text` is removed as well,
+/// since the rest of it has not arrived yet.
+pub(crate) fn strip(text: &str) -> (String, Vec<usize>) {
+    let bytes = text.as_bytes();
+    let mut plain = Vec::with_capacity(bytes.len());
+    let mut offsets = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != 0x1b {
+            plain.push(bytes[i]);
+            offsets.push(i);
+            i += 1;
+            continue;
+        }
+        i = match bytes.get(i + 1) {
+            Some(b'[') => {
+                let params = i + 2;
+                match bytes[params..]
+                    .iter()
+                    .position(|b| (0x40..=0x7e).contains(b))
+                {
+                    Some(end) => params + end + 1,
+                    None => bytes.len(),
+                }
+            }
+            Some(b']' | b'P' | b'_' | b'^' | b'X') => {
+                let mut j = i + 2;
+                loop {
+                    match bytes.get(j) {
+                        None => break j,
+                        Some(0x07) => break j + 1,
+                        Some(0x1b) if bytes.get(j + 1) == Some(&b'\\') => break j + 2,
+                        Some(_) => j += 1,
+                    }
+                }
+            }
+            Some(b'(' | b')' | b'*' | b'+') => (i + 3).min(bytes.len()),
+            Some(_) => i + 2,
+            None => bytes.len(),
+        };
+    }
+    // Only whole escape sequences (which are ASCII) were removed, so the
+    // remaining bytes are still valid UTF-8.
+    let plain = String::from_utf8(plain).expect("stripping escapes keeps UTF-8 intact");
+    (plain, offsets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip() {
+        let (plain, offsets) = strip("\x1b[1;32muser\x1b[0m:\x1b]0;title\x07~ é\x1b(B$");
+        assert_eq!(plain, "user:~ é$");
+        assert_eq!(offsets[0], 7);
+        assert_eq!(offsets[4], 15);
+        assert_eq!(offsets.len(), plain.len());
+    }
+
+    #[test]
+    fn test_strip_unfinished_sequence() {
+        assert_eq!(strip("done\x1b[3").0, "done");
+        assert_eq!(strip("done\x1b]0;ti").0, "done");
+        assert_eq!(strip("done\x1b").0, "done");
+        assert_eq!(strip("a\x1b]0;t\x1b\\b").0, "ab");
+    }
+
+    #[test]
+    fn test_strip_images() {
+        assert_eq!(strip("a\x1bPq#0;2;0;0;0~~-\x1b\\b").0, "ab");
+        assert_eq!(strip("a\x1b_Gf=100;iVBORw0K\x1b\\b").0, "ab");
+        assert_eq!(strip("a\x1bPq~~").0, "a");
+    }
+}
@@ -0,0 +1,141 @@
+//! Behavior profiles: the defaults of a release, kept when a later release
+//! changes them.
+//!
+//! A large suite written against one release can pin its profile (with
+//! `set behavior 0.1`, `--behavior 0.1`, or [`Engine::set_behavior`]) and
+//! upgrade the crate without auditing every script at the same time. When a
+//! release changes one of these defaults, the old value stays available
+//! under the old profile and the new one becomes [`Behavior::LATEST`].
+//!
+//! [`Engine::set_behavior`]: crate::Engine::set_behavior
+
+use anyhow::{Result, anyhow};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The defaults of one release.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Behavior {
+    /// The 0.1 releases.
+    V0_1,
+    /// The 0.2 releases.
+    #[default]
+    V0_2,
+}
+
+impl Behavior {
+    /// Every profile, oldest first.
+    pub const ALL: &'static [Behavior] = &[Behavior::V0_1, Behavior::V0_2];
+
+    /// The profile of this release, used unless another one is pinned.
+    pub const LATEST: Behavior = Behavior::V0_2;
+
+    /// The release this profile preserves, e.g. `"0.1"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::V0_1 => "0.1",
+            Self::V0_2 => "0.2",
+        }
+    }
+
+    /// Whether `${NAME}` references in script strings are expanded. 0.1 had
+    /// no variables and sent them as written.
+    pub fn interpolates(self) -> bool {
+        match self {
+            Self::V0_1 => false,
+            Self::V0_2 => true,
+        }
+    }
+
+    /// Whether `show` ends its text with a newline. Unchanged since 0.1.
+    pub fn auto_newline(self) -> bool {
+        true
+    }
+
+    /// Pause after `send` and `paste`, to give the program a moment to
+    /// process the input. Unchanged since 0.1.
+    pub fn input_settle(self) -> Duration {
+        Duration::from_millis(50)
+    }
+
+    /// Pause after a run's last command, for the program's remaining output
+    /// to reach the output handler. Unchanged since 0.1.
+    pub fn final_settle(self) -> Duration {
+        Duration::from_millis(300)
+    }
+
+    /// Bytes of program output `expect` searches, at most; once the output
+    /// grows past this, the oldest [`buffer_cut`](Self::buffer_cut) bytes are
+    /// dropped. Unchanged since 0.1.
+    pub fn buffer_limit(self) -> usize {
+        10_000
+    }
+
+    /// How many of the oldest bytes to drop from `len` bytes of output that
+    /// grew past [`buffer_limit`](Self::buffer_limit). 0.1 dropped half the
+    /// limit, which kept a large chunk of output whole; 0.2 keeps only the
+    /// newest half of the limit.
+    pub fn buffer_cut(self, len: usize) -> usize {
+        let half = self.buffer_limit() / 2;
+        match self {
+            Self::V0_1 => half,
+            Self::V0_2 => len.saturating_sub(half),
+        }
+    }
+}
+
+impl FromStr for Behavior {
+    type Err = anyhow::Error;
+
+    /// Parse a release such as `0.1`; a patch release such as `0.1.0` is
+    /// accepted as well.
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|b| {
+                s.strip_prefix(b.name())
+                    .is_some_and(|patch| patch.is_empty() || patch.starts_with('.'))
+            })
+            .ok_or_else(|| {
+                let known: Vec<_> = Self::ALL.iter().map(|b| b.name()).collect();
+                anyhow!(
+                    "Unknown behavior profile '{}' (known: {})",
+                    s,
+                    known.join(", ")
+                )
+            })
+    }
+}
+
+impl fmt::Display for Behavior {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("0.1".parse::<Behavior>().unwrap(), Behavior::V0_1);
+        assert_eq!("0.1.0".parse::<Behavior>().unwrap(), Behavior::V0_1);
+        assert_eq!("0.2".parse::<Behavior>().unwrap(), Behavior::V0_2);
+        assert_eq!(Behavior::default(), Behavior::LATEST);
+        assert_eq!(Behavior::LATEST.to_string(), "0.2");
+        assert!("0.10".parse::<Behavior>().is_err());
+        let err = "1.0".parse::<Behavior>().unwrap_err().to_string();
+        assert!(err.contains("known: 0.1, 0.2"), "got: {err}");
+    }
+
+    #[test]
+    fn test_buffer_cut() {
+        // 0.1 keeps everything but the oldest 5 000 bytes.
+        assert_eq!(Behavior::V0_1.buffer_cut(14_000), 5_000);
+        assert_eq!(Behavior::V0_2.buffer_cut(14_000), 9_000);
+    }
+}
@@ -0,0 +1,472 @@
+//! [`EngineBuilder`]: configure how the program starts, then spawn it.
+
+use crate::command::OutputHandler;
+use crate::engine::{Engine, write_stdout};
+use crate::pty::SpawnOptions;
+use crate::sandbox::Sandbox;
+use crate::terminal::TerminalSettings;
+use crate::workspace::Workspace;
+use anyhow::{Context as _, Result, anyhow};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{SyncSender, TrySendError, sync_channel};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::runtime::Handle;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// Chunks of output a [`EngineBuilder::writer`] or
+/// [`EngineBuilder::async_handler`] may fall behind by before the program's
+/// output waits for it.
+const QUEUE_CAPACITY: usize = 1024;
+
+/// Builds an [`Engine`] with control over the program's arguments,
+/// environment, working directory, and terminal. Create one with
+/// [`Engine::builder`]:
+///
+/// ```no_run
+/// use scriptty::Engine;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let engine = Engine::builder("bash")
+///     .arg("-l")
+///     .env("PS1", "$ ")
+///     .cwd("/tmp")
+///     .size(40, 120)
+///     .term("xterm-256color")
+///     .spawn()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Everything set here applies to restarts (`respawn`) as well.
+pub struct EngineBuilder {
+    command: String,
+    args: Vec<String>,
+    options: SpawnOptions,
+    handler: OutputHandler,
+    sandbox: Option<Sandbox>,
+    workspace: bool,
+    queue: Option<(SyncSender<Queued>, Drain)>,
+}
+
+impl EngineBuilder {
+    pub(crate) fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+            args: Vec::new(),
+            options: SpawnOptions::default(),
+            handler: Arc::new(write_stdout),
+            sandbox: None,
+            workspace: false,
+            queue: None,
+        }
+    }
+
+    /// Add an argument for the program.
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Add arguments for the program.
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Set an environment variable for the program, on top of the ones it
+    /// inherits from this process.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Start the program in `dir` instead of this process's working
+    /// directory.
+    pub fn cwd(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.cwd = Some(dir.into());
+        self
+    }
+
+    /// The terminal's size in rows and columns; 24x80 by default.
+    pub fn size(mut self, rows: u16, cols: u16) -> Self {
+        self.options.size = Some((rows, cols));
+        self
+    }
+
+    /// The terminal type the program sees in `$TERM`; `key` commands send
+    /// the key sequences of this terminal. Inherited by default.
+    pub fn term(self, term: impl Into<String>) -> Self {
+        self.env("TERM", term)
+    }
+
+    /// Line settings and pixel size of the terminal.
+    pub fn terminal(mut self, settings: TerminalSettings) -> Self {
+        self.options.terminal = settings;
+        self
+    }
+
+    /// Run the program in `sandbox`.
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    /// Start the program in a fresh temporary [`Workspace`], available to
+    /// the script as `${WORKSPACE}` and to the program as `$WORKSPACE`.
+    /// It is removed when the engine is dropped; see
+    /// [`Engine::keep_workspace_on_failure`].
+    pub fn workspace(mut self) -> Self {
+        self.workspace = true;
+        self
+    }
+
+    /// Pass the program's output to `handler` instead of writing it to
+    /// stdout. See [`Engine::spawn_with_handler`].
+    pub fn handler(mut self, handler: impl Fn(&[u8]) + Send + Sync + 'static) -> Self {
+        self.handler = Arc::new(handler);
+        self
+    }
+
+    /// Stream the program's output into `writer`, e.g. a tokio file, socket,
+    /// or pipe, instead of writing it to stdout.
+    ///
+    /// Output is queued and written by a background task, so a slow writer
+    /// does not hold up the script; once it falls far behind, the program's
+    /// output waits for it. Text the script shows (`show`) can't wait, so it
+    /// is dropped while the queue is full and the run fails. Each run
+    /// flushes the queue before it ends. Writing stops at the first error,
+    /// which fails the run.
+    pub fn writer(self, mut writer: impl AsyncWrite + Unpin + Send + 'static) -> Self {
+        self.queued(move |runtime, data| {
+            runtime
+                .block_on(async {
+                    writer.write_all(&data).await?;
+                    writer.flush().await
+                })
+                .context("Failed to write the program's output")
+        })
+    }
+
+    /// Pass each chunk of the program's output to the async `handler`
+    /// instead of writing it to stdout, e.g. to send it into a channel.
+    ///
+    /// Calls run one at a time, in order, on a background task, like
+    /// [`writer`](Self::writer).
+    pub fn async_handler<F, Fut>(self, mut handler: F) -> Self
+    where
+        F: FnMut(Vec<u8>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        self.queued(move |runtime, data| {
+            runtime.block_on(handler(data));
+            Ok(())
+        })
+    }
+
+    /// Queue the program's output for `sink`, which a background task
+    /// started by [`spawn`](Self::spawn) calls with each chunk in turn,
+    /// until it fails.
+    fn queued(self, mut sink: impl FnMut(&Handle, Vec<u8>) -> Result<()> + Send + 'static) -> Self {
+        let (sender, receiver) = sync_channel(QUEUE_CAPACITY);
+        let drain: Drain = Box::new(move || {
+            let runtime = Handle::current();
+            while let Ok(queued) = receiver.recv() {
+                match queued {
+                    Queued::Output(data) => sink(&runtime, data)?,
+                    Queued::Flush(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+            Ok(())
+        });
+        let output = sender.clone();
+        let handler = Arc::new(move |data: &[u8]| {
+            // Called from the thread reading the program's output, which
+            // waits while the queue is full. The task may have stopped after
+            // an error, which the run reports.
+            let _ = output.send(Queued::Output(data.to_vec()));
+        });
+        Self {
+            handler,
+            queue: Some((sender, drain)),
+            ..self
+        }
+    }
+
+    /// Start the program.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if both a working directory and a workspace were
+    /// requested, the workspace cannot be created, sandboxing is unsupported
+    /// on this platform, the PTY cannot be opened or set up, or the command
+    /// cannot be spawned.
+    pub fn spawn(self) -> Result<Engine> {
+        let Self {
+            command,
+            args,
+            mut options,
+            handler,
+            sandbox,
+            workspace,
+            queue,
+        } = self;
+        let workspace = match workspace {
+            true if options.cwd.is_some() => {
+                return Err(anyhow!(
+                    "A workspace is the program's working directory; don't set both"
+                ));
+            }
+            true => Some(Workspace::create()?),
+            false => None,
+        };
+        if let Some(workspace) = &workspace {
+            options.cwd = Some(workspace.path().to_path_buf());
+            options.env.push((
+                "WORKSPACE".to_string(),
+                workspace.path().to_string_lossy().into_owned(),
+            ));
+        }
+        let (command, args) = match &sandbox {
+            Some(sandbox) => sandbox.command(&command, &args, &options.env)?,
+            None => (command, args),
+        };
+        let mut engine = Engine::spawn_with_options(&command, &args, &options, handler)?;
+        if let Some(workspace) = workspace {
+            engine.set_workspace(workspace);
+        }
+        if let Some((sender, drain)) = queue {
+            let task = tokio::task::spawn_blocking(drain);
+            engine.set_output_queue(OutputQueue {
+                sender,
+                task: Some(task),
+                dropped: Arc::default(),
+            });
+        }
+        Ok(engine)
+    }
+}
+
+/// A message for the task behind an output queue.
+enum Queued {
+    Output(Vec<u8>),
+    /// Answered once everything queued before it has been handled.
+    Flush(oneshot::Sender<()>),
+}
+
+/// The loop of the task behind an output queue.
+type Drain = Box<dyn FnOnce() -> Result<()> + Send>;
+
+/// The background task a [`EngineBuilder::writer`] or
+/// [`EngineBuilder::async_handler`] queues the program's output for.
+pub(crate) struct OutputQueue {
+    sender: SyncSender<Queued>,
+    /// `None` once the task has stopped and its result was reported.
+    task: Option<JoinHandle<Result<()>>>,
+    /// Bytes of shown text dropped since the last flush.
+    dropped: Arc<AtomicUsize>,
+}
+
+impl OutputQueue {
+    /// A handler for text the script shows ([`Context::emit`]). It runs on
+    /// the runtime and so must not wait for the queue: text that doesn't fit
+    /// is dropped and reported by the next [`flush`](Self::flush).
+    ///
+    /// [`Context::emit`]: crate::Context::emit
+    pub(crate) fn emitter(&self) -> OutputHandler {
+        let sender = self.sender.clone();
+        let dropped = self.dropped.clone();
+        Arc::new(move |data: &[u8]| {
+            if let Err(TrySendError::Full(_)) = sender.try_send(Queued::Output(data.to_vec())) {
+                dropped.fetch_add(data.len(), Ordering::Relaxed);
+            }
+        })
+    }
+
+    /// Wait until everything queued so far has been handled, then, if the
+    /// task has stopped, for its result.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error the task stopped with, or an error if shown text
+    /// was dropped since the last flush.
+    pub(crate) async fn flush(&mut self) -> Result<()> {
+        let (done, flushed) = oneshot::channel();
+        let sender = self.sender.clone();
+        // Sending waits while the queue is full, which must not hold up the
+        // runtime.
+        let _ = tokio::task::spawn_blocking(move || sender.send(Queued::Flush(done))).await;
+        // Fails if the task has stopped, which the join below reports.
+        let _ = flushed.await;
+        if let Some(task) = self.task.take_if(|task| task.is_finished()) {
+            task.await.context("The output task panicked")??;
+        }
+        match self.dropped.swap(0, Ordering::Relaxed) {
+            0 => Ok(()),
+            dropped => Err(anyhow!(
+                "Dropped {} bytes of shown text: the output writer fell behind",
+                dropped
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_str;
+
+    #[tokio::test]
+    async fn test_spawn() {
+        let dir = std::env::temp_dir();
+        let mut engine = Engine::builder("sh")
+            .arg("-c")
+            .arg(r#"echo "$GREETING $TERM $(pwd) $(stty size)"; sleep 1"#)
+            .env("GREETING", "hello")
+            .cwd(&dir)
+            .size(40, 120)
+            .term("vt100")
+            .handler(|_| {})
+            .spawn()
+            .unwrap();
+        let expected = format!("hello vt100 {} 40 120", dir.display());
+        engine
+            .execute(parse_str(&format!("expect \"{}\"", expected)).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(engine.screen().size(), (40, 120));
+
+        let err = Engine::builder("sh")
+            .cwd(&dir)
+            .workspace()
+            .spawn()
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("workspace"), "got: {err}");
+    }
+
+    #[tokio::test]
+    async fn test_writer() {
+        use tokio::io::AsyncReadExt;
+
+        let (writer, mut reader) = tokio::io::duplex(4096);
+        let mut engine = Engine::builder("sh")
+            .args(["-c", "echo streamed; sleep 1"])
+            .writer(writer)
+            .spawn()
+            .unwrap();
+        engine
+            .execute(parse_str("expect \"streamed\"").unwrap())
+            .await
+            .unwrap();
+        let mut buffer = vec![0; 64];
+        let n = reader.read(&mut buffer).await.unwrap();
+        assert!(String::from_utf8_lossy(&buffer[..n]).contains("streamed"));
+
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let mut engine = Engine::builder("sh")
+            .args(["-c", "echo queued; sleep 1"])
+            .async_handler(move |data| {
+                let sender = sender.clone();
+                async move {
+                    let _ = sender.send(data);
+                }
+            })
+            .spawn()
+            .unwrap();
+        engine
+            .execute(parse_str("expect \"queued\"").unwrap())
+            .await
+            .unwrap();
+        let data = receiver.recv().await.unwrap();
+        assert!(String::from_utf8_lossy(&data).contains("queued"));
+    }
+
+    #[test]
+    fn test_writer_outside_runtime() {
+        // Only spawning needs a runtime.
+        let _builder = Engine::builder("sh").writer(tokio::io::sink());
+    }
+
+    #[tokio::test]
+    async fn test_writer_flushed_and_failed() {
+        use tokio::io::AsyncReadExt;
+
+        let (writer, mut reader) = tokio::io::duplex(1 << 16);
+        let mut engine = Engine::builder("sh")
+            .args(["-c", "echo first; echo last"])
+            .writer(writer)
+            .spawn()
+            .unwrap();
+        engine
+            .execute(parse_str("expect \"last\"").unwrap())
+            .await
+            .unwrap();
+        drop(engine);
+        let mut output = String::new();
+        reader.read_to_string(&mut output).await.unwrap();
+        assert!(
+            output.contains("first") && output.contains("last"),
+            "got: {output:?}"
+        );
+
+        let (writer, reader) = tokio::io::duplex(64);
+        drop(reader);
+        let mut engine = Engine::builder("sh")
+            .args(["-c", "echo lost; sleep 1"])
+            .writer(writer)
+            .spawn()
+            .unwrap();
+        let err = engine
+            .execute(parse_str("expect \"lost\"").unwrap())
+            .await
+            .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("Failed to write"),
+            "got: {err:#}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shown_text_never_waits() {
+        let (open, gate) = tokio::sync::watch::channel(false);
+        let mut engine = Engine::builder("sh")
+            .args(["-c", "sleep 5"])
+            .async_handler(move |_| {
+                let mut gate = gate.clone();
+                async move {
+                    let _ = gate.wait_for(|open| *open).await;
+                }
+            })
+            .spawn()
+            .unwrap();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+            let _ = open.send(true);
+        });
+        // More than the queue holds, on a single-threaded runtime: shown
+        // text that waited for the handler would never finish.
+        let script = format!("repeat {}\n  show \"x\"\nend\n", QUEUE_CAPACITY + 10);
+        let err = engine
+            .execute(parse_str(&script).unwrap())
+            .await
+            .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("bytes of shown text"),
+            "got: {err:#}"
+        );
+        engine
+            .execute(parse_str("show \"y\"").unwrap())
+            .await
+            .unwrap();
+    }
+}
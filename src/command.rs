@@ -1,58 +1,857 @@
 //! The [`ScripttyCommand`] trait and the [`Context`] type commands receive when executed.
 
-use crate::pty::PtySession;
+use crate::accessible::InteractionLog;
+use crate::behavior::Behavior;
+use crate::commands::KeyTable;
+use crate::coverage::BranchCoverage;
+use crate::engine::{OutputTask, Session};
+use crate::fuzz::Fuzz;
+use crate::graphics::Image;
+use crate::interpolate::{interpolate, interpolate_strict, resolve};
+use crate::metrics::ExpectMetrics;
+use crate::notify::Notification;
+use crate::pty::{PtySession, SpawnSpec};
+use crate::secrets::{SECRET_VARS, Secrets};
+use crate::supervisor::Supervisor;
 use anyhow::Result;
 use async_trait::async_trait;
+use rand::rngs::StdRng;
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-type OutputHandler = Arc<dyn Fn(&[u8]) + Send + Sync>;
+/// Upper bound of [`Context::scale_timeout`].
+const MAX_TIMEOUT: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+/// How long a program killed with SIGKILL may take to be reaped.
+const REAP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long [`Context::skip_echo`] watches for the echo of a typed line.
+const ECHO_WAIT: Duration = Duration::from_secs(2);
+
+/// Characters at the end of a typed line used to find its echo. The tail is
+/// enough and survives line editors redrawing the start of the line.
+const ECHO_TAIL: usize = 16;
+
+/// How long the output must stay quiet for [`Context::wait_for_settle`].
+const SETTLE: Duration = Duration::from_millis(300);
+
+pub(crate) type OutputHandler = Arc<dyn Fn(&[u8]) + Send + Sync>;
+pub(crate) type MarkerHandler = Box<dyn FnMut(&str)>;
+pub(crate) type EventHandler = Arc<dyn Fn(&OutputEvent) + Send + Sync>;
+
+/// Something that happened during a run, as passed to a handler registered
+/// with [`Engine::on_event`](crate::Engine::on_event).
+///
+/// Unlike the raw output handler, this tells the program's output apart from
+/// the input the script sent, and marks where each command starts and ends.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputEvent {
+    /// A chunk of the program's output, escape sequences included.
+    ProgramOutput(Vec<u8>),
+    /// Bytes the script sent to the program. The terminal usually echoes
+    /// them, so they tend to come back as [`OutputEvent::ProgramOutput`] too.
+    InputEcho(Vec<u8>),
+    /// A top-level script command started; `index` counts the commands of
+    /// the run from 0.
+    CommandStarted { name: &'static str, index: usize },
+    /// The command with this `index` finished after `duration`, with its
+    /// error message if it failed.
+    CommandFinished {
+        name: &'static str,
+        index: usize,
+        duration: Duration,
+        error: Option<String>,
+    },
+    /// The program was seen to exit with this code.
+    ProcessExited(u32),
+}
+
+/// A top-level script command about to run or just finished, as passed to
+/// the callback registered with [`Engine::on_command`](crate::Engine::on_command).
+pub struct CommandHook<'a> {
+    /// The command's keyword, e.g. `"expect"`.
+    pub name: &'static str,
+    /// The arguments as written after the keyword; see
+    /// [`ScripttyCommand::args`].
+    pub args: &'a str,
+    /// Position of the command in the run, from 0.
+    pub index: usize,
+    /// How long the command took; `None` before it runs.
+    pub duration: Option<Duration>,
+    /// The error the command failed with, once it has.
+    pub error: Option<&'a anyhow::Error>,
+    pub(crate) ctx: &'a Context,
+}
+
+impl CommandHook<'_> {
+    /// Whether the command has finished, as opposed to being about to start.
+    pub fn is_finished(&self) -> bool {
+        self.duration.is_some()
+    }
+
+    /// The terminal screen at this point, e.g. for a screenshot of each step.
+    pub fn screen(&self) -> vt100::Screen {
+        self.ctx.screen()
+    }
+}
 
 /// Execution context passed to [`ScripttyCommand::execute`].
 ///
-/// Provides access to the PTY stdin, the output handler, and the rolling output
-/// buffer used by pattern-matching commands.
+/// Provides access to the PTY stdin, the output handler, the rolling output
+/// buffer used by pattern-matching commands, and the script variable store.
 pub struct Context {
     pub(crate) pty: PtySession,
     pub(crate) output_buffer: Arc<Mutex<String>>,
+    pub(crate) notifications: Arc<Mutex<Vec<Notification>>>,
+    pub(crate) images: Arc<Mutex<Vec<Image>>>,
+    pub(crate) last_image: Option<Image>,
+    pub(crate) transcript: Arc<Mutex<String>>,
+    pub(crate) last_output: Arc<Mutex<std::time::Instant>>,
+    pub(crate) screen: Arc<Mutex<vt100::Parser>>,
+    pub(crate) interactions: Arc<Mutex<InteractionLog>>,
+    pub(crate) sinks: Arc<Mutex<crate::engine::Sinks>>,
+    /// Values masked wherever the run is recorded; shared with the sinks.
+    pub(crate) secrets: Secrets,
+    pub(crate) output_task: OutputTask,
+    pub(crate) spawn: SpawnSpec,
+    pub(crate) snapshots: HashMap<String, String>,
+    pub(crate) supervisor: Supervisor,
     pub(crate) output_handler: OutputHandler,
+    /// The output handler for [`Context::emit`], which must not wait; the
+    /// same as `output_handler` unless that one may.
+    pub(crate) emit_handler: OutputHandler,
+    pub(crate) run_start: tokio::time::Instant,
+    pub(crate) last_match: Option<String>,
+    pub(crate) variables: HashMap<String, String>,
+    pub(crate) metrics: ExpectMetrics,
+    pub(crate) timeout_scale: f64,
+    pub(crate) coverage: BranchCoverage,
+    pub(crate) trace: Option<crate::trace::Trace>,
+    pub(crate) soak: crate::soak::SoakReport,
+    pub(crate) fuzz: Option<Fuzz>,
+    pub(crate) rng: StdRng,
+    pub(crate) default_timeout: Duration,
+    pub(crate) typing_delay: (Duration, Duration),
+    /// Skip pacing pauses (typing, key presses, `wait`); see [`Context::pace`].
+    pub(crate) instant: bool,
+    pub(crate) typo_rate: f64,
+    pub(crate) plain_expect: bool,
+    pub(crate) key_delay: Duration,
+    pub(crate) key_table: KeyTable,
+    /// Shared with the output task, which trims the output buffer by it.
+    pub(crate) behavior: Arc<Mutex<Behavior>>,
+    pub(crate) repl: Option<crate::commands::Repl>,
+    pub(crate) mi_token: u32,
+    pub(crate) narration: Option<crate::commands::Narration>,
+    /// Called with the name of each `marker` the script reaches.
+    pub(crate) marker_handler: Option<MarkerHandler>,
+    pub(crate) snapshot_dir: std::path::PathBuf,
+    pub(crate) snapshot_mode: crate::commands::SnapshotMode,
+    #[cfg(feature = "network")]
+    pub(crate) vendor: Option<crate::commands::Vendor>,
+    pub(crate) exit_code: Option<u32>,
+    pub(crate) quotas: crate::quota::Quotas,
+    /// Bytes sent to the program so far, for the input quota.
+    pub(crate) input_bytes: u64,
+    /// The top-level command running now, named in quota errors.
+    pub(crate) current_command: &'static str,
 }
 
 impl Context {
     /// Write raw bytes to the program's stdin.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error (and stops the program) if the bytes would exceed
+    /// the run's input quota, or if writing fails.
     pub fn write_to_pty(&mut self, data: &[u8]) -> Result<()> {
+        if let Some(quota) = self.quotas.input_exceeded(self.input_bytes, data.len()) {
+            return Err(self.quota_exceeded(quota));
+        }
+        self.input_bytes += data.len() as u64;
+        let recorded = self.secrets.mask_bytes(data);
+        self.interactions.lock().unwrap().record_input(&recorded);
+        {
+            let mut sinks = self.sinks.lock().unwrap();
+            // Best effort, as for the program's output.
+            if let Some(transcript) = sinks.transcript.as_mut() {
+                let _ = transcript.input(&recorded);
+            }
+            if let Some(events) = sinks.events.as_mut() {
+                let _ = events.input(&recorded);
+            }
+            sinks.log.input(&recorded);
+            sinks.event(&OutputEvent::InputEcho(recorded.into_owned()));
+        }
         self.pty.write(data)
     }
 
+    /// Stop the program because `quota` was exceeded, and return the error
+    /// the run fails with.
+    pub(crate) fn quota_exceeded(&mut self, quota: crate::quota::Quota) -> anyhow::Error {
+        self.sinks.lock().unwrap().closed = true;
+        if self.pty.is_running() {
+            // Best effort: the program may exit on its own in the meantime.
+            let _ = self.pty.kill();
+            let _ = self.pty.wait();
+        }
+        crate::quota::error(quota, self.current_command)
+    }
+
+    /// The error for a wait that timed out with `message`, explaining the
+    /// timeout if the program is stopped by a signal and so could not answer.
+    pub(crate) fn timeout_error(&self, message: String) -> anyhow::Error {
+        if self.pty.is_stopped() {
+            anyhow::anyhow!(
+                "{} (the program is stopped by a signal; resume it with `signal CONT`)",
+                message
+            )
+        } else {
+            anyhow::anyhow!(message)
+        }
+    }
+
     /// Pass bytes through the output handler (e.g. to stdout or a custom sink).
+    ///
+    /// Every other sink sees them as well, like the program's output: added
+    /// handlers, recordings, transcripts, the event log, and the
+    /// [`Engine::on_event`](crate::Engine::on_event) handler (as
+    /// [`OutputEvent::ProgramOutput`]). They don't count towards the output
+    /// quota.
     pub fn emit(&self, data: &[u8]) {
-        (self.output_handler)(data);
+        (self.emit_handler)(data);
+        self.sinks.lock().unwrap().display(data);
+    }
+
+    /// Time elapsed since the start of the current run.
+    pub fn elapsed(&self) -> Duration {
+        self.run_start.elapsed()
+    }
+
+    /// The pattern matched by the most recent [`wait_for_any`](Self::wait_for_any)
+    /// call (including `expect` and `expect_any`), if any.
+    pub fn last_match(&self) -> Option<&str> {
+        self.last_match.as_deref()
+    }
+
+    /// Fuzz settings, if the engine runs in fuzz mode.
+    pub fn fuzz(&self) -> Option<&Fuzz> {
+        self.fuzz.as_ref()
+    }
+
+    /// The engine's random number generator.
+    ///
+    /// Commands that make random choices should draw from this generator, not
+    /// from `rand::thread_rng`, so fuzzed runs can be reproduced from their seed.
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+
+    /// Apply the engine's timeout scale factor to `duration`.
+    ///
+    /// Commands with timeouts or fixed pauses should pass them through this so
+    /// a whole script can be slowed down for sluggish environments. The
+    /// result saturates at a century, so deadlines computed from it can't
+    /// overflow.
+    pub fn scale_timeout(&self, duration: Duration) -> Duration {
+        Duration::try_from_secs_f64(duration.as_secs_f64() * self.timeout_scale)
+            .map_or(MAX_TIMEOUT, |scaled| scaled.min(MAX_TIMEOUT))
+    }
+
+    /// The profile whose defaults the run keeps; see [`Behavior`].
+    pub fn behavior(&self) -> Behavior {
+        *self.behavior.lock().unwrap()
+    }
+
+    /// `delay`, a pause that only paces the run for a viewer (between typed
+    /// characters or key presses, or a `wait`), or zero in instant mode.
+    pub fn pace(&self, delay: Duration) -> Duration {
+        if self.instant { Duration::ZERO } else { delay }
+    }
+
+    /// `timeout` if given, otherwise the script's default timeout (5 seconds
+    /// unless changed with `set default_timeout`).
+    pub fn timeout_or_default(&self, timeout: Option<Duration>) -> Duration {
+        timeout.unwrap_or(self.default_timeout)
+    }
+
+    /// The script's per-character typing delay range (50–150 ms unless changed
+    /// with `set typing_speed`).
+    pub fn typing_delay(&self) -> (Duration, Duration) {
+        self.typing_delay
+    }
+
+    /// Pause between the presses of one `key` command (30 ms unless changed
+    /// with `set key_delay`).
+    pub fn key_delay(&self) -> Duration {
+        self.key_delay
+    }
+
+    /// Escape sequences used for keys whose encoding depends on the terminal
+    /// type (from the program's `TERM` unless changed with `set key_table`).
+    pub fn key_table(&self) -> KeyTable {
+        self.key_table
+    }
+
+    /// Record that a branching command at `site` took branch `taken` of the
+    /// branches described by `labels`. See [`crate::coverage`].
+    pub fn record_branch<S: AsRef<str>>(&mut self, site: &str, labels: &[S], taken: usize) {
+        self.coverage.record(site, labels, taken);
+    }
+
+    /// Record a wait for `pattern` that started at `started` and found it if
+    /// `matched`, for the wait statistics and, when enabled, the trace and
+    /// the event log.
+    fn record_wait(&mut self, pattern: &str, started: tokio::time::Instant, matched: bool) {
+        let wait = started.elapsed();
+        if matched {
+            self.metrics.record(pattern, wait);
+        } else {
+            self.metrics.record_timeout(pattern);
+        }
+        if let Some(trace) = &mut self.trace {
+            trace.record_wait(pattern, wait, matched);
+        }
+        if let Some(events) = self.sinks.lock().unwrap().events.as_mut() {
+            let _ = events.expect(pattern, wait, matched);
+        }
+    }
+
+    /// Look up a script variable.
+    pub fn var(&self, name: &str) -> Option<&str> {
+        self.variables.get(name).map(String::as_str)
+    }
+
+    /// Set a script variable, replacing any previous value.
+    ///
+    /// The values of [`SECRET_VARS`] are registered as secrets.
+    pub fn set_var(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let (name, value) = (name.into(), value.into());
+        if SECRET_VARS.contains(&name.as_str()) {
+            self.secrets.register(value.as_str());
+        }
+        self.variables.insert(name, value);
+    }
+
+    /// Mask `value` wherever the run is recorded from now on: the event
+    /// log, transcripts, input events, `debug_state`, and the failure
+    /// report. Commands call this before sending a password.
+    pub fn register_secret(&self, value: impl Into<String>) {
+        self.secrets.register(value);
+    }
+
+    /// The run's registered secrets, to mask text a custom command records.
+    pub fn secrets(&self) -> &Secrets {
+        &self.secrets
+    }
+
+    /// Expand `${NAME}` references in `text` from the variable store, and
+    /// `${env:NAME}` references from the environment.
+    ///
+    /// `$${` yields a literal `${`. A reference to a variable (or
+    /// environment variable) that is not defined is left as written, so shell
+    /// text such as `echo ${HOME}` reaches the program unchanged. Under a
+    /// [`Behavior`] without variables, `text` is returned as written.
+    ///
+    /// # Errors
+    ///
+    /// Doesn't fail at present; the `Result` is kept for compatibility.
+    pub fn interpolate(&self, text: &str) -> Result<String> {
+        if !self.behavior().interpolates() {
+            return Ok(text.to_string());
+        }
+        Ok(interpolate(text, |name| resolve(name, &self.variables)))
+    }
+
+    /// Like [`interpolate`](Self::interpolate), but fail on a reference to a
+    /// variable that is not defined, for values such as passwords.
+    pub(crate) fn interpolate_strict(&self, text: &str) -> Result<String> {
+        interpolate_strict(text, |name| resolve(name, &self.variables))
+    }
+
+    /// Like [`interpolate`](Self::interpolate), for raw bytes. Data that is not
+    /// valid UTF-8 or has no `${` is returned unchanged.
+    pub fn interpolate_bytes<'a>(&self, data: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        match std::str::from_utf8(data) {
+            Ok(text) if text.contains("${") => Ok(Cow::Owned(self.interpolate(text)?.into_bytes())),
+            _ => Ok(Cow::Borrowed(data)),
+        }
     }
 
     /// Block until `pattern` appears in the rolling output buffer, or until
-    /// `timeout` elapses.
+    /// `timeout` (scaled by [`scale_timeout`](Self::scale_timeout)) elapses.
     ///
     /// Once found, the buffer is consumed up to and including the pattern so
     /// subsequent calls do not match the same occurrence.
-    pub async fn wait_for_pattern(&self, pattern: &str, timeout: Duration) -> Result<()> {
-        let deadline = tokio::time::Instant::now() + timeout;
+    ///
+    /// With `set plain_expect on`, escape sequences in the output are ignored
+    /// (see [`wait_for_any`](Self::wait_for_any)).
+    pub async fn wait_for_pattern(&mut self, pattern: &str, timeout: Duration) -> Result<()> {
+        self.wait_for_pattern_in(pattern, timeout, self.plain_expect)
+            .await
+    }
+
+    /// Like [`wait_for_pattern`](Self::wait_for_pattern), ignoring escape
+    /// sequences in the output when `plain` is set.
+    pub(crate) async fn wait_for_pattern_in(
+        &mut self,
+        pattern: &str,
+        timeout: Duration,
+        plain: bool,
+    ) -> Result<()> {
+        if self
+            .wait_for_any_in(&[pattern], timeout, plain)
+            .await
+            .is_err()
+        {
+            return Err(self.timeout_error(format!("Timeout waiting for pattern: '{}'", pattern)));
+        }
+        Ok(())
+    }
+
+    /// Block until any of `patterns` appears in the rolling output buffer, or
+    /// until the scaled `timeout` elapses, and return the index of the pattern that matched.
+    ///
+    /// If several patterns are present, the one occurring earliest in the
+    /// output wins (ties go to the pattern listed first). The buffer is consumed
+    /// up to and including the match, and the matched pattern is recorded as
+    /// [`last_match`](Self::last_match).
+    ///
+    /// With `set plain_expect on`, ANSI escape sequences (colors, cursor
+    /// movement, window titles) are removed from the output before matching,
+    /// so a colored prompt matches a pattern without the escape bytes.
+    pub async fn wait_for_any<S: AsRef<str>>(
+        &mut self,
+        patterns: &[S],
+        timeout: Duration,
+    ) -> Result<usize> {
+        self.wait_for_any_in(patterns, timeout, self.plain_expect)
+            .await
+    }
+
+    /// Like [`wait_for_any`](Self::wait_for_any), ignoring escape sequences
+    /// in the output when `plain` is set.
+    pub(crate) async fn wait_for_any_in<S: AsRef<str>>(
+        &mut self,
+        patterns: &[S],
+        timeout: Duration,
+        plain: bool,
+    ) -> Result<usize> {
+        let started = tokio::time::Instant::now();
+        let deadline = started + self.scale_timeout(timeout);
         loop {
             {
                 let mut buffer = self.output_buffer.lock().unwrap();
-                if let Some(idx) = buffer.find(pattern) {
-                    buffer.drain(..idx + pattern.len());
-                    return Ok(());
+                let stripped = plain.then(|| crate::ansi::strip(&buffer));
+                let haystack = stripped.as_ref().map_or(buffer.as_str(), |(s, _)| s);
+                let found = patterns
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, p)| haystack.find(p.as_ref()).map(|idx| (idx, i)))
+                    .min();
+                if let Some((idx, i)) = found {
+                    let pattern = patterns[i].as_ref();
+                    let end = match &stripped {
+                        Some((_, offsets)) if !pattern.is_empty() => {
+                            offsets[idx + pattern.len() - 1] + 1
+                        }
+                        _ => idx + pattern.len(),
+                    };
+                    buffer.drain(..end);
+                    drop(buffer);
+                    self.last_match = Some(pattern.to_string());
+                    self.record_wait(pattern, started, true);
+                    return Ok(i);
                 }
             }
             if tokio::time::Instant::now() >= deadline {
+                for p in patterns {
+                    self.record_wait(p.as_ref(), started, false);
+                }
+                let list: Vec<String> = patterns
+                    .iter()
+                    .map(|p| format!("'{}'", p.as_ref()))
+                    .collect();
+                return Err(self.timeout_error(format!(
+                    "Timeout waiting for any of patterns: {}",
+                    list.join(", ")
+                )));
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Block until the program emits a bell or notification for which `matches`
+    /// returns true, or until the scaled `timeout` elapses.
+    ///
+    /// The matching notification and every one received before it are consumed.
+    /// `what` describes the awaited event in the timeout error and in the wait
+    /// statistics.
+    pub async fn wait_for_notification<F>(
+        &mut self,
+        matches: F,
+        what: &str,
+        timeout: Duration,
+    ) -> Result<Notification>
+    where
+        F: Fn(&Notification) -> bool,
+    {
+        let started = tokio::time::Instant::now();
+        let deadline = started + self.scale_timeout(timeout);
+        loop {
+            {
+                let mut pending = self.notifications.lock().unwrap();
+                if let Some(idx) = pending.iter().position(&matches) {
+                    let found = pending.drain(..=idx).next_back().unwrap();
+                    drop(pending);
+                    self.record_wait(what, started, true);
+                    return Ok(found);
+                }
+            }
+            if tokio::time::Instant::now() >= deadline {
+                self.record_wait(what, started, false);
+                return Err(self.timeout_error(format!("Timeout waiting for {}", what)));
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Block until the program draws an inline image, or until the scaled
+    /// `timeout` elapses. The oldest image not yet waited for is consumed and
+    /// becomes [`Context::last_image`].
+    pub async fn wait_for_image(&mut self, timeout: Duration) -> Result<Image> {
+        let started = tokio::time::Instant::now();
+        let deadline = started + self.scale_timeout(timeout);
+        loop {
+            let found = {
+                let mut pending = self.images.lock().unwrap();
+                (!pending.is_empty()).then(|| pending.remove(0))
+            };
+            if let Some(image) = found {
+                self.record_wait("<image>", started, true);
+                self.last_image = Some(image.clone());
+                return Ok(image);
+            }
+            if tokio::time::Instant::now() >= deadline {
+                self.record_wait("<image>", started, false);
+                return Err(self.timeout_error("Timeout waiting for an image".to_string()));
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// The most recent inline image the program drew, consuming any images
+    /// not yet waited for.
+    pub fn last_image(&mut self) -> Option<&Image> {
+        if let Some(latest) = self.images.lock().unwrap().drain(..).next_back() {
+            self.last_image = Some(latest);
+        }
+        self.last_image.as_ref()
+    }
+
+    /// Block until the program has produced no output for `quiet`, or until
+    /// the scaled `timeout` elapses.
+    ///
+    /// `quiet` is scaled like `timeout`, since slow environments also stretch
+    /// the pauses in a program's output. Quiet time already passed when this
+    /// is called counts towards `quiet`.
+    pub async fn wait_for_idle(&mut self, quiet: Duration, timeout: Duration) -> Result<()> {
+        let started = tokio::time::Instant::now();
+        let deadline = started + self.scale_timeout(timeout);
+        let quiet = self.scale_timeout(quiet);
+        loop {
+            if self.last_output.lock().unwrap().elapsed() >= quiet {
+                self.record_wait("<idle>", started, true);
+                return Ok(());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                self.record_wait("<idle>", started, false);
                 return Err(anyhow::anyhow!(
-                    "Timeout waiting for pattern: '{}'",
-                    pattern
+                    "Timeout waiting for {:?} without output",
+                    quiet
                 ));
             }
             tokio::time::sleep(Duration::from_millis(10)).await;
         }
     }
+
+    /// Block until the program has stopped redrawing after a key press, or
+    /// until the scaled `timeout` elapses.
+    ///
+    /// Used when there is no text to wait for, e.g. after a Tab completion.
+    pub async fn wait_for_settle(&mut self, timeout: Duration) -> Result<()> {
+        self.wait_for_idle(SETTLE, timeout).await
+    }
+
+    /// Wait briefly for the echo of `line`, just typed, so its text can't be
+    /// mistaken for the output waited for next.
+    ///
+    /// Gives up silently if the echo does not show, e.g. with echo turned off.
+    pub async fn skip_echo(&mut self, line: &str) {
+        let line = line.trim_end();
+        if line.is_empty() {
+            return;
+        }
+        let start = line
+            .char_indices()
+            .rev()
+            .nth(ECHO_TAIL - 1)
+            .map_or(0, |(i, _)| i);
+        let _ = self.wait_for_pattern(&line[start..], ECHO_WAIT).await;
+    }
+
+    /// Block until the child process exits, or until the scaled `timeout`
+    /// elapses, and return its exit code.
+    ///
+    /// The code is also stored as the `EXIT_CODE` script variable and is
+    /// available afterwards through [`exit_code`](Self::exit_code).
+    pub async fn wait_for_exit(&mut self, timeout: Duration) -> Result<u32> {
+        let started = tokio::time::Instant::now();
+        let deadline = started + self.scale_timeout(timeout);
+        loop {
+            if let Some(code) = self.pty.try_wait()? {
+                self.record_wait("<eof>", started, true);
+                self.set_exit_code(code);
+                return Ok(code);
+            }
+            if tokio::time::Instant::now() >= deadline {
+                self.record_wait("<eof>", started, false);
+                return Err(self.timeout_error("Timeout waiting for the program to exit".into()));
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Stop the program: send it SIGTERM, and SIGKILL (to its whole process
+    /// group) if it is still running after the scaled `grace` period. Then
+    /// wait briefly for its remaining output to be read, and return its exit
+    /// code.
+    ///
+    /// The code is stored like in [`wait_for_exit`](Self::wait_for_exit). A
+    /// program that already exited is only reaped.
+    pub async fn kill(&mut self, grace: Duration) -> Result<u32> {
+        if self.pty.try_wait()?.is_none() {
+            // The program may exit between the check and the signal.
+            let _ = self.pty.signal(libc::SIGTERM);
+            let deadline = tokio::time::Instant::now() + self.scale_timeout(grace);
+            while self.pty.try_wait()?.is_none() && tokio::time::Instant::now() < deadline {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }
+        let code = match self.pty.try_wait()? {
+            Some(code) => code,
+            None => {
+                let _ = self.pty.kill();
+                self.reap().await?
+            }
+        };
+        self.set_exit_code(code);
+        self.drain_output().await;
+        Ok(code)
+    }
+
+    /// Wait for the killed program to exit and return its exit code,
+    /// polling so the runtime is not blocked meanwhile.
+    async fn reap(&mut self) -> Result<u32> {
+        let deadline = tokio::time::Instant::now() + REAP_TIMEOUT;
+        loop {
+            if let Some(code) = self.pty.try_wait()? {
+                return Ok(code);
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(anyhow::anyhow!(
+                    "Program did not exit within {:?} of SIGKILL",
+                    REAP_TIMEOUT
+                ));
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Give the output task up to a second to forward the last output of an
+    /// exited program. The reader stops at the end of the output, unless a
+    /// process that left the group still holds the terminal open.
+    async fn drain_output(&self) {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(1);
+        while !self.output_task.is_finished() && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// The terminal screen as rendered from the program's output so far.
+    ///
+    /// The output is run through a terminal emulator, so the screen reflects
+    /// cursor movement, clearing, and redraws the way a user would see them:
+    /// the grid of cells with their contents and colors, and the cursor
+    /// position. The returned screen is a copy and does not change afterwards.
+    ///
+    /// ```
+    /// use scriptty::Context;
+    /// use scriptty::vt100::Color;
+    ///
+    /// fn status_bar_is_red(ctx: &Context) -> bool {
+    ///     let screen = ctx.screen();
+    ///     let (rows, _) = screen.size();
+    ///     screen
+    ///         .cell(rows - 1, 0)
+    ///         .is_some_and(|cell| cell.bgcolor() == Color::Idx(1))
+    /// }
+    /// ```
+    pub fn screen(&self) -> vt100::Screen {
+        self.screen.lock().unwrap().screen().clone()
+    }
+
+    /// Resize the terminal to `rows` x `cols` and notify the program with
+    /// SIGWINCH. The size is kept when the program is respawned.
+    pub fn resize(&mut self, rows: u16, cols: u16) -> Result<()> {
+        self.pty.resize(rows, cols)?;
+        self.screen
+            .lock()
+            .unwrap()
+            .screen_mut()
+            .set_size(rows, cols);
+        self.spawn.options.size = Some((rows, cols));
+        let mut sinks = self.sinks.lock().unwrap();
+        sinks.log.resize(rows, cols);
+        if let Some(recorder) = sinks.cast.as_mut() {
+            recorder.resize(cols, rows)?;
+        }
+        Ok(())
+    }
+
+    /// The child's exit code, once it has been observed to exit.
+    pub fn exit_code(&self) -> Option<u32> {
+        self.exit_code
+    }
+
+    pub(crate) fn set_exit_code(&mut self, code: u32) {
+        if self.exit_code.is_none() {
+            self.sinks
+                .lock()
+                .unwrap()
+                .event(&OutputEvent::ProcessExited(code));
+        }
+        self.exit_code = Some(code);
+        self.set_var("EXIT_CODE", code.to_string());
+    }
+
+    /// Kill the program and start it again with the same command line and
+    /// fresh output buffers.
+    ///
+    /// With `Some(label)`, the old session's transcript is saved under that
+    /// label for [`snapshot`](Self::snapshot). The `EXIT_CODE` variable and
+    /// [`exit_code`](Self::exit_code) are cleared.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error instead of restarting if the program has exited on
+    /// its own too often recently (see [`crate::supervisor`]).
+    pub async fn respawn(&mut self, snapshot: Option<&str>) -> Result<()> {
+        match self.pty.try_wait()? {
+            Some(code) => self
+                .supervisor
+                .record_exit(code, std::time::Instant::now())?,
+            None => {
+                // The child may exit between the check and the kill; that's fine.
+                let _ = self.pty.kill();
+                self.reap().await?;
+            }
+        }
+        // Let the old session's last output drain into its transcript, then
+        // make sure it cannot reach the new session's sinks.
+        self.drain_output().await;
+        self.output_task.abort();
+        if let Some(label) = snapshot {
+            let transcript = self.transcript.lock().unwrap().clone();
+            self.snapshots.insert(label.to_string(), transcript);
+        }
+
+        let session = Session::start(
+            &self.spawn,
+            self.output_handler.clone(),
+            self.interactions.clone(),
+            self.sinks.clone(),
+            self.behavior.clone(),
+        )?;
+        self.pty = session.pty;
+        self.output_buffer = session.output_buffer;
+        self.notifications = session.notifications;
+        self.images = session.images;
+        self.transcript = session.transcript;
+        self.last_output = session.last_output;
+        self.screen = session.screen;
+        self.output_task = session.output_task;
+        self.exit_code = None;
+        self.variables.remove("EXIT_CODE");
+        Ok(())
+    }
+
+    /// The transcript of an earlier session, saved under `label` by
+    /// [`respawn`](Self::respawn).
+    pub fn snapshot(&self, label: &str) -> Option<&str> {
+        self.snapshots.get(label).map(String::as_str)
+    }
+
+    /// Store the groups of a [`wait_for_regex`](Self::wait_for_regex) match
+    /// of `re` as script variables: the whole match as `MATCH`, group `n` as
+    /// `MATCH_n`, and each named group (`(?<NAME>...)`) under its name.
+    /// Groups that did not take part in the match are set to the empty string.
+    pub fn set_match_vars(&mut self, re: &Regex, groups: &[Option<String>]) {
+        for (i, (name, value)) in re.capture_names().zip(groups).enumerate() {
+            let value = value.clone().unwrap_or_default();
+            if let Some(name) = name {
+                self.set_var(name, value.as_str());
+            }
+            match i {
+                0 => self.set_var("MATCH", value),
+                _ => self.set_var(format!("MATCH_{}", i), value),
+            }
+        }
+    }
+
+    /// Block until `re` matches the rolling output buffer, or until the scaled
+    /// `timeout` elapses, and return the capture groups of the match (index 0 is the
+    /// whole match).
+    ///
+    /// The buffer is consumed up to and including the match. Pass the groups
+    /// to [`set_match_vars`](Self::set_match_vars) to make them available to
+    /// the script.
+    pub async fn wait_for_regex(
+        &mut self,
+        re: &Regex,
+        timeout: Duration,
+    ) -> Result<Vec<Option<String>>> {
+        let started = tokio::time::Instant::now();
+        let deadline = started + self.scale_timeout(timeout);
+        loop {
+            {
+                let mut buffer = self.output_buffer.lock().unwrap();
+                let found = re.captures(&buffer).map(|caps| {
+                    let groups: Vec<Option<String>> = caps
+                        .iter()
+                        .map(|m| m.map(|m| m.as_str().to_string()))
+                        .collect();
+                    (caps.get(0).unwrap().end(), groups)
+                });
+                if let Some((end, groups)) = found {
+                    buffer.drain(..end);
+                    drop(buffer);
+                    self.record_wait(re.as_str(), started, true);
+                    return Ok(groups);
+                }
+            }
+            if tokio::time::Instant::now() >= deadline {
+                self.record_wait(re.as_str(), started, false);
+                return Err(
+                    self.timeout_error(format!("Timeout waiting for regex: '{}'", re.as_str()))
+                );
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
 }
 
 /// A single scriptty script command.
@@ -88,6 +887,12 @@ pub trait ScripttyCommand: 'static {
         Ok(Box::new(Self::parse(args)?))
     }
 
+    /// The arguments the command was parsed from, as written after its
+    /// keyword; empty for commands constructed in code.
+    fn args(&self) -> &str {
+        ""
+    }
+
     /// Execute the command using the provided engine context.
     async fn execute(&self, ctx: &mut Context) -> Result<()>;
 }
@@ -0,0 +1,82 @@
+//! [`AnyOrder`] block — commands whose relative order does not matter.
+//!
+//! Script syntax:
+//!
+//! ```text
+//! any_order
+//!   send "export A=1\n"
+//!   send "export B=2\n"
+//! end
+//! ```
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::ScriptReader;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use rand::seq::SliceRandom;
+
+/// Runs its commands in script order normally, and in a random (seeded)
+/// order when fuzz mode is enabled.
+///
+/// Use it to tag steps the program under test must accept in any order, so
+/// fuzzing can check that assumption.
+pub struct AnyOrder {
+    pub commands: Vec<Box<dyn ScripttyCommand>>,
+}
+
+impl AnyOrder {
+    pub const NAME: &'static str = "any_order";
+
+    /// Parse the block body up to its closing `end`.
+    pub(crate) fn parse_block(
+        args: &str,
+        reader: &mut ScriptReader,
+    ) -> Result<Box<dyn ScripttyCommand>> {
+        if !args.trim().is_empty() {
+            return Err(anyhow!("any_order takes no arguments"));
+        }
+        let (commands, _) = reader.parse_block(&["end"])?;
+        Ok(Box::new(Self { commands }))
+    }
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for AnyOrder {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(_args: &str) -> Result<Self> {
+        Err(anyhow!(
+            "'any_order' starts a block and must be followed by 'end'"
+        ))
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let mut order: Vec<usize> = (0..self.commands.len()).collect();
+        if ctx.fuzz().is_some() {
+            order.shuffle(ctx.rng());
+        }
+        for i in order {
+            self.commands[i].execute(ctx).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_str;
+
+    #[test]
+    fn test_parse() {
+        let cmds = parse_str("any_order\nkey a\nkey b\nend\n").unwrap();
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0].name(), "any_order");
+    }
+
+    #[test]
+    fn test_parse_with_args() {
+        assert!(parse_str("any_order now\nkey a\nend\n").is_err());
+    }
+}
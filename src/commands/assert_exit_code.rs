@@ -0,0 +1,83 @@
+//! [`AssertExitCode`] command — fails the script unless the program exited with a given code.
+//!
+//! Script syntax:
+//! - `assert_exit_code 0` — wait for exit (default timeout) and check the code
+//! - `assert_exit_code 0 30s` — custom timeout
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::parse_duration;
+use anyhow::{Context as _, Result, anyhow};
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// Waits for the program to exit (unless it already has) and fails unless its
+/// exit code is `code`.
+pub struct AssertExitCode {
+    pub code: u32,
+    /// `None` uses the script's default timeout.
+    pub timeout: Option<Duration>,
+}
+
+impl AssertExitCode {
+    pub const NAME: &'static str = "assert_exit_code";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for AssertExitCode {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let args = args.trim();
+        let (code, timeout) = args.split_once(' ').unwrap_or((args, ""));
+        let code = code.parse().context("Invalid exit code")?;
+        let timeout = match timeout.trim() {
+            "" => None,
+            timeout => Some(parse_duration(timeout)?),
+        };
+        Ok(Self { code, timeout })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let actual = match ctx.exit_code() {
+            Some(code) => code,
+            None => {
+                let timeout = ctx.timeout_or_default(self.timeout);
+                ctx.wait_for_exit(timeout).await?
+            }
+        };
+        if actual != self.code {
+            return Err(anyhow!(
+                "Program exited with code {}, expected {}",
+                actual,
+                self.code
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        let cmd = AssertExitCode::parse("0").unwrap();
+        assert_eq!(cmd.code, 0);
+        assert_eq!(cmd.timeout, None);
+
+        let cmd = AssertExitCode::parse("2 30s").unwrap();
+        assert_eq!(cmd.code, 2);
+        assert_eq!(cmd.timeout, Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(AssertExitCode::parse("").is_err());
+        assert!(AssertExitCode::parse("-1").is_err());
+        assert!(AssertExitCode::parse("0 soon").is_err());
+    }
+}
@@ -0,0 +1,103 @@
+//! [`AssertImageSimilar`] command — compares the program's last image with a
+//! PNG file.
+//!
+//! Script syntax:
+//! - `assert_image_similar "golden.png"` — require a similarity of 0.99
+//! - `assert_image_similar "golden.png" 0.98` — custom threshold
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::split_quoted;
+use anyhow::{Context as _, Result, anyhow};
+use async_trait::async_trait;
+
+/// Fails unless the last inline image the program drew looks like the PNG
+/// file at `path`.
+///
+/// Similarity runs from 0.0 to 1.0: one minus the mean difference of the two
+/// images' color and alpha channels, so small rendering differences (anti-
+/// aliasing, a shifted palette) still pass a threshold below 1.0. The images
+/// must be the same size. The program's image must be a PNG; sixel and raw
+/// kitty pixel data are converted to PNG when they arrive.
+///
+/// Relative paths are resolved against scriptty's working directory;
+/// `${NAME}` variables are expanded when the command runs.
+pub struct AssertImageSimilar {
+    pub path: String,
+    pub threshold: f64,
+}
+
+impl AssertImageSimilar {
+    pub const NAME: &'static str = "assert_image_similar";
+}
+
+/// Threshold used when the script gives none.
+const DEFAULT_THRESHOLD: f64 = 0.99;
+
+#[async_trait(?Send)]
+impl ScripttyCommand for AssertImageSimilar {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let (path, remainder) = split_quoted(args)?;
+        let threshold = if remainder.is_empty() {
+            DEFAULT_THRESHOLD
+        } else {
+            remainder
+                .parse::<f64>()
+                .ok()
+                .filter(|t| (0.0..=1.0).contains(t))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Invalid similarity threshold, expected a number from 0 to 1: {}",
+                        remainder
+                    )
+                })?
+        };
+        Ok(Self { path, threshold })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let path = ctx.interpolate(&self.path)?;
+        let expected = std::fs::read(&path)
+            .with_context(|| format!("Failed to read expected image: {}", path))?;
+        let image = ctx
+            .last_image()
+            .ok_or_else(|| anyhow!("The program has not drawn an image"))?;
+        let similarity = image
+            .similarity(&expected)
+            .with_context(|| format!("Failed to compare the image with {}", path))?;
+        if similarity < self.threshold {
+            return Err(anyhow!(
+                "Image differs from {}: similarity {:.4}, expected at least {}",
+                path,
+                similarity,
+                self.threshold
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        let cmd = AssertImageSimilar::parse(r#""golden.png" 0.98"#).unwrap();
+        assert_eq!(cmd.path, "golden.png");
+        assert_eq!(cmd.threshold, 0.98);
+        assert_eq!(
+            AssertImageSimilar::parse(r#""golden.png""#)
+                .unwrap()
+                .threshold,
+            DEFAULT_THRESHOLD
+        );
+        assert!(AssertImageSimilar::parse(r#""golden.png" 1.5"#).is_err());
+        assert!(AssertImageSimilar::parse(r#""golden.png" high"#).is_err());
+        assert!(AssertImageSimilar::parse("golden.png").is_err());
+    }
+}
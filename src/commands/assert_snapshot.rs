@@ -0,0 +1,87 @@
+//! [`AssertSnapshot`] command — checks the output saved by `respawn snapshot`.
+//!
+//! Script syntax:
+//! - `assert_snapshot before "count: 3"` — fail unless snapshot `before` contains the text
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::{parse_identifier, split_quoted};
+use crate::testing::Golden;
+use anyhow::{Context as _, Result, anyhow};
+use async_trait::async_trait;
+
+/// Fails unless the transcript saved under `label` by `respawn snapshot`
+/// contains `pattern`.
+///
+/// The transcript is normalized like a golden file first (see
+/// [`crate::testing::Golden`]): escape sequences are removed and line
+/// endings become `\n`.
+///
+/// Unlike `expect`, this does not wait: the snapshot is the complete output
+/// of a session that has already ended.
+pub struct AssertSnapshot {
+    pub label: String,
+    pub pattern: String,
+}
+
+impl AssertSnapshot {
+    pub const NAME: &'static str = "assert_snapshot";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for AssertSnapshot {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let args = args.trim();
+        let (label, rest) = args
+            .split_once(' ')
+            .ok_or_else(|| anyhow!("Expected a snapshot label and a quoted pattern"))?;
+        let label = parse_identifier(label).context("Invalid snapshot label")?;
+        let (pattern, rest) = split_quoted(rest)?;
+        if !rest.is_empty() {
+            return Err(anyhow!("Unexpected text after pattern: '{}'", rest));
+        }
+        Ok(Self {
+            label: label.to_string(),
+            pattern,
+        })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let pattern = ctx.interpolate(&self.pattern)?;
+        let snapshot = ctx
+            .snapshot(&self.label)
+            .ok_or_else(|| anyhow!("No snapshot named '{}'", self.label))?;
+        let snapshot = Golden::new().normalized(snapshot);
+        if !snapshot.contains(&pattern) {
+            return Err(anyhow!(
+                "Snapshot '{}' does not contain: '{}'",
+                self.label,
+                pattern
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        let cmd = AssertSnapshot::parse(r#"before "count: 3""#).unwrap();
+        assert_eq!(cmd.label, "before");
+        assert_eq!(cmd.pattern, "count: 3");
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(AssertSnapshot::parse("before").is_err());
+        assert!(AssertSnapshot::parse(r#""count: 3""#).is_err());
+        assert!(AssertSnapshot::parse(r#"before "a" 5s"#).is_err());
+    }
+}
@@ -0,0 +1,123 @@
+//! [`At`] command — runs another command at a fixed offset from the start of the run.
+//!
+//! Script syntax: `at 00:01:30 type "hello"` (also `MM:SS`, or a duration like `90s`)
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::{parse_duration, parse_line};
+use anyhow::{Context as _, Result, anyhow};
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// Runs `command` once `offset` has elapsed since the start of the run.
+///
+/// Unlike [`crate::commands::Wait`], which adds a delay relative to the
+/// previous command, `at` targets an absolute point on the run timeline. This
+/// keeps a recording in sync with a pre-written voiceover regardless of how
+/// long earlier steps took. If the target time has already passed, the command
+/// runs immediately.
+pub struct At {
+    pub offset: Duration,
+    pub command: Box<dyn ScripttyCommand>,
+}
+
+impl At {
+    pub const NAME: &'static str = "at";
+}
+
+/// Parse a timeline offset: `HH:MM:SS`, `MM:SS` (seconds may be fractional),
+/// or any duration accepted by [`parse_duration`].
+pub(crate) fn parse_offset(s: &str) -> Result<Duration> {
+    if !s.contains(':') {
+        return parse_duration(s);
+    }
+    let parts: Vec<&str> = s.split(':').collect();
+    let (hours, minutes, seconds) = match parts.as_slice() {
+        [h, m, s] => (*h, *m, *s),
+        [m, s] => ("0", *m, *s),
+        _ => {
+            return Err(anyhow!(
+                "Invalid timestamp, expected HH:MM:SS or MM:SS: {}",
+                s
+            ));
+        }
+    };
+    let hours: u64 = hours.parse().context("Invalid hours value")?;
+    let minutes: u64 = minutes.parse().context("Invalid minutes value")?;
+    let seconds: f64 = seconds.parse().context("Invalid seconds value")?;
+    if minutes >= 60 || !(0.0..60.0).contains(&seconds) {
+        return Err(anyhow!("Timestamp component out of range: {}", s));
+    }
+    let whole = hours
+        .checked_mul(3600)
+        .and_then(|secs| secs.checked_add(minutes * 60))
+        .ok_or_else(|| anyhow!("Timestamp too large: {}", s))?;
+    Ok(Duration::from_secs(whole) + Duration::from_secs_f64(seconds))
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for At {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let (timestamp, rest) = args
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| anyhow!("at command requires a timestamp and a command"))?;
+        Ok(Self {
+            offset: parse_offset(timestamp)?,
+            command: parse_line(rest.trim())?,
+        })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let target = ctx
+            .run_start
+            .checked_add(self.offset)
+            .ok_or_else(|| anyhow!("at offset too large: {:?}", self.offset))?;
+        tokio::time::sleep_until(target).await;
+        self.command.execute(ctx).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse_hms() {
+        let cmd = At::parse(r#"00:01:30 type "hello""#).unwrap();
+        assert_eq!(cmd.offset, Duration::from_secs(90));
+        assert_eq!(cmd.command.name(), "type");
+    }
+
+    #[test]
+    fn test_parse_ms_fractional() {
+        let cmd = At::parse("02:05.5 key Enter").unwrap();
+        assert_eq!(cmd.offset, Duration::from_secs_f64(125.5));
+    }
+
+    #[test]
+    fn test_parse_duration_offset() {
+        let cmd = At::parse(r#"1500ms show "note""#).unwrap();
+        assert_eq!(cmd.offset, Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn test_parse_out_of_range() {
+        assert!(At::parse("00:75:00 key Enter").is_err());
+    }
+
+    #[test]
+    fn test_parse_overflow() {
+        let err = parse_offset("99999999999999999:00:00").unwrap_err();
+        assert!(err.to_string().contains("too large"), "got: {err}");
+    }
+
+    #[test]
+    fn test_parse_missing_command() {
+        assert!(At::parse("00:00:10").is_err());
+    }
+}
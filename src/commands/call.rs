@@ -0,0 +1,198 @@
+//! User-defined macros: `def` … `end` definitions and the [`Call`] command.
+//!
+//! Script syntax:
+//!
+//! ```text
+//! def login(user, pass)
+//!   expect "login:"
+//!   send "${user}"
+//!   key Enter
+//!   expect "Password:"
+//!   send "${pass}"
+//!   key Enter
+//! end
+//!
+//! call login("bob", "hunter2")
+//! ```
+//!
+//! A macro must be defined before it is called. Inside the body, parameters
+//! are ordinary script variables bound to the call's arguments.
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::{ScriptReader, parse_identifier, split_quoted};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use std::rc::Rc;
+
+/// A named, parameterized sequence of commands defined with `def`.
+pub struct Macro {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Vec<Box<dyn ScripttyCommand>>,
+}
+
+impl Macro {
+    /// Parse a `def name(params)` line and the body up to its closing `end`.
+    pub(crate) fn parse_definition(args: &str, reader: &mut ScriptReader) -> Result<Self> {
+        let (name, params) = split_signature(args)?;
+        let params = if params.trim().is_empty() {
+            Vec::new()
+        } else {
+            params
+                .split(',')
+                .map(|p| parse_identifier(p).map(str::to_string))
+                .collect::<Result<Vec<_>>>()?
+        };
+        let (body, _) = reader.parse_block(&["end"])?;
+        Ok(Self {
+            name: name.to_string(),
+            params,
+            body,
+        })
+    }
+}
+
+/// Runs a [`Macro`] with its parameters bound to `args`.
+///
+/// Arguments may reference variables; they are expanded when the call runs.
+/// Parameter bindings last for the duration of the call, after which any
+/// variables they shadowed are restored.
+pub struct Call {
+    pub target: Rc<Macro>,
+    pub args: Vec<String>,
+}
+
+impl Call {
+    pub const NAME: &'static str = "call";
+
+    /// Parse `call name("arg", ...)`, resolving `name` against the macros
+    /// defined so far.
+    pub(crate) fn parse_call(
+        args: &str,
+        reader: &mut ScriptReader,
+    ) -> Result<Box<dyn ScripttyCommand>> {
+        let (name, mut remainder) = split_signature(args)?;
+        let mut call_args = Vec::new();
+        while !remainder.is_empty() {
+            let (arg, rest) = split_quoted(remainder)?;
+            call_args.push(arg);
+            remainder = match rest.strip_prefix(',') {
+                Some(rest) => rest.trim(),
+                None if rest.is_empty() => rest,
+                None => return Err(anyhow!("Expected ',' between arguments, got: {}", rest)),
+            };
+        }
+
+        let target = reader
+            .macro_named(name)
+            .ok_or_else(|| anyhow!("Unknown macro: '{}'", name))?;
+        if call_args.len() != target.params.len() {
+            return Err(anyhow!(
+                "Macro '{}' takes {} argument(s), got {}",
+                name,
+                target.params.len(),
+                call_args.len()
+            ));
+        }
+        Ok(Box::new(Self {
+            target,
+            args: call_args,
+        }))
+    }
+}
+
+/// Split `name(inner)` into the macro name and the text between the parentheses.
+fn split_signature(s: &str) -> Result<(&str, &str)> {
+    let s = s.trim();
+    let (name, rest) = s
+        .split_once('(')
+        .ok_or_else(|| anyhow!("Expected 'name(...)', got: {}", s))?;
+    let inner = rest
+        .strip_suffix(')')
+        .ok_or_else(|| anyhow!("Expected ')' at end of: {}", s))?;
+    Ok((parse_identifier(name)?, inner.trim()))
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for Call {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(_args: &str) -> Result<Self> {
+        Err(anyhow!(
+            "'call' can only refer to macros defined earlier in the script"
+        ))
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let values = self
+            .args
+            .iter()
+            .map(|a| ctx.interpolate(a))
+            .collect::<Result<Vec<_>>>()?;
+        let shadowed: Vec<(String, Option<String>)> = self
+            .target
+            .params
+            .iter()
+            .map(|p| (p.clone(), ctx.var(p).map(str::to_string)))
+            .collect();
+        for (param, value) in self.target.params.iter().zip(values) {
+            ctx.set_var(param.clone(), value);
+        }
+
+        let mut result = Ok(());
+        for cmd in &self.target.body {
+            result = cmd.execute(ctx).await;
+            if result.is_err() {
+                break;
+            }
+        }
+
+        for (param, previous) in shadowed {
+            match previous {
+                Some(value) => ctx.set_var(param, value),
+                None => {
+                    ctx.variables.remove(&param);
+                }
+            }
+        }
+        result.map_err(|e| e.context(format!("In macro '{}'", self.target.name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_str;
+
+    #[test]
+    fn test_def_and_call() {
+        let cmds = parse_str(
+            "def login(user, pass)\nsend \"${user}\"\nsend \"${pass}\"\nend\ncall login(\"bob\", \"hunter2\")\ncall login(\"alice\", \"pw\")\n",
+        )
+        .unwrap();
+        let names: Vec<_> = cmds.iter().map(|c| c.name()).collect();
+        assert_eq!(names, vec!["call", "call"]);
+    }
+
+    #[test]
+    fn test_no_params() {
+        let cmds = parse_str("def prompt()\nexpect \"$ \"\nend\ncall prompt()\n").unwrap();
+        assert_eq!(cmds.len(), 1);
+    }
+
+    #[test]
+    fn test_unknown_macro() {
+        assert!(parse_str("call missing()\n").is_err());
+    }
+
+    #[test]
+    fn test_wrong_arity() {
+        assert!(parse_str("def greet(name)\nshow \"${name}\"\nend\ncall greet()\n").is_err());
+    }
+
+    #[test]
+    fn test_duplicate_definition() {
+        assert!(parse_str("def a()\nend\ndef a()\nend\n").is_err());
+    }
+}
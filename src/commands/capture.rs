@@ -0,0 +1,140 @@
+//! [`Capture`] command — waits for a regex in the PTY output and stores matched text in variables.
+//!
+//! Script syntax:
+//! - `capture "version (\d+\.\d+)" into VER` — store the first group (or the whole match)
+//! - `capture "(?<MAJOR>\d+)\.(?<MINOR>\d+)"` — store each named group under its name
+//! - `capture "pid=(\d+)" into PID 10s` — custom timeout (see `set default_timeout`)
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::{parse_duration, parse_identifier, split_quoted};
+use anyhow::{Context as _, Result, anyhow};
+use async_trait::async_trait;
+use regex::Regex;
+use std::time::Duration;
+
+/// Blocks until `regex` matches the PTY output, then stores the matched text
+/// in script variables.
+///
+/// With `into NAME`, the first capture group (or the whole match if the regex
+/// has no groups) is stored as `NAME`. Named groups (`(?<NAME>...)`) are always
+/// stored under their own names, and every group as `MATCH_n` (see
+/// [`Context::set_match_vars`]). As with [`crate::commands::Expect`], the
+/// output buffer is consumed up to and including the match.
+///
+/// Build one with the constructors; fields may be added in minor releases.
+#[non_exhaustive]
+pub struct Capture {
+    pub regex: Regex,
+    pub into: Option<String>,
+    /// `None` uses the script's default timeout.
+    pub timeout: Option<Duration>,
+}
+
+impl Capture {
+    pub const NAME: &'static str = "capture";
+
+    /// Create a `Capture` command with the default timeout, storing the first
+    /// group (or the whole match) as `into` if given.
+    pub fn new(regex: Regex, into: Option<String>) -> Self {
+        Self {
+            regex,
+            into,
+            timeout: None,
+        }
+    }
+
+    /// Create a `Capture` command with a custom timeout.
+    pub fn with_timeout(regex: Regex, into: Option<String>, timeout: Duration) -> Self {
+        Self {
+            regex,
+            into,
+            timeout: Some(timeout),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for Capture {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let (pattern, mut remainder) = split_quoted(args)?;
+        let regex = Regex::new(&pattern).context("Invalid regex in capture command")?;
+
+        let mut into = None;
+        if let Some(rest) = remainder.strip_prefix("into ") {
+            let rest = rest.trim();
+            let (name, after) = rest.split_once(' ').unwrap_or((rest, ""));
+            into = Some(parse_identifier(name)?.to_string());
+            remainder = after.trim();
+        }
+        if into.is_none() && regex.capture_names().flatten().next().is_none() {
+            return Err(anyhow!(
+                "capture needs 'into NAME' or named groups in the regex"
+            ));
+        }
+
+        let timeout = if remainder.is_empty() {
+            None
+        } else {
+            Some(parse_duration(remainder)?)
+        };
+        Ok(Self {
+            regex,
+            into,
+            timeout,
+        })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let timeout = ctx.timeout_or_default(self.timeout);
+        let groups = ctx.wait_for_regex(&self.regex, timeout).await?;
+        ctx.set_match_vars(&self.regex, &groups);
+        if let Some(into) = &self.into {
+            let value = groups.get(1).unwrap_or(&groups[0]).clone();
+            ctx.set_var(into.clone(), value.unwrap_or_default());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse_into() {
+        let cmd = Capture::parse(r#""version (\d+\.\d+)" into VER"#).unwrap();
+        assert_eq!(cmd.regex.as_str(), r"version (\d+\.\d+)");
+        assert_eq!(cmd.into.as_deref(), Some("VER"));
+        assert_eq!(cmd.timeout, None);
+    }
+
+    #[test]
+    fn test_parse_into_with_timeout() {
+        let cmd = Capture::parse(r#""pid=(\d+)" into PID 10s"#).unwrap();
+        assert_eq!(cmd.into.as_deref(), Some("PID"));
+        assert_eq!(cmd.timeout, Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_parse_named_groups() {
+        let cmd = Capture::parse(r#""(?<MAJOR>\d+)\.(?<MINOR>\d+)" 2s"#).unwrap();
+        assert_eq!(cmd.into, None);
+        assert_eq!(cmd.timeout, Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_parse_requires_target() {
+        assert!(Capture::parse(r#""(\d+)""#).is_err());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Capture::parse(r#""(unclosed" into X"#).is_err());
+        assert!(Capture::parse(r#""(\d+)" into 9X"#).is_err());
+    }
+}
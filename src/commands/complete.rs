@@ -0,0 +1,130 @@
+//! [`Complete`] command — demonstrates tab completion.
+//!
+//! Script syntax:
+//! - `complete "git ch"` — type, press Tab, and wait for the output to settle
+//! - `complete "git che" expect "checkout"` — press Tab and wait for `checkout`
+//! - `complete "git ch" expect_menu` — press Tab twice to list the candidates
+//! - `complete "git ch" expect_menu "cherry-pick" 10s` — and wait for one of them
+//!
+//! A timeout may end any form; it defaults to the script's default timeout.
+
+use super::type_text::type_chars;
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::{parse_duration, split_quoted};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// Types a prefix, presses Tab, and waits for the shell's completion.
+///
+/// With `expect_menu`, Tab is pressed a second time after the key delay, which
+/// makes readline-style editors list all candidates. The wait ignores the
+/// echo of the prefix itself, so `expect` can name text the prefix contains.
+pub struct Complete {
+    pub prefix: String,
+    /// Press Tab twice to show the candidate list.
+    pub menu: bool,
+    /// Text the completion must show; `None` waits for the output to settle.
+    pub expect: Option<String>,
+    /// `None` uses the script's default timeout.
+    pub timeout: Option<Duration>,
+}
+
+impl Complete {
+    pub const NAME: &'static str = "complete";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for Complete {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let (prefix, after) = split_quoted(args)?;
+        let (mode, tail) = after.split_once(' ').unwrap_or((after, ""));
+        let tail = tail.trim_start();
+        let (menu, rest) = match mode {
+            "expect" if !tail.starts_with('"') => {
+                return Err(anyhow!("Expected quoted text after 'expect'"));
+            }
+            "expect" => (false, tail),
+            "expect_menu" => (true, tail),
+            _ => (false, after),
+        };
+        let (expect, rest) = match rest.starts_with('"') {
+            true => {
+                let (text, rest) = split_quoted(rest)?;
+                (Some(text), rest)
+            }
+            false => (None, rest),
+        };
+        let timeout = match rest {
+            "" => None,
+            rest => Some(parse_duration(rest)?),
+        };
+        Ok(Self {
+            prefix,
+            menu,
+            expect,
+            timeout,
+        })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let prefix = ctx.interpolate(&self.prefix)?;
+        type_chars(ctx, &prefix, None).await?;
+        ctx.skip_echo(&prefix).await;
+
+        ctx.write_to_pty(b"\t")?;
+        if self.menu {
+            tokio::time::sleep(ctx.pace(ctx.scale_timeout(ctx.key_delay()))).await;
+            ctx.write_to_pty(b"\t")?;
+        }
+        let timeout = ctx.timeout_or_default(self.timeout);
+        match &self.expect {
+            Some(text) => {
+                let text = ctx.interpolate(text)?;
+                ctx.wait_for_pattern(&text, timeout).await
+            }
+            None => ctx.wait_for_settle(timeout).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        let cmd = Complete::parse(r#""git ch""#).unwrap();
+        assert_eq!(cmd.prefix, "git ch");
+        assert!(!cmd.menu);
+        assert_eq!(cmd.expect, None);
+
+        let cmd = Complete::parse(r#""git che" expect "checkout" 10s"#).unwrap();
+        assert!(!cmd.menu);
+        assert_eq!(cmd.expect.as_deref(), Some("checkout"));
+        assert_eq!(cmd.timeout, Some(Duration::from_secs(10)));
+
+        let cmd = Complete::parse(r#""git ch" expect_menu"#).unwrap();
+        assert!(cmd.menu);
+        assert_eq!(cmd.expect, None);
+
+        let cmd = Complete::parse(r#""git ch" expect_menu "cherry-pick""#).unwrap();
+        assert_eq!(cmd.expect.as_deref(), Some("cherry-pick"));
+
+        let cmd = Complete::parse(r#""ls /u" 2s"#).unwrap();
+        assert_eq!(cmd.timeout, Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Complete::parse("git ch").is_err());
+        assert!(Complete::parse(r#""git ch" expect"#).is_err());
+        assert!(Complete::parse(r#""git ch" expect_menu soon"#).is_err());
+        assert!(Complete::parse(r#""git ch" menu"#).is_err());
+    }
+}
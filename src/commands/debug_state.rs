@@ -0,0 +1,97 @@
+//! [`DebugState`] command — reports the engine's state, for debugging a
+//! script where no interactive debugger is available (e.g. in CI).
+//!
+//! Script syntax: `debug_state`
+
+use crate::command::{Context, ScripttyCommand};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use std::fmt::Write as _;
+
+/// Characters of unmatched output shown in the report.
+const BUFFER_TAIL: usize = 200;
+
+/// Writes the engine's state to stderr and the transcript: the time since the
+/// start of the run, the end of the output not yet consumed by `expect`, the
+/// last match, the script variables, and the notifications and images no
+/// command has waited for yet. Nothing is sent to the program, and the report
+/// stays out of the output handlers and recordings, which show only what the
+/// program printed. Registered secrets (see [`crate::secrets`]) are masked.
+pub struct DebugState;
+
+impl DebugState {
+    pub const NAME: &'static str = "debug_state";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for DebugState {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        if !args.trim().is_empty() {
+            return Err(anyhow!("debug_state takes no arguments: {}", args.trim()));
+        }
+        Ok(Self)
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let report = report(ctx);
+        let report = ctx.secrets().mask(&report).into_owned();
+        eprint!("{}", report);
+        if let Some(transcript) = ctx.sinks.lock().unwrap().transcript.as_mut() {
+            for line in report.lines() {
+                // Best effort, like the rest of the transcript.
+                let _ = transcript.note(line);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The state as lines prefixed with `[debug_state]`.
+fn report(ctx: &Context) -> String {
+    let buffer = ctx.output_buffer.lock().unwrap();
+    let start = buffer
+        .char_indices()
+        .rev()
+        .nth(BUFFER_TAIL - 1)
+        .map_or(0, |(i, _)| i);
+    let mut out = format!(
+        "[debug_state] elapsed: {:.3}s\n[debug_state] buffer ({} bytes unmatched), tail: {:?}\n",
+        ctx.elapsed().as_secs_f64(),
+        buffer.len(),
+        &buffer[start..]
+    );
+    drop(buffer);
+    if let Some(pattern) = ctx.last_match() {
+        let _ = writeln!(out, "[debug_state] last match: {:?}", pattern);
+    }
+    let mut variables: Vec<_> = ctx.variables.iter().collect();
+    variables.sort();
+    let _ = writeln!(out, "[debug_state] variables: {}", variables.len());
+    for (name, value) in variables {
+        let _ = writeln!(out, "[debug_state]   {} = {:?}", name, value);
+    }
+    let _ = writeln!(
+        out,
+        "[debug_state] pending: {} notifications, {} images",
+        ctx.notifications.lock().unwrap().len(),
+        ctx.images.lock().unwrap().len()
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        assert!(DebugState::parse("").is_ok());
+        assert!(DebugState::parse("  ").is_ok());
+        assert!(DebugState::parse("verbose").is_err());
+    }
+}
@@ -1,32 +1,53 @@
 //! [`Expect`] command — blocks until a pattern appears in the PTY output.
 //!
 //! Script syntax:
-//! - `expect "$ "` — 5-second default timeout
+//! - `expect "$ "` — default timeout (5 seconds, see `set default_timeout`)
 //! - `expect "Password:" 10s` — custom timeout
+//! - `expect --plain "Done"` — ignore ANSI escape sequences (colors, cursor
+//!   movement) in the output while matching
+//! - `expect --regex "v(\d+)\.(\d+)"` — match a regular expression and store
+//!   its groups as `${MATCH_1}`, `${MATCH_2}`, ... (named groups under their names)
 
 use crate::command::{Context, ScripttyCommand};
-use crate::parser::{parse_duration, parse_quoted_string};
-use anyhow::{Result, anyhow};
+use crate::parser::{parse_duration, split_quoted};
+use anyhow::{Context as _, Result, anyhow};
 use async_trait::async_trait;
+use regex::Regex;
 use std::time::Duration;
 
 /// Blocks until `pattern` appears in the PTY output, or until `timeout` elapses.
 ///
 /// When the pattern is found the output buffer is consumed up to and including
 /// it, so a subsequent `Expect` will not match the same occurrence again.
+/// `${NAME}` variables in the pattern are expanded when the command runs.
+///
+/// With `--regex`, the pattern is a regular expression instead, and the groups
+/// of the match are stored as script variables; see
+/// [`Context::set_match_vars`].
+///
+/// Build one with the constructors; fields may be added in minor releases.
+#[non_exhaustive]
 pub struct Expect {
     pub pattern: String,
-    pub timeout: Duration,
+    /// `None` uses the script's default timeout.
+    pub timeout: Option<Duration>,
+    /// Match against the output with escape sequences removed, even without
+    /// `set plain_expect on`.
+    pub plain: bool,
+    /// The pattern compiled as a regular expression, for `--regex`.
+    pub regex: Option<Regex>,
 }
 
 impl Expect {
     pub const NAME: &'static str = "expect";
 
-    /// Create an `Expect` command with the default 5-second timeout.
+    /// Create an `Expect` command with the default timeout.
     pub fn new(pattern: impl Into<String>) -> Self {
         Self {
             pattern: pattern.into(),
-            timeout: Duration::from_secs(5),
+            timeout: None,
+            plain: false,
+            regex: None,
         }
     }
 
@@ -34,11 +55,27 @@ impl Expect {
     pub fn with_timeout(pattern: impl Into<String>, timeout: Duration) -> Self {
         Self {
             pattern: pattern.into(),
-            timeout,
+            timeout: Some(timeout),
+            plain: false,
+            regex: None,
         }
     }
 }
 
+/// Split a leading `--plain` flag off `args`.
+pub(crate) fn strip_plain_flag(args: &str) -> (bool, &str) {
+    strip_flag(args, "--plain")
+}
+
+/// Split a leading `flag` off `args`.
+fn strip_flag<'a>(args: &'a str, flag: &str) -> (bool, &'a str) {
+    let args = args.trim_start();
+    match args.strip_prefix(flag) {
+        Some(rest) if rest.is_empty() || rest.starts_with(' ') => (true, rest.trim_start()),
+        _ => (false, args),
+    }
+}
+
 #[async_trait(?Send)]
 impl ScripttyCommand for Expect {
     fn name(&self) -> &'static str {
@@ -46,42 +83,35 @@ impl ScripttyCommand for Expect {
     }
 
     fn parse(args: &str) -> Result<Self> {
-        let args = args.trim();
-        if !args.starts_with('"') {
-            return Err(anyhow!("Expected quoted string after 'expect'"));
-        }
-
-        // Locate the closing quote, respecting backslash escapes.
-        let mut escaped = false;
-        let mut end_idx = None;
-        for (i, ch) in args.chars().enumerate().skip(1) {
-            if escaped {
-                escaped = false;
-                continue;
-            }
-            if ch == '\\' {
-                escaped = true;
-                continue;
-            }
-            if ch == '"' {
-                end_idx = Some(i);
-                break;
-            }
-        }
-
-        let end_idx = end_idx.ok_or_else(|| anyhow!("Unclosed quote in expect command"))?;
-        let pattern = parse_quoted_string(&args[..=end_idx])?;
-        let remainder = args[end_idx + 1..].trim();
-
-        if remainder.is_empty() {
-            Ok(Self::new(pattern))
+        let (plain, args) = strip_plain_flag(args);
+        let (regex, args) = strip_flag(args, "--regex");
+        let (pattern, remainder) = split_quoted(args)?;
+        let mut cmd = if remainder.is_empty() {
+            Self::new(pattern)
         } else {
-            Ok(Self::with_timeout(pattern, parse_duration(remainder)?))
+            Self::with_timeout(pattern, parse_duration(remainder)?)
+        };
+        cmd.plain = plain;
+        if regex {
+            if plain {
+                return Err(anyhow!("expect --regex can't be combined with --plain"));
+            }
+            cmd.regex = Some(Regex::new(&cmd.pattern).context("Invalid regex in expect command")?);
         }
+        Ok(cmd)
     }
 
     async fn execute(&self, ctx: &mut Context) -> Result<()> {
-        ctx.wait_for_pattern(&self.pattern, self.timeout).await
+        if let Some(regex) = &self.regex {
+            let timeout = ctx.timeout_or_default(self.timeout);
+            let groups = ctx.wait_for_regex(regex, timeout).await?;
+            ctx.set_match_vars(regex, &groups);
+            return Ok(());
+        }
+        let pattern = ctx.interpolate(&self.pattern)?;
+        let timeout = ctx.timeout_or_default(self.timeout);
+        let plain = self.plain || ctx.plain_expect;
+        ctx.wait_for_pattern_in(&pattern, timeout, plain).await
     }
 }
 
@@ -94,20 +124,40 @@ mod tests {
     fn test_parse_default_timeout() {
         let cmd = Expect::parse(r#""$ ""#).unwrap();
         assert_eq!(cmd.pattern, "$ ");
-        assert_eq!(cmd.timeout, Duration::from_secs(5));
+        assert_eq!(cmd.timeout, None);
     }
 
     #[test]
     fn test_parse_custom_timeout() {
         let cmd = Expect::parse(r#""hello world" 2s"#).unwrap();
         assert_eq!(cmd.pattern, "hello world");
-        assert_eq!(cmd.timeout, Duration::from_secs(2));
+        assert_eq!(cmd.timeout, Some(Duration::from_secs(2)));
     }
 
     #[test]
     fn test_parse_ms_timeout() {
         let cmd = Expect::parse(r#""Ready" 500ms"#).unwrap();
-        assert_eq!(cmd.timeout, Duration::from_millis(500));
+        assert_eq!(cmd.timeout, Some(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn test_parse_plain() {
+        let cmd = Expect::parse(r#"--plain "Done" 2s"#).unwrap();
+        assert!(cmd.plain);
+        assert_eq!(cmd.pattern, "Done");
+        assert_eq!(cmd.timeout, Some(Duration::from_secs(2)));
+        assert!(!Expect::parse(r#""--plain""#).unwrap().plain);
+        assert!(Expect::parse(r#"--plainer "Done""#).is_err());
+    }
+
+    #[test]
+    fn test_parse_regex() {
+        let cmd = Expect::parse(r#"--regex "v(\d+)" 2s"#).unwrap();
+        assert_eq!(cmd.regex.unwrap().as_str(), r"v(\d+)");
+        assert_eq!(cmd.timeout, Some(Duration::from_secs(2)));
+        assert!(Expect::parse(r#""v(\d+)""#).unwrap().regex.is_none());
+        assert!(Expect::parse(r#"--regex "(unclosed""#).is_err());
+        assert!(Expect::parse(r#"--plain --regex "x""#).is_err());
     }
 
     #[test]
@@ -0,0 +1,143 @@
+//! [`ExpectAny`] command — blocks until the first of several patterns appears.
+//!
+//! Script syntax:
+//! - `expect_any "yes/no" "Password:"` — default timeout (5 seconds, see `set default_timeout`)
+//! - `expect_any "yes/no" "Password:" 10s` — custom timeout
+//! - `expect_any --plain "ok" "failed"` — ignore ANSI escape sequences while matching
+
+use super::expect::strip_plain_flag;
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::{parse_duration, split_quoted};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// Blocks until any of `patterns` appears in the PTY output, or until `timeout`
+/// elapses.
+///
+/// Useful when the order of prompts varies, e.g. SSH asking either to confirm
+/// a host key or for a password. The pattern that matched is available to
+/// later commands through [`Context::last_match`], and is recorded for branch
+/// coverage.
+///
+/// Build one with the constructors; fields may be added in minor releases.
+#[non_exhaustive]
+pub struct ExpectAny {
+    pub patterns: Vec<String>,
+    /// `None` uses the script's default timeout.
+    pub timeout: Option<Duration>,
+    /// Match against the output with escape sequences removed, even without
+    /// `set plain_expect on`.
+    pub plain: bool,
+}
+
+impl ExpectAny {
+    pub const NAME: &'static str = "expect_any";
+
+    /// Create an `ExpectAny` command with the default timeout.
+    pub fn new<S: Into<String>>(patterns: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            patterns: patterns.into_iter().map(Into::into).collect(),
+            timeout: None,
+            plain: false,
+        }
+    }
+
+    /// Create an `ExpectAny` command with a custom timeout.
+    pub fn with_timeout<S: Into<String>>(
+        patterns: impl IntoIterator<Item = S>,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            patterns: patterns.into_iter().map(Into::into).collect(),
+            timeout: Some(timeout),
+            plain: false,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for ExpectAny {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let (plain, args) = strip_plain_flag(args);
+        let mut patterns = Vec::new();
+        let mut remainder = args.trim();
+        while remainder.starts_with('"') {
+            let (pattern, rest) = split_quoted(remainder)?;
+            patterns.push(pattern);
+            remainder = rest;
+        }
+        if patterns.is_empty() {
+            return Err(anyhow!(
+                "Expected at least one quoted pattern after 'expect_any'"
+            ));
+        }
+
+        let mut cmd = if remainder.is_empty() {
+            Self::new(patterns)
+        } else {
+            Self::with_timeout(patterns, parse_duration(remainder)?)
+        };
+        cmd.plain = plain;
+        Ok(cmd)
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let patterns = self
+            .patterns
+            .iter()
+            .map(|p| ctx.interpolate(p))
+            .collect::<Result<Vec<_>>>()?;
+        let timeout = ctx.timeout_or_default(self.timeout);
+        let plain = self.plain || ctx.plain_expect;
+        let taken = ctx.wait_for_any_in(&patterns, timeout, plain).await?;
+        let labels: Vec<String> = self.patterns.iter().map(|p| format!("{:?}", p)).collect();
+        ctx.record_branch(
+            &format!("{} {}", Self::NAME, labels.join(" ")),
+            &labels,
+            taken,
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse_default_timeout() {
+        let cmd = ExpectAny::parse(r#""yes/no" "Password:""#).unwrap();
+        assert_eq!(cmd.patterns, vec!["yes/no", "Password:"]);
+        assert_eq!(cmd.timeout, None);
+    }
+
+    #[test]
+    fn test_parse_plain() {
+        let cmd = ExpectAny::parse(r#"--plain "ok" "failed""#).unwrap();
+        assert!(cmd.plain);
+        assert_eq!(cmd.patterns, vec!["ok", "failed"]);
+    }
+
+    #[test]
+    fn test_parse_custom_timeout() {
+        let cmd = ExpectAny::parse(r#""a" "b" "c" 10s"#).unwrap();
+        assert_eq!(cmd.patterns.len(), 3);
+        assert_eq!(cmd.timeout, Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_parse_no_patterns() {
+        assert!(ExpectAny::parse("10s").is_err());
+    }
+
+    #[test]
+    fn test_parse_unclosed_quote() {
+        assert!(ExpectAny::parse(r#""a" "b"#).is_err());
+    }
+}
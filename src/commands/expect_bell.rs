@@ -0,0 +1,92 @@
+//! [`ExpectBell`] command — blocks until the program rings the terminal bell.
+//!
+//! Script syntax:
+//! - `expect_bell` — default timeout (5 seconds, see `set default_timeout`)
+//! - `expect_bell 30s` — custom timeout
+
+use crate::command::{Context, ScripttyCommand};
+use crate::notify::Notification;
+use crate::parser::parse_duration;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// Blocks until the program emits a BEL character (outside of an escape
+/// sequence), or until `timeout` elapses.
+///
+/// Build one with the constructors; fields may be added in minor releases.
+#[non_exhaustive]
+pub struct ExpectBell {
+    /// `None` uses the script's default timeout.
+    pub timeout: Option<Duration>,
+}
+
+impl ExpectBell {
+    pub const NAME: &'static str = "expect_bell";
+
+    /// Create an `ExpectBell` command with the default timeout.
+    pub fn new() -> Self {
+        Self { timeout: None }
+    }
+
+    /// Create an `ExpectBell` command with a custom timeout.
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+        }
+    }
+}
+
+impl Default for ExpectBell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for ExpectBell {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let args = args.trim();
+        let timeout = if args.is_empty() {
+            None
+        } else {
+            Some(parse_duration(args)?)
+        };
+        Ok(Self { timeout })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let timeout = ctx.timeout_or_default(self.timeout);
+        ctx.wait_for_notification(|n| *n == Notification::Bell, "bell", timeout)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse_default_timeout() {
+        assert_eq!(ExpectBell::parse("").unwrap().timeout, None);
+    }
+
+    #[test]
+    fn test_parse_custom_timeout() {
+        assert_eq!(
+            ExpectBell::parse("30s").unwrap().timeout,
+            Some(Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(ExpectBell::parse("soon").is_err());
+    }
+}
@@ -0,0 +1,64 @@
+//! [`ExpectEof`] command — blocks until the program exits.
+//!
+//! Script syntax:
+//! - `expect_eof` — default timeout (5 seconds, see `set default_timeout`)
+//! - `expect_eof 30s` — custom timeout
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::parse_duration;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// Blocks until the program running in the PTY exits, or until `timeout`
+/// elapses.
+///
+/// The exit code is stored in the `EXIT_CODE` variable for later commands
+/// and is available to library users through [`crate::Engine::exit_code`].
+pub struct ExpectEof {
+    /// `None` uses the script's default timeout.
+    pub timeout: Option<Duration>,
+}
+
+impl ExpectEof {
+    pub const NAME: &'static str = "expect_eof";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for ExpectEof {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let args = args.trim();
+        let timeout = if args.is_empty() {
+            None
+        } else {
+            Some(parse_duration(args)?)
+        };
+        Ok(Self { timeout })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let timeout = ctx.timeout_or_default(self.timeout);
+        ctx.wait_for_exit(timeout).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(ExpectEof::parse("").unwrap().timeout, None);
+        assert_eq!(
+            ExpectEof::parse("30s").unwrap().timeout,
+            Some(Duration::from_secs(30))
+        );
+        assert!(ExpectEof::parse("later").is_err());
+    }
+}
@@ -0,0 +1,75 @@
+//! [`ExpectIdle`] command — blocks until the program stops producing output.
+//!
+//! Script syntax:
+//! - `expect_idle 500ms` — default timeout (5 seconds, see `set default_timeout`)
+//! - `expect_idle 500ms 10s` — custom timeout
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::parse_duration;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// Blocks until the PTY has produced no output for `quiet`, or until
+/// `timeout` elapses.
+///
+/// Useful for programs without a stable prompt to match on, e.g. full-screen
+/// applications that redraw in place: wait for the redraw to settle before
+/// sending the next key.
+pub struct ExpectIdle {
+    pub quiet: Duration,
+    /// `None` uses the script's default timeout.
+    pub timeout: Option<Duration>,
+}
+
+impl ExpectIdle {
+    pub const NAME: &'static str = "expect_idle";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for ExpectIdle {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let mut parts = args.split_whitespace();
+        let quiet = parts
+            .next()
+            .ok_or_else(|| anyhow!("Expected a quiet period after 'expect_idle'"))?;
+        let quiet = parse_duration(quiet)?;
+        let timeout = parts.next().map(parse_duration).transpose()?;
+        if let Some(extra) = parts.next() {
+            return Err(anyhow!("Unexpected argument: '{}'", extra));
+        }
+        Ok(Self { quiet, timeout })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let timeout = ctx.timeout_or_default(self.timeout);
+        ctx.wait_for_idle(self.quiet, timeout).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        let cmd = ExpectIdle::parse("500ms").unwrap();
+        assert_eq!(cmd.quiet, Duration::from_millis(500));
+        assert_eq!(cmd.timeout, None);
+
+        let cmd = ExpectIdle::parse("500ms 10s").unwrap();
+        assert_eq!(cmd.timeout, Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(ExpectIdle::parse("").is_err());
+        assert!(ExpectIdle::parse("soon").is_err());
+        assert!(ExpectIdle::parse("1s 2s 3s").is_err());
+    }
+}
@@ -0,0 +1,64 @@
+//! [`ExpectImage`] command — blocks until the program draws an inline image.
+//!
+//! Script syntax:
+//! - `expect_image` — default timeout (5 seconds, see `set default_timeout`)
+//! - `expect_image 10s` — custom timeout
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::parse_duration;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// Blocks until the program draws an image with sixel, the kitty graphics
+/// protocol, or iTerm2 inline images, or until `timeout` elapses.
+///
+/// Each image is waited for once: the oldest image not yet waited for
+/// satisfies the command, and `assert_image_similar` then checks it.
+pub struct ExpectImage {
+    /// `None` uses the script's default timeout.
+    pub timeout: Option<Duration>,
+}
+
+impl ExpectImage {
+    pub const NAME: &'static str = "expect_image";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for ExpectImage {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let args = args.trim();
+        let timeout = if args.is_empty() {
+            None
+        } else {
+            Some(parse_duration(args)?)
+        };
+        Ok(Self { timeout })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let timeout = ctx.timeout_or_default(self.timeout);
+        ctx.wait_for_image(timeout).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(ExpectImage::parse("").unwrap().timeout, None);
+        assert_eq!(
+            ExpectImage::parse("10s").unwrap().timeout,
+            Some(Duration::from_secs(10))
+        );
+        assert!(ExpectImage::parse("soon").is_err());
+    }
+}
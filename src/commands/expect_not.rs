@@ -0,0 +1,105 @@
+//! [`ExpectNot`] command — fails if a pattern shows up in the PTY output.
+//!
+//! Script syntax:
+//! - `expect_not "panic" 2s` — watch the output for 2 seconds
+//! - `expect_not "warning"` — default window (5 seconds, see
+//!   `set default_timeout`)
+//! - `expect_not --plain "error"` — ignore ANSI escape sequences while
+//!   matching, like `expect --plain`
+
+use super::expect::strip_plain_flag;
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::{parse_duration, split_quoted};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// Watches the PTY output for `window` and fails as soon as `pattern`
+/// appears in it.
+///
+/// Output that arrived earlier but has not been consumed by an `expect` yet
+/// is checked too, so `expect_not` right after a step covers all of the
+/// step's output. The buffer is left untouched either way.
+pub struct ExpectNot {
+    pub pattern: String,
+    /// `None` uses the script's default timeout.
+    pub window: Option<Duration>,
+    /// Match against the output with escape sequences removed, even without
+    /// `set plain_expect on`.
+    pub plain: bool,
+}
+
+impl ExpectNot {
+    pub const NAME: &'static str = "expect_not";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for ExpectNot {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let (plain, args) = strip_plain_flag(args);
+        let (pattern, remainder) = split_quoted(args)?;
+        let window = if remainder.is_empty() {
+            None
+        } else {
+            Some(parse_duration(remainder)?)
+        };
+        Ok(Self {
+            pattern,
+            window,
+            plain,
+        })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let pattern = ctx.interpolate(&self.pattern)?;
+        let window = ctx.scale_timeout(ctx.timeout_or_default(self.window));
+        let plain = self.plain || ctx.plain_expect;
+        let deadline = tokio::time::Instant::now() + window;
+        loop {
+            {
+                let buffer = ctx.output_buffer.lock().unwrap();
+                let found = if plain {
+                    crate::ansi::strip(&buffer).0.contains(pattern.as_str())
+                } else {
+                    buffer.contains(pattern.as_str())
+                };
+                if found {
+                    return Err(anyhow!("Unexpected pattern in output: '{}'", pattern));
+                }
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Ok(());
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        let cmd = ExpectNot::parse(r#""panic" 2s"#).unwrap();
+        assert_eq!(cmd.pattern, "panic");
+        assert_eq!(cmd.window, Some(Duration::from_secs(2)));
+        assert!(!cmd.plain);
+
+        let cmd = ExpectNot::parse(r#"--plain "error""#).unwrap();
+        assert_eq!(cmd.pattern, "error");
+        assert_eq!(cmd.window, None);
+        assert!(cmd.plain);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(ExpectNot::parse("panic").is_err());
+        assert!(ExpectNot::parse(r#""panic" soon"#).is_err());
+    }
+}
@@ -0,0 +1,94 @@
+//! [`ExpectNotification`] command — blocks until the program posts a desktop notification.
+//!
+//! Script syntax:
+//! - `expect_notification "Build finished"` — default timeout (5 seconds, see `set default_timeout`)
+//! - `expect_notification "Build finished" 60s` — custom timeout
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::{parse_duration, split_quoted};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// Blocks until the program emits an OSC 9 or OSC 777 notification whose title
+/// or body contains `text`, or until `timeout` elapses.
+///
+/// `${NAME}` variables in `text` are expanded when the command runs.
+///
+/// Build one with the constructors; fields may be added in minor releases.
+#[non_exhaustive]
+pub struct ExpectNotification {
+    pub text: String,
+    /// `None` uses the script's default timeout.
+    pub timeout: Option<Duration>,
+}
+
+impl ExpectNotification {
+    pub const NAME: &'static str = "expect_notification";
+
+    /// Create an `ExpectNotification` command with the default timeout.
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            timeout: None,
+        }
+    }
+
+    /// Create an `ExpectNotification` command with a custom timeout.
+    pub fn with_timeout(text: impl Into<String>, timeout: Duration) -> Self {
+        Self {
+            text: text.into(),
+            timeout: Some(timeout),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for ExpectNotification {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let (text, remainder) = split_quoted(args)?;
+        let timeout = if remainder.is_empty() {
+            None
+        } else {
+            Some(parse_duration(remainder)?)
+        };
+        Ok(Self { text, timeout })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let text = ctx.interpolate(&self.text)?;
+        let what = format!("notification: '{}'", text);
+        let timeout = ctx.timeout_or_default(self.timeout);
+        ctx.wait_for_notification(|n| n.contains(&text), &what, timeout)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        let cmd = ExpectNotification::parse(r#""Build finished""#).unwrap();
+        assert_eq!(cmd.text, "Build finished");
+        assert_eq!(cmd.timeout, None);
+    }
+
+    #[test]
+    fn test_parse_custom_timeout() {
+        let cmd = ExpectNotification::parse(r#""done" 60s"#).unwrap();
+        assert_eq!(cmd.timeout, Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_parse_missing_quote() {
+        assert!(ExpectNotification::parse("done").is_err());
+    }
+}
@@ -0,0 +1,276 @@
+//! [`ExpectScreen`] command — waits for the rendered terminal screen to match.
+//!
+//! Script syntax:
+//! - `expect_screen contains "Saved"` — anywhere on the screen
+//! - `expect_screen row 3 contains "OK"` — on one row (1-based)
+//! - `expect_screen row 24 is "-- INSERT --"` — the row's whole text,
+//!   ignoring surrounding blanks
+//! - `expect_screen cursor 5 1` — the cursor is at row 5, column 1
+//!
+//! Each form takes an optional timeout at the end, e.g.
+//! `expect_screen row 3 contains "OK" 10s`.
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::{parse_duration, split_quoted};
+use anyhow::{Context as _, Result, anyhow};
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// What the screen must show.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScreenCondition {
+    /// Some row contains the text.
+    Contains(String),
+    /// Row `row` (1-based) contains the text.
+    RowContains { row: u16, text: String },
+    /// Row `row` (1-based), trimmed, is exactly the text.
+    RowIs { row: u16, text: String },
+    /// The cursor is at `row`, `col` (1-based).
+    Cursor { row: u16, col: u16 },
+}
+
+impl ScreenCondition {
+    /// Expand `${NAME}` references in the condition's text.
+    fn interpolate(&self, ctx: &Context) -> Result<Self> {
+        Ok(match self {
+            Self::Contains(text) => Self::Contains(ctx.interpolate(text)?),
+            Self::RowContains { row, text } => Self::RowContains {
+                row: *row,
+                text: ctx.interpolate(text)?,
+            },
+            Self::RowIs { row, text } => Self::RowIs {
+                row: *row,
+                text: ctx.interpolate(text)?,
+            },
+            Self::Cursor { row, col } => Self::Cursor {
+                row: *row,
+                col: *col,
+            },
+        })
+    }
+
+    fn matches(&self, screen: &vt100::Screen) -> bool {
+        match self {
+            Self::Contains(text) => rows(screen).any(|line| line.contains(text.as_str())),
+            Self::RowContains { row, text } => {
+                row_text(screen, *row).is_some_and(|line| line.contains(text.as_str()))
+            }
+            Self::RowIs { row, text } => {
+                row_text(screen, *row).is_some_and(|line| line.trim() == text)
+            }
+            Self::Cursor { row, col } => screen.cursor_position() == (row - 1, col - 1),
+        }
+    }
+
+    /// What the screen showed instead, for the timeout error.
+    fn actual(&self, screen: &vt100::Screen) -> String {
+        match self {
+            Self::Contains(_) => format!("screen:\n{}", screen.contents()),
+            Self::RowContains { row, .. } | Self::RowIs { row, .. } => {
+                match row_text(screen, *row) {
+                    Some(line) => format!("row {} was: '{}'", row, line.trim_end()),
+                    None => format!("the screen has {} rows", screen.size().0),
+                }
+            }
+            Self::Cursor { .. } => {
+                let (row, col) = screen.cursor_position();
+                format!("cursor was at {} {}", row + 1, col + 1)
+            }
+        }
+    }
+}
+
+/// The text of each screen row.
+fn rows(screen: &vt100::Screen) -> impl Iterator<Item = String> + '_ {
+    let (_, cols) = screen.size();
+    screen.rows(0, cols)
+}
+
+/// The text of row `row` (1-based), if the screen has that many rows.
+fn row_text(screen: &vt100::Screen, row: u16) -> Option<String> {
+    rows(screen).nth(usize::from(row) - 1)
+}
+
+/// Blocks until the terminal screen, as rendered from the program's output,
+/// satisfies `condition`, or until `timeout` elapses.
+///
+/// Unlike [`crate::commands::Expect`], which searches the raw output stream,
+/// this looks at what a user would see: text that was overwritten or cleared
+/// no longer matches, and escape sequences never get in the way.
+pub struct ExpectScreen {
+    pub condition: ScreenCondition,
+    /// `None` uses the script's default timeout.
+    pub timeout: Option<Duration>,
+}
+
+impl ExpectScreen {
+    pub const NAME: &'static str = "expect_screen";
+}
+
+/// Parse a 1-based row or column number.
+fn parse_position(word: Option<&str>, what: &str) -> Result<u16> {
+    let word = word.ok_or_else(|| anyhow!("Expected a {} number", what))?;
+    match word.parse::<u16>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(anyhow!(
+            "Invalid {} number, expected 1 or more: '{}'",
+            what,
+            word
+        )),
+    }
+}
+
+/// Split the quoted text off the front of `rest`.
+fn parse_text(rest: &str) -> Result<(String, &str)> {
+    split_quoted(rest.trim_start()).context("Expected quoted text")
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for ExpectScreen {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let args = args.trim();
+        let (condition, rest) = if let Some(rest) = args.strip_prefix("contains") {
+            let (text, rest) = parse_text(rest)?;
+            (ScreenCondition::Contains(text), rest)
+        } else if let Some(rest) = args.strip_prefix("row ") {
+            let rest = rest.trim_start();
+            let (number, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+            let row = parse_position(Some(number), "row")?;
+            let rest = rest.trim_start();
+            if let Some(rest) = rest.strip_prefix("contains") {
+                let (text, rest) = parse_text(rest)?;
+                (ScreenCondition::RowContains { row, text }, rest)
+            } else if let Some(rest) = rest.strip_prefix("is") {
+                let (text, rest) = parse_text(rest)?;
+                (ScreenCondition::RowIs { row, text }, rest)
+            } else {
+                return Err(anyhow!("Expected 'contains' or 'is' after the row number"));
+            }
+        } else if let Some(rest) = args.strip_prefix("cursor ") {
+            let mut words = rest.split_whitespace();
+            let row = parse_position(words.next(), "row")?;
+            let col = parse_position(words.next(), "column")?;
+            let rest = words.next().unwrap_or("");
+            if let Some(extra) = words.next() {
+                return Err(anyhow!("Unexpected argument: '{}'", extra));
+            }
+            (ScreenCondition::Cursor { row, col }, rest)
+        } else {
+            return Err(anyhow!(
+                "Expected 'contains', 'row', or 'cursor' after 'expect_screen'"
+            ));
+        };
+        let timeout = match rest.trim() {
+            "" => None,
+            rest => Some(parse_duration(rest)?),
+        };
+        Ok(Self { condition, timeout })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let condition = self.condition.interpolate(ctx)?;
+        let timeout = ctx.timeout_or_default(self.timeout);
+        let deadline = tokio::time::Instant::now() + ctx.scale_timeout(timeout);
+        loop {
+            let screen = ctx.screen();
+            if condition.matches(&screen) {
+                return Ok(());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(ctx.timeout_error(format!(
+                    "Timeout waiting for the screen to match {:?}; {}",
+                    condition,
+                    condition.actual(&screen)
+                )));
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        let cmd = ExpectScreen::parse(r#"contains "Saved""#).unwrap();
+        assert_eq!(cmd.condition, ScreenCondition::Contains("Saved".into()));
+        assert_eq!(cmd.timeout, None);
+
+        let cmd = ExpectScreen::parse(r#"row 3 contains "OK" 10s"#).unwrap();
+        assert_eq!(
+            cmd.condition,
+            ScreenCondition::RowContains {
+                row: 3,
+                text: "OK".into()
+            }
+        );
+        assert_eq!(cmd.timeout, Some(Duration::from_secs(10)));
+
+        let cmd = ExpectScreen::parse(r#"row 24 is "-- INSERT --""#).unwrap();
+        assert_eq!(
+            cmd.condition,
+            ScreenCondition::RowIs {
+                row: 24,
+                text: "-- INSERT --".into()
+            }
+        );
+
+        let cmd = ExpectScreen::parse("cursor 5 1 2s").unwrap();
+        assert_eq!(cmd.condition, ScreenCondition::Cursor { row: 5, col: 1 });
+        assert_eq!(cmd.timeout, Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(ExpectScreen::parse("").is_err());
+        assert!(ExpectScreen::parse(r#""OK""#).is_err());
+        assert!(ExpectScreen::parse(r#"row 0 contains "OK""#).is_err());
+        assert!(ExpectScreen::parse(r#"row 3 has "OK""#).is_err());
+        assert!(ExpectScreen::parse("row 3 contains OK").is_err());
+        assert!(ExpectScreen::parse("cursor 5").is_err());
+        assert!(ExpectScreen::parse("cursor 5 1 2s 3s").is_err());
+    }
+
+    #[test]
+    fn test_matches() {
+        let mut parser = vt100::Parser::new(5, 20, 0);
+        parser.process(b"loading...\r\x1b[Kdone\r\n\x1b[1;31mOK\x1b[0m  ");
+        let screen = parser.screen();
+
+        assert!(ScreenCondition::Contains("done".into()).matches(screen));
+        assert!(!ScreenCondition::Contains("loading".into()).matches(screen));
+        assert!(
+            ScreenCondition::RowContains {
+                row: 2,
+                text: "OK".into()
+            }
+            .matches(screen)
+        );
+        assert!(
+            ScreenCondition::RowIs {
+                row: 1,
+                text: "done".into()
+            }
+            .matches(screen)
+        );
+        assert!(
+            !ScreenCondition::RowIs {
+                row: 9,
+                text: "done".into()
+            }
+            .matches(screen)
+        );
+        assert!(ScreenCondition::Cursor { row: 2, col: 5 }.matches(screen));
+        assert_eq!(
+            ScreenCondition::Cursor { row: 1, col: 1 }.actual(screen),
+            "cursor was at 2 5"
+        );
+    }
+}
@@ -0,0 +1,278 @@
+//! [`Gdb`] command — drives gdb through its machine interface (GDB/MI).
+//!
+//! Start gdb with `--interpreter=mi` (e.g. `scriptty -c gdb -s walk.script --
+//! --interpreter=mi ./app`), then:
+//!
+//! ```text
+//! gdb break main.c:42
+//! gdb run
+//! gdb expect_stopped at main.c:42
+//! gdb next
+//! gdb expect_stopped at 43 10s
+//! gdb continue
+//! gdb expect_stopped
+//! ```
+//!
+//! Each command is sent with a numeric token and waits for gdb's result
+//! record with that token, failing on `^error`. `expect_stopped` waits for
+//! the next `*stopped` record; with `at` it checks the location, given as
+//! `FILE:LINE`, `LINE`, or a function name. The stop's function, file, and
+//! line are stored in the `GDB_FUNC`, `GDB_FILE`, and `GDB_LINE` variables.
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::parse_duration;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use regex::Regex;
+use std::time::Duration;
+
+/// What a [`Gdb`] command does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GdbAction {
+    /// Insert a breakpoint at a location (`main`, `main.c:42`, ...).
+    Break(String),
+    Run,
+    Continue,
+    Next,
+    Step,
+    Finish,
+    /// Wait for the program to stop, optionally checking where.
+    ExpectStopped {
+        at: Option<String>,
+        /// `None` uses the script's default timeout.
+        timeout: Option<Duration>,
+    },
+}
+
+/// Sends structured debugger commands to gdb running in MI mode.
+pub struct Gdb {
+    pub action: GdbAction,
+}
+
+impl Gdb {
+    pub const NAME: &'static str = "gdb";
+
+    /// The MI command for the action; `None` for `expect_stopped`.
+    fn mi_command(&self) -> Option<String> {
+        let command = match &self.action {
+            GdbAction::Break(location) => return Some(format!("-break-insert {}", location)),
+            GdbAction::Run => "-exec-run",
+            GdbAction::Continue => "-exec-continue",
+            GdbAction::Next => "-exec-next",
+            GdbAction::Step => "-exec-step",
+            GdbAction::Finish => "-exec-finish",
+            GdbAction::ExpectStopped { .. } => return None,
+        };
+        Some(command.to_string())
+    }
+}
+
+/// Where the program stopped, from a `*stopped` record.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Stop {
+    reason: String,
+    func: String,
+    file: String,
+    line: String,
+}
+
+impl Stop {
+    /// Parse the fields of a `*stopped,...` record (everything after the comma).
+    fn parse(record: &str) -> Self {
+        let field = |name: &str| {
+            Regex::new(&format!(r#"\b{}="((?:[^"\\]|\\.)*)""#, name))
+                .expect("field regex is valid")
+                .captures(record)
+                .map(|caps| caps[1].to_string())
+                .unwrap_or_default()
+        };
+        Self {
+            reason: field("reason"),
+            func: field("func"),
+            file: field("file"),
+            line: field("line"),
+        }
+    }
+
+    /// Whether the stop matches a `FILE:LINE`, `LINE`, or function location.
+    fn is_at(&self, location: &str) -> bool {
+        match location.rsplit_once(':') {
+            Some((file, line)) => {
+                line == self.line
+                    && (self.file == file || self.file.ends_with(&format!("/{}", file)))
+            }
+            None if location.chars().all(|c| c.is_ascii_digit()) => location == self.line,
+            None => location == self.func,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match (self.func.as_str(), self.file.as_str()) {
+            ("", _) => format!("reason {}", self.reason),
+            (func, "") => format!("{} ({})", func, self.reason),
+            (func, file) => format!("{} at {}:{} ({})", func, file, self.line, self.reason),
+        }
+    }
+}
+
+/// The message of an `^error,msg="..."` result.
+fn error_message(fields: &str) -> String {
+    fields
+        .strip_prefix("msg=\"")
+        .and_then(|m| m.strip_suffix('"'))
+        .unwrap_or(fields)
+        .replace("\\\"", "\"")
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for Gdb {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let args = args.trim();
+        let (verb, rest) = args.split_once(' ').unwrap_or((args, ""));
+        let rest = rest.trim();
+        let no_args = |action: GdbAction| match rest {
+            "" => Ok(action),
+            _ => Err(anyhow!("'gdb {}' takes no arguments", verb)),
+        };
+        let action = match verb {
+            "break" if rest.is_empty() => return Err(anyhow!("Expected a location after 'break'")),
+            "break" => GdbAction::Break(rest.to_string()),
+            "run" => no_args(GdbAction::Run)?,
+            "continue" => no_args(GdbAction::Continue)?,
+            "next" => no_args(GdbAction::Next)?,
+            "step" => no_args(GdbAction::Step)?,
+            "finish" => no_args(GdbAction::Finish)?,
+            "expect_stopped" => {
+                let mut words: Vec<&str> = rest.split_whitespace().collect();
+                let timeout = match words.last().map(|w| parse_duration(w)) {
+                    Some(Ok(timeout)) => {
+                        words.pop();
+                        Some(timeout)
+                    }
+                    _ => None,
+                };
+                let at = match words.as_slice() {
+                    [] => None,
+                    ["at", location] => Some(location.to_string()),
+                    _ => {
+                        return Err(anyhow!(
+                            "Expected 'expect_stopped [at LOCATION] [TIMEOUT]', got: {}",
+                            rest
+                        ));
+                    }
+                };
+                GdbAction::ExpectStopped { at, timeout }
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Expected break, run, continue, next, step, finish, or expect_stopped after 'gdb'"
+                ));
+            }
+        };
+        Ok(Self { action })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        if let GdbAction::ExpectStopped { at, timeout } = &self.action {
+            let stopped = Regex::new(r"(?m)^\*stopped,?([^\r\n]*)").expect("regex is valid");
+            let groups = ctx
+                .wait_for_regex(&stopped, ctx.timeout_or_default(*timeout))
+                .await?;
+            let stop = Stop::parse(groups[1].as_deref().unwrap_or_default());
+            ctx.set_var("GDB_FUNC", stop.func.as_str());
+            ctx.set_var("GDB_FILE", stop.file.as_str());
+            ctx.set_var("GDB_LINE", stop.line.as_str());
+            if let Some(location) = at {
+                let location = ctx.interpolate(location)?;
+                if !stop.is_at(&location) {
+                    return Err(anyhow!(
+                        "Expected to stop at {}, stopped in {}",
+                        location,
+                        stop.describe()
+                    ));
+                }
+            }
+            return Ok(());
+        }
+
+        let command = ctx.interpolate(&self.mi_command().expect("not expect_stopped"))?;
+        ctx.mi_token += 1;
+        let token = ctx.mi_token;
+        ctx.write_to_pty(format!("{}{}\r", token, command).as_bytes())?;
+        let result = Regex::new(&format!(
+            r"(?m)^{}\^(done|running|connected|error|exit),?([^\r\n]*)",
+            token
+        ))
+        .expect("regex is valid");
+        let timeout = ctx.timeout_or_default(None);
+        let groups = ctx.wait_for_regex(&result, timeout).await?;
+        if groups[1].as_deref() == Some("error") {
+            return Err(anyhow!(
+                "gdb rejected '{}': {}",
+                command,
+                error_message(groups[2].as_deref().unwrap_or_default())
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Gdb::parse("break main.c:42").unwrap().action,
+            GdbAction::Break("main.c:42".into())
+        );
+        assert_eq!(Gdb::parse("run").unwrap().action, GdbAction::Run);
+        assert_eq!(
+            Gdb::parse("expect_stopped at main 10s").unwrap().action,
+            GdbAction::ExpectStopped {
+                at: Some("main".into()),
+                timeout: Some(Duration::from_secs(10)),
+            }
+        );
+        assert_eq!(
+            Gdb::parse("expect_stopped").unwrap().action,
+            GdbAction::ExpectStopped {
+                at: None,
+                timeout: None,
+            }
+        );
+        assert!(Gdb::parse("break").is_err());
+        assert!(Gdb::parse("run now").is_err());
+        assert!(Gdb::parse("expect_stopped main").is_err());
+        assert!(Gdb::parse("attach 1").is_err());
+    }
+
+    #[test]
+    fn test_stop_location() {
+        let stop = Stop::parse(
+            r#"reason="breakpoint-hit",disp="keep",bkptno="1",frame={addr="0x1149",func="main",args=[],file="src/main.c",fullname="/w/src/main.c",line="42",arch="i386:x86-64"},thread-id="1""#,
+        );
+        assert_eq!(stop.reason, "breakpoint-hit");
+        assert!(stop.is_at("main.c:42"));
+        assert!(stop.is_at("src/main.c:42"));
+        assert!(stop.is_at("42"));
+        assert!(stop.is_at("main"));
+        assert!(!stop.is_at("ain.c:42"));
+        assert!(!stop.is_at("main.c:43"));
+        assert!(!stop.is_at("helper"));
+    }
+
+    #[test]
+    fn test_error_message() {
+        assert_eq!(
+            error_message(r#"msg="Function \"nope\" not defined.""#),
+            "Function \"nope\" not defined."
+        );
+    }
+}
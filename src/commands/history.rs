@@ -0,0 +1,250 @@
+//! Shell history helpers: [`HistoryUp`] and [`SearchHistory`].
+//!
+//! Script syntax:
+//! - `history_up 3` — press Up three times, then Enter
+//! - `history_up 3 expect "make test"` — check the recalled line before Enter
+//! - `search_history "docker run"` — Ctrl+R, type the query, then Enter
+//! - `search_history "docker run" expect "docker run -it alpine" 10s`
+//!
+//! Without `expect`, the line is submitted once the output settles. The
+//! timeout applies to the `expect` text and defaults to the script's default
+//! timeout.
+//!
+//! Shells redraw only the changed part of a recalled line, so the check looks
+//! at the row the cursor is on in the emulated screen (see [`cursor_line`])
+//! rather than at the raw output stream.
+
+use super::type_text::type_chars;
+use crate::command::{Context, ScripttyCommand};
+use crate::commands::Key;
+use crate::parser::{parse_duration, split_quoted};
+use anyhow::{Context as _, Result, anyhow};
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// What readline and zsh show when an incremental search finds nothing.
+const SEARCH_FAILURES: &[&str] = &["failed reverse-i-search", "failing bck-i-search"];
+
+/// Parse the optional `expect "LINE"` and timeout that end both commands.
+fn parse_check(rest: &str) -> Result<(Option<String>, Option<Duration>)> {
+    let (expect, rest) = match rest.strip_prefix("expect") {
+        Some(after) => {
+            let (line, rest) = split_quoted(after.trim_start())
+                .context("Expected the quoted line after 'expect'")?;
+            (Some(line), rest)
+        }
+        None => (None, rest),
+    };
+    let timeout = match rest {
+        "" => None,
+        rest => Some(parse_duration(rest)?),
+    };
+    Ok((expect, timeout))
+}
+
+/// The text of the row the cursor is on, without trailing blanks.
+pub(crate) fn cursor_line(screen: &vt100::Screen) -> String {
+    let (row, _) = screen.cursor_position();
+    let (_, cols) = screen.size();
+    screen
+        .contents_between(row, 0, row, cols)
+        .trim_end()
+        .to_string()
+}
+
+/// Poll the terminal's current line until `matches` accepts it, or until the
+/// scaled `timeout` elapses.
+async fn wait_for_line<F>(ctx: &Context, matches: F, timeout: Duration) -> Result<String>
+where
+    F: Fn(&str) -> bool,
+{
+    let deadline = tokio::time::Instant::now() + ctx.scale_timeout(timeout);
+    loop {
+        let line = cursor_line(&ctx.screen());
+        if matches(&line) {
+            return Ok(line);
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(anyhow!("The current line reads '{}'", line));
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+/// Wait until the recalled line shows `expect` (or the output settles), then
+/// press Enter.
+async fn verify_and_submit(
+    ctx: &mut Context,
+    expect: Option<&str>,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    let timeout = ctx.timeout_or_default(timeout);
+    match expect {
+        Some(text) => {
+            let text = ctx.interpolate(text)?;
+            wait_for_line(ctx, |line| line.contains(text.as_str()), timeout)
+                .await
+                .with_context(|| format!("The recalled line does not show '{}'", text))?;
+        }
+        None => ctx.wait_for_settle(timeout).await?,
+    }
+    ctx.write_to_pty(b"\r")
+}
+
+/// Recalls an earlier command with the Up arrow and runs it again.
+pub struct HistoryUp {
+    pub count: usize,
+    /// Text the recalled line must show before it is submitted.
+    pub expect: Option<String>,
+    /// `None` uses the script's default timeout.
+    pub timeout: Option<Duration>,
+}
+
+impl HistoryUp {
+    pub const NAME: &'static str = "history_up";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for HistoryUp {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let args = args.trim();
+        let (count, rest) = args.split_once(' ').unwrap_or((args, ""));
+        let count: usize = count
+            .parse()
+            .with_context(|| format!("Expected a count after 'history_up', got: {}", count))?;
+        if count == 0 {
+            return Err(anyhow!("history_up count must be at least 1"));
+        }
+        let (expect, timeout) = parse_check(rest.trim_start())?;
+        Ok(Self {
+            count,
+            expect,
+            timeout,
+        })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let up = "Up".parse::<Key>()?.bytes(ctx.key_table())?;
+        for i in 0..self.count {
+            if i > 0 {
+                tokio::time::sleep(ctx.pace(ctx.scale_timeout(ctx.key_delay()))).await;
+            }
+            ctx.write_to_pty(&up)?;
+        }
+        verify_and_submit(ctx, self.expect.as_deref(), self.timeout).await
+    }
+}
+
+/// Finds an earlier command with reverse incremental search (Ctrl+R) and
+/// runs it again.
+///
+/// Fails if the shell reports that no history entry matches the query.
+pub struct SearchHistory {
+    pub query: String,
+    /// Text the found line must show before it is submitted.
+    pub expect: Option<String>,
+    /// `None` uses the script's default timeout.
+    pub timeout: Option<Duration>,
+}
+
+impl SearchHistory {
+    pub const NAME: &'static str = "search_history";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for SearchHistory {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let (query, rest) = split_quoted(args)?;
+        if query.is_empty() {
+            return Err(anyhow!("search_history needs a non-empty query"));
+        }
+        let (expect, timeout) = parse_check(rest)?;
+        Ok(Self {
+            query,
+            expect,
+            timeout,
+        })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let query = ctx.interpolate(&self.query)?;
+        ctx.write_to_pty(b"\x12")?;
+        tokio::time::sleep(ctx.pace(ctx.scale_timeout(ctx.key_delay()))).await;
+        type_chars(ctx, &query, None).await?;
+        let timeout = ctx.timeout_or_default(self.timeout);
+        ctx.wait_for_settle(timeout).await?;
+        let line = cursor_line(&ctx.screen());
+        if SEARCH_FAILURES.iter().any(|f| line.contains(f)) {
+            // Leave the search so the next command starts on a clean line.
+            ctx.write_to_pty(b"\x07")?;
+            return Err(anyhow!("No history entry matches '{}'", query));
+        }
+        verify_and_submit(ctx, self.expect.as_deref(), self.timeout).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse_history_up() {
+        let cmd = HistoryUp::parse("3").unwrap();
+        assert_eq!(cmd.count, 3);
+        assert_eq!(cmd.expect, None);
+        let cmd = HistoryUp::parse(r#"2 expect "make test" 10s"#).unwrap();
+        assert_eq!(cmd.expect.as_deref(), Some("make test"));
+        assert_eq!(cmd.timeout, Some(Duration::from_secs(10)));
+        assert!(HistoryUp::parse("").is_err());
+        assert!(HistoryUp::parse("0").is_err());
+        assert!(HistoryUp::parse("2 expect make").is_err());
+    }
+
+    /// The cursor row after `output` is written to a fresh terminal.
+    fn current_line(output: &str) -> String {
+        let mut parser = vt100::Parser::new(24, 80, 0);
+        parser.process(output.as_bytes());
+        cursor_line(parser.screen())
+    }
+
+    #[test]
+    fn test_cursor_line() {
+        assert_eq!(current_line("old\r\n$ echo hi"), "$ echo hi");
+        // bash recalling a shorter line: backspaces over the differing part.
+        assert_eq!(
+            current_line("$ echo beta-two\x08\x08\x08\x08\x08\x08\x08\x08alpha-one"),
+            "$ echo alpha-one"
+        );
+        assert_eq!(current_line("$ make test-all\x1b[4D\x1b[K"), "$ make test");
+        assert_eq!(
+            current_line("$ git sttus\x08\x08\x08\x1b[1@a"),
+            "$ git status"
+        );
+        assert_eq!(current_line("$ lss\x08\x08\x1b[P"), "$ ls");
+        assert_eq!(current_line("\x1b]0;user@host: ~\x07$ ls"), "$ ls");
+        assert_eq!(
+            current_line("\r(reverse-i-search)`dock': docker run -it\x1b[?2004h"),
+            "(reverse-i-search)`dock': docker run -it"
+        );
+    }
+
+    #[test]
+    fn test_parse_search_history() {
+        let cmd = SearchHistory::parse(r#""docker run""#).unwrap();
+        assert_eq!(cmd.query, "docker run");
+        let cmd = SearchHistory::parse(r#""docker" expect "docker run -it alpine""#).unwrap();
+        assert_eq!(cmd.expect.as_deref(), Some("docker run -it alpine"));
+        assert!(SearchHistory::parse(r#""""#).is_err());
+        assert!(SearchHistory::parse("docker").is_err());
+        assert!(SearchHistory::parse(r#""docker" soon"#).is_err());
+    }
+}
@@ -0,0 +1,120 @@
+//! [`If`] block — runs one of two command lists depending on whether a pattern appears.
+//!
+//! Script syntax:
+//!
+//! ```text
+//! if expect "yes/no" 2s
+//!   send "yes"
+//!   key Enter
+//! else
+//!   show "host already known"
+//! end
+//! ```
+//!
+//! The `else` branch is optional.
+
+use crate::command::{Context, ScripttyCommand};
+use crate::commands::Expect;
+use crate::parser::ScriptReader;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+
+/// Waits for the [`Expect`] condition and runs `then_branch` if the pattern
+/// appeared before the timeout, `else_branch` otherwise.
+///
+/// A timeout is not an error here: it selects the `else` branch. Which branch
+/// ran is recorded for branch coverage.
+pub struct If {
+    pub condition: Expect,
+    pub then_branch: Vec<Box<dyn ScripttyCommand>>,
+    pub else_branch: Vec<Box<dyn ScripttyCommand>>,
+}
+
+impl If {
+    pub const NAME: &'static str = "if";
+
+    /// Parse the block from its opening line arguments and the lines that follow.
+    pub(crate) fn parse_block(
+        args: &str,
+        reader: &mut ScriptReader,
+    ) -> Result<Box<dyn ScripttyCommand>> {
+        let condition = args
+            .trim()
+            .strip_prefix("expect ")
+            .ok_or_else(|| anyhow!("Expected 'if expect \"pattern\" [timeout]'"))?;
+        let condition = Expect::parse(condition)?;
+
+        let (then_branch, terminator) = reader.parse_block(&["else", "end"])?;
+        let else_branch = match terminator {
+            Some("else") => reader.parse_block(&["end"])?.0,
+            _ => Vec::new(),
+        };
+        Ok(Box::new(Self {
+            condition,
+            then_branch,
+            else_branch,
+        }))
+    }
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for If {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(_args: &str) -> Result<Self> {
+        Err(anyhow!("'if' starts a block and must be followed by 'end'"))
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let pattern = ctx.interpolate(&self.condition.pattern)?;
+        let matched = ctx
+            .wait_for_pattern(&pattern, ctx.timeout_or_default(self.condition.timeout))
+            .await
+            .is_ok();
+        ctx.record_branch(
+            &format!("{} expect {:?}", Self::NAME, self.condition.pattern),
+            &["then", "else"],
+            if matched { 0 } else { 1 },
+        );
+
+        let branch = if matched {
+            &self.then_branch
+        } else {
+            &self.else_branch
+        };
+        for cmd in branch {
+            cmd.execute(ctx).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_str;
+
+    #[test]
+    fn test_parse_branches() {
+        let mut reader = ScriptReader::new("send \"yes\"\nkey Enter\nelse\nshow \"no\"\nend\n");
+        let block = If::parse_block(r#"expect "yes/no" 2s"#, &mut reader).unwrap();
+        assert_eq!(block.name(), "if");
+    }
+
+    #[test]
+    fn test_parse_requires_expect() {
+        assert!(parse_str("if \"pattern\"\nend\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_junk_after_end() {
+        assert!(parse_str("if expect \"a\"\nend now\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_single_line() {
+        assert!(If::parse(r#"expect "a""#).is_err());
+    }
+}
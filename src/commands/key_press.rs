@@ -1,17 +1,22 @@
-//! [`KeyPress`] command — sends a single key (with optional modifiers) to the PTY.
+//! [`KeyPress`] command — sends key presses (with optional modifiers) to the PTY.
 //!
-//! Script syntax: `key [Ctrl+][Alt+][Shift+]<key>`
+//! Script syntax: `key [Ctrl+][Alt+][Shift+]<key>[ xN] ...`
 
 use crate::command::{Context, ScripttyCommand};
 use anyhow::{Result, anyhow};
 use async_trait::async_trait;
+use std::str::FromStr;
 
-/// Sends a single key press (with optional modifiers) to the PTY.
+/// Sends one or more key presses (with optional modifiers) to the PTY.
 ///
-/// Script syntax: `key [Ctrl+][Alt+][Shift+]<key>`
+/// Script syntax: `key [Ctrl+][Alt+][Shift+]<key>[ xN] ...`
 ///
 /// Modifiers may appear in any order before the key name. The generated byte
-/// sequence follows xterm conventions.
+/// sequences follow xterm conventions, adjusted for the terminal type the
+/// program sees (see [`KeyTable`]). Several space-separated keys are sent
+/// in order with a short pause between them (30 ms unless changed with
+/// `set key_delay`), e.g. for readline editing sequences. `xN` after a key
+/// presses it `N` times in total, e.g. to move through a menu.
 ///
 /// # Examples
 ///
@@ -21,15 +26,162 @@ use async_trait::async_trait;
 /// key Shift+Tab
 /// key Alt+Left
 /// key Ctrl+Alt+Delete
+/// key Ctrl+A Ctrl+K Enter
+/// key Down x10 Enter
 /// ```
 pub struct KeyPress {
-    pub bytes: Vec<u8>,
+    presses: Vec<Key>,
+}
+
+/// A key and its modifiers, as written in a script (`Ctrl+Left`).
+///
+/// Parse one with [`str::parse`], e.g. `"Ctrl+Left".parse::<Key>()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Key {
+    name: String,
+    ctrl: bool,
+    alt: bool,
+    shift: bool,
+}
+
+impl Key {
+    /// The key name without modifiers, e.g. `Left`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether Ctrl is held.
+    pub fn ctrl(&self) -> bool {
+        self.ctrl
+    }
+
+    /// Whether Alt is held.
+    pub fn alt(&self) -> bool {
+        self.alt
+    }
+
+    /// Whether Shift is held.
+    pub fn shift(&self) -> bool {
+        self.shift
+    }
+
+    /// The byte sequence this key sends on a terminal using `table`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for unknown key names and unsupported modifiers.
+    pub fn bytes(&self, table: KeyTable) -> Result<Vec<u8>> {
+        key_to_bytes(&self.name, self.ctrl, self.alt, self.shift, table)
+    }
+}
+
+/// Escape sequences for keys whose encoding differs between terminal types.
+///
+/// Only Home, End, and F1–F5 differ; the other keys use xterm's encoding
+/// everywhere, and modifiers always use xterm's `;m` parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyTable {
+    /// xterm and compatibles (`xterm-256color`, `alacritty`, `kitty`, ...).
+    #[default]
+    Xterm,
+    /// DEC VT220 style Home/End (`vt100`, `vt220`, `screen`, `tmux`).
+    Vt220,
+    /// The Linux console (`linux`): VT220 Home/End, `ESC [ [ A` style F1–F5.
+    Linux,
+    /// rxvt and urxvt (`rxvt`, `rxvt-unicode`).
+    Rxvt,
+}
+
+impl KeyTable {
+    /// The table for a `TERM` value; unknown terminals get [`KeyTable::Xterm`].
+    pub fn for_term(term: &str) -> Self {
+        if term.starts_with("rxvt") {
+            Self::Rxvt
+        } else if term == "linux" || term.starts_with("linux-") {
+            Self::Linux
+        } else if ["vt1", "vt2", "screen", "tmux"]
+            .iter()
+            .any(|prefix| term.starts_with(prefix))
+        {
+            Self::Vt220
+        } else {
+            Self::Xterm
+        }
+    }
 }
 
 impl KeyPress {
     pub const NAME: &'static str = "key";
+
+    /// Largest accepted repeat count.
+    pub const MAX_REPEAT: usize = 10_000;
+
+    /// Press `presses` in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `presses` is empty.
+    pub fn new(presses: Vec<Key>) -> Result<Self> {
+        if presses.is_empty() {
+            return Err(anyhow!("key command requires a key name"));
+        }
+        Ok(Self { presses })
+    }
+
+    /// The key presses, in order.
+    pub fn presses(&self) -> &[Key] {
+        &self.presses
+    }
 }
 
+/// Named keys accepted by `key` (besides single ASCII characters), e.g. for
+/// editor completion.
+pub(crate) const KEY_NAMES: &[&str] = &[
+    "Enter",
+    "Backspace",
+    "Tab",
+    "Escape",
+    "Space",
+    "Up",
+    "Down",
+    "Right",
+    "Left",
+    "Home",
+    "End",
+    "Insert",
+    "Delete",
+    "PageUp",
+    "PageDown",
+    "F1",
+    "F2",
+    "F3",
+    "F4",
+    "F5",
+    "F6",
+    "F7",
+    "F8",
+    "F9",
+    "F10",
+    "F11",
+    "F12",
+    "KP0",
+    "KP1",
+    "KP2",
+    "KP3",
+    "KP4",
+    "KP5",
+    "KP6",
+    "KP7",
+    "KP8",
+    "KP9",
+    "KPEnter",
+    "KPPlus",
+    "KPMinus",
+    "KPMultiply",
+    "KPDivide",
+    "KPDecimal",
+];
+
 /// Compute the xterm modifier code for a combination of modifiers.
 ///
 /// `n = shift | (alt<<1) | (ctrl<<2)`. Returns `None` for no modifiers,
@@ -77,8 +229,53 @@ fn f1f4_seq(final_byte: u8, ctrl: bool, alt: bool, shift: bool) -> Vec<u8> {
     }
 }
 
+/// Final byte of the application keypad sequence (`ESC O {final}`) for a
+/// keypad key name.
+fn keypad_final(key: &str) -> Option<u8> {
+    let final_byte = match key.strip_prefix("KP")? {
+        "Enter" => b'M',
+        "Plus" => b'k',
+        "Minus" => b'm',
+        "Multiply" => b'j',
+        "Divide" => b'o',
+        "Decimal" => b'n',
+        digit => {
+            let [d @ b'0'..=b'9'] = digit.as_bytes() else {
+                return None;
+            };
+            b'p' + (d - b'0')
+        }
+    };
+    Some(final_byte)
+}
+
 /// Map a key name and modifier flags to the corresponding byte sequence.
-fn key_to_bytes(key: &str, ctrl: bool, alt: bool, shift: bool) -> Result<Vec<u8>> {
+fn key_to_bytes(key: &str, ctrl: bool, alt: bool, shift: bool, table: KeyTable) -> Result<Vec<u8>> {
+    let no_mods = !(ctrl || alt || shift);
+    match (key, table) {
+        ("Home", KeyTable::Vt220 | KeyTable::Linux) => return Ok(tilde_seq(1, ctrl, alt, shift)),
+        ("End", KeyTable::Vt220 | KeyTable::Linux) => return Ok(tilde_seq(4, ctrl, alt, shift)),
+        ("Home", KeyTable::Rxvt) => return Ok(tilde_seq(7, ctrl, alt, shift)),
+        ("End", KeyTable::Rxvt) => return Ok(tilde_seq(8, ctrl, alt, shift)),
+        ("F1" | "F2" | "F3" | "F4" | "F5", KeyTable::Linux) if no_mods => {
+            let letter = b'A' + (key.as_bytes()[1] - b'1');
+            return Ok(vec![0x1b, b'[', b'[', letter]);
+        }
+        ("F1" | "F2" | "F3" | "F4", KeyTable::Rxvt) => {
+            let n = 11 + (key.as_bytes()[1] - b'1');
+            return Ok(tilde_seq(n, ctrl, alt, shift));
+        }
+        _ => {}
+    }
+    if let Some(final_byte) = keypad_final(key) {
+        if !no_mods {
+            return Err(anyhow!(
+                "Modifiers are not supported with keypad keys: {}",
+                key
+            ));
+        }
+        return Ok(vec![0x1b, b'O', final_byte]);
+    }
     match key {
         "Enter" => {
             if alt {
@@ -171,37 +368,83 @@ impl ScripttyCommand for KeyPress {
     }
 
     fn parse(args: &str) -> Result<Self> {
-        let mut token = args.trim();
+        let mut presses: Vec<Key> = Vec::new();
+        for token in args.split_whitespace() {
+            match token
+                .strip_prefix('x')
+                .and_then(|n| n.parse::<usize>().ok())
+            {
+                Some(count) => {
+                    let last = presses
+                        .last()
+                        .cloned()
+                        .ok_or_else(|| anyhow!("Repeat count '{}' must follow a key", token))?;
+                    if count == 0 || count > Self::MAX_REPEAT {
+                        return Err(anyhow!(
+                            "Repeat count must be between 1 and {}, got: {}",
+                            Self::MAX_REPEAT,
+                            count
+                        ));
+                    }
+                    presses.extend(std::iter::repeat_n(last, count - 1));
+                }
+                None => {
+                    presses.push(token.parse()?);
+                }
+            }
+        }
+        Self::new(presses)
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        for (i, key) in self.presses.iter().enumerate() {
+            if i > 0 {
+                tokio::time::sleep(ctx.pace(ctx.scale_timeout(ctx.key_delay()))).await;
+            }
+            ctx.write_to_pty(&key.bytes(ctx.key_table())?)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Key {
+    type Err = anyhow::Error;
+
+    /// Parse one `[Ctrl+][Alt+][Shift+]<key>` token.
+    fn from_str(token: &str) -> Result<Self> {
+        let mut key = token;
         let mut ctrl = false;
         let mut alt = false;
         let mut shift = false;
 
         loop {
-            if let Some(rest) = token.strip_prefix("Ctrl+") {
+            if let Some(rest) = key.strip_prefix("Ctrl+") {
                 ctrl = true;
-                token = rest;
-            } else if let Some(rest) = token.strip_prefix("Alt+") {
+                key = rest;
+            } else if let Some(rest) = key.strip_prefix("Alt+") {
                 alt = true;
-                token = rest;
-            } else if let Some(rest) = token.strip_prefix("Shift+") {
+                key = rest;
+            } else if let Some(rest) = key.strip_prefix("Shift+") {
                 shift = true;
-                token = rest;
+                key = rest;
             } else {
                 break;
             }
         }
 
-        if token.is_empty() {
-            return Err(anyhow!("key command requires a key name"));
+        if key.is_empty() {
+            return Err(anyhow!("Missing key name after modifiers: {}", token));
         }
 
-        let bytes = key_to_bytes(token, ctrl, alt, shift)?;
-        Ok(Self { bytes })
-    }
-
-    async fn execute(&self, ctx: &mut Context) -> Result<()> {
-        ctx.write_to_pty(&self.bytes)?;
-        Ok(())
+        let key = Key {
+            name: key.to_string(),
+            ctrl,
+            alt,
+            shift,
+        };
+        // All tables accept the same keys, so this validates the key.
+        key.bytes(KeyTable::Xterm)?;
+        Ok(key)
     }
 }
 
@@ -210,94 +453,102 @@ mod tests {
     use super::*;
     use crate::command::ScripttyCommand;
 
-    fn parse(s: &str) -> KeyPress {
-        KeyPress::parse(s).unwrap()
+    /// Bytes of a single key press.
+    fn parse(s: &str) -> Vec<u8> {
+        parse_for(s, KeyTable::Xterm)
+    }
+
+    /// Bytes of a single key press on a terminal using `table`.
+    fn parse_for(s: &str, table: KeyTable) -> Vec<u8> {
+        let cmd = KeyPress::parse(s).unwrap();
+        assert_eq!(cmd.presses().len(), 1);
+        cmd.presses()[0].bytes(table).unwrap()
     }
 
     #[test]
     fn test_enter() {
-        assert_eq!(parse("Enter").bytes, b"\r");
+        assert_eq!(parse("Enter"), b"\r");
     }
 
     #[test]
     fn test_ctrl_c() {
-        assert_eq!(parse("Ctrl+C").bytes, b"\x03");
+        assert_eq!(parse("Ctrl+C"), b"\x03");
     }
 
     #[test]
     fn test_ctrl_d() {
-        assert_eq!(parse("Ctrl+D").bytes, b"\x04");
+        assert_eq!(parse("Ctrl+D"), b"\x04");
     }
 
     #[test]
     fn test_shift_tab() {
-        assert_eq!(parse("Shift+Tab").bytes, b"\x1b[Z");
+        assert_eq!(parse("Shift+Tab"), b"\x1b[Z");
     }
 
     #[test]
     fn test_up() {
-        assert_eq!(parse("Up").bytes, b"\x1b[A");
+        assert_eq!(parse("Up"), b"\x1b[A");
     }
 
     #[test]
     fn test_ctrl_up() {
-        assert_eq!(parse("Ctrl+Up").bytes, b"\x1b[1;5A");
+        assert_eq!(parse("Ctrl+Up"), b"\x1b[1;5A");
     }
 
     #[test]
     fn test_alt_left() {
-        assert_eq!(parse("Alt+Left").bytes, b"\x1b[1;3D");
+        assert_eq!(parse("Alt+Left"), b"\x1b[1;3D");
     }
 
     #[test]
     fn test_page_up() {
-        assert_eq!(parse("PageUp").bytes, b"\x1b[5~");
+        assert_eq!(parse("PageUp"), b"\x1b[5~");
     }
 
     #[test]
     fn test_ctrl_page_down() {
-        assert_eq!(parse("Ctrl+PageDown").bytes, b"\x1b[6;5~");
+        assert_eq!(parse("Ctrl+PageDown"), b"\x1b[6;5~");
     }
 
     #[test]
     fn test_f1() {
-        assert_eq!(parse("F1").bytes, b"\x1bOP");
+        assert_eq!(parse("F1"), b"\x1bOP");
     }
 
     #[test]
     fn test_ctrl_f5() {
-        assert_eq!(parse("Ctrl+F5").bytes, b"\x1b[15;5~");
+        assert_eq!(parse("Ctrl+F5"), b"\x1b[15;5~");
     }
 
     #[test]
     fn test_alt_enter() {
-        assert_eq!(parse("Alt+Enter").bytes, b"\x1b\r");
+        assert_eq!(parse("Alt+Enter"), b"\x1b\r");
     }
 
     #[test]
     fn test_backspace() {
-        assert_eq!(parse("Backspace").bytes, b"\x7f");
+        assert_eq!(parse("Backspace"), b"\x7f");
     }
 
     #[test]
     fn test_delete() {
-        assert_eq!(parse("Delete").bytes, b"\x1b[3~");
+        assert_eq!(parse("Delete"), b"\x1b[3~");
     }
 
     #[test]
     fn test_home() {
-        assert_eq!(parse("Home").bytes, b"\x1b[H");
+        assert_eq!(parse("Home"), b"\x1b[H");
     }
 
     #[test]
     fn test_ctrl_home() {
-        assert_eq!(parse("Ctrl+Home").bytes, b"\x1b[1;5H");
+        assert_eq!(parse("Ctrl+Home"), b"\x1b[1;5H");
     }
 
     #[test]
     fn test_ctrl_alt_left() {
         // Ctrl+Alt: ctrl<<2 | alt<<1 = 4|2 = 6 → code = 7
-        assert_eq!(parse("Ctrl+Alt+Left").bytes, b"\x1b[1;7D");
+        assert_eq!(parse("Ctrl+Alt+Left"), b"\x1b[1;7D");
     }
 
     #[test]
@@ -309,4 +560,83 @@ mod tests {
     fn test_empty_key() {
         assert!(KeyPress::parse("").is_err());
     }
+
+    #[test]
+    fn test_chord() {
+        let cmd = KeyPress::parse("Ctrl+A  Ctrl+K Enter").unwrap();
+        let bytes: Vec<_> = cmd
+            .presses()
+            .iter()
+            .map(|k| k.bytes(KeyTable::Xterm).unwrap())
+            .collect();
+        assert_eq!(
+            bytes,
+            vec![b"\x01".to_vec(), b"\x0b".to_vec(), b"\r".to_vec()]
+        );
+        assert!(KeyPress::parse("Ctrl+A Bogus").is_err());
+        assert!(KeyPress::parse("Ctrl+").is_err());
+    }
+
+    #[test]
+    fn test_repeat() {
+        let cmd = KeyPress::parse("Down x3 Enter").unwrap();
+        assert_eq!(cmd.presses().len(), 4);
+        assert_eq!(cmd.presses()[2].name(), "Down");
+        assert_eq!(cmd.presses()[3].name(), "Enter");
+        assert_eq!(KeyPress::parse("x x2").unwrap().presses().len(), 2);
+        assert!(KeyPress::parse("x3").is_err());
+        assert!(KeyPress::parse("Down x0").is_err());
+        assert!(KeyPress::parse("Down x99999").is_err());
+    }
+
+    #[test]
+    fn test_new() {
+        let key: Key = "Ctrl+Alt+Left".parse().unwrap();
+        assert_eq!(key.name(), "Left");
+        assert!(key.ctrl() && key.alt() && !key.shift());
+        assert!("Bogus".parse::<Key>().is_err());
+        let cmd = KeyPress::new(vec![key.clone(), key]).unwrap();
+        assert_eq!(cmd.presses().len(), 2);
+        assert!(KeyPress::new(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_key_tables() {
+        assert_eq!(parse_for("Home", KeyTable::Vt220), b"\x1b[1~");
+        assert_eq!(parse_for("End", KeyTable::Linux), b"\x1b[4~");
+        assert_eq!(parse_for("Ctrl+End", KeyTable::Vt220), b"\x1b[4;5~");
+        assert_eq!(parse_for("Home", KeyTable::Rxvt), b"\x1b[7~");
+        assert_eq!(parse_for("F1", KeyTable::Linux), b"\x1b[[A");
+        assert_eq!(parse_for("F5", KeyTable::Linux), b"\x1b[[E");
+        assert_eq!(parse_for("F6", KeyTable::Linux), b"\x1b[17~");
+        assert_eq!(parse_for("F2", KeyTable::Rxvt), b"\x1b[12~");
+        assert_eq!(parse_for("Up", KeyTable::Rxvt), b"\x1b[A");
+    }
+
+    #[test]
+    fn test_for_term() {
+        assert_eq!(KeyTable::for_term("xterm-256color"), KeyTable::Xterm);
+        assert_eq!(KeyTable::for_term("screen-256color"), KeyTable::Vt220);
+        assert_eq!(KeyTable::for_term("tmux-256color"), KeyTable::Vt220);
+        assert_eq!(KeyTable::for_term("linux"), KeyTable::Linux);
+        assert_eq!(KeyTable::for_term("rxvt-unicode-256color"), KeyTable::Rxvt);
+        assert_eq!(KeyTable::for_term("dumb"), KeyTable::Xterm);
+    }
+
+    #[test]
+    fn test_keypad() {
+        assert_eq!(parse("KP0"), b"\x1bOp");
+        assert_eq!(parse("KP9"), b"\x1bOy");
+        assert_eq!(parse("KPEnter"), b"\x1bOM");
+        assert_eq!(parse("KPMinus"), b"\x1bOm");
+        assert!(KeyPress::parse("Ctrl+KP1").is_err());
+        assert!(KeyPress::parse("KP10").is_err());
+    }
+
+    #[test]
+    fn test_key_names_parse() {
+        for name in KEY_NAMES {
+            assert!(KeyPress::parse(name).is_ok(), "{name}");
+        }
+    }
 }
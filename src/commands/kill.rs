@@ -0,0 +1,66 @@
+//! [`Kill`] command — stops the program, gracefully if it lets itself be.
+//!
+//! Script syntax:
+//! - `kill` — SIGTERM, then SIGKILL after 2 seconds
+//! - `kill 10s` — custom grace period
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::parse_duration;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// Grace period between SIGTERM and SIGKILL when none is given.
+pub const DEFAULT_GRACE: Duration = Duration::from_secs(2);
+
+/// Sends SIGTERM to the program and, if it has not exited after the grace
+/// period, SIGKILL to it and its process group. The exit code is stored in
+/// `EXIT_CODE`.
+///
+/// Unlike `signal KILL`, this waits for the program to be gone, so a
+/// misbehaving program cannot outlive the command; `respawn` starts it again.
+pub struct Kill {
+    pub grace: Duration,
+}
+
+impl Kill {
+    pub const NAME: &'static str = "kill";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for Kill {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let args = args.trim();
+        let grace = if args.is_empty() {
+            DEFAULT_GRACE
+        } else {
+            parse_duration(args)?
+        };
+        Ok(Self { grace })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        ctx.kill(self.grace).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Kill::parse("").unwrap().grace, DEFAULT_GRACE);
+        assert_eq!(
+            Kill::parse("500ms").unwrap().grace,
+            Duration::from_millis(500)
+        );
+        assert!(Kill::parse("later").is_err());
+    }
+}
@@ -0,0 +1,215 @@
+//! [`Login`] command — walks through a login conversation up to the shell prompt.
+//!
+//! Script syntax:
+//! `login user=NAME (password=TEXT | password_env=VAR) prompt=TEXT [login_prompt=TEXT] [password_prompt=TEXT] [retries=N] [timeout=DURATION]`
+//!
+//! Values are bare words or quoted strings and may reference `${NAME}` variables:
+//! `login user=${U} password_env=PW prompt="${PROMPT}" retries=3`
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::{parse_duration, parse_options};
+use anyhow::{Context as _, Result, anyhow};
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// Messages login programs print when the credentials are rejected.
+const FAILURES: &[&str] = &[
+    "Login incorrect",
+    "Permission denied",
+    "Access denied",
+    "Authentication failed",
+];
+
+/// Where the password comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Password {
+    /// Literal text, interpolated when the command runs; a reference to an
+    /// undefined variable is an error.
+    Text(String),
+    /// Name of an environment variable read when the command runs.
+    Env(String),
+}
+
+/// Answers the login and password prompts of ssh, telnet, `getty`, or a
+/// serial console until the shell `prompt` appears.
+///
+/// Prompts are answered in whatever order they show up, so the same command
+/// handles consoles that ask for a user name and ssh sessions that only ask
+/// for a password. When the credentials are rejected the next password
+/// prompt is answered again, up to `retries` times (useful on noisy serial
+/// lines); after that the command fails. The password is masked wherever the
+/// run is recorded (see [`crate::secrets`]).
+pub struct Login {
+    /// `None` fails if a login prompt appears.
+    pub user: Option<String>,
+    pub password: Password,
+    /// Shell prompt that marks a successful login.
+    pub prompt: String,
+    pub login_prompt: String,
+    pub password_prompt: String,
+    pub retries: u32,
+    /// Timeout for each step; `None` uses the script's default timeout.
+    pub timeout: Option<Duration>,
+}
+
+impl Login {
+    pub const NAME: &'static str = "login";
+
+    const OPTIONS: &'static [&'static str] = &[
+        "user",
+        "password",
+        "password_env",
+        "prompt",
+        "login_prompt",
+        "password_prompt",
+        "retries",
+        "timeout",
+    ];
+
+    fn password(&self, ctx: &Context) -> Result<String> {
+        match &self.password {
+            Password::Text(text) => ctx.interpolate_strict(text),
+            Password::Env(var) => std::env::var(var)
+                .with_context(|| format!("Environment variable '{}' is not set", var)),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for Login {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let mut options = parse_options(args, Self::OPTIONS)?;
+        let password = match (options.remove("password"), options.remove("password_env")) {
+            (Some(text), None) => Password::Text(text),
+            (None, Some(var)) => Password::Env(var),
+            _ => {
+                return Err(anyhow!(
+                    "Expected exactly one of password= or password_env="
+                ));
+            }
+        };
+        let prompt = options
+            .remove("prompt")
+            .ok_or_else(|| anyhow!("Missing prompt= (the shell prompt after logging in)"))?;
+        let retries = match options.remove("retries") {
+            Some(n) => n.parse().context("Invalid retries")?,
+            None => 0,
+        };
+        let timeout = options
+            .remove("timeout")
+            .map(|t| parse_duration(&t))
+            .transpose()?;
+        Ok(Self {
+            user: options.remove("user"),
+            password,
+            prompt,
+            login_prompt: options
+                .remove("login_prompt")
+                .unwrap_or_else(|| "login:".to_string()),
+            password_prompt: options
+                .remove("password_prompt")
+                .unwrap_or_else(|| "assword:".to_string()),
+            retries,
+            timeout,
+        })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let prompt = ctx.interpolate(&self.prompt)?;
+        let login_prompt = ctx.interpolate(&self.login_prompt)?;
+        let password_prompt = ctx.interpolate(&self.password_prompt)?;
+        let user = self
+            .user
+            .as_deref()
+            .map(|u| ctx.interpolate(u))
+            .transpose()?;
+        let password = self.password(ctx)?;
+        ctx.register_secret(password.as_str());
+        let timeout = ctx.timeout_or_default(self.timeout);
+
+        let mut targets = vec![
+            prompt.as_str(),
+            login_prompt.as_str(),
+            password_prompt.as_str(),
+        ];
+        targets.extend(FAILURES);
+        let mut attempts = 0;
+        loop {
+            let matched = ctx
+                .wait_for_any(&targets, timeout)
+                .await
+                .context("Login did not reach the shell prompt")?;
+            match matched {
+                0 => return Ok(()),
+                1 => {
+                    let user = user
+                        .as_deref()
+                        .ok_or_else(|| anyhow!("Asked for a user name but no user= was given"))?;
+                    ctx.write_to_pty(format!("{}\r", user).as_bytes())?;
+                }
+                2 => {
+                    if attempts > self.retries {
+                        return Err(anyhow!(
+                            "Login failed after {} attempt(s): credentials rejected",
+                            attempts
+                        ));
+                    }
+                    attempts += 1;
+                    ctx.write_to_pty(format!("{}\r", password).as_bytes())?;
+                }
+                _ => {
+                    if attempts > self.retries {
+                        return Err(anyhow!(
+                            "Login failed after {} attempt(s): '{}'",
+                            attempts,
+                            targets[matched]
+                        ));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        let cmd =
+            Login::parse(r#"user=${U} password_env=PW prompt="${PROMPT}" retries=3"#).unwrap();
+        assert_eq!(cmd.user.as_deref(), Some("${U}"));
+        assert_eq!(cmd.password, Password::Env("PW".into()));
+        assert_eq!(cmd.prompt, "${PROMPT}");
+        assert_eq!(cmd.login_prompt, "login:");
+        assert_eq!(cmd.password_prompt, "assword:");
+        assert_eq!(cmd.retries, 3);
+        assert_eq!(cmd.timeout, None);
+    }
+
+    #[test]
+    fn test_parse_custom_prompts() {
+        let cmd = Login::parse(
+            r##"password="${PW}" prompt="# " login_prompt="Username:" password_prompt="Secret:" timeout=30s"##,
+        )
+        .unwrap();
+        assert_eq!(cmd.user, None);
+        assert_eq!(cmd.password, Password::Text("${PW}".into()));
+        assert_eq!(cmd.login_prompt, "Username:");
+        assert_eq!(cmd.timeout, Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Login::parse(r#"user=a prompt="$ ""#).is_err());
+        assert!(Login::parse(r#"password=a password_env=B prompt="$ ""#).is_err());
+        assert!(Login::parse("password=a").is_err());
+        assert!(Login::parse(r#"password=a prompt="$ " retries=-1"#).is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! [`Loop`] block — repeats a list of commands until a condition holds, or forever.
+//!
+//! Script syntax:
+//!
+//! ```text
+//! loop until ${iterations} == 1000
+//!   send "status"
+//!   key Enter
+//!   expect "ok"
+//! end
+//! ```
+//!
+//! Without `until`, the block runs until one of its commands fails. The
+//! condition compares two values with `==`, `!=`, `<`, `<=`, `>`, or `>=`;
+//! values may be quoted and reference variables.
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::{ScriptReader, parse_quoted_string, split_args};
+use crate::soak::ResourceUsage;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+
+/// Comparison operators for `loop until`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn from_token(token: &str) -> Option<Self> {
+        Some(match token {
+            "==" => Self::Eq,
+            "!=" => Self::Ne,
+            "<" => Self::Lt,
+            "<=" => Self::Le,
+            ">" => Self::Gt,
+            ">=" => Self::Ge,
+            _ => return None,
+        })
+    }
+
+    /// Compare numerically when both sides are numbers; otherwise only `==`
+    /// and `!=` are allowed, comparing the text.
+    fn holds(self, left: &str, right: &str) -> Result<bool> {
+        let ordering = match (left.trim().parse::<f64>(), right.trim().parse::<f64>()) {
+            (Ok(l), Ok(r)) => l.partial_cmp(&r),
+            _ => match self {
+                Self::Eq => return Ok(left == right),
+                Self::Ne => return Ok(left != right),
+                _ => {
+                    return Err(anyhow!(
+                        "Cannot compare non-numbers '{}' and '{}' by order",
+                        left,
+                        right
+                    ));
+                }
+            },
+        };
+        let Some(ordering) = ordering else {
+            return Ok(false);
+        };
+        Ok(match self {
+            Self::Eq => ordering.is_eq(),
+            Self::Ne => ordering.is_ne(),
+            Self::Lt => ordering.is_lt(),
+            Self::Le => ordering.is_le(),
+            Self::Gt => ordering.is_gt(),
+            Self::Ge => ordering.is_ge(),
+        })
+    }
+}
+
+/// The `until` condition of a [`Loop`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    pub left: String,
+    pub op: Comparison,
+    pub right: String,
+}
+
+impl Condition {
+    /// Parse `LEFT OP RIGHT`, where the operands are words or quoted strings.
+    fn parse(text: &str) -> Result<Self> {
+        let [left, op, right] = split_args(text)[..] else {
+            return Err(anyhow!("Expected 'VALUE OP VALUE', got: '{}'", text));
+        };
+        let op = Comparison::from_token(op)
+            .ok_or_else(|| anyhow!("Unknown comparison '{}', expected ==, !=, <, <=, >, >=", op))?;
+        let operand = |word: &str| {
+            if word.starts_with('"') {
+                parse_quoted_string(word)
+            } else {
+                Ok(word.to_string())
+            }
+        };
+        Ok(Self {
+            left: operand(left)?,
+            op,
+            right: operand(right)?,
+        })
+    }
+
+    fn holds(&self, ctx: &Context) -> Result<bool> {
+        self.op.holds(
+            &ctx.interpolate(&self.left)?,
+            &ctx.interpolate(&self.right)?,
+        )
+    }
+}
+
+/// Runs `commands` over and over, until the `until` condition holds after
+/// an iteration, or forever when there is none.
+///
+/// The `iterations` variable holds the number of completed iterations (0
+/// during the first one). After each iteration the program's memory and open
+/// file descriptors are sampled for the soak report (see [`crate::soak`]).
+pub struct Loop {
+    pub until: Option<Condition>,
+    pub commands: Vec<Box<dyn ScripttyCommand>>,
+    /// How the loop is identified in the soak report: its opening line.
+    site: String,
+}
+
+impl Loop {
+    pub const NAME: &'static str = "loop";
+
+    /// Parse the optional `until` condition and the block body up to its
+    /// closing `end`.
+    pub(crate) fn parse_block(
+        args: &str,
+        reader: &mut ScriptReader,
+    ) -> Result<Box<dyn ScripttyCommand>> {
+        let args = args.trim();
+        let until = match args {
+            "" => None,
+            _ => {
+                let condition = args
+                    .strip_prefix("until ")
+                    .ok_or_else(|| anyhow!("Expected 'loop' or 'loop until VALUE OP VALUE'"))?;
+                Some(Condition::parse(condition)?)
+            }
+        };
+        let (commands, _) = reader.parse_block(&["end"])?;
+        let site = match args {
+            "" => Self::NAME.to_string(),
+            _ => format!("{} {}", Self::NAME, args),
+        };
+        Ok(Box::new(Self {
+            until,
+            commands,
+            site,
+        }))
+    }
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for Loop {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(_args: &str) -> Result<Self> {
+        Err(anyhow!(
+            "'loop' starts a block and must be followed by 'end'"
+        ))
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let mut iterations: u64 = 0;
+        loop {
+            ctx.set_var("iterations", iterations.to_string());
+            for cmd in &self.commands {
+                cmd.execute(ctx).await?;
+            }
+            iterations += 1;
+            ctx.set_var("iterations", iterations.to_string());
+            if let Some(usage) = ctx.pty.pid().and_then(ResourceUsage::sample) {
+                ctx.soak.record(&self.site, usage);
+            }
+            if let Some(until) = &self.until
+                && until.holds(ctx)?
+            {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_str;
+
+    #[test]
+    fn test_parse() {
+        let cmds = parse_str("loop until ${iterations} == 1000\nkey Down\nend\n").unwrap();
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0].name(), "loop");
+        assert!(parse_str("loop\nkey Down\nend\n").is_ok());
+        assert!(parse_str("loop while x\nkey Down\nend\n").is_err());
+        assert!(parse_str("loop until ${iterations} = 3\nkey Down\nend\n").is_err());
+        assert!(parse_str("loop until 3\nkey Down\nend\n").is_err());
+    }
+
+    #[test]
+    fn test_condition() {
+        let condition = Condition::parse(r#"${STATE} != "two words""#).unwrap();
+        assert_eq!(condition.left, "${STATE}");
+        assert_eq!(condition.op, Comparison::Ne);
+        assert_eq!(condition.right, "two words");
+    }
+
+    #[test]
+    fn test_comparison() {
+        assert!(Comparison::Eq.holds("1000", "1000.0").unwrap());
+        assert!(Comparison::Lt.holds("9", "10").unwrap());
+        assert!(Comparison::Ge.holds("10", "10").unwrap());
+        assert!(Comparison::Ne.holds("ok", "fail").unwrap());
+        assert!(Comparison::Eq.holds("ok", "ok").unwrap());
+        assert!(Comparison::Lt.holds("a", "b").is_err());
+    }
+}
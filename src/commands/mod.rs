@@ -1,13 +1,101 @@
+mod any_order;
+mod assert_exit_code;
+mod assert_image_similar;
+mod assert_snapshot;
+mod at;
+mod call;
+mod capture;
+mod complete;
+mod debug_state;
 mod expect;
+mod expect_any;
+mod expect_bell;
+mod expect_eof;
+mod expect_idle;
+mod expect_image;
+mod expect_not;
+mod expect_notification;
+mod expect_screen;
+mod gdb;
+mod history;
+mod if_block;
 mod key_press;
+mod kill;
+mod login;
+mod loop_block;
+mod mouse;
+#[cfg(feature = "network")]
+mod network;
+mod paste;
+mod repeat;
+mod repl;
+mod resize;
+mod respawn;
+mod screenshot;
 mod send_input;
+mod set;
+mod setting;
 mod show;
+mod signal;
+mod snapshot;
+mod sudo;
+mod sync_audio;
+mod type_edit;
 mod type_text;
 mod wait;
+mod wait_file;
+mod wait_signal;
+mod while_block;
 
+pub use any_order::AnyOrder;
+pub use assert_exit_code::AssertExitCode;
+pub use assert_image_similar::AssertImageSimilar;
+pub use assert_snapshot::AssertSnapshot;
+pub use at::At;
+pub use call::{Call, Macro};
+pub use capture::Capture;
+pub use complete::Complete;
+pub use debug_state::DebugState;
 pub use expect::Expect;
-pub use key_press::KeyPress;
+pub use expect_any::ExpectAny;
+pub use expect_bell::ExpectBell;
+pub use expect_eof::ExpectEof;
+pub use expect_idle::ExpectIdle;
+pub use expect_image::ExpectImage;
+pub use expect_not::ExpectNot;
+pub use expect_notification::ExpectNotification;
+pub use expect_screen::{ExpectScreen, ScreenCondition};
+pub use gdb::{Gdb, GdbAction};
+pub(crate) use history::cursor_line;
+pub use history::{HistoryUp, SearchHistory};
+pub use if_block::If;
+pub(crate) use key_press::KEY_NAMES;
+pub use key_press::{Key, KeyPress, KeyTable};
+pub use kill::Kill;
+pub use login::{Login, Password};
+pub use loop_block::{Comparison, Condition, Loop};
+pub use mouse::{Mouse, MouseAction, MouseButton};
+#[cfg(feature = "network")]
+pub use network::{ConfigMode, Enable, SaveConfig, Vendor};
+pub use paste::Paste;
+pub use repeat::Repeat;
+pub use repl::{Eval, ExpectPrompt, Repl, UseProfile};
+pub use resize::Resize;
+pub use respawn::Respawn;
+pub use screenshot::Screenshot;
 pub use send_input::SendInput;
+pub use set::Set;
+pub use setting::Setting;
 pub use show::Show;
+pub use signal::SendSignal;
+pub(crate) use signal::signal_number;
+pub use snapshot::{Snapshot, SnapshotMode};
+pub use sudo::Sudo;
+pub(crate) use sync_audio::Narration;
+pub use sync_audio::{Marker, SyncAudio};
+pub use type_edit::{Edit, TypeEdit};
 pub use type_text::TypeText;
 pub use wait::Wait;
+pub use wait_file::WaitFile;
+pub use wait_signal::WaitSignal;
+pub use while_block::While;
@@ -0,0 +1,209 @@
+//! [`Mouse`] command — sends mouse events using the xterm SGR mouse protocol.
+//!
+//! Script syntax (rows and columns are 1-based; the button defaults to `left`):
+//! - `mouse click 10 42` — press and release at row 10, column 42
+//! - `mouse click 10 42 right` — with another button (`left`, `middle`, `right`)
+//! - `mouse press 10 42` / `mouse release 10 42` — one half of a click
+//! - `mouse scroll up 10 42` / `mouse scroll down 10 42` — one wheel step
+//! - `mouse drag 10 42 12 60` — press at 10,42, move to 12,60, release there
+
+use crate::command::{Context, ScripttyCommand};
+use anyhow::{Context as _, Result, anyhow};
+use async_trait::async_trait;
+
+/// A mouse button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+impl MouseButton {
+    fn code(self) -> u16 {
+        match self {
+            Self::Left => 0,
+            Self::Middle => 1,
+            Self::Right => 2,
+        }
+    }
+}
+
+/// What a [`Mouse`] command does; positions are `(row, column)`, 1-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MouseAction {
+    Click(MouseButton, (u16, u16)),
+    Press(MouseButton, (u16, u16)),
+    Release(MouseButton, (u16, u16)),
+    ScrollUp((u16, u16)),
+    ScrollDown((u16, u16)),
+    Drag(MouseButton, (u16, u16), (u16, u16)),
+}
+
+/// Sends mouse events to programs that enabled mouse reporting, e.g. file
+/// managers and other mouse-first TUIs.
+///
+/// Events use the SGR encoding (`ESC [ < b ; x ; y M`), which xterm-compatible
+/// applications understand once they enable mouse mode 1006. Multi-event
+/// actions (click, drag) pause between events like `key` does between keys.
+pub struct Mouse {
+    pub action: MouseAction,
+}
+
+impl Mouse {
+    pub const NAME: &'static str = "mouse";
+
+    /// The escape sequences for the action, in order.
+    pub fn sequences(&self) -> Vec<String> {
+        let sgr = |code: u16, (row, col): (u16, u16), press: bool| {
+            format!(
+                "\x1b[<{};{};{}{}",
+                code,
+                col,
+                row,
+                if press { 'M' } else { 'm' }
+            )
+        };
+        match self.action {
+            MouseAction::Click(button, at) => {
+                vec![sgr(button.code(), at, true), sgr(button.code(), at, false)]
+            }
+            MouseAction::Press(button, at) => vec![sgr(button.code(), at, true)],
+            MouseAction::Release(button, at) => vec![sgr(button.code(), at, false)],
+            MouseAction::ScrollUp(at) => vec![sgr(64, at, true)],
+            MouseAction::ScrollDown(at) => vec![sgr(65, at, true)],
+            MouseAction::Drag(button, from, to) => vec![
+                sgr(button.code(), from, true),
+                // Motion with the button held adds 32 to the button code.
+                sgr(button.code() + 32, to, true),
+                sgr(button.code(), to, false),
+            ],
+        }
+    }
+}
+
+/// Parse `ROW COL` from the front of `args`.
+fn parse_position<'a>(args: &mut impl Iterator<Item = &'a str>) -> Result<(u16, u16)> {
+    let mut coordinate = |what: &str| -> Result<u16> {
+        let value = args
+            .next()
+            .ok_or_else(|| anyhow!("Expected a {} number", what))?;
+        let n: u16 = value
+            .parse()
+            .with_context(|| format!("Invalid {}: {}", what, value))?;
+        if n == 0 {
+            return Err(anyhow!("Rows and columns start at 1, got {} 0", what));
+        }
+        Ok(n)
+    };
+    Ok((coordinate("row")?, coordinate("column")?))
+}
+
+fn parse_button(name: Option<&str>) -> Result<MouseButton> {
+    match name {
+        None | Some("left") => Ok(MouseButton::Left),
+        Some("middle") => Ok(MouseButton::Middle),
+        Some("right") => Ok(MouseButton::Right),
+        Some(other) => Err(anyhow!(
+            "Unknown mouse button '{}', expected left, middle, or right",
+            other
+        )),
+    }
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for Mouse {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let mut words = args.split_whitespace();
+        let action = match words.next() {
+            Some("click") => {
+                let at = parse_position(&mut words)?;
+                MouseAction::Click(parse_button(words.next())?, at)
+            }
+            Some("press") => {
+                let at = parse_position(&mut words)?;
+                MouseAction::Press(parse_button(words.next())?, at)
+            }
+            Some("release") => {
+                let at = parse_position(&mut words)?;
+                MouseAction::Release(parse_button(words.next())?, at)
+            }
+            Some("scroll") => match words.next() {
+                Some("up") => MouseAction::ScrollUp(parse_position(&mut words)?),
+                Some("down") => MouseAction::ScrollDown(parse_position(&mut words)?),
+                _ => return Err(anyhow!("Expected 'scroll up' or 'scroll down'")),
+            },
+            Some("drag") => {
+                let from = parse_position(&mut words)?;
+                let to = parse_position(&mut words)?;
+                MouseAction::Drag(parse_button(words.next())?, from, to)
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Expected click, press, release, scroll, or drag after 'mouse'"
+                ));
+            }
+        };
+        if let Some(extra) = words.next() {
+            return Err(anyhow!("Unexpected argument: '{}'", extra));
+        }
+        Ok(Self { action })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        for (i, sequence) in self.sequences().iter().enumerate() {
+            if i > 0 {
+                tokio::time::sleep(ctx.pace(ctx.scale_timeout(ctx.key_delay()))).await;
+            }
+            ctx.write_to_pty(sequence.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    fn sequences(args: &str) -> Vec<String> {
+        Mouse::parse(args).unwrap().sequences()
+    }
+
+    #[test]
+    fn test_click() {
+        assert_eq!(
+            sequences("click 10 42"),
+            vec!["\x1b[<0;42;10M", "\x1b[<0;42;10m"]
+        );
+        assert_eq!(sequences("click 1 2 right")[0], "\x1b[<2;2;1M");
+    }
+
+    #[test]
+    fn test_scroll() {
+        assert_eq!(sequences("scroll up 5 6"), vec!["\x1b[<64;6;5M"]);
+        assert_eq!(sequences("scroll down 5 6"), vec!["\x1b[<65;6;5M"]);
+    }
+
+    #[test]
+    fn test_drag() {
+        assert_eq!(
+            sequences("drag 1 1 3 4 middle"),
+            vec!["\x1b[<1;1;1M", "\x1b[<33;4;3M", "\x1b[<1;4;3m"]
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Mouse::parse("").is_err());
+        assert!(Mouse::parse("click 10").is_err());
+        assert!(Mouse::parse("click 0 5").is_err());
+        assert!(Mouse::parse("click 1 2 thumb").is_err());
+        assert!(Mouse::parse("scroll left 1 2").is_err());
+        assert!(Mouse::parse("press 1 2 left extra").is_err());
+    }
+}
@@ -0,0 +1,294 @@
+//! Helpers for the CLIs of network devices: [`Enable`], [`ConfigMode`], and
+//! [`SaveConfig`].
+//!
+//! Script syntax:
+//!
+//! ```text
+//! set vendor cisco
+//! enable
+//! config_mode
+//! send "hostname edge1"
+//! key Enter
+//! save_config
+//! ```
+//!
+//! The prompts and commands come from the [`Vendor`] chosen with
+//! `set vendor`. `enable` answers a password prompt with the
+//! `ENABLE_SECRET` variable, and fails if the device asks for one while it
+//! is unset. Each step fails on the vendor's error messages
+//! (e.g. `% Invalid input`) and times out after the script's default timeout.
+
+use crate::command::{Context, ScripttyCommand};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+
+/// Device families with different CLI conventions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vendor {
+    /// IOS-style: `enable`, `configure terminal`, `write memory`.
+    Cisco,
+    /// Junos-style: no enable level, `configure`, `commit and-quit`.
+    Juniper,
+}
+
+/// One command sent to the device and the text that confirms it worked.
+struct Step {
+    line: &'static str,
+    done: &'static str,
+}
+
+/// Prompts and commands of a [`Vendor`].
+struct Profile {
+    /// `None` when the CLI has no privileged level to enter.
+    enable: Option<Step>,
+    config: Step,
+    save: &'static [Step],
+    errors: &'static [&'static str],
+}
+
+impl Vendor {
+    /// Parse a vendor name as used with `set vendor`.
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "cisco" => Ok(Self::Cisco),
+            "juniper" => Ok(Self::Juniper),
+            _ => Err(anyhow!(
+                "Unknown vendor '{}', expected 'cisco' or 'juniper'",
+                name
+            )),
+        }
+    }
+
+    fn profile(self) -> Profile {
+        match self {
+            Self::Cisco => Profile {
+                enable: Some(Step {
+                    line: "enable",
+                    done: "#",
+                }),
+                config: Step {
+                    line: "configure terminal",
+                    done: "(config)#",
+                },
+                save: &[
+                    Step {
+                        line: "end",
+                        done: "#",
+                    },
+                    Step {
+                        line: "write memory",
+                        done: "[OK]",
+                    },
+                ],
+                errors: &["% Invalid input", "% Access denied", "% Bad secrets"],
+            },
+            Self::Juniper => Profile {
+                enable: None,
+                config: Step {
+                    line: "configure",
+                    done: "[edit]",
+                },
+                save: &[Step {
+                    line: "commit and-quit",
+                    done: "commit complete",
+                }],
+                errors: &["error:", "unknown command", "syntax error"],
+            },
+        }
+    }
+}
+
+/// Variable holding the password for `enable`.
+pub const ENABLE_SECRET_VAR: &str = "ENABLE_SECRET";
+
+fn vendor(ctx: &Context, command: &str) -> Result<Vendor> {
+    ctx.vendor.ok_or_else(|| {
+        anyhow!(
+            "'{}' needs a vendor; add 'set vendor cisco' or 'set vendor juniper'",
+            command
+        )
+    })
+}
+
+/// Send `step.line` and wait for `step.done`, failing on any of `errors`.
+/// With `secret`, a `Password:` prompt on the way is answered with it.
+async fn run_step(
+    ctx: &mut Context,
+    step: &Step,
+    errors: &[&str],
+    secret_var: Option<&str>,
+) -> Result<()> {
+    ctx.write_to_pty(format!("{}\r", step.line).as_bytes())?;
+    // Skip the echo of the command so its text can't match `done`.
+    let timeout = ctx.timeout_or_default(None);
+    ctx.wait_for_pattern(step.line, timeout).await?;
+    let mut targets = vec![step.done];
+    targets.extend(errors);
+    if secret_var.is_some() {
+        targets.push("assword:");
+    }
+    loop {
+        let matched = ctx.wait_for_any(&targets, timeout).await?;
+        match targets[matched] {
+            t if t == step.done => return Ok(()),
+            "assword:" => {
+                let var = secret_var.unwrap_or_default();
+                let secret = ctx.var(var).ok_or_else(|| {
+                    anyhow!(
+                        "'{}' asked for a password but {} is not set",
+                        step.line,
+                        var
+                    )
+                })?;
+                ctx.register_secret(secret);
+                ctx.write_to_pty(format!("{}\r", secret).as_bytes())?;
+                targets.pop();
+            }
+            error => return Err(anyhow!("'{}' failed: '{}'", step.line, error)),
+        }
+    }
+}
+
+/// Enters privileged mode (`enable` on Cisco); a no-op for vendors without one.
+pub struct Enable;
+
+impl Enable {
+    pub const NAME: &'static str = "enable";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for Enable {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        no_args(Self::NAME, args)?;
+        Ok(Self)
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let profile = vendor(ctx, Self::NAME)?.profile();
+        let Some(step) = &profile.enable else {
+            return Ok(());
+        };
+        run_step(ctx, step, profile.errors, Some(ENABLE_SECRET_VAR)).await
+    }
+}
+
+/// Enters configuration mode.
+pub struct ConfigMode;
+
+impl ConfigMode {
+    pub const NAME: &'static str = "config_mode";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for ConfigMode {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        no_args(Self::NAME, args)?;
+        Ok(Self)
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let profile = vendor(ctx, Self::NAME)?.profile();
+        run_step(ctx, &profile.config, profile.errors, None).await
+    }
+}
+
+/// Leaves configuration mode and saves (or commits) the configuration.
+pub struct SaveConfig;
+
+impl SaveConfig {
+    pub const NAME: &'static str = "save_config";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for SaveConfig {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        no_args(Self::NAME, args)?;
+        Ok(Self)
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let profile = vendor(ctx, Self::NAME)?.profile();
+        for step in profile.save {
+            run_step(ctx, step, profile.errors, None).await?;
+        }
+        Ok(())
+    }
+}
+
+fn no_args(command: &str, args: &str) -> Result<()> {
+    if args.trim().is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("'{}' takes no arguments", command))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        assert!(Enable::parse("").is_ok());
+        assert!(ConfigMode::parse(" ").is_ok());
+        assert!(SaveConfig::parse("now").is_err());
+    }
+
+    #[test]
+    fn test_vendor_from_name() {
+        assert_eq!(Vendor::from_name("cisco").unwrap(), Vendor::Cisco);
+        assert_eq!(Vendor::from_name("juniper").unwrap(), Vendor::Juniper);
+        assert!(Vendor::from_name("Cisco").is_err());
+    }
+
+    #[test]
+    fn test_profiles() {
+        assert!(Vendor::Juniper.profile().enable.is_none());
+        assert_eq!(Vendor::Cisco.profile().config.done, "(config)#");
+    }
+
+    #[tokio::test]
+    async fn test_enable_secret() {
+        let spawn = || {
+            crate::Engine::builder("sh")
+                .args([
+                    "-c",
+                    "printf 'edge1>'; read l; printf 'Password: '; read pw; printf 'edge1#'; sleep 1",
+                ])
+                .handler(|_| {})
+                .spawn()
+                .unwrap()
+        };
+        let script = "set vendor cisco\nexpect \"edge1>\"\nenable\n";
+
+        let mut engine = spawn();
+        let err = engine
+            .execute(crate::parse_str(script).unwrap())
+            .await
+            .unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("ENABLE_SECRET is not set"),
+            "got: {err:#}"
+        );
+
+        let mut engine = spawn();
+        engine.set_var(ENABLE_SECRET_VAR, "s3cret");
+        engine
+            .execute(crate::parse_str(script).unwrap())
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+//! [`Paste`] command — sends text as a bracketed paste.
+//!
+//! Script syntax: `paste "text here"`, or a heredoc for multi-line text
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::parse_quoted_string;
+use anyhow::Result;
+use async_trait::async_trait;
+use tokio::time::sleep;
+
+/// Sequence that starts a bracketed paste.
+const PASTE_START: &[u8] = b"\x1b[200~";
+
+/// Sequence that ends a bracketed paste.
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// Sends text at once wrapped in bracketed-paste markers, the way a terminal
+/// delivers a clipboard paste.
+///
+/// Editors and shells that enable bracketed paste mode insert the text
+/// literally instead of treating it as keystrokes, so pasting code into vim
+/// does not trigger auto-indent or mappings. Newlines are sent as carriage
+/// returns, as terminals do. `${NAME}` variables are expanded when the
+/// command runs.
+pub struct Paste {
+    pub text: String,
+}
+
+impl Paste {
+    pub const NAME: &'static str = "paste";
+
+    /// The bytes sent for `text`: the markers around the text with `\n`
+    /// turned into `\r`, and any end marker inside the text removed so the
+    /// paste cannot end early.
+    fn payload(text: &str) -> Vec<u8> {
+        let body = text.replace("\x1b[201~", "").replace("\r\n", "\r");
+        let mut data = PASTE_START.to_vec();
+        data.extend(body.bytes().map(|b| if b == b'\n' { b'\r' } else { b }));
+        data.extend_from_slice(PASTE_END);
+        data
+    }
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for Paste {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        Ok(Self {
+            text: parse_quoted_string(args)?,
+        })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let text = ctx.interpolate(&self.text)?;
+        ctx.write_to_pty(&Self::payload(&text))?;
+        // Give the program a moment to process the input.
+        sleep(ctx.behavior().input_settle()).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        let cmd = Paste::parse(r#""line1\nline2""#).unwrap();
+        assert_eq!(cmd.text, "line1\nline2");
+        assert!(Paste::parse("unquoted").is_err());
+    }
+
+    #[test]
+    fn test_payload() {
+        assert_eq!(
+            Paste::payload("a\nb\r\nc"),
+            b"\x1b[200~a\rb\rc\x1b[201~".to_vec()
+        );
+        assert_eq!(
+            Paste::payload("x\x1b[201~y"),
+            b"\x1b[200~xy\x1b[201~".to_vec()
+        );
+    }
+}
@@ -0,0 +1,75 @@
+//! [`Repeat`] block — runs a list of commands a fixed number of times.
+//!
+//! Script syntax:
+//!
+//! ```text
+//! repeat 5
+//!   key Down
+//!   wait 100ms
+//! end
+//! ```
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::ScriptReader;
+use anyhow::{Context as _, Result, anyhow};
+use async_trait::async_trait;
+
+/// Runs `commands` in order, `count` times.
+pub struct Repeat {
+    pub count: u32,
+    pub commands: Vec<Box<dyn ScripttyCommand>>,
+}
+
+impl Repeat {
+    pub const NAME: &'static str = "repeat";
+
+    /// Parse the repeat count and the block body up to its closing `end`.
+    pub(crate) fn parse_block(
+        args: &str,
+        reader: &mut ScriptReader,
+    ) -> Result<Box<dyn ScripttyCommand>> {
+        let count = args.trim().parse().context("Invalid repeat count")?;
+        let (commands, _) = reader.parse_block(&["end"])?;
+        Ok(Box::new(Self { count, commands }))
+    }
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for Repeat {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(_args: &str) -> Result<Self> {
+        Err(anyhow!(
+            "'repeat' starts a block and must be followed by 'end'"
+        ))
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        for _ in 0..self.count {
+            for cmd in &self.commands {
+                cmd.execute(ctx).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_str;
+
+    #[test]
+    fn test_parse() {
+        let cmds = parse_str("repeat 3\nkey Down\nend\n").unwrap();
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0].name(), "repeat");
+    }
+
+    #[test]
+    fn test_parse_invalid_count() {
+        assert!(parse_str("repeat many\nkey Down\nend\n").is_err());
+        assert!(parse_str("repeat -1\nkey Down\nend\n").is_err());
+    }
+}
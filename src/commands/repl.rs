@@ -0,0 +1,351 @@
+//! Helpers for interactive interpreters: [`UseProfile`], [`ExpectPrompt`], and
+//! [`Eval`].
+//!
+//! Script syntax:
+//!
+//! ```text
+//! @profile python
+//! expect_prompt
+//! eval "import json"
+//! eval <<PY
+//! for i in range(3):
+//!     print(i)
+//! PY
+//! show "${EVAL_OUTPUT}"
+//! ```
+//!
+//! The prompts and statement conventions come from the [`Repl`] chosen with
+//! `@profile` (`python`, `node`, `psql`, `mysql`, or `gdb`).
+
+use super::type_text::type_chars;
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::{parse_duration, split_quoted};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use regex::Regex;
+use std::time::Duration;
+
+/// Interactive interpreters with known prompts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repl {
+    /// CPython: `>>> `, `... ` inside blocks, which an empty line closes.
+    Python,
+    /// Node.js: `> `, `... ` until the statement is complete. Run it with
+    /// `NODE_NO_READLINE=1`; its line editor redraws the prompt on every key.
+    Node,
+    /// PostgreSQL: `db=# `, `db-# ` until the statement ends with `;`.
+    Psql,
+    /// MySQL and MariaDB: `mysql> `, `    -> ` until the statement ends with `;`.
+    Mysql,
+    /// GDB: `(gdb) `, `>` inside `commands`/`define` blocks.
+    Gdb,
+}
+
+/// Prompts and input conventions of a [`Repl`].
+struct Profile {
+    /// Regex (without capture groups) for the primary prompt.
+    prompt: &'static str,
+    /// Regex (without capture groups) for the prompt shown while a statement
+    /// is incomplete.
+    continuation: &'static str,
+    /// Appended to the last line of `eval` when missing; meta-commands
+    /// starting with `\` are left alone.
+    statement_end: Option<&'static str>,
+    /// Whether an empty line finishes a statement left open after the last
+    /// line (Python's indented blocks).
+    empty_line_closes: bool,
+}
+
+/// Escape sequences (colors, cursor moves) allowed in front of a prompt.
+const ANSI_PREFIX: &str = r"(?:\x1b\[[0-9;?]*[A-Za-z])*";
+
+impl Repl {
+    /// Parse a profile name as used with `@profile`.
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "python" => Ok(Self::Python),
+            "node" => Ok(Self::Node),
+            "psql" => Ok(Self::Psql),
+            "mysql" => Ok(Self::Mysql),
+            "gdb" => Ok(Self::Gdb),
+            _ => Err(anyhow!(
+                "Unknown profile '{}', expected python, node, psql, mysql, or gdb",
+                name
+            )),
+        }
+    }
+
+    fn profile(self) -> Profile {
+        match self {
+            Self::Python => Profile {
+                prompt: r">>> ",
+                continuation: r"\.\.\. ",
+                statement_end: None,
+                empty_line_closes: true,
+            },
+            Self::Node => Profile {
+                prompt: r"> ",
+                continuation: r"\.\.\. ",
+                statement_end: None,
+                empty_line_closes: false,
+            },
+            Self::Psql => Profile {
+                prompt: r"[^\s=]*=[#>] ",
+                continuation: r#"[^\s=]*[-'"(][#>] "#,
+                statement_end: Some(";"),
+                empty_line_closes: false,
+            },
+            Self::Mysql => Profile {
+                prompt: r"(?:mysql|MariaDB \[[^\]\n]*\])> ",
+                continuation: r#" *(?:->|'>|">|`>|/\*>) "#,
+                statement_end: Some(";"),
+                empty_line_closes: false,
+            },
+            Self::Gdb => Profile {
+                prompt: r"\(gdb\) ",
+                continuation: r">",
+                statement_end: None,
+                empty_line_closes: false,
+            },
+        }
+    }
+
+    /// Regex matching the primary prompt at the start of a line.
+    pub fn prompt(self) -> Regex {
+        line_start(self.profile().prompt)
+    }
+
+    /// Regex for the first prompt at the start of a line in the output:
+    /// group 1 is the output before it, group 2 matches a primary prompt and
+    /// group 3 a continuation prompt.
+    fn any_prompt(self) -> Regex {
+        let profile = self.profile();
+        Regex::new(&format!(
+            r"(?ms)\A(.*?)^{}(?:({})|({}))",
+            ANSI_PREFIX, profile.prompt, profile.continuation
+        ))
+        .expect("profile prompts are valid regexes")
+    }
+}
+
+fn line_start(pattern: &str) -> Regex {
+    Regex::new(&format!(r"(?m)^{}(?:{})", ANSI_PREFIX, pattern))
+        .expect("profile prompts are valid regexes")
+}
+
+/// The profile chosen with `@profile`, or an error naming `command`.
+fn current(ctx: &Context, command: &str) -> Result<Repl> {
+    ctx.repl.ok_or_else(|| {
+        anyhow!(
+            "{} needs a REPL profile; add '@profile NAME' first",
+            command
+        )
+    })
+}
+
+/// Selects the REPL profile used by `expect_prompt` and `eval`.
+pub struct UseProfile {
+    pub repl: Repl,
+}
+
+impl UseProfile {
+    pub const NAME: &'static str = "@profile";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for UseProfile {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        Ok(Self {
+            repl: Repl::from_name(args.trim())?,
+        })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        ctx.repl = Some(self.repl);
+        Ok(())
+    }
+}
+
+/// Waits for the profile's primary prompt, e.g. once after starting the REPL.
+///
+/// `expect_prompt` or `expect_prompt 30s`.
+pub struct ExpectPrompt {
+    /// `None` uses the script's default timeout.
+    pub timeout: Option<Duration>,
+}
+
+impl ExpectPrompt {
+    pub const NAME: &'static str = "expect_prompt";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for ExpectPrompt {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let timeout = match args.trim() {
+            "" => None,
+            value => Some(parse_duration(value)?),
+        };
+        Ok(Self { timeout })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let repl = current(ctx, Self::NAME)?;
+        let timeout = ctx.timeout_or_default(self.timeout);
+        ctx.wait_for_regex(&repl.prompt(), timeout).await?;
+        Ok(())
+    }
+}
+
+/// Types a statement into the REPL line by line and waits for the primary
+/// prompt that follows it.
+///
+/// After each line the command waits for either prompt. If the REPL still
+/// shows its continuation prompt after the last line, Python gets the empty
+/// line that closes the block; for the other profiles the statement is
+/// incomplete and the command fails. With `psql` and `mysql`, a missing `;`
+/// is added to the last line.
+///
+/// What the REPL printed after the last line, up to the prompt, is stored in
+/// the `EVAL_OUTPUT` variable.
+///
+/// `eval "print(1)"` or `eval "SELECT 1" 30s`; multi-line code fits a heredoc.
+pub struct Eval {
+    pub code: String,
+    /// Per-line timeout; `None` uses the script's default timeout.
+    pub timeout: Option<Duration>,
+}
+
+impl Eval {
+    pub const NAME: &'static str = "eval";
+
+    /// Variable holding the output of the last line.
+    pub const OUTPUT_VAR: &'static str = "EVAL_OUTPUT";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for Eval {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let (code, rest) = split_quoted(args)?;
+        let timeout = match rest {
+            "" => None,
+            rest => Some(parse_duration(rest)?),
+        };
+        Ok(Self { code, timeout })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let repl = current(ctx, Self::NAME)?;
+        let profile = repl.profile();
+        let timeout = ctx.timeout_or_default(self.timeout);
+        let any_prompt = repl.any_prompt();
+        let lines: Vec<&str> = match self.code.lines().collect::<Vec<_>>() {
+            lines if lines.is_empty() => vec![""],
+            lines => lines,
+        };
+        for (i, raw) in lines.iter().enumerate() {
+            let last = i + 1 == lines.len();
+            let mut line = ctx.interpolate(raw)?;
+            if let Some(end) = profile.statement_end {
+                let trimmed = line.trim_end();
+                if last && !trimmed.ends_with(end) && !trimmed.trim_start().starts_with('\\') {
+                    line.push_str(end);
+                }
+            }
+            type_chars(ctx, &line, None).await?;
+            // Skip the echo of the line so output can't be mistaken for it.
+            ctx.skip_echo(&line).await;
+            ctx.write_to_pty(b"\r")?;
+
+            let mut groups = ctx.wait_for_regex(&any_prompt, timeout).await?;
+            if !last {
+                continue;
+            }
+            if groups[3].is_some() && profile.empty_line_closes {
+                ctx.write_to_pty(b"\r")?;
+                groups = ctx.wait_for_regex(&any_prompt, timeout).await?;
+            }
+            if groups[3].is_some() {
+                return Err(anyhow!(
+                    "The statement is incomplete: the REPL still waits for more input"
+                ));
+            }
+            let output = groups[1].as_deref().unwrap_or_default();
+            let output = output.trim_matches(['\r', '\n']).replace("\r\n", "\n");
+            ctx.set_var(Self::OUTPUT_VAR, output);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    const ALL: &[Repl] = &[Repl::Python, Repl::Node, Repl::Psql, Repl::Mysql, Repl::Gdb];
+
+    fn prompt_kind(repl: Repl, output: &str) -> Option<&'static str> {
+        let caps = repl.any_prompt().captures(output)?;
+        Some(if caps.get(2).is_some() {
+            "prompt"
+        } else {
+            "continuation"
+        })
+    }
+
+    #[test]
+    fn test_profiles_compile() {
+        for repl in ALL {
+            repl.prompt();
+            repl.any_prompt();
+        }
+    }
+
+    #[test]
+    fn test_prompts() {
+        assert_eq!(prompt_kind(Repl::Python, "4\r\n>>> "), Some("prompt"));
+        assert_eq!(
+            prompt_kind(Repl::Python, "\x1b[1;35m... \x1b[0m"),
+            Some("continuation")
+        );
+        assert_eq!(prompt_kind(Repl::Python, "x >>> y"), None);
+        assert_eq!(prompt_kind(Repl::Psql, "app=# "), Some("prompt"));
+        assert_eq!(prompt_kind(Repl::Psql, "app(# "), Some("continuation"));
+        assert_eq!(prompt_kind(Repl::Mysql, "MariaDB [app]> "), Some("prompt"));
+        assert_eq!(prompt_kind(Repl::Mysql, "    -> "), Some("continuation"));
+        assert_eq!(prompt_kind(Repl::Gdb, "(gdb) "), Some("prompt"));
+    }
+
+    #[test]
+    fn test_output_before_prompt() {
+        let caps = Repl::Python
+            .any_prompt()
+            .captures("\r\n0\r\n>>> 1\r\n>>> ")
+            .unwrap();
+        assert_eq!(&caps[1], "\r\n0\r\n");
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(UseProfile::parse("node").unwrap().repl, Repl::Node);
+        assert!(UseProfile::parse("ruby").is_err());
+        let eval = Eval::parse(r#""SELECT 1" 30s"#).unwrap();
+        assert_eq!(eval.code, "SELECT 1");
+        assert_eq!(eval.timeout, Some(Duration::from_secs(30)));
+        assert!(Eval::parse("SELECT 1").is_err());
+        assert_eq!(ExpectPrompt::parse("").unwrap().timeout, None);
+        assert!(ExpectPrompt::parse("soon").is_err());
+    }
+}
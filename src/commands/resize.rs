@@ -0,0 +1,63 @@
+//! [`Resize`] command — changes the terminal size mid-script.
+//!
+//! Script syntax:
+//! - `resize 40 120` — 40 rows by 120 columns
+
+use crate::command::{Context, ScripttyCommand};
+use anyhow::{Context as _, Result, anyhow};
+use async_trait::async_trait;
+
+/// Resizes the PTY and sends SIGWINCH, so demos can show a TUI reflowing
+/// its layout. The new size also applies after a `respawn`.
+pub struct Resize {
+    pub rows: u16,
+    pub cols: u16,
+}
+
+impl Resize {
+    pub const NAME: &'static str = "resize";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for Resize {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let words: Vec<&str> = args.split_whitespace().collect();
+        let [rows, cols] = words[..] else {
+            return Err(anyhow!("Expected 'resize ROWS COLS', got: {}", args.trim()));
+        };
+        let rows: u16 = rows
+            .parse()
+            .with_context(|| format!("Invalid row count: {}", rows))?;
+        let cols: u16 = cols
+            .parse()
+            .with_context(|| format!("Invalid column count: {}", cols))?;
+        if rows == 0 || cols == 0 {
+            return Err(anyhow!("The terminal needs at least one row and column"));
+        }
+        Ok(Self { rows, cols })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        ctx.resize(self.rows, self.cols)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        let cmd = Resize::parse("40 120").unwrap();
+        assert_eq!((cmd.rows, cmd.cols), (40, 120));
+        assert!(Resize::parse("40").is_err());
+        assert!(Resize::parse("40 120 1").is_err());
+        assert!(Resize::parse("0 120").is_err());
+        assert!(Resize::parse("40 wide").is_err());
+    }
+}
@@ -0,0 +1,74 @@
+//! [`Respawn`] command — restarts the program under test.
+//!
+//! Script syntax:
+//! - `respawn` — kill the program and start it again
+//! - `respawn snapshot before` — same, keeping the old session's output as snapshot `before`
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::parse_identifier;
+use anyhow::{Context as _, Result, anyhow};
+use async_trait::async_trait;
+
+/// Kills the program and starts it again with the same command line,
+/// environment, and working directory.
+///
+/// Pending output is discarded. With `snapshot`, the old session's full
+/// output is kept under that label so `assert_snapshot` can check it after
+/// the restart, e.g. to compare what the program showed before and after.
+pub struct Respawn {
+    pub snapshot: Option<String>,
+}
+
+impl Respawn {
+    pub const NAME: &'static str = "respawn";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for Respawn {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let args = args.trim();
+        if args.is_empty() {
+            return Ok(Self { snapshot: None });
+        }
+        let label = args
+            .strip_prefix("snapshot ")
+            .ok_or_else(|| anyhow!("Expected 'snapshot LABEL' after 'respawn'"))?;
+        let label = parse_identifier(label).context("Invalid snapshot label")?;
+        Ok(Self {
+            snapshot: Some(label.to_string()),
+        })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        ctx.respawn(self.snapshot.as_deref()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Respawn::parse("").unwrap().snapshot, None);
+        assert_eq!(
+            Respawn::parse("snapshot before")
+                .unwrap()
+                .snapshot
+                .as_deref(),
+            Some("before")
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Respawn::parse("before").is_err());
+        assert!(Respawn::parse("snapshot 1st").is_err());
+        assert!(Respawn::parse("snapshot").is_err());
+    }
+}
@@ -0,0 +1,103 @@
+//! [`Screenshot`] command — saves the current terminal screen as an image.
+//!
+//! Script syntax: `screenshot "docs/step4.svg"` or `screenshot "docs/step4.png"`
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::parse_quoted_string;
+use anyhow::{Context as _, Result, anyhow};
+use async_trait::async_trait;
+use std::path::Path;
+
+/// Renders the emulated terminal screen to an SVG or PNG image, so one
+/// script run can produce still images for documentation next to the
+/// recorded session.
+///
+/// The image shows what a terminal would display at this point: text with
+/// its colors and attributes, and the cursor. The file extension picks the
+/// format. SVG text is drawn in the viewer's monospace font; PNG uses a
+/// built-in bitmap font covering Latin-1. Relative paths are resolved
+/// against scriptty's working directory; `${NAME}` variables are expanded
+/// when the command runs.
+pub struct Screenshot {
+    pub path: String,
+}
+
+impl Screenshot {
+    pub const NAME: &'static str = "screenshot";
+}
+
+/// Image formats a screenshot can be saved as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Svg,
+    Png,
+}
+
+/// The format named by the extension of `path`.
+fn format_of(path: &str) -> Result<Format> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("svg") => Ok(Format::Svg),
+        Some("png") => Ok(Format::Png),
+        _ => Err(anyhow!(
+            "Screenshots are saved as SVG or PNG; use a .svg or .png file name: {}",
+            path
+        )),
+    }
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for Screenshot {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let path = parse_quoted_string(args)?;
+        format_of(&path)?;
+        Ok(Self { path })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let path = ctx.interpolate(&self.path)?;
+        let image = match format_of(&path)? {
+            Format::Svg => crate::render::render_svg(&ctx.screen()).into_bytes(),
+            Format::Png => crate::render::render_png(&ctx.screen()),
+        };
+        std::fs::write(&path, image)
+            .with_context(|| format!("Failed to write screenshot: {}", path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Screenshot::parse(r#""docs/step4.svg""#).unwrap().path,
+            "docs/step4.svg"
+        );
+        assert!(Screenshot::parse(r#""step4.SVG""#).is_ok());
+        assert!(Screenshot::parse(r#""step4.png""#).is_ok());
+        assert!(Screenshot::parse(r#""step4.jpg""#).is_err());
+        assert!(Screenshot::parse("step4.svg").is_err());
+    }
+
+    #[test]
+    fn test_png() {
+        let mut parser = vt100::Parser::new(2, 4, 0);
+        parser.process(b"\x1b[41mhi");
+        let png = crate::render::render_png(parser.screen());
+        let (width, height, rgba) = crate::png::decode(&png).unwrap();
+        assert_eq!((width, height), (4 * 8 + 16, 2 * 16 + 16));
+        // The first cell has a red background.
+        let i = ((8 * width + 8) * 4) as usize;
+        assert_eq!(&rgba[i..i + 3], &[0xcd, 0, 0]);
+    }
+}
@@ -6,10 +6,11 @@ use crate::command::{Context, ScripttyCommand};
 use crate::parser::parse_quoted_string;
 use anyhow::Result;
 use async_trait::async_trait;
-use std::time::Duration;
 use tokio::time::sleep;
 
 /// Sends bytes to the program's stdin immediately without any visible output.
+///
+/// `${NAME}` variables are expanded when the command runs.
 pub struct SendInput {
     pub data: Vec<u8>,
 }
@@ -36,9 +37,10 @@ impl ScripttyCommand for SendInput {
     }
 
     async fn execute(&self, ctx: &mut Context) -> Result<()> {
-        ctx.write_to_pty(&self.data)?;
+        let data = ctx.interpolate_bytes(&self.data)?;
+        ctx.write_to_pty(&data)?;
         // Give the program a moment to process the input.
-        sleep(Duration::from_millis(50)).await;
+        sleep(ctx.behavior().input_settle()).await;
         Ok(())
     }
 }
@@ -0,0 +1,84 @@
+//! [`Set`] command — assigns a script variable.
+//!
+//! Script syntax: `set NAME "value"`
+
+use crate::command::{Context, ScripttyCommand};
+use crate::commands::Setting;
+use crate::parser::{parse_identifier, parse_quoted_string};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+
+/// Stores `value` in the variable `name` for later `${NAME}` interpolation.
+///
+/// The value itself may reference other variables; they are expanded when the
+/// command runs, so `set URL "https://${HOST}/"` captures the current `HOST`.
+pub struct Set {
+    pub variable: String,
+    pub value: String,
+}
+
+impl Set {
+    pub const NAME: &'static str = "set";
+
+    /// Create a `Set` command.
+    pub fn new(variable: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            variable: variable.into(),
+            value: value.into(),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for Set {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let (name, value) = args
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| anyhow!("set command requires a name and a quoted value"))?;
+        Ok(Self::new(
+            parse_identifier(name)?,
+            parse_quoted_string(value)?,
+        ))
+    }
+
+    /// Dispatch `set` lines naming a reserved setting (e.g. `default_timeout`)
+    /// to [`Setting`]; everything else assigns a variable.
+    fn parse_boxed(args: &str) -> Result<Box<dyn ScripttyCommand>> {
+        let name = args.split_whitespace().next().unwrap_or_default();
+        if Setting::is_setting(name) {
+            return Setting::parse_boxed(args);
+        }
+        Ok(Box::new(Self::parse(args)?))
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let value = ctx.interpolate(&self.value)?;
+        ctx.set_var(self.variable.clone(), value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        let cmd = Set::parse(r#"HOST "db1.example.com""#).unwrap();
+        assert_eq!(cmd.variable, "HOST");
+        assert_eq!(cmd.value, "db1.example.com");
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Set::parse("HOST").is_err());
+        assert!(Set::parse(r#"1HOST "x""#).is_err());
+        assert!(Set::parse("HOST unquoted").is_err());
+    }
+}
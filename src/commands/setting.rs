@@ -0,0 +1,262 @@
+//! [`Setting`] command — changes script-wide defaults for later commands.
+//!
+//! Script syntax:
+//! - `set default_timeout 10s` — timeout for `expect`-style commands that don't give one
+//! - `set typing_speed 20ms..60ms` — per-character delay range for `type`
+//! - `set typo_rate 3%` — chance that `type` mistypes a letter or digit and corrects it
+//! - `set plain_expect on` — ignore ANSI escape sequences when matching output
+//! - `set key_delay 100ms` — pause between the presses of one `key` command
+//! - `set key_table linux` — key escape sequences for this `TERM` instead of the program's
+//! - `set vendor cisco` — device CLI used by `enable`, `config_mode`, and `save_config`
+//!   (with the `network` feature)
+//! - `set crash_loop 3 10s` — refuse to `respawn` after 3 exits within 10 seconds
+//! - `set crash_loop off` — always `respawn`
+//! - `set behavior 0.1` — keep the defaults of the 0.1 releases after upgrading
+//!
+//! Settings share the `set` keyword with variables; their names are reserved.
+
+use crate::behavior::Behavior;
+use crate::command::{Context, ScripttyCommand};
+use crate::commands::KeyTable;
+use crate::parser::parse_duration;
+use crate::supervisor::CrashLoopLimit;
+use anyhow::{Context as _, Result, anyhow};
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// A change to a script-wide default, applied when the command runs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Setting {
+    /// Timeout for `expect`, `expect_any`, `expect_bell`,
+    /// `expect_notification`, `capture`, and `if`/`while` conditions that
+    /// don't specify their own.
+    DefaultTimeout(Duration),
+    /// Minimum and maximum per-character delay for `type`.
+    TypingSpeed(Duration, Duration),
+    /// Probability (0 to 1) that `type` mistypes a character and corrects it.
+    TypoRate(f64),
+    /// Whether pattern matching ignores ANSI escape sequences in the output.
+    PlainExpect(bool),
+    /// Pause between the presses of one `key` command.
+    KeyDelay(Duration),
+    /// Escape sequences for terminal-dependent keys.
+    KeyTable(KeyTable),
+    /// Device CLI for the network helper commands.
+    #[cfg(feature = "network")]
+    Vendor(crate::commands::Vendor),
+    /// Crash-loop limit for `respawn`; `None` disables it.
+    CrashLoop(Option<CrashLoopLimit>),
+    /// Profile of defaults to keep.
+    Behavior(Behavior),
+}
+
+impl Setting {
+    pub const NAME: &'static str = "set";
+
+    /// Names reserved for settings; they cannot be used as variable names with `set`.
+    pub const NAMES: &'static [&'static str] = &[
+        "default_timeout",
+        "typing_speed",
+        "typo_rate",
+        "plain_expect",
+        "key_delay",
+        "key_table",
+        "vendor",
+        "crash_loop",
+        "behavior",
+    ];
+
+    /// Whether `name` refers to a setting rather than a variable.
+    pub fn is_setting(name: &str) -> bool {
+        Self::NAMES.contains(&name)
+    }
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for Setting {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let args = args.trim();
+        let (name, value) = args.split_once(' ').unwrap_or((args, ""));
+        let value = value.trim();
+        match name {
+            "default_timeout" => Ok(Self::DefaultTimeout(parse_duration(value)?)),
+            "typing_speed" => {
+                let (min, max) = value.split_once("..").ok_or_else(|| {
+                    anyhow!("Expected 'set typing_speed MIN..MAX', got: {}", value)
+                })?;
+                let (min, max) = (parse_duration(min.trim())?, parse_duration(max.trim())?);
+                if min > max {
+                    return Err(anyhow!("typing_speed minimum exceeds maximum: {}", value));
+                }
+                Ok(Self::TypingSpeed(min, max))
+            }
+            "typo_rate" => {
+                let rate = match value.strip_suffix('%') {
+                    Some(percent) => percent.trim().parse::<f64>().map(|p| p / 100.0),
+                    None => value.parse::<f64>(),
+                }
+                .with_context(|| format!("Invalid typo_rate: {}", value))?;
+                if !(0.0..=1.0).contains(&rate) {
+                    return Err(anyhow!("typo_rate must be between 0% and 100%: {}", value));
+                }
+                Ok(Self::TypoRate(rate))
+            }
+            "plain_expect" => match value {
+                "on" => Ok(Self::PlainExpect(true)),
+                "off" => Ok(Self::PlainExpect(false)),
+                _ => Err(anyhow!(
+                    "Expected 'set plain_expect on|off', got: {}",
+                    value
+                )),
+            },
+            "key_delay" => Ok(Self::KeyDelay(parse_duration(value)?)),
+            "key_table" if value.is_empty() => {
+                Err(anyhow!("Expected a TERM value after key_table"))
+            }
+            "key_table" => Ok(Self::KeyTable(KeyTable::for_term(value))),
+            #[cfg(feature = "network")]
+            "vendor" => Ok(Self::Vendor(crate::commands::Vendor::from_name(value)?)),
+            "crash_loop" if value == "off" => Ok(Self::CrashLoop(None)),
+            "crash_loop" => {
+                let (count, window) = value.split_once(' ').ok_or_else(|| {
+                    anyhow!("Expected 'set crash_loop COUNT WINDOW', got: {}", value)
+                })?;
+                let max_exits = count.parse().context("Invalid crash_loop count")?;
+                if max_exits == 0 {
+                    return Err(anyhow!("crash_loop count must be at least 1"));
+                }
+                Ok(Self::CrashLoop(Some(CrashLoopLimit {
+                    max_exits,
+                    window: parse_duration(window.trim())?,
+                })))
+            }
+            "behavior" => Ok(Self::Behavior(value.parse()?)),
+            _ => Err(anyhow!("Unknown setting: {}", name)),
+        }
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        match self {
+            Self::DefaultTimeout(timeout) => ctx.default_timeout = *timeout,
+            Self::TypingSpeed(min, max) => ctx.typing_delay = (*min, *max),
+            Self::TypoRate(rate) => ctx.typo_rate = *rate,
+            Self::PlainExpect(plain) => ctx.plain_expect = *plain,
+            Self::KeyDelay(delay) => ctx.key_delay = *delay,
+            Self::KeyTable(table) => ctx.key_table = *table,
+            #[cfg(feature = "network")]
+            Self::Vendor(vendor) => ctx.vendor = Some(*vendor),
+            Self::CrashLoop(limit) => ctx.supervisor.set_limit(*limit),
+            Self::Behavior(behavior) => *ctx.behavior.lock().unwrap() = *behavior,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse_default_timeout() {
+        assert_eq!(
+            Setting::parse("default_timeout 10s").unwrap(),
+            Setting::DefaultTimeout(Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn test_parse_typing_speed() {
+        assert_eq!(
+            Setting::parse("typing_speed 20ms..60ms").unwrap(),
+            Setting::TypingSpeed(Duration::from_millis(20), Duration::from_millis(60))
+        );
+        assert!(Setting::parse("typing_speed 60ms..20ms").is_err());
+        assert!(Setting::parse("typing_speed 20ms").is_err());
+    }
+
+    #[test]
+    fn test_parse_typo_rate() {
+        assert_eq!(
+            Setting::parse("typo_rate 3%").unwrap(),
+            Setting::TypoRate(0.03)
+        );
+        assert_eq!(
+            Setting::parse("typo_rate 0.5").unwrap(),
+            Setting::TypoRate(0.5)
+        );
+        assert!(Setting::parse("typo_rate 150%").is_err());
+        assert!(Setting::parse("typo_rate often").is_err());
+    }
+
+    #[test]
+    fn test_parse_plain_expect() {
+        assert_eq!(
+            Setting::parse("plain_expect on").unwrap(),
+            Setting::PlainExpect(true)
+        );
+        assert_eq!(
+            Setting::parse("plain_expect off").unwrap(),
+            Setting::PlainExpect(false)
+        );
+        assert!(Setting::parse("plain_expect yes").is_err());
+    }
+
+    #[test]
+    fn test_parse_key_delay() {
+        assert_eq!(
+            Setting::parse("key_delay 100ms").unwrap(),
+            Setting::KeyDelay(Duration::from_millis(100))
+        );
+    }
+
+    #[test]
+    fn test_parse_key_table() {
+        assert_eq!(
+            Setting::parse("key_table rxvt-unicode").unwrap(),
+            Setting::KeyTable(KeyTable::Rxvt)
+        );
+        assert!(Setting::parse("key_table").is_err());
+    }
+
+    #[test]
+    fn test_parse_crash_loop() {
+        assert_eq!(
+            Setting::parse("crash_loop 3 10s").unwrap(),
+            Setting::CrashLoop(Some(CrashLoopLimit {
+                max_exits: 3,
+                window: Duration::from_secs(10)
+            }))
+        );
+        assert_eq!(
+            Setting::parse("crash_loop off").unwrap(),
+            Setting::CrashLoop(None)
+        );
+        assert!(Setting::parse("crash_loop 0 10s").is_err());
+        assert!(Setting::parse("crash_loop 3").is_err());
+    }
+
+    #[test]
+    fn test_parse_behavior() {
+        assert_eq!(
+            Setting::parse("behavior 0.1").unwrap(),
+            Setting::Behavior(Behavior::V0_1)
+        );
+        assert_eq!(
+            Setting::parse("behavior 0.2").unwrap(),
+            Setting::Behavior(Behavior::V0_2)
+        );
+        assert!(Setting::parse("behavior 1.0").is_err());
+    }
+
+    #[test]
+    fn test_set_dispatch() {
+        let cmds = crate::parse_str("set default_timeout 1s\nset HOST \"db1\"\n").unwrap();
+        assert_eq!(cmds.len(), 2);
+        assert!(crate::parse_str("set default_timeout \"1s\"\n").is_err());
+    }
+}
@@ -10,6 +10,7 @@ use async_trait::async_trait;
 /// Writes text directly to the output handler without sending anything to the program.
 ///
 /// Useful for inserting annotations or commentary into the output stream.
+/// `${NAME}` variables are expanded when the command runs.
 pub struct Show {
     pub data: Vec<u8>,
 }
@@ -17,12 +18,13 @@ pub struct Show {
 impl Show {
     pub const NAME: &'static str = "show";
 
-    /// Create a `Show` command from a string. A newline is appended automatically.
+    /// Create a `Show` command from a string. A newline is appended when the
+    /// command runs (see [`Behavior::auto_newline`]).
+    ///
+    /// [`Behavior::auto_newline`]: crate::Behavior::auto_newline
     pub fn new(text: impl Into<String>) -> Self {
-        let mut t = text.into();
-        t.push('\n');
         Self {
-            data: t.into_bytes(),
+            data: text.into().into_bytes(),
         }
     }
 }
@@ -38,7 +40,11 @@ impl ScripttyCommand for Show {
     }
 
     async fn execute(&self, ctx: &mut Context) -> Result<()> {
-        ctx.emit(&self.data);
+        let mut data = ctx.interpolate_bytes(&self.data)?.into_owned();
+        if ctx.behavior().auto_newline() {
+            data.push(b'\n');
+        }
+        ctx.emit(&data);
         Ok(())
     }
 }
@@ -51,9 +57,7 @@ mod tests {
     #[test]
     fn test_parse() {
         let cmd = Show::parse(r#""hello world""#).unwrap();
-        let text = String::from_utf8_lossy(&cmd.data);
-        assert!(text.contains("hello world"));
-        assert!(text.ends_with('\n'));
+        assert_eq!(cmd.data, b"hello world");
     }
 
     #[test]
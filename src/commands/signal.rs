@@ -0,0 +1,90 @@
+//! [`SendSignal`] command — delivers a POSIX signal to the program.
+//!
+//! Script syntax:
+//! - `signal INT` — like Ctrl+C, but independent of the terminal's settings
+//! - `signal SIGTSTP` — the `SIG` prefix is optional
+
+use crate::command::{Context, ScripttyCommand};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+
+/// Signals that can be sent by name.
+const SIGNALS: &[(&str, libc::c_int)] = &[
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("CHLD", libc::SIGCHLD),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("TSTP", libc::SIGTSTP),
+    ("TTIN", libc::SIGTTIN),
+    ("TTOU", libc::SIGTTOU),
+    ("WINCH", libc::SIGWINCH),
+];
+
+/// The number of the signal called `name` (`INT` or `SIGINT`).
+pub(crate) fn signal_number(name: &str) -> Result<libc::c_int> {
+    let short = name.strip_prefix("SIG").unwrap_or(name);
+    SIGNALS
+        .iter()
+        .find(|(signal, _)| *signal == short)
+        .map(|(_, number)| *number)
+        .ok_or_else(|| anyhow!("Unsupported signal: {}", name))
+}
+
+/// Sends a signal to the spawned process, e.g. to demonstrate Ctrl-C
+/// handling or job control without relying on the terminal's key bindings.
+///
+/// The signal goes to the process scriptty started, not to processes that
+/// process started in turn (such as a shell's foreground job).
+pub struct SendSignal {
+    pub signal: String,
+    number: libc::c_int,
+}
+
+impl SendSignal {
+    pub const NAME: &'static str = "signal";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for SendSignal {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let signal = args.trim();
+        if signal.is_empty() {
+            return Err(anyhow!("signal command requires a signal name"));
+        }
+        Ok(Self {
+            number: signal_number(signal)?,
+            signal: signal.to_string(),
+        })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        ctx.pty.signal(self.number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(SendSignal::parse("INT").unwrap().number, libc::SIGINT);
+        assert_eq!(SendSignal::parse("SIGTSTP").unwrap().number, libc::SIGTSTP);
+        assert!(SendSignal::parse("").is_err());
+        assert!(SendSignal::parse("SEGV").is_err());
+        assert!(SendSignal::parse("int").is_err());
+    }
+}
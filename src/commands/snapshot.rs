@@ -0,0 +1,126 @@
+//! [`Snapshot`] command — golden-screen testing.
+//!
+//! Script syntax: `snapshot "main_menu"`
+//!
+//! Snapshots are stored as `<dir>/<name>.txt` (`snapshots/` unless changed
+//! with `--snapshot-dir`). A normal run compares the screen against the
+//! stored file; with `--update-snapshots` (or
+//! [`SCRIPTTY_UPDATE_SNAPSHOTS`](crate::testing::UPDATE_ENV)) the file is
+//! written instead. The comparison is the one [`crate::testing::Golden`]
+//! does for golden files.
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::parse_quoted_string;
+use crate::testing::{Golden, UPDATE_ENV};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use std::path::{Component, Path, PathBuf};
+
+/// What `snapshot` does with the rendered screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SnapshotMode {
+    /// Fail unless the screen matches the stored snapshot file.
+    #[default]
+    Check,
+    /// Write the screen to the snapshot file, replacing the stored one.
+    Record,
+}
+
+impl SnapshotMode {
+    /// [`SnapshotMode::Record`] if [`UPDATE_ENV`] is set to anything but
+    /// `0`, [`SnapshotMode::Check`] otherwise.
+    pub fn from_env() -> Self {
+        match std::env::var(UPDATE_ENV) {
+            Ok(value) if value != "0" => Self::Record,
+            _ => Self::Check,
+        }
+    }
+}
+
+/// Saves the rendered terminal screen as a golden snapshot, or compares the
+/// screen against one (see [`SnapshotMode`]).
+///
+/// The snapshot is the screen's text, one line per row with trailing blanks
+/// removed; colors and the cursor are not part of it. Take it once the
+/// program has finished drawing, e.g. after `expect_screen` or `expect_idle`.
+/// A mismatch fails with a row-by-row diff.
+pub struct Snapshot {
+    pub name: String,
+}
+
+impl Snapshot {
+    pub const NAME: &'static str = "snapshot";
+}
+
+/// Check that `name` is a relative path inside the snapshot directory.
+fn check_name(name: &str) -> Result<()> {
+    let path = Path::new(name);
+    let inside = path.components().all(|c| matches!(c, Component::Normal(_)));
+    if name.is_empty() || !inside {
+        return Err(anyhow!(
+            "Invalid snapshot name, expected a relative path without '..': '{}'",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// The screen's rows with trailing blanks removed, one per line.
+fn screen_text(screen: &vt100::Screen) -> String {
+    let (_, cols) = screen.size();
+    screen
+        .rows(0, cols)
+        .map(|row| format!("{}\n", row.trim_end()))
+        .collect()
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for Snapshot {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let name = parse_quoted_string(args)?;
+        check_name(&name)?;
+        Ok(Self { name })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let name = ctx.interpolate(&self.name)?;
+        check_name(&name)?;
+        let path: PathBuf = ctx.snapshot_dir.join(format!("{}.txt", name));
+        let actual = screen_text(&ctx.screen());
+        Golden::new().compare(
+            &actual,
+            &path,
+            ctx.snapshot_mode,
+            &format!("snapshot '{}' ({})", name, path.display()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Snapshot::parse(r#""main_menu""#).unwrap().name, "main_menu");
+        assert!(Snapshot::parse(r#""settings/dialog""#).is_ok());
+        assert!(Snapshot::parse(r#""../outside""#).is_err());
+        assert!(Snapshot::parse(r#""/tmp/x""#).is_err());
+        assert!(Snapshot::parse(r#""""#).is_err());
+        assert!(Snapshot::parse("main_menu").is_err());
+    }
+
+    #[test]
+    fn test_screen_text() {
+        let mut parser = vt100::Parser::new(3, 10, 0);
+        parser.process(b"\x1b[1mMenu\x1b[0m   \r\n> Open");
+        let text = screen_text(parser.screen());
+        assert_eq!(text, "Menu\n> Open\n\n");
+        assert_eq!(Golden::new().normalized(&text), text);
+    }
+}
@@ -0,0 +1,162 @@
+//! [`Sudo`] command — runs a shell command with `sudo`, answering the password prompt.
+//!
+//! Script syntax:
+//! - `sudo "apt install jq"` — run and wait for the shell prompt to come back
+//! - `sudo "apt install jq" 60s` — the same with a custom timeout
+//! - `sudo "apt install jq" until "done" 60s` — wait for `done` instead of the prompt
+//!
+//! The password is read from the `SUDO_PASSWORD` variable, e.g. set with
+//! `-D SUDO_PASSWORD=...` or `set SUDO_PASSWORD "${env:DEPLOY_PW}"`, and is
+//! masked wherever the run is recorded.
+
+use super::type_text::type_chars;
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::{parse_duration, split_quoted};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// Prompts `sudo` shows when it needs the password.
+const PASSWORD_PROMPTS: &[&str] = &["[sudo] password for", "Password:"];
+
+/// Messages `sudo` prints when the password or the user is rejected.
+const FAILURES: &[&str] = &[
+    "Sorry, try again",
+    "incorrect password attempt",
+    "is not in the sudoers file",
+    "is not allowed to execute",
+];
+
+/// Types `sudo <command>`, presses Enter, supplies `SUDO_PASSWORD` if sudo
+/// asks for it, and fails if sudo rejects the password or the user.
+///
+/// The command succeeds once the shell prompt shown before it (read from the
+/// screen) appears again, i.e. the command finished, or once the `until`
+/// pattern appears. It fails if neither shows up within the timeout.
+pub struct Sudo {
+    pub command: String,
+    /// Pattern showing the command finished; `None` waits for the prompt.
+    pub until: Option<String>,
+    /// `None` uses the script's default timeout.
+    pub timeout: Option<Duration>,
+}
+
+impl Sudo {
+    pub const NAME: &'static str = "sudo";
+
+    /// Variable holding the password.
+    pub const PASSWORD_VAR: &'static str = "SUDO_PASSWORD";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for Sudo {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let (command, rest) = split_quoted(args)?;
+        let (until, rest) = match rest.strip_prefix("until") {
+            Some(rest) => {
+                let (until, rest) = split_quoted(rest)?;
+                (Some(until), rest)
+            }
+            None => (None, rest),
+        };
+        let timeout = match rest {
+            "" => None,
+            rest => Some(parse_duration(rest)?),
+        };
+        Ok(Self {
+            command,
+            until,
+            timeout,
+        })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let until = match &self.until {
+            Some(pattern) => ctx.interpolate(pattern)?,
+            None => shell_prompt(ctx).ok_or_else(|| {
+                anyhow!("sudo found no shell prompt to wait for; add 'until \"pattern\"'")
+            })?,
+        };
+        let line = ctx.interpolate(&format!("sudo {}", self.command))?;
+        type_chars(ctx, &line, None).await?;
+        // Skip the echo of the command line so its text can't match `until`.
+        ctx.skip_echo(&line).await;
+        ctx.write_to_pty(b"\r")?;
+
+        // The prompt is read from the screen, so match it without escapes.
+        let plain = self.until.is_none() || ctx.plain_expect;
+        let timeout = ctx.timeout_or_default(self.timeout);
+        let mut answered = false;
+        loop {
+            let mut targets: Vec<&str> = FAILURES.to_vec();
+            targets.push(&until);
+            if !answered {
+                targets.extend(PASSWORD_PROMPTS);
+            }
+            let matched = match ctx.wait_for_any_in(&targets, timeout, plain).await {
+                Ok(i) => targets[i],
+                Err(e) => return Err(e.context("sudo command did not finish")),
+            };
+            if FAILURES.contains(&matched) {
+                return Err(anyhow!("sudo failed: '{}'", matched));
+            }
+            if PASSWORD_PROMPTS.contains(&matched) {
+                let password = ctx.var(Self::PASSWORD_VAR).ok_or_else(|| {
+                    anyhow!(
+                        "sudo asked for a password but {} is not set",
+                        Self::PASSWORD_VAR
+                    )
+                })?;
+                ctx.register_secret(password);
+                let mut input = password.as_bytes().to_vec();
+                input.push(b'\r');
+                ctx.write_to_pty(&input)?;
+                answered = true;
+                continue;
+            }
+            return Ok(());
+        }
+    }
+}
+
+/// The shell prompt on the cursor's line, before the cursor, or `None` when
+/// the cursor is at the start of an empty line.
+fn shell_prompt(ctx: &Context) -> Option<String> {
+    let screen = ctx.screen();
+    let (row, col) = screen.cursor_position();
+    let prompt = screen.contents_between(row, 0, row, col);
+    let prompt = prompt.trim();
+    (!prompt.is_empty()).then(|| prompt.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        let cmd = Sudo::parse(r#""apt install jq""#).unwrap();
+        assert_eq!(cmd.command, "apt install jq");
+        assert_eq!(cmd.until, None);
+
+        let cmd = Sudo::parse(r#""apt install jq" until "$ " 60s"#).unwrap();
+        assert_eq!(cmd.until.as_deref(), Some("$ "));
+        assert_eq!(cmd.timeout, Some(Duration::from_secs(60)));
+
+        let cmd = Sudo::parse(r#""apt install jq" 60s"#).unwrap();
+        assert_eq!(cmd.until, None);
+        assert_eq!(cmd.timeout, Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Sudo::parse("apt install jq").is_err());
+        assert!(Sudo::parse(r#""ls" soon"#).is_err());
+        assert!(Sudo::parse(r#""ls" until"#).is_err());
+    }
+}
@@ -0,0 +1,224 @@
+//! Narration sync: [`SyncAudio`] and [`Marker`].
+//!
+//! Script syntax:
+//! - `sync_audio "narration.json"` — load marker timestamps
+//! - `marker "install"` — this point of the script lines up with the
+//!   narration's `install` marker
+//!
+//! The narration file lists where each marker falls in the audio, in
+//! seconds or as `MM:SS` / `HH:MM:SS`:
+//!
+//! ```json
+//! {"markers": [{"name": "intro", "time": 0}, {"name": "install", "time": "00:12.5"}]}
+//! ```
+
+use super::at::parse_offset;
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::parse_quoted_string;
+use anyhow::{Context as _, Result, anyhow};
+use async_trait::async_trait;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Marker timestamps loaded from a narration file, measured from the start
+/// of the run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Narration {
+    markers: HashMap<String, Duration>,
+    /// How far the run is behind the narration; later `wait`s are shortened
+    /// by this much.
+    behind: Duration,
+}
+
+impl Narration {
+    /// Parse a narration file's JSON.
+    pub(crate) fn from_json(json: &str) -> Result<Self> {
+        let value: Value = serde_json::from_str(json).context("Invalid narration JSON")?;
+        let entries = value
+            .get("markers")
+            .and_then(Value::as_array)
+            .ok_or_else(|| anyhow!("Narration file must have a \"markers\" array"))?;
+        let mut markers = HashMap::new();
+        for entry in entries {
+            let name = entry
+                .get("name")
+                .and_then(Value::as_str)
+                .ok_or_else(|| anyhow!("Narration marker without a \"name\": {}", entry))?;
+            let time = match entry.get("time") {
+                Some(Value::Number(seconds)) => seconds
+                    .as_f64()
+                    .and_then(|s| Duration::try_from_secs_f64(s).ok()),
+                Some(Value::String(timestamp)) => Some(parse_offset(timestamp)?),
+                _ => None,
+            }
+            .ok_or_else(|| anyhow!("Narration marker '{}' has no valid \"time\"", name))?;
+            if markers.insert(name.to_string(), time).is_some() {
+                return Err(anyhow!("Duplicate narration marker: {}", name));
+            }
+        }
+        Ok(Self {
+            markers,
+            behind: Duration::ZERO,
+        })
+    }
+
+    /// Where `name` falls in the narration.
+    pub(crate) fn marker(&self, name: &str) -> Option<Duration> {
+        self.markers.get(name).copied()
+    }
+
+    /// Take as much of `pause` as is needed to catch up with the narration,
+    /// and return what is left of it.
+    pub(crate) fn compress(&mut self, pause: Duration) -> Duration {
+        let skipped = pause.min(self.behind);
+        self.behind -= skipped;
+        pause - skipped
+    }
+}
+
+/// Loads a narration file so [`Marker`] commands can keep the run in step
+/// with a voice-over.
+///
+/// Relative paths are resolved against scriptty's working directory;
+/// `${NAME}` variables are expanded when the command runs.
+pub struct SyncAudio {
+    pub path: String,
+}
+
+impl SyncAudio {
+    pub const NAME: &'static str = "sync_audio";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for SyncAudio {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        Ok(Self {
+            path: parse_quoted_string(args)?,
+        })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let path = ctx.interpolate(&self.path)?;
+        let json = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read narration file: {}", path))?;
+        ctx.narration = Some(
+            Narration::from_json(&json)
+                .with_context(|| format!("Failed to load narration file: {}", path))?,
+        );
+        Ok(())
+    }
+}
+
+/// Lines this point of the script up with a narration marker.
+///
+/// When the run is ahead of the narration, the marker waits until the
+/// marker's timestamp (measured from the start of the run, like
+/// [`crate::commands::At`]). When it is behind, the following `wait`
+/// commands are shortened until the run has caught up.
+///
+/// Without an earlier [`SyncAudio`], a marker only names a point of the
+/// script; every marker reached is also reported to
+/// [`Engine::on_marker`](crate::Engine::on_marker) and kept in
+/// [`Engine::transcript`](crate::Engine::transcript).
+pub struct Marker {
+    pub name: String,
+}
+
+impl Marker {
+    pub const NAME: &'static str = "marker";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for Marker {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        Ok(Self {
+            name: parse_quoted_string(args)?,
+        })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        ctx.sinks.lock().unwrap().log.marker(&self.name);
+        if let Some(handler) = ctx.marker_handler.as_mut() {
+            handler(&self.name);
+        }
+        let elapsed = ctx.elapsed();
+        let Some(narration) = ctx.narration.as_mut() else {
+            return Ok(());
+        };
+        let at = narration
+            .marker(&self.name)
+            .ok_or_else(|| anyhow!("Narration has no marker named '{}'", self.name))?;
+        narration.behind = elapsed.saturating_sub(at);
+        if let Some(ahead) = at.checked_sub(elapsed) {
+            tokio::time::sleep(ahead).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            SyncAudio::parse(r#""narration.json""#).unwrap().path,
+            "narration.json"
+        );
+        assert_eq!(Marker::parse(r#""install""#).unwrap().name, "install");
+        assert!(Marker::parse("install").is_err());
+    }
+
+    #[test]
+    fn test_narration_from_json() {
+        let narration = Narration::from_json(
+            r#"{"markers": [{"name": "intro", "time": 1.5}, {"name": "install", "time": "01:02"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(narration.marker("intro"), Some(Duration::from_millis(1500)));
+        assert_eq!(narration.marker("install"), Some(Duration::from_secs(62)));
+        assert_eq!(narration.marker("outro"), None);
+
+        assert!(Narration::from_json(r#"{"intro": 1}"#).is_err());
+        assert!(Narration::from_json(r#"{"markers": [{"name": "a"}]}"#).is_err());
+        assert!(Narration::from_json(r#"{"markers": [{"name": "a", "time": -1}]}"#).is_err());
+        assert!(Narration::from_json(r#"{"markers": [{"name": "a", "time": 1e300}]}"#).is_err());
+        assert!(
+            Narration::from_json(
+                r#"{"markers": [{"name": "a", "time": 1}, {"name": "a", "time": 2}]}"#
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_compress() {
+        let mut narration = Narration {
+            behind: Duration::from_millis(700),
+            ..Narration::default()
+        };
+        assert_eq!(
+            narration.compress(Duration::from_millis(500)),
+            Duration::ZERO
+        );
+        assert_eq!(
+            narration.compress(Duration::from_millis(500)),
+            Duration::from_millis(300)
+        );
+        assert_eq!(
+            narration.compress(Duration::from_millis(500)),
+            Duration::from_millis(500)
+        );
+    }
+}
@@ -0,0 +1,258 @@
+//! [`TypeEdit`] command — types a line, then corrects it with line-editor keys.
+//!
+//! Script syntax: `type_edit "TEXT" EDIT...`, where each edit is one of
+//! - `fix POS "TEXT"` — insert TEXT after the first POS characters
+//! - `delete POS [COUNT]` — remove COUNT characters (default 1) starting at POS
+//! - `replace POS "TEXT"` — overwrite characters starting at POS with TEXT
+//!
+//! `type_edit "git sttus" fix 6 "a"` types `git sttus`, moves back to insert
+//! the missing `a`, and leaves the cursor at the end of `git status`.
+
+use super::type_text::type_chars;
+use crate::command::{Context, ScripttyCommand};
+use crate::commands::Key;
+use crate::parser::split_quoted;
+use anyhow::{Context as _, Result, anyhow};
+use async_trait::async_trait;
+
+/// A correction applied to the typed line. Positions count characters from
+/// the start of the line as it reads after the previous edits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Edit {
+    Fix(usize, String),
+    Delete(usize, usize),
+    Replace(usize, String),
+}
+
+/// One step of the keystroke plan.
+#[derive(Debug, PartialEq, Eq)]
+enum Step {
+    /// Move the cursor by this many characters (negative is left).
+    Move(isize),
+    Backspace(usize),
+    Type(String),
+}
+
+/// Types a line and then fixes it the way a person would in a readline-style
+/// editor: arrow keys to the mistake, Backspace and retyping, and arrows back
+/// to the end of the line.
+///
+/// Text is typed at the script's typing speed and keys are pressed with the
+/// script's key delay, so the "oops" moment looks the same on every run.
+/// `${NAME}` variables are expanded in all texts when the command runs.
+pub struct TypeEdit {
+    pub text: String,
+    pub edits: Vec<Edit>,
+}
+
+impl TypeEdit {
+    pub const NAME: &'static str = "type_edit";
+}
+
+/// Plan the keystrokes that type `text` and apply `edits`, checking that
+/// every position lies within the line.
+fn plan(text: &str, edits: &[Edit]) -> Result<Vec<Step>> {
+    let mut line: Vec<char> = text.chars().collect();
+    let mut cursor = line.len();
+    let mut steps = vec![Step::Type(text.to_string())];
+    let move_to = |steps: &mut Vec<Step>, cursor: &mut usize, target: usize| {
+        if target != *cursor {
+            steps.push(Step::Move(target as isize - *cursor as isize));
+            *cursor = target;
+        }
+    };
+    for edit in edits {
+        let (pos, count, insert) = match edit {
+            Edit::Fix(pos, text) => (*pos, 0, text.as_str()),
+            Edit::Delete(pos, count) => (*pos, *count, ""),
+            Edit::Replace(pos, text) => (*pos, text.chars().count(), text.as_str()),
+        };
+        if pos + count > line.len() {
+            return Err(anyhow!(
+                "Edit at {} reaches past the end of '{}'",
+                pos,
+                line.iter().collect::<String>()
+            ));
+        }
+        move_to(&mut steps, &mut cursor, pos + count);
+        if count > 0 {
+            steps.push(Step::Backspace(count));
+            line.drain(pos..pos + count);
+            cursor = pos;
+        }
+        if !insert.is_empty() {
+            steps.push(Step::Type(insert.to_string()));
+            line.splice(pos..pos, insert.chars());
+            cursor = pos + insert.chars().count();
+        }
+    }
+    move_to(&mut steps, &mut cursor, line.len());
+    Ok(steps)
+}
+
+fn parse_position(word: Option<&str>) -> Result<usize> {
+    let word = word.ok_or_else(|| anyhow!("Expected a position"))?;
+    word.parse()
+        .with_context(|| format!("Invalid position: {}", word))
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for TypeEdit {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let (text, mut rest) = split_quoted(args)?;
+        let mut edits = Vec::new();
+        while !rest.is_empty() {
+            let (verb, after) = rest.split_once(' ').unwrap_or((rest, ""));
+            let (pos, after) = after.trim_start().split_once(' ').unwrap_or((after, ""));
+            let pos = parse_position(Some(pos).filter(|p| !p.is_empty()))
+                .with_context(|| format!("In '{}' edit", verb))?;
+            let after = after.trim_start();
+            rest = match verb {
+                "fix" | "replace" => {
+                    let (insert, after) = split_quoted(after)?;
+                    if insert.is_empty() {
+                        return Err(anyhow!("'{}' needs a non-empty text", verb));
+                    }
+                    edits.push(match verb {
+                        "fix" => Edit::Fix(pos, insert),
+                        _ => Edit::Replace(pos, insert),
+                    });
+                    after
+                }
+                "delete" => {
+                    // The count is optional, so the next word may start another edit.
+                    let (word, tail) = after.split_once(' ').unwrap_or((after, ""));
+                    match word.parse::<usize>() {
+                        Ok(0) => return Err(anyhow!("'delete' count must be at least 1")),
+                        Ok(count) => {
+                            edits.push(Edit::Delete(pos, count));
+                            tail.trim_start()
+                        }
+                        Err(_) => {
+                            edits.push(Edit::Delete(pos, 1));
+                            after
+                        }
+                    }
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "Unknown edit '{}', expected fix, delete, or replace",
+                        verb
+                    ));
+                }
+            };
+        }
+        if edits.is_empty() {
+            return Err(anyhow!(
+                "type_edit needs at least one edit; use 'type' otherwise"
+            ));
+        }
+        // Positions can only be checked now if no variable changes the lengths.
+        if !args.contains("${") {
+            plan(&text, &edits)?;
+        }
+        Ok(Self { text, edits })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let text = ctx.interpolate(&self.text)?;
+        let edits = self
+            .edits
+            .iter()
+            .map(|edit| {
+                Ok(match edit {
+                    Edit::Fix(pos, text) => Edit::Fix(*pos, ctx.interpolate(text)?),
+                    Edit::Replace(pos, text) => Edit::Replace(*pos, ctx.interpolate(text)?),
+                    Edit::Delete(pos, count) => Edit::Delete(*pos, *count),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for step in plan(&text, &edits)? {
+            let (key, count) = match step {
+                Step::Type(text) => {
+                    type_chars(ctx, &text, None).await?;
+                    continue;
+                }
+                Step::Move(n) if n < 0 => ("Left", n.unsigned_abs()),
+                Step::Move(n) => ("Right", n as usize),
+                Step::Backspace(n) => ("Backspace", n),
+            };
+            let bytes = key.parse::<Key>()?.bytes(ctx.key_table())?;
+            for _ in 0..count {
+                tokio::time::sleep(ctx.pace(ctx.scale_timeout(ctx.key_delay()))).await;
+                ctx.write_to_pty(&bytes)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        let cmd = TypeEdit::parse(r#""git sttus" fix 6 "a""#).unwrap();
+        assert_eq!(cmd.text, "git sttus");
+        assert_eq!(cmd.edits, vec![Edit::Fix(6, "a".into())]);
+        let cmd = TypeEdit::parse(r#""lss -la" delete 2 replace 3 "-l" delete 0 1"#).unwrap();
+        assert_eq!(
+            cmd.edits,
+            vec![
+                Edit::Delete(2, 1),
+                Edit::Replace(3, "-l".into()),
+                Edit::Delete(0, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(TypeEdit::parse(r#""ls""#).is_err());
+        assert!(TypeEdit::parse(r#""ls" fix"#).is_err());
+        assert!(TypeEdit::parse(r#""ls" fix 1"#).is_err());
+        assert!(TypeEdit::parse(r#""ls" fix 3 "x""#).is_err());
+        assert!(TypeEdit::parse(r#""ls" delete 2"#).is_err());
+        assert!(TypeEdit::parse(r#""ls" delete 0 0"#).is_err());
+        assert!(TypeEdit::parse(r#""ls" undo 1"#).is_err());
+        assert!(TypeEdit::parse(r#""${CMD}" fix 9 "x""#).is_ok());
+    }
+
+    #[test]
+    fn test_plan_fix() {
+        assert_eq!(
+            plan("git sttus", &[Edit::Fix(6, "a".into())]).unwrap(),
+            vec![
+                Step::Type("git sttus".into()),
+                Step::Move(-3),
+                Step::Type("a".into()),
+                Step::Move(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_delete_and_replace() {
+        assert_eq!(
+            plan(
+                "lss -la",
+                &[Edit::Delete(2, 1), Edit::Replace(4, "lh".into())]
+            )
+            .unwrap(),
+            vec![
+                Step::Type("lss -la".into()),
+                Step::Move(-4),
+                Step::Backspace(1),
+                Step::Move(4),
+                Step::Backspace(2),
+                Step::Type("lh".into()),
+            ]
+        );
+    }
+}
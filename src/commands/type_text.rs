@@ -1,6 +1,9 @@
 //! [`TypeText`] command — simulates human typing character by character.
 //!
 //! Script syntax: `type "text here"`
+//!
+//! With `set typo_rate 3%`, `type` now and then hits a neighbouring key,
+//! backspaces, and types the right character.
 
 use crate::command::{Context, ScripttyCommand};
 use crate::parser::parse_quoted_string;
@@ -14,27 +17,39 @@ use tokio::time::sleep;
 /// with random per-character delays.
 ///
 /// The PTY's own echo produces the visible output, so each character appears
-/// exactly once regardless of the delay.
+/// exactly once regardless of the delay. `${NAME}` variables are expanded when
+/// the command runs.
+///
+/// When the script sets a typo rate, each letter or digit is mistyped with
+/// that probability: a neighbouring key on a QWERTY keyboard is sent first,
+/// then Backspace, then the intended character. The choices come from the
+/// engine's random number generator, so a fixed seed reproduces them.
+///
+/// Build one with the constructors; fields may be added in minor releases.
+#[non_exhaustive]
 pub struct TypeText {
     pub text: String,
-    pub min_delay: Duration,
-    pub max_delay: Duration,
+    /// Per-character delay range; `None` uses the script's typing speed.
+    pub timing: Option<(Duration, Duration)>,
 }
 
 impl TypeText {
     pub const NAME: &'static str = "type";
 
-    /// Create a `TypeText` command with default timing (50–150 ms per character).
+    /// Create a `TypeText` command with the script's typing speed (50–150 ms
+    /// per character unless changed with `set typing_speed`).
     pub fn new(text: impl Into<String>) -> Self {
-        Self::with_timing(text, Duration::from_millis(50), Duration::from_millis(150))
+        Self {
+            text: text.into(),
+            timing: None,
+        }
     }
 
     /// Create a `TypeText` command with custom per-character timing.
     pub fn with_timing(text: impl Into<String>, min_delay: Duration, max_delay: Duration) -> Self {
         Self {
             text: text.into(),
-            min_delay,
-            max_delay,
+            timing: Some((min_delay, max_delay)),
         }
     }
 }
@@ -50,18 +65,91 @@ impl ScripttyCommand for TypeText {
     }
 
     async fn execute(&self, ctx: &mut Context) -> Result<()> {
-        for ch in self.text.chars() {
-            ctx.write_to_pty(ch.to_string().as_bytes())?;
-            // Drop rng before the await so it does not cross the yield point.
-            let delay_ms = {
-                let mut rng = rand::thread_rng();
-                rng.gen_range(self.min_delay.as_millis()..=self.max_delay.as_millis())
-            };
-            sleep(Duration::from_millis(delay_ms as u64)).await;
+        let text = ctx.interpolate(&self.text)?;
+        let typo_rate = ctx.typo_rate;
+        type_with_typos(ctx, &text, self.timing, typo_rate).await
+    }
+}
+
+/// QWERTY keyboard rows, used to pick a plausible wrong key.
+const KEYBOARD_ROWS: &[&str] = &["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
+
+/// A key next to `ch` in its keyboard row, in the same case, or `None` for
+/// characters that are not ASCII letters or digits.
+fn neighbour(ch: char, rng: &mut impl Rng) -> Option<char> {
+    let lower = ch.to_ascii_lowercase();
+    let row: Vec<char> = KEYBOARD_ROWS
+        .iter()
+        .find(|r| r.contains(lower))?
+        .chars()
+        .collect();
+    let i = row.iter().position(|&c| c == lower)?;
+    let left = i.checked_sub(1).map(|j| row[j]);
+    let right = row.get(i + 1).copied();
+    let wrong = match (left, right) {
+        (Some(l), Some(r)) => {
+            if rng.gen_bool(0.5) {
+                l
+            } else {
+                r
+            }
         }
+        (Some(c), None) | (None, Some(c)) => c,
+        (None, None) => return None,
+    };
+    Some(if ch.is_ascii_uppercase() {
+        wrong.to_ascii_uppercase()
+    } else {
+        wrong
+    })
+}
 
-        Ok(())
+/// Type already-interpolated `text` one character at a time, with `timing`
+/// or the script's typing speed (adjusted in fuzz mode).
+///
+/// Helpers that wait for the echo of what they typed use this rather than
+/// [`TypeText`], so they never make typos.
+pub(crate) async fn type_chars(
+    ctx: &mut Context,
+    text: &str,
+    timing: Option<(Duration, Duration)>,
+) -> Result<()> {
+    type_with_typos(ctx, text, timing, 0.0).await
+}
+
+async fn type_with_typos(
+    ctx: &mut Context,
+    text: &str,
+    timing: Option<(Duration, Duration)>,
+    typo_rate: f64,
+) -> Result<()> {
+    let (mut min_delay, mut max_delay) = timing.unwrap_or(ctx.typing_delay());
+    if let Some((slowest, fastest)) = ctx.fuzz().map(|f| f.typing_speed) {
+        let speed = ctx.rng().gen_range(slowest..=fastest);
+        min_delay = min_delay.div_f64(speed);
+        max_delay = max_delay.div_f64(speed);
+    }
+    let pause = async |ctx: &mut Context| {
+        let delay_ms = ctx
+            .rng()
+            .gen_range(min_delay.as_millis()..=max_delay.as_millis());
+        sleep(ctx.pace(Duration::from_millis(delay_ms as u64))).await;
+    };
+    for ch in text.chars() {
+        if typo_rate > 0.0
+            && ctx.rng().gen_bool(typo_rate)
+            && let Some(wrong) = neighbour(ch, ctx.rng())
+        {
+            ctx.write_to_pty(wrong.to_string().as_bytes())?;
+            pause(ctx).await;
+            ctx.write_to_pty(b"\x7f")?;
+            pause(ctx).await;
+        }
+        ctx.write_to_pty(ch.to_string().as_bytes())?;
+        pause(ctx).await;
     }
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -87,17 +175,31 @@ mod tests {
         assert_eq!(cmd.text, "line1\nline2");
     }
 
+    #[test]
+    fn test_neighbour() {
+        use rand::SeedableRng;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        for _ in 0..20 {
+            assert!(matches!(neighbour('a', &mut rng), Some('s')));
+            assert!(matches!(neighbour('G', &mut rng), Some('F' | 'H')));
+            assert!(matches!(neighbour('0', &mut rng), Some('9')));
+        }
+        assert_eq!(neighbour(' ', &mut rng), None);
+        assert_eq!(neighbour('é', &mut rng), None);
+    }
+
     #[test]
     fn test_default_timing() {
         let cmd = TypeText::new("hello");
-        assert_eq!(cmd.min_delay, Duration::from_millis(50));
-        assert_eq!(cmd.max_delay, Duration::from_millis(150));
+        assert_eq!(cmd.timing, None);
     }
 
     #[test]
     fn test_custom_timing() {
         let cmd = TypeText::with_timing("hi", Duration::from_millis(10), Duration::from_millis(20));
-        assert_eq!(cmd.min_delay, Duration::from_millis(10));
-        assert_eq!(cmd.max_delay, Duration::from_millis(20));
+        assert_eq!(
+            cmd.timing,
+            Some((Duration::from_millis(10), Duration::from_millis(20)))
+        );
     }
 }
@@ -9,6 +9,9 @@ use async_trait::async_trait;
 use std::time::Duration;
 
 /// Pauses execution for a fixed duration before running the next command.
+///
+/// The duration is subject to the engine's timeout scale, and is shortened
+/// while the run is behind a narration (see [`crate::commands::Marker`]).
 pub struct Wait {
     pub duration: Duration,
 }
@@ -29,8 +32,12 @@ impl ScripttyCommand for Wait {
         })
     }
 
-    async fn execute(&self, _ctx: &mut Context) -> Result<()> {
-        tokio::time::sleep(self.duration).await;
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let mut pause = ctx.pace(ctx.scale_timeout(self.duration));
+        if let Some(narration) = ctx.narration.as_mut() {
+            pause = narration.compress(pause);
+        }
+        tokio::time::sleep(pause).await;
         Ok(())
     }
 }
@@ -0,0 +1,83 @@
+//! [`WaitFile`] command — pauses the script until a file exists.
+//!
+//! Script syntax:
+//! - `wait_file "/tmp/go"` — wait indefinitely
+//! - `wait_file "/tmp/go" 30s` — fail if the file does not appear within 30 seconds
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::{parse_duration, split_quoted};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Pauses execution until `path` exists, letting an external process signal
+/// readiness by creating a file.
+pub struct WaitFile {
+    pub path: PathBuf,
+    pub timeout: Option<Duration>,
+}
+
+impl WaitFile {
+    pub const NAME: &'static str = "wait_file";
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for WaitFile {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let (path, remainder) = split_quoted(args)?;
+        let timeout = if remainder.is_empty() {
+            None
+        } else {
+            Some(parse_duration(remainder)?)
+        };
+        Ok(Self {
+            path: path.into(),
+            timeout,
+        })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let deadline = self
+            .timeout
+            .map(|t| tokio::time::Instant::now() + ctx.scale_timeout(t));
+        while !self.path.exists() {
+            if deadline.is_some_and(|d| tokio::time::Instant::now() >= d) {
+                return Err(anyhow!(
+                    "Timeout waiting for file: '{}'",
+                    self.path.display()
+                ));
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        let cmd = WaitFile::parse(r#""/tmp/go""#).unwrap();
+        assert_eq!(cmd.path, PathBuf::from("/tmp/go"));
+        assert_eq!(cmd.timeout, None);
+    }
+
+    #[test]
+    fn test_parse_timeout() {
+        let cmd = WaitFile::parse(r#""/tmp/go" 30s"#).unwrap();
+        assert_eq!(cmd.timeout, Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_parse_missing_quote() {
+        assert!(WaitFile::parse("/tmp/go").is_err());
+    }
+}
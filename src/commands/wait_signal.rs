@@ -0,0 +1,110 @@
+//! [`WaitSignal`] command — pauses the script until scriptty receives a signal.
+//!
+//! Script syntax:
+//! - `wait_signal USR1` — wait indefinitely
+//! - `wait_signal USR2 30s` — fail if the signal does not arrive within 30 seconds
+
+use crate::command::{Context, ScripttyCommand};
+use crate::parser::parse_duration;
+use anyhow::{Context as _, Result, anyhow};
+use async_trait::async_trait;
+use std::cell::RefCell;
+use std::time::Duration;
+use tokio::signal::unix::{Signal, SignalKind, signal};
+
+/// Pauses execution until the scriptty process receives a POSIX signal, e.g.
+/// `kill -USR1 <pid>` from another tool in the pipeline.
+///
+/// Supported signals are `USR1`, `USR2`, `HUP`, and `ALRM` (the `SIG` prefix
+/// is optional). The signal handler is installed when the command first
+/// runs and kept afterwards, so a signal that arrives while the command
+/// repeats in a loop is not lost. Parsing has no side effects.
+pub struct WaitSignal {
+    pub signal: String,
+    pub timeout: Option<Duration>,
+    kind: SignalKind,
+    listener: RefCell<Option<Signal>>,
+}
+
+impl WaitSignal {
+    pub const NAME: &'static str = "wait_signal";
+}
+
+/// Map a signal name to its [`SignalKind`].
+fn signal_kind(name: &str) -> Result<SignalKind> {
+    match name.strip_prefix("SIG").unwrap_or(name) {
+        "USR1" => Ok(SignalKind::user_defined1()),
+        "USR2" => Ok(SignalKind::user_defined2()),
+        "HUP" => Ok(SignalKind::hangup()),
+        "ALRM" => Ok(SignalKind::alarm()),
+        _ => Err(anyhow!("Unsupported signal: {}", name)),
+    }
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for WaitSignal {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(args: &str) -> Result<Self> {
+        let args = args.trim();
+        let (name, remainder) = args.split_once(' ').unwrap_or((args, ""));
+        if name.is_empty() {
+            return Err(anyhow!("wait_signal command requires a signal name"));
+        }
+        let kind = signal_kind(name)?;
+        let timeout = if remainder.trim().is_empty() {
+            None
+        } else {
+            Some(parse_duration(remainder)?)
+        };
+        Ok(Self {
+            signal: name.strip_prefix("SIG").unwrap_or(name).to_string(),
+            timeout,
+            kind,
+            listener: RefCell::new(None),
+        })
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        let mut listener = match self.listener.take() {
+            Some(listener) => listener,
+            None => signal(self.kind).context("Failed to install signal handler")?,
+        };
+        let received = match self.timeout {
+            Some(timeout) => tokio::time::timeout(ctx.scale_timeout(timeout), listener.recv())
+                .await
+                .map_err(|_| anyhow!("Timeout waiting for signal: {}", self.signal))?,
+            None => listener.recv().await,
+        };
+        self.listener.replace(Some(listener));
+        received.ok_or_else(|| anyhow!("Signal stream closed: {}", self.signal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ScripttyCommand;
+
+    #[test]
+    fn test_parse() {
+        let cmd = WaitSignal::parse("USR1").unwrap();
+        assert_eq!(cmd.signal, "USR1");
+        assert_eq!(cmd.timeout, None);
+    }
+
+    #[test]
+    fn test_parse_prefix_and_timeout() {
+        let cmd = WaitSignal::parse("SIGUSR2 10s").unwrap();
+        assert_eq!(cmd.signal, "USR2");
+        assert_eq!(cmd.timeout, Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_parse_unsupported() {
+        assert!(WaitSignal::parse("KILL").is_err());
+        assert!(WaitSignal::parse("").is_err());
+    }
+}
@@ -0,0 +1,91 @@
+//! [`While`] block — repeats a list of commands as long as a pattern keeps appearing.
+//!
+//! Script syntax:
+//!
+//! ```text
+//! while expect "--More--" 1s
+//!   key Space
+//! end
+//! ```
+
+use crate::command::{Context, ScripttyCommand};
+use crate::commands::Expect;
+use crate::parser::ScriptReader;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+
+/// Waits for the [`Expect`] condition and, each time the pattern appears
+/// before the timeout, runs `commands` and checks again.
+///
+/// The loop ends when the condition times out; that timeout is not an error.
+pub struct While {
+    pub condition: Expect,
+    pub commands: Vec<Box<dyn ScripttyCommand>>,
+}
+
+impl While {
+    pub const NAME: &'static str = "while";
+
+    /// Parse the condition and the block body up to its closing `end`.
+    pub(crate) fn parse_block(
+        args: &str,
+        reader: &mut ScriptReader,
+    ) -> Result<Box<dyn ScripttyCommand>> {
+        let condition = args
+            .trim()
+            .strip_prefix("expect ")
+            .ok_or_else(|| anyhow!("Expected 'while expect \"pattern\" [timeout]'"))?;
+        let condition = Expect::parse(condition)?;
+        let (commands, _) = reader.parse_block(&["end"])?;
+        Ok(Box::new(Self {
+            condition,
+            commands,
+        }))
+    }
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for While {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn parse(_args: &str) -> Result<Self> {
+        Err(anyhow!(
+            "'while' starts a block and must be followed by 'end'"
+        ))
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        loop {
+            let pattern = ctx.interpolate(&self.condition.pattern)?;
+            if ctx
+                .wait_for_pattern(&pattern, ctx.timeout_or_default(self.condition.timeout))
+                .await
+                .is_err()
+            {
+                return Ok(());
+            }
+            for cmd in &self.commands {
+                cmd.execute(ctx).await?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_str;
+
+    #[test]
+    fn test_parse() {
+        let cmds = parse_str("while expect \"--More--\" 1s\nkey Space\nend\n").unwrap();
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(cmds[0].name(), "while");
+    }
+
+    #[test]
+    fn test_parse_requires_expect() {
+        assert!(parse_str("while true\nkey Space\nend\n").is_err());
+    }
+}
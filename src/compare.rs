@@ -0,0 +1,358 @@
+//! Comparing the event logs of two runs.
+//!
+//! When a script passes on one machine and fails on another, the question is
+//! where the two runs started to behave differently. [`compare`] lines up the
+//! `command` events of two [event logs](crate::events) and reports commands
+//! that only one run got to, commands that failed in only one run, commands
+//! during which the program printed something else, and commands whose
+//! duration differs markedly.
+//!
+//! Output is compared without escape sequences and carriage returns, so
+//! differences in colors or line endings alone are not reported.
+
+use anyhow::{Context as _, Result, anyhow};
+use serde_json::Value;
+use std::fmt;
+use std::time::Duration;
+
+/// Durations closer than this are never reported as a timing difference.
+const MIN_TIMING_DELTA: Duration = Duration::from_millis(100);
+
+/// A duration at least this many times the other is a timing difference.
+const MIN_TIMING_RATIO: f64 = 1.5;
+
+/// One command of a run, read from a `command` event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandRecord {
+    /// Position among the run's commands, from 0.
+    pub index: usize,
+    pub command: String,
+    pub duration: Duration,
+    pub output: String,
+    pub error: Option<String>,
+}
+
+/// Read the `command` events of an event log; other events are skipped.
+///
+/// # Errors
+///
+/// Returns an error if a line is not JSON or a `command` event lacks a field.
+pub fn parse_log(text: &str) -> Result<Vec<CommandRecord>> {
+    let mut records = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event: Value =
+            serde_json::from_str(line).with_context(|| format!("Line {}: invalid JSON", i + 1))?;
+        if event["event"] != "command" {
+            continue;
+        }
+        let field = |name: &str| {
+            event
+                .get(name)
+                .ok_or_else(|| anyhow!("Line {}: command event without \"{}\"", i + 1, name))
+        };
+        let number = |name: &str| {
+            field(name)?
+                .as_f64()
+                .filter(|n| *n >= 0.0)
+                .ok_or_else(|| anyhow!("Line {}: \"{}\" must be a number", i + 1, name))
+        };
+        records.push(CommandRecord {
+            index: number("index")? as usize,
+            command: field("command")?.as_str().unwrap_or_default().to_string(),
+            duration: Duration::try_from_secs_f64(number("duration")?)
+                .map_err(|_| anyhow!("Line {}: \"duration\" is out of range", i + 1))?,
+            output: field("output")?.as_str().unwrap_or_default().to_string(),
+            error: field("error")?.as_str().map(str::to_string),
+        });
+    }
+    Ok(records)
+}
+
+/// A difference between two runs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Difference {
+    /// A command only the first run executed.
+    OnlyInA(CommandRecord),
+    /// A command only the second run executed.
+    OnlyInB(CommandRecord),
+    /// The same command failed in one run (or differently in both).
+    Outcome { a: CommandRecord, b: CommandRecord },
+    /// The program printed different output during the same command.
+    Output {
+        a: CommandRecord,
+        b: CommandRecord,
+        /// Output lines only in the first run.
+        removed: Vec<String>,
+        /// Output lines only in the second run.
+        added: Vec<String>,
+    },
+    /// The same command took markedly longer in one run.
+    Timing { a: CommandRecord, b: CommandRecord },
+}
+
+/// The differences between two runs, in command order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunComparison {
+    pub differences: Vec<Difference>,
+}
+
+impl RunComparison {
+    /// Whether the runs behaved the same.
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+impl fmt::Display for RunComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for difference in &self.differences {
+            match difference {
+                Difference::OnlyInA(a) => {
+                    writeln!(f, "A#{} {}: only in A", a.index, a.command)?;
+                }
+                Difference::OnlyInB(b) => {
+                    writeln!(f, "B#{} {}: only in B", b.index, b.command)?;
+                }
+                Difference::Outcome { a, b } => {
+                    writeln!(f, "{}: outcome differs", label(a, b))?;
+                    writeln!(f, "  A: {}", a.error.as_deref().unwrap_or("ok"))?;
+                    writeln!(f, "  B: {}", b.error.as_deref().unwrap_or("ok"))?;
+                }
+                Difference::Output {
+                    a,
+                    b,
+                    removed,
+                    added,
+                } => {
+                    writeln!(f, "{}: output differs", label(a, b))?;
+                    for line in removed {
+                        writeln!(f, "  - {}", line)?;
+                    }
+                    for line in added {
+                        writeln!(f, "  + {}", line)?;
+                    }
+                }
+                Difference::Timing { a, b } => {
+                    let delta = b.duration.as_millis() as i128 - a.duration.as_millis() as i128;
+                    writeln!(
+                        f,
+                        "{}: {}ms in A, {}ms in B ({:+}ms)",
+                        label(a, b),
+                        a.duration.as_millis(),
+                        b.duration.as_millis(),
+                        delta
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a pair of matched commands is referred to.
+fn label(a: &CommandRecord, b: &CommandRecord) -> String {
+    if a.index == b.index {
+        format!("#{} {}", a.index, a.command)
+    } else {
+        format!("A#{}/B#{} {}", a.index, b.index, a.command)
+    }
+}
+
+/// Line up the commands of runs `a` and `b` by name and report how they
+/// differ.
+///
+/// Commands are matched along the longest common subsequence of command
+/// names, so a command one run skipped does not shift every later pair.
+pub fn compare(a: &[CommandRecord], b: &[CommandRecord]) -> RunComparison {
+    let mut differences = Vec::new();
+    for pair in align(a, b) {
+        match pair {
+            (Some(a), None) => differences.push(Difference::OnlyInA(a.clone())),
+            (None, Some(b)) => differences.push(Difference::OnlyInB(b.clone())),
+            (Some(a), Some(b)) => differences.extend(compare_pair(a, b)),
+            (None, None) => {}
+        }
+    }
+    RunComparison { differences }
+}
+
+/// The differences between two matched commands.
+fn compare_pair(a: &CommandRecord, b: &CommandRecord) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    if a.error != b.error {
+        differences.push(Difference::Outcome {
+            a: a.clone(),
+            b: b.clone(),
+        });
+    }
+    let a_lines = plain_lines(&a.output);
+    let b_lines = plain_lines(&b.output);
+    if a_lines != b_lines {
+        differences.push(Difference::Output {
+            a: a.clone(),
+            b: b.clone(),
+            removed: a_lines
+                .iter()
+                .filter(|line| !b_lines.contains(line))
+                .cloned()
+                .collect(),
+            added: b_lines
+                .iter()
+                .filter(|line| !a_lines.contains(line))
+                .cloned()
+                .collect(),
+        });
+    }
+    let (shorter, longer) = if a.duration <= b.duration {
+        (a.duration, b.duration)
+    } else {
+        (b.duration, a.duration)
+    };
+    if longer - shorter >= MIN_TIMING_DELTA
+        && longer.as_secs_f64() >= shorter.as_secs_f64() * MIN_TIMING_RATIO
+    {
+        differences.push(Difference::Timing {
+            a: a.clone(),
+            b: b.clone(),
+        });
+    }
+    differences
+}
+
+/// The non-empty lines of `output`, without escape sequences and trailing
+/// blanks.
+pub(crate) fn plain_lines(output: &str) -> Vec<String> {
+    crate::ansi::strip(output)
+        .0
+        .split(['\n', '\r'])
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Pair up the commands of `a` and `b` along the longest common subsequence
+/// of their names; unmatched commands are paired with `None`.
+fn align<'a>(
+    a: &'a [CommandRecord],
+    b: &'a [CommandRecord],
+) -> Vec<(Option<&'a CommandRecord>, Option<&'a CommandRecord>)> {
+    // lcs[i][j]: length of the LCS of a[i..] and b[j..].
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i].command == b[j].command {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut pairs = Vec::new();
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i].command == b[j].command {
+            pairs.push((Some(&a[i]), Some(&b[j])));
+            i += 1;
+            j += 1;
+        } else if j == b.len() || (i < a.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            pairs.push((Some(&a[i]), None));
+            i += 1;
+        } else {
+            pairs.push((None, Some(&b[j])));
+            j += 1;
+        }
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(index: usize, command: &str, ms: u64, output: &str) -> CommandRecord {
+        CommandRecord {
+            index,
+            command: command.to_string(),
+            duration: Duration::from_millis(ms),
+            output: output.to_string(),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_log() {
+        let log = concat!(
+            r#"{"event":"command","index":0,"command":"expect","start":0.1,"duration":0.25,"output":"$ ","error":null}"#,
+            "\n",
+            r#"{"event":"input","data":"ls\r"}"#,
+            "\n\n",
+            r#"{"event":"command","index":1,"command":"type","start":0.4,"duration":1,"output":"","error":"boom"}"#,
+            "\n",
+        );
+        let records = parse_log(log).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0], record(0, "expect", 250, "$ "));
+        assert_eq!(records[1].error.as_deref(), Some("boom"));
+        assert!(parse_log("not json").is_err());
+        assert!(parse_log(r#"{"event":"command","index":0}"#).is_err());
+        let huge = r#"{"event":"command","index":0,"command":"wait","duration":1e300,"output":"","error":null}"#;
+        let err = parse_log(huge).unwrap_err();
+        assert!(err.to_string().contains("out of range"), "got: {err}");
+    }
+
+    #[test]
+    fn test_identical_runs() {
+        let run = [
+            record(0, "expect", 100, "\x1b[1m$ \x1b[0m"),
+            record(1, "type", 500, ""),
+        ];
+        let other = [
+            record(0, "expect", 120, "$ \r\n"),
+            record(1, "type", 480, ""),
+        ];
+        assert!(compare(&run, &other).is_empty());
+    }
+
+    #[test]
+    fn test_differences() {
+        let a = [
+            record(0, "expect", 100, "$ "),
+            record(1, "type", 500, "ls\r\nfoo\r\n"),
+            record(2, "expect", 100, "$ "),
+        ];
+        let mut failed = record(2, "expect", 5000, "");
+        failed.error = Some("Timeout waiting for pattern: '$ '".into());
+        let b = [
+            record(0, "expect", 100, "$ "),
+            record(1, "type", 500, "ls\r\nbar\r\n"),
+            failed,
+            record(3, "key", 10, ""),
+        ];
+        let comparison = compare(&a, &b);
+        let text = comparison.to_string();
+        assert_eq!(
+            text,
+            "#1 type: output differs\n  - foo\n  + bar\n\
+             #2 expect: outcome differs\n  A: ok\n  B: Timeout waiting for pattern: '$ '\n\
+             #2 expect: output differs\n  - $\n\
+             #2 expect: 100ms in A, 5000ms in B (+4900ms)\n\
+             B#3 key: only in B\n"
+        );
+    }
+
+    #[test]
+    fn test_align_skipped_command() {
+        let a = [
+            record(0, "expect", 10, ""),
+            record(1, "wait", 10, ""),
+            record(2, "type", 10, ""),
+        ];
+        let b = [record(0, "expect", 10, ""), record(1, "type", 10, "")];
+        let comparison = compare(&a, &b);
+        assert_eq!(comparison.to_string(), "A#1 wait: only in A\n");
+    }
+}
@@ -0,0 +1,247 @@
+//! Control socket for driving a running [`crate::Engine`] from outside.
+//!
+//! The engine listens on a unix socket and accepts one command per line:
+//!
+//! | Line | Effect |
+//! |------|--------|
+//! | `pause` | Stop before the next scripted command |
+//! | `resume` | Continue running the script |
+//! | `step` | While paused, run exactly one scripted command |
+//! | `inject <command>` | Run a script line (e.g. `inject show "hi"`) before the next scripted command |
+//!
+//! Each line is answered with `ok` or `error: <reason>`; `inject` is
+//! answered once the command has run, with its error if it failed (which
+//! fails the script, like a scripted command). A line that doesn't parse is
+//! rejected without affecting the script. Injected commands run even while
+//! the script is paused, which lets a presenter improvise during a live
+//! demo.
+
+use crate::parser::parse_line;
+use anyhow::{Context as _, Result, anyhow};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+use tokio::sync::oneshot;
+
+#[derive(Debug)]
+pub(crate) enum ControlMessage {
+    Pause,
+    Resume,
+    Step,
+    Inject(Injected),
+}
+
+/// A script line to run before the next scripted command.
+#[derive(Debug)]
+pub(crate) struct Injected {
+    pub(crate) line: String,
+    /// Receives the outcome once the line has run: `Err` with the reason if
+    /// it could not be parsed or failed.
+    pub(crate) reply: Option<oneshot::Sender<Result<(), String>>>,
+}
+
+impl Injected {
+    /// Tell the client how the line went, if it is still listening.
+    pub(crate) fn answer(mut self, result: &Result<()>) {
+        if let Some(reply) = self.reply.take() {
+            let _ = reply.send(result.as_ref().map(|_| ()).map_err(|e| format!("{:#}", e)));
+        }
+    }
+}
+
+/// Parse one line of the control protocol.
+fn parse_message(line: &str) -> Result<ControlMessage> {
+    let line = line.trim();
+    let (verb, rest) = line.split_once(' ').unwrap_or((line, ""));
+    match verb {
+        "pause" => Ok(ControlMessage::Pause),
+        "resume" => Ok(ControlMessage::Resume),
+        "step" => Ok(ControlMessage::Step),
+        "inject" => {
+            let rest = rest.trim();
+            // Validate eagerly so the client gets the parse error back.
+            parse_line(rest)?;
+            Ok(ControlMessage::Inject(Injected {
+                line: rest.to_string(),
+                reply: None,
+            }))
+        }
+        _ => Err(anyhow!("Unknown control command: {}", line)),
+    }
+}
+
+/// Pause/step state driven by incoming [`ControlMessage`]s.
+pub(crate) struct Controller {
+    rx: UnboundedReceiver<ControlMessage>,
+    paused: bool,
+    step: bool,
+}
+
+impl Controller {
+    fn new(rx: UnboundedReceiver<ControlMessage>) -> Self {
+        Self {
+            rx,
+            paused: false,
+            step: false,
+        }
+    }
+
+    /// Wait until the next scripted command may run.
+    ///
+    /// Returns `Some` for each injected command that must run first, and
+    /// `None` once the scripted command may proceed.
+    pub(crate) async fn next_action(&mut self) -> Option<Injected> {
+        loop {
+            let msg = match self.rx.try_recv() {
+                Ok(msg) => msg,
+                Err(_) if !self.paused => return None,
+                Err(_) if self.step => {
+                    self.step = false;
+                    return None;
+                }
+                Err(_) => match self.rx.recv().await {
+                    Some(msg) => msg,
+                    // Every client is gone and the listener stopped: never stay paused forever.
+                    None => {
+                        self.paused = false;
+                        return None;
+                    }
+                },
+            };
+            match msg {
+                ControlMessage::Pause => self.paused = true,
+                ControlMessage::Resume => {
+                    self.paused = false;
+                    self.step = false;
+                }
+                ControlMessage::Step => self.step = self.paused,
+                ControlMessage::Inject(injected) => return Some(injected),
+            }
+        }
+    }
+}
+
+/// A listening control socket together with the [`Controller`] it feeds.
+///
+/// Dropping the server stops the listener and removes the socket file.
+pub(crate) struct ControlServer {
+    pub(crate) controller: Controller,
+    path: PathBuf,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl ControlServer {
+    /// Bind a unix socket at `path` and start accepting control connections.
+    pub(crate) fn bind(path: &Path) -> Result<Self> {
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("Failed to bind control socket: {}", path.display()))?;
+        let (tx, rx) = unbounded_channel();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_client(stream, tx.clone()));
+            }
+        });
+        Ok(Self {
+            controller: Controller::new(rx),
+            path: path.to_path_buf(),
+            task,
+        })
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.task.abort();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+async fn serve_client(stream: UnixStream, tx: UnboundedSender<ControlMessage>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let outcome = match parse_message(&line) {
+            Ok(msg) => deliver(&tx, msg).await,
+            Err(e) => Err(format!("{:#}", e)),
+        };
+        let reply = match outcome {
+            Ok(()) => "ok\n".to_string(),
+            Err(reason) => format!("error: {}\n", reason),
+        };
+        if writer.write_all(reply.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Pass `msg` to the engine. An injected line is answered once it has run.
+async fn deliver(
+    tx: &UnboundedSender<ControlMessage>,
+    mut msg: ControlMessage,
+) -> Result<(), String> {
+    let gone = || "engine is no longer running".to_string();
+    let outcome = match &mut msg {
+        ControlMessage::Inject(injected) => {
+            let (reply, outcome) = oneshot::channel();
+            injected.reply = Some(reply);
+            Some(outcome)
+        }
+        _ => None,
+    };
+    tx.send(msg).map_err(|_| gone())?;
+    match outcome {
+        Some(outcome) => outcome.await.map_err(|_| gone())?,
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_message() {
+        assert!(matches!(parse_message("pause"), Ok(ControlMessage::Pause)));
+        assert!(matches!(
+            parse_message(" resume "),
+            Ok(ControlMessage::Resume)
+        ));
+        assert!(matches!(parse_message("step"), Ok(ControlMessage::Step)));
+        assert!(matches!(
+            parse_message(r#"inject show "hi""#),
+            Ok(ControlMessage::Inject(Injected { line, .. })) if line == r#"show "hi""#
+        ));
+    }
+
+    #[test]
+    fn test_parse_message_invalid() {
+        assert!(parse_message("jump").is_err());
+        assert!(parse_message("inject bogus").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_controller_step_and_inject() {
+        let (tx, rx) = unbounded_channel();
+        let mut controller = Controller::new(rx);
+        assert!(controller.next_action().await.is_none());
+
+        tx.send(ControlMessage::Pause).unwrap();
+        tx.send(ControlMessage::Inject(Injected {
+            line: "key Enter".into(),
+            reply: None,
+        }))
+        .unwrap();
+        tx.send(ControlMessage::Step).unwrap();
+        let injected = controller.next_action().await.unwrap();
+        assert_eq!(injected.line, "key Enter");
+        assert!(controller.next_action().await.is_none());
+
+        // Still paused after the step: resumes once told to.
+        tx.send(ControlMessage::Resume).unwrap();
+        assert!(controller.next_action().await.is_none());
+    }
+}
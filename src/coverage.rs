@@ -0,0 +1,117 @@
+//! Branch coverage for commands that choose between alternatives.
+//!
+//! Branching commands (such as `expect_any`) record which of their branches
+//! was taken each time they run. The report lists, per branching site, the
+//! branches that were never exercised — untested paths in the script's logic.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+/// Hit counts for the branches of one site.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SiteCoverage {
+    /// Branch labels and how many times each was taken.
+    pub branches: Vec<(String, usize)>,
+}
+
+impl SiteCoverage {
+    /// Number of branches taken at least once.
+    pub fn taken(&self) -> usize {
+        self.branches.iter().filter(|(_, hits)| *hits > 0).count()
+    }
+}
+
+/// Branch hit counts keyed by site description.
+#[derive(Debug, Clone, Default)]
+pub struct BranchCoverage {
+    sites: BTreeMap<String, SiteCoverage>,
+}
+
+impl BranchCoverage {
+    /// Record that branch `taken` of `site` (whose branches are `labels`) ran.
+    pub(crate) fn record<S: AsRef<str>>(&mut self, site: &str, labels: &[S], taken: usize) {
+        let entry = self
+            .sites
+            .entry(site.to_string())
+            .or_insert_with(|| SiteCoverage {
+                branches: labels.iter().map(|l| (l.as_ref().to_string(), 0)).collect(),
+            });
+        if let Some((_, hits)) = entry.branches.get_mut(taken) {
+            *hits += 1;
+        }
+    }
+
+    /// Fold the hit counts from `other` into `self`, e.g. to aggregate a suite.
+    pub fn merge(&mut self, other: &BranchCoverage) {
+        for (site, coverage) in &other.sites {
+            match self.sites.get_mut(site) {
+                Some(entry) => {
+                    for ((_, hits), (_, other_hits)) in
+                        entry.branches.iter_mut().zip(&coverage.branches)
+                    {
+                        *hits += other_hits;
+                    }
+                }
+                None => {
+                    self.sites.insert(site.clone(), coverage.clone());
+                }
+            }
+        }
+    }
+
+    /// Coverage per site, ordered by site description.
+    pub fn sites(&self) -> impl Iterator<Item = (&str, &SiteCoverage)> {
+        self.sites.iter().map(|(site, cov)| (site.as_str(), cov))
+    }
+
+    /// Render the coverage as a human-readable report.
+    pub fn report(&self) -> String {
+        let mut out = String::from("branch coverage:\n");
+        for (site, coverage) in self.sites() {
+            let _ = writeln!(
+                out,
+                "  {}: {}/{} branches taken",
+                site,
+                coverage.taken(),
+                coverage.branches.len()
+            );
+            for (label, hits) in &coverage.branches {
+                let mark = if *hits > 0 { 'x' } else { ' ' };
+                let _ = writeln!(out, "    [{}] {} ({})", mark, label, hits);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_report() {
+        let mut coverage = BranchCoverage::default();
+        coverage.record("expect_any a|b", &["a", "b"], 0);
+        coverage.record("expect_any a|b", &["a", "b"], 0);
+        let (_, site) = coverage.sites().next().unwrap();
+        assert_eq!(site.taken(), 1);
+        assert_eq!(site.branches[0].1, 2);
+
+        let report = coverage.report();
+        assert!(report.contains("1/2 branches taken"), "got: {report}");
+        assert!(report.contains("[ ] b (0)"), "got: {report}");
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = BranchCoverage::default();
+        a.record("site", &["x", "y"], 0);
+        let mut b = BranchCoverage::default();
+        b.record("site", &["x", "y"], 1);
+        b.record("other", &["z"], 0);
+        a.merge(&b);
+        let sites: Vec<_> = a.sites().collect();
+        assert_eq!(sites.len(), 2);
+        assert_eq!(sites[1].1.taken(), 2);
+    }
+}
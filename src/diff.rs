@@ -0,0 +1,207 @@
+//! Command-level diffs between two scripts.
+//!
+//! Text diffs of automation scripts are noisy: re-indenting a block, editing a
+//! comment, or writing `1000ms` instead of `1s` all show up as changes. This
+//! module compares scripts after normalizing each command line, so only
+//! changes to what the script actually does are reported.
+//!
+//! Normalization drops blank lines and comments, ignores indentation and
+//! repeated spaces outside quoted strings, and rewrites durations in a
+//! canonical unit (`1000ms` and `1.0s` both become `1s`). Heredoc bodies are
+//! compared line by line as written, apart from indentation.
+
+use crate::parser::{heredoc_tag, parse_duration, split_args, strip_inline_comment};
+use std::fmt;
+
+/// One normalized command line and its 1-based line number in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptLine {
+    pub line: usize,
+    pub text: String,
+}
+
+/// A difference between two scripts.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// A command only present in the old script.
+    Removed(ScriptLine),
+    /// A command only present in the new script.
+    Added(ScriptLine),
+}
+
+/// The command-level differences between two scripts, in script order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScriptDiff {
+    pub changes: Vec<Change>,
+}
+
+impl ScriptDiff {
+    /// Whether the two scripts run the same commands.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for ScriptDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            match change {
+                Change::Removed(l) => writeln!(f, "-{:>5}: {}", l.line, l.text)?,
+                Change::Added(l) => writeln!(f, "+{:>5}: {}", l.line, l.text)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Normalize a script into its command lines (see the module docs).
+pub fn normalize(script: &str) -> Vec<ScriptLine> {
+    let mut lines = Vec::new();
+    let mut heredoc: Option<&str> = None;
+    for (i, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if let Some(tag) = heredoc {
+            if line == tag {
+                heredoc = None;
+            }
+            lines.push(ScriptLine {
+                line: i + 1,
+                text: line.to_string(),
+            });
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let command = strip_inline_comment(line);
+        heredoc = heredoc_tag(command);
+        let text = split_args(command)
+            .into_iter()
+            .map(normalize_token)
+            .collect::<Vec<_>>()
+            .join(" ");
+        lines.push(ScriptLine { line: i + 1, text });
+    }
+    lines
+}
+
+/// Compare two scripts command by command.
+pub fn diff(old: &str, new: &str) -> ScriptDiff {
+    diff_lines(&normalize(old), &normalize(new))
+}
+
+/// Compare two sequences of lines, reporting the fewest removals and
+/// additions that turn `old` into `new`.
+pub fn diff_lines(old: &[ScriptLine], new: &[ScriptLine]) -> ScriptDiff {
+    // Longest common subsequence table, filled from the end.
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i].text == new[j].text {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i].text == new[j].text {
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            changes.push(Change::Removed(old[i].clone()));
+            i += 1;
+        } else {
+            changes.push(Change::Added(new[j].clone()));
+            j += 1;
+        }
+    }
+    ScriptDiff { changes }
+}
+
+/// Rewrite duration tokens (and `MIN..MAX` duration ranges) canonically.
+fn normalize_token(token: &str) -> String {
+    if let Some((min, max)) = token.split_once("..")
+        && let (Some(min), Some(max)) = (canonical_duration(min), canonical_duration(max))
+    {
+        return format!("{}..{}", min, max);
+    }
+    canonical_duration(token).unwrap_or_else(|| token.to_string())
+}
+
+fn canonical_duration(token: &str) -> Option<String> {
+    if token.starts_with('"') || !token.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let millis = parse_duration(token).ok()?.as_millis();
+    Some(if millis % 1000 == 0 {
+        format!("{}s", millis / 1000)
+    } else {
+        format!("{}ms", millis)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let lines = normalize("# setup\n\n  wait   1000ms  # pause\nexpect \"a  b\"   2.5s\n");
+        let texts: Vec<_> = lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(texts, vec!["wait 1s", "expect \"a  b\" 2500ms"]);
+        assert_eq!(lines[0].line, 3);
+    }
+
+    #[test]
+    fn test_cosmetic_changes_ignored() {
+        let old = "if expect \"x\" 1s\nsend \"y\"\nend\n";
+        let new = "# answer the prompt\nif expect \"x\" 1000ms\n    send \"y\"   # reply\nend\n";
+        assert!(diff(old, new).is_empty());
+    }
+
+    #[test]
+    fn test_changes() {
+        let old = "expect \"$ \"\ntype \"ls\"\nwait 1s\n";
+        let new = "expect \"$ \"\ntype \"ls -la\"\nwait 1s\nkey Enter\n";
+        let changes = diff(old, new).changes;
+        assert_eq!(
+            changes,
+            vec![
+                Change::Removed(ScriptLine {
+                    line: 2,
+                    text: "type \"ls\"".into()
+                }),
+                Change::Added(ScriptLine {
+                    line: 2,
+                    text: "type \"ls -la\"".into()
+                }),
+                Change::Added(ScriptLine {
+                    line: 4,
+                    text: "key Enter".into()
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_heredoc_body_compared_verbatim() {
+        let old = "type <<EOF\n  # keep me\n  a   b\nEOF\nwait 1s\n";
+        let new = "type <<EOF\n  # keep me\n  a b\nEOF\nwait 1000ms\n";
+        let texts: Vec<_> = normalize(old).into_iter().map(|l| l.text).collect();
+        assert_eq!(
+            texts,
+            vec!["type <<EOF", "# keep me", "a   b", "EOF", "wait 1s"]
+        );
+        assert_eq!(diff(old, new).changes.len(), 2);
+    }
+
+    #[test]
+    fn test_typing_speed_range() {
+        assert_eq!(normalize_token("20ms..1000ms"), "20ms..1s");
+        assert_eq!(normalize_token("a..b"), "a..b");
+    }
+}
@@ -1,37 +1,107 @@
 //! The [`Engine`] that executes [`ScripttyCommand`] sequences against a live PTY process.
 
-use crate::command::{Context, ScripttyCommand};
-use crate::pty::PtySession;
-use anyhow::Result;
+use crate::accessible::{self, InteractionLog};
+use crate::behavior::Behavior;
+use crate::builder::{EngineBuilder, OutputQueue};
+use crate::command::{
+    CommandHook, Context, EventHandler, OutputEvent, OutputHandler, ScripttyCommand,
+};
+use crate::commands::{KeyTable, SnapshotMode};
+use crate::control::ControlServer;
+use crate::coverage::BranchCoverage;
+use crate::events::EventLog;
+use crate::fuzz::Fuzz;
+use crate::graphics::{Image, ImageScanner, ImageWriter};
+use crate::metrics::ExpectMetrics;
+use crate::notify::{Notification, NotificationScanner};
+use crate::parser::{ScriptReader, parse_line};
+use crate::pty::{PtySession, SpawnOptions, SpawnSpec};
+use crate::quota::Quotas;
+use crate::recorder::Recorder;
+use crate::secrets::Secrets;
+use crate::soak::SoakReport;
+use crate::supervisor::{CrashLoopLimit, Supervisor};
+use crate::trace::Trace;
+use crate::transcript::{Transcript, TranscriptLog, TranscriptWriter};
+use crate::workspace::Workspace;
+use anyhow::{Context as _, Result};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
 use std::io::Write;
-use std::sync::mpsc::Receiver;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
 use tokio::time::sleep;
 
+/// What to wait for before a script runs, so that it starts against a
+/// program that is ready for input; see [`Engine::set_startup_policy`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum StartupPolicy {
+    /// Don't wait.
+    #[default]
+    Immediate,
+    /// Wait until the program prints this text, e.g. its prompt. The text is
+    /// not consumed, so the script's first `expect` can still match it.
+    Pattern(String),
+    /// Wait until the program has printed nothing for this long.
+    Idle(Duration),
+}
+
 /// Executes a sequence of [`ScripttyCommand`]s against a program running in a PTY.
 ///
 /// Create an engine with [`Engine::spawn`] (output to stdout) or
-/// [`Engine::spawn_with_handler`] (custom output sink), then call
+/// [`Engine::spawn_with_handler`] (custom output sink), or configure the
+/// program's environment and terminal with [`Engine::builder`], then call
 /// [`Engine::execute`] with the commands produced by the parser.
+///
+/// Dropping the engine kills the program if it is still running; use
+/// [`Engine::kill`] to give it a chance to exit cleanly first.
 pub struct Engine {
     ctx: Context,
-    _output_task: tokio::task::JoinHandle<()>,
+    control: Option<ControlServer>,
+    workspace: Option<Workspace>,
+    keep_workspace_on_failure: bool,
+    /// The task an async output handler's output is queued for.
+    output_queue: Option<OutputQueue>,
+    failure_hook: Option<FailureHook>,
+    command_hooks: Vec<CommandHookFn>,
+    startup: StartupPolicy,
+    /// What the program printed before the first run started.
+    banner: Option<String>,
+    /// Top-level commands started in the current run.
+    commands_started: usize,
+}
+
+type FailureHook = Box<dyn FnMut(&Path)>;
+type CommandHookFn = Box<dyn FnMut(CommandHook<'_>)>;
+
+/// Where a top-level command is, as passed to [`Engine::report_command`].
+#[derive(Clone, Copy)]
+enum CommandStep<'a> {
+    Started,
+    /// It finished after this long, with this result.
+    Finished(Duration, &'a Result<()>),
 }
 
 impl Engine {
+    /// Start configuring an engine that runs `command`; see [`EngineBuilder`].
+    pub fn builder(command: impl Into<String>) -> EngineBuilder {
+        EngineBuilder::new(command)
+    }
+
     /// Spawn a new engine that runs `command` in a PTY and writes all output to stdout.
     ///
     /// # Errors
     ///
     /// Returns an error if the PTY cannot be opened or the command cannot be spawned.
     pub fn spawn<S: AsRef<str>>(command: &str, args: &[S]) -> Result<Self> {
-        Self::spawn_with_handler(command, args, |data| {
-            let stdout = std::io::stdout();
-            let mut stdout = stdout.lock();
-            stdout.write_all(data).ok();
-            stdout.flush().ok();
-        })
+        Self::builder(command)
+            .args(args.iter().map(AsRef::as_ref))
+            .spawn()
     }
 
     /// Spawn a new engine that runs `command` in a PTY and passes all output to `handler`.
@@ -96,57 +166,1675 @@ impl Engine {
         S: AsRef<str>,
         F: Fn(&[u8]) + Send + Sync + 'static,
     {
-        let string_args: Vec<String> = args.iter().map(|s| s.as_ref().to_string()).collect();
-        let (pty, reader) = PtySession::spawn(command, &string_args)?;
-        let output_rx = crate::pty_reader::spawn_reader(reader);
-        Ok(Self::from_parts(pty, output_rx, handler))
+        Self::builder(command)
+            .args(args.iter().map(AsRef::as_ref))
+            .handler(handler)
+            .spawn()
     }
 
-    fn from_parts<F>(pty: PtySession, output_rx: Receiver<Vec<u8>>, handler: F) -> Self
-    where
-        F: Fn(&[u8]) + Send + Sync + 'static,
-    {
-        let output_buffer = Arc::new(Mutex::new(String::new()));
-        let buffer_clone = output_buffer.clone();
-        let handler = Arc::new(handler);
-        let handler_clone = handler.clone();
-
-        let output_task = tokio::task::spawn_blocking(move || {
-            while let Ok(data) = output_rx.recv() {
-                handler_clone(&data);
-                if let Ok(mut buffer) = buffer_clone.lock() {
-                    buffer.push_str(&String::from_utf8_lossy(&data));
-                    if buffer.len() > 10_000 {
-                        buffer.drain(..5_000);
-                    }
-                }
-            }
-        });
+    pub(crate) fn spawn_with_options(
+        command: &str,
+        args: &[String],
+        options: &SpawnOptions,
+        handler: OutputHandler,
+    ) -> Result<Self> {
+        let spawn = SpawnSpec {
+            command: command.to_string(),
+            args: args.to_vec(),
+            options: options.clone(),
+        };
+        let interactions = Arc::new(Mutex::new(InteractionLog::default()));
+        let secrets = Secrets::default();
+        let sinks = Arc::new(Mutex::new(Sinks {
+            secrets: secrets.clone(),
+            ..Sinks::default()
+        }));
+        let behavior = Arc::new(Mutex::new(Behavior::default()));
+        let session = Session::start(
+            &spawn,
+            handler.clone(),
+            interactions.clone(),
+            sinks.clone(),
+            behavior.clone(),
+        )?;
+        let term = spawn
+            .options
+            .env
+            .iter()
+            .rev()
+            .find(|(key, _)| key == "TERM")
+            .map(|(_, value)| value.clone())
+            .or_else(|| std::env::var("TERM").ok());
 
-        Engine {
+        Ok(Engine {
             ctx: Context {
-                pty,
-                output_buffer,
+                pty: session.pty,
+                output_buffer: session.output_buffer,
+                notifications: session.notifications,
+                images: session.images,
+                last_image: None,
+                transcript: session.transcript,
+                last_output: session.last_output,
+                screen: session.screen,
+                interactions,
+                sinks,
+                secrets,
+                output_task: session.output_task,
+                spawn,
+                snapshots: HashMap::new(),
+                supervisor: Supervisor::new(Some(CrashLoopLimit::default())),
+                emit_handler: handler.clone(),
                 output_handler: handler,
+                run_start: tokio::time::Instant::now(),
+                last_match: None,
+                variables: HashMap::new(),
+                metrics: ExpectMetrics::default(),
+                timeout_scale: 1.0,
+                coverage: BranchCoverage::default(),
+                trace: None,
+                soak: SoakReport::default(),
+                fuzz: None,
+                rng: StdRng::from_entropy(),
+                default_timeout: Duration::from_secs(5),
+                typing_delay: (Duration::from_millis(50), Duration::from_millis(150)),
+                instant: false,
+                typo_rate: 0.0,
+                plain_expect: false,
+                key_delay: Duration::from_millis(30),
+                repl: None,
+                mi_token: 0,
+                narration: None,
+                marker_handler: None,
+                snapshot_dir: PathBuf::from("snapshots"),
+                snapshot_mode: SnapshotMode::from_env(),
+                #[cfg(feature = "network")]
+                vendor: None,
+                behavior,
+                key_table: term.map_or_else(KeyTable::default, |t| KeyTable::for_term(&t)),
+                exit_code: None,
+                quotas: Quotas::default(),
+                input_bytes: 0,
+                current_command: "",
             },
-            _output_task: output_task,
+            control: None,
+            workspace: None,
+            keep_workspace_on_failure: false,
+            output_queue: None,
+            failure_hook: None,
+            command_hooks: Vec::new(),
+            startup: StartupPolicy::default(),
+            banner: None,
+            commands_started: 0,
+        })
+    }
+
+    /// Wait until the program is ready according to the startup policy, for
+    /// at most the default timeout of `expect`.
+    async fn wait_until_ready(&mut self) -> Result<()> {
+        let policy = &self.startup;
+        if *policy == StartupPolicy::Immediate {
+            return Ok(());
+        }
+        let deadline =
+            tokio::time::Instant::now() + self.ctx.scale_timeout(self.ctx.default_timeout);
+        loop {
+            let ready = match policy {
+                StartupPolicy::Immediate => true,
+                StartupPolicy::Pattern(pattern) => self
+                    .ctx
+                    .output_buffer
+                    .lock()
+                    .unwrap()
+                    .contains(pattern.as_str()),
+                StartupPolicy::Idle(quiet) => {
+                    self.ctx.last_output.lock().unwrap().elapsed() >= self.ctx.scale_timeout(*quiet)
+                }
+            };
+            if ready {
+                return Ok(());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                let message = match policy {
+                    StartupPolicy::Pattern(pattern) => format!(
+                        "Timeout waiting for the program to print '{}' at startup",
+                        pattern
+                    ),
+                    StartupPolicy::Idle(quiet) => format!(
+                        "Timeout waiting for the program's startup output to pause for {:?}",
+                        quiet
+                    ),
+                    StartupPolicy::Immediate => unreachable!("an immediate start is always ready"),
+                };
+                return Err(self.ctx.timeout_error(message));
+            }
+            sleep(Duration::from_millis(10)).await;
         }
     }
 
     /// Execute a sequence of commands in order.
     ///
-    /// After the last command the engine waits briefly for any remaining PTY
+    /// The run timeline used by [`crate::commands::At`] starts when this method
+    /// is called. After the last command the engine waits briefly for any remaining PTY
     /// output to be flushed through the output handler before returning.
     pub async fn execute(&mut self, commands: Vec<Box<dyn ScripttyCommand>>) -> Result<()> {
+        let result = self.execute_commands(commands).await;
+        self.finish(result).await
+    }
+
+    /// Parse and execute the script file at `path` one statement at a time.
+    ///
+    /// Behaves like [`parse_file`](crate::parse_file) followed by
+    /// [`Engine::execute`], except that each command is parsed right before
+    /// it runs and dropped afterwards, so generated scripts with hundreds of
+    /// thousands of commands run in constant memory beyond the script text.
+    /// A block (`repeat ... end`) or an `include`d file is parsed as a whole.
+    /// A syntax error fails the run when it is reached, after the commands
+    /// before it have run.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, a line fails to parse, or
+    /// a command fails.
+    pub async fn run_script_file(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read script file: {}", path.display()))?;
+        let result = self.run_script_source(&content, path).await;
+        self.finish(result).await
+    }
+
+    async fn run_script_source(&mut self, content: &str, path: &Path) -> Result<()> {
+        let mut reader = ScriptReader::for_file(content, path)?;
+        if self.banner.is_none() {
+            self.wait_until_ready().await?;
+        }
+        self.start_run();
+        while let Some(commands) = reader.next_statement()? {
+            for cmd in commands {
+                self.execute_one(cmd.as_ref()).await?;
+            }
+        }
+        sleep(self.ctx.behavior().final_settle()).await;
+        Ok(())
+    }
+
+    /// Keep the workspace if `result` of a run is a failure and that was asked for.
+    async fn finish(&mut self, result: Result<()>) -> Result<()> {
+        // Everything the run printed reaches a queued writer before the run
+        // returns; a writer that failed fails the run.
+        let flushed = match self.output_queue.as_mut() {
+            Some(queue) => queue.flush().await,
+            None => Ok(()),
+        };
+        let result = result.and(flushed);
+        if result.is_err()
+            && self.keep_workspace_on_failure
+            && let Some(workspace) = self.workspace.as_mut()
+        {
+            workspace.keep();
+        }
+        if let Some(trace) = &mut self.ctx.trace {
+            let command_line = self.ctx.spawn.command_line();
+            let error = result.as_ref().err().map(|e| format!("{:#}", e));
+            trace.end_run("scriptty run", &command_line, error);
+        }
+        if self.ctx.pty.is_stopped() {
+            // A program the script left stopped would never exit on its own.
+            let _ = self.ctx.pty.signal(libc::SIGCONT);
+        }
+        if let Some(writer) = self.ctx.sinks.lock().unwrap().transcript.as_mut() {
+            // Best effort, like the other sinks: the run's result matters more.
+            let _ = writer.finish();
+        }
+        if let Err(e) = &result
+            && self.failure_hook.is_some()
+        {
+            // Best effort: the run's own error matters more.
+            if let Ok(path) = self.write_failure_report(e)
+                && let Some(hook) = self.failure_hook.as_mut()
+            {
+                hook(&path);
+            }
+        }
+        result
+    }
+
+    /// Write what is known about a failed run to a new file in the temp
+    /// directory: the error, the program, the screen, and the output, with
+    /// secrets masked. The file is created afresh, readable by the owner
+    /// only, so another user cannot read it or plant a file in its place.
+    fn write_failure_report(&self, error: &anyhow::Error) -> Result<PathBuf> {
+        let path = std::env::temp_dir().join(format!(
+            "scriptty-failure-{}-{:08x}.txt",
+            std::process::id(),
+            rand::random::<u32>()
+        ));
+        let (output, _) = crate::ansi::strip(&self.ctx.transcript.lock().unwrap());
+        let report = format!(
+            "error: {:#}\ncommand: {}\nprogram: {}\nelapsed: {}ms\n\nscreen:\n{}\n\noutput:\n{}\n",
+            error,
+            self.ctx.current_command,
+            self.ctx.spawn.command_line(),
+            self.ctx.elapsed().as_millis(),
+            self.ctx.screen().contents().trim_end(),
+            output.trim_end()
+        );
+        std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .and_then(|mut file| file.write_all(self.ctx.secrets.mask(&report).as_bytes()))
+            .with_context(|| format!("Failed to write failure report: {}", path.display()))?;
+        Ok(path)
+    }
+
+    async fn execute_commands(&mut self, commands: Vec<Box<dyn ScripttyCommand>>) -> Result<()> {
+        if self.banner.is_none() {
+            self.wait_until_ready().await?;
+        }
+        self.start_run();
         for cmd in commands {
-            cmd.execute(&mut self.ctx).await?;
+            self.execute_one(cmd.as_ref()).await?;
+        }
+        sleep(self.ctx.behavior().final_settle()).await;
+        Ok(())
+    }
+
+    /// Start the run timeline, which event log times count from, and keep
+    /// the output so far as the banner on the first run.
+    fn start_run(&mut self) {
+        if self.banner.is_none() {
+            let (output, _) = crate::ansi::strip(&self.ctx.transcript.lock().unwrap());
+            let banner = output.replace('\r', "").trim_end().to_string();
+            self.ctx.set_var("banner", banner.as_str());
+            self.banner = Some(banner);
+        }
+        self.ctx.run_start = tokio::time::Instant::now();
+        self.commands_started = 0;
+        if let Some(events) = self.ctx.sinks.lock().unwrap().events.as_mut() {
+            events.set_run_start(self.ctx.run_start.into_std());
+        }
+    }
+
+    /// Run one top-level command, after any pending control requests and the
+    /// fuzz mode's random pause.
+    async fn execute_one(&mut self, cmd: &dyn ScripttyCommand) -> Result<()> {
+        self.run_control_actions().await?;
+        if let Some(fuzz) = &self.ctx.fuzz {
+            let max = fuzz.max_extra_wait.as_millis() as u64;
+            sleep(Duration::from_millis(self.ctx.rng.gen_range(0..=max))).await;
+        }
+        self.dispatch(cmd).await
+    }
+
+    /// Run one top-level command, scripted or injected, within the run's
+    /// quotas, and report its start and end; see [`Engine::report_command`].
+    async fn dispatch(&mut self, cmd: &dyn ScripttyCommand) -> Result<()> {
+        let start = self.ctx.elapsed();
+        self.ctx.current_command = cmd.name();
+        let index = self.commands_started;
+        self.commands_started += 1;
+        self.report_command(cmd, index, start, CommandStep::Started);
+        let result = if self.ctx.quotas.is_unlimited() {
+            cmd.execute(&mut self.ctx).await
+        } else {
+            self.execute_within_quotas(cmd).await
+        };
+        let duration = self.ctx.elapsed().saturating_sub(start);
+        self.report_command(cmd, index, start, CommandStep::Finished(duration, &result));
+        result
+    }
+
+    /// Tell everything observing the run that the top-level command `cmd`,
+    /// the run's command number `index`, which started `start` into the run,
+    /// has reached `step`: the trace, the event log, the [`Engine::on_event`]
+    /// handler, and the [`Engine::on_command`] hooks, in that order.
+    fn report_command(
+        &mut self,
+        cmd: &dyn ScripttyCommand,
+        index: usize,
+        start: Duration,
+        step: CommandStep<'_>,
+    ) {
+        let name = cmd.name();
+        let (event, duration, error) = match step {
+            CommandStep::Started => {
+                if let Some(trace) = &mut self.ctx.trace {
+                    trace.begin_command();
+                }
+                (OutputEvent::CommandStarted { name, index }, None, None)
+            }
+            CommandStep::Finished(duration, result) => {
+                let message = result.as_ref().err().map(|e| format!("{:#}", e));
+                if let Some(trace) = &mut self.ctx.trace {
+                    trace.end_command(name, duration, message.clone());
+                }
+                let event = OutputEvent::CommandFinished {
+                    name,
+                    index,
+                    duration,
+                    error: message,
+                };
+                (event, Some(duration), result.as_ref().err())
+            }
+        };
+        {
+            let mut sinks = self.ctx.sinks.lock().unwrap();
+            if let Some(events) = sinks.events.as_mut() {
+                // Best effort, like the cast recorder: logging must not fail the run.
+                let _ = match step {
+                    CommandStep::Started => events.command_start(name),
+                    CommandStep::Finished(duration, result) => {
+                        events.command(name, start, duration, result)
+                    }
+                };
+            }
+            sinks.event(&event);
+        }
+        for hook in &mut self.command_hooks {
+            hook(CommandHook {
+                name,
+                args: cmd.args(),
+                index,
+                duration,
+                error,
+                ctx: &self.ctx,
+            });
+        }
+    }
+
+    /// Run `cmd`, stopping it and the program as soon as the output or
+    /// duration quota is exceeded.
+    async fn execute_within_quotas(&mut self, cmd: &dyn ScripttyCommand) -> Result<()> {
+        let quotas = self.ctx.quotas;
+        let sinks = self.ctx.sinks.clone();
+        let run_start = self.ctx.run_start;
+        let exceeded = move || {
+            let output = sinks.lock().unwrap().output_bytes;
+            quotas.exceeded(run_start.elapsed(), output)
+        };
+        let watch = {
+            let exceeded = exceeded.clone();
+            async move {
+                loop {
+                    if let Some(quota) = exceeded() {
+                        return quota;
+                    }
+                    sleep(QUOTA_POLL_INTERVAL).await;
+                }
+            }
+        };
+        let result = tokio::select! {
+            result = cmd.execute(&mut self.ctx) => result,
+            quota = watch => return Err(self.ctx.quota_exceeded(quota)),
+        };
+        // Output printed while a quick command ran is charged to that command.
+        match exceeded() {
+            Some(quota) if result.is_ok() => Err(self.ctx.quota_exceeded(quota)),
+            _ => result,
+        }
+    }
+
+    /// Set a script variable before (or between) runs, e.g. from CLI definitions.
+    pub fn set_var(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.ctx.set_var(name, value);
+    }
+
+    /// Mask `value` wherever the run is recorded; see
+    /// [`Context::register_secret`].
+    pub fn register_secret(&self, value: impl Into<String>) {
+        self.ctx.register_secret(value);
+    }
+
+    /// Run without the pauses that only pace a run for a viewer: text is
+    /// typed and keys are pressed without delays, and `wait` returns at once.
+    /// Commands that wait for the program, like `expect`, still wait. Suits
+    /// checking that demo scripts still work.
+    pub fn set_instant(&mut self, instant: bool) {
+        self.ctx.instant = instant;
+    }
+
+    /// Keep the defaults of an older release series, as `set behavior` does;
+    /// see [`Behavior`].
+    pub fn set_behavior(&mut self, behavior: Behavior) {
+        *self.ctx.behavior.lock().unwrap() = behavior;
+    }
+
+    /// Multiply every expect timeout and `wait` duration by `scale`.
+    ///
+    /// Lets the same script run unmodified on slow machines such as emulated
+    /// CI runners, e.g. with a scale of `3.0`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `scale` is not a positive, finite number.
+    pub fn set_timeout_scale(&mut self, scale: f64) -> Result<()> {
+        if !scale.is_finite() || scale <= 0.0 {
+            return Err(anyhow::anyhow!(
+                "Timeout scale must be a positive number, got: {}",
+                scale
+            ));
+        }
+        self.ctx.timeout_scale = scale;
+        Ok(())
+    }
+
+    /// Enable fuzz mode: randomized typing speed, extra pauses between
+    /// commands, and shuffled `any_order` blocks, all driven by `fuzz.seed`.
+    ///
+    /// See [`crate::fuzz`].
+    pub fn set_fuzz(&mut self, fuzz: Fuzz) {
+        self.ctx.rng = StdRng::seed_from_u64(fuzz.seed);
+        self.ctx.fuzz = Some(fuzz);
+    }
+
+    /// Limit how often the program may exit on its own before
+    /// [`Engine::respawn`] (or the `respawn` command) refuses to restart it.
+    ///
+    /// Defaults to [`CrashLoopLimit::default`]; `None` restarts without limit.
+    pub fn set_crash_loop_limit(&mut self, limit: Option<CrashLoopLimit>) {
+        self.ctx.supervisor.set_limit(limit);
+    }
+
+    /// Store the files of `snapshot` commands in `dir` instead of
+    /// `snapshots/`. Relative paths are resolved against the working directory.
+    pub fn set_snapshot_dir(&mut self, dir: impl Into<PathBuf>) {
+        self.ctx.snapshot_dir = dir.into();
+    }
+
+    /// Whether `snapshot` commands check the screen against the stored
+    /// snapshots or record it (see [`SnapshotMode`]). The default is
+    /// [`SnapshotMode::from_env`].
+    pub fn set_snapshot_mode(&mut self, mode: SnapshotMode) {
+        self.ctx.snapshot_mode = mode;
+    }
+
+    /// Pass the program's output to `handler` as well as to the handler the
+    /// engine was spawned with, e.g. to show it on stdout while also
+    /// capturing it. Output the program printed before this call is passed
+    /// first, in one chunk; `show` text is passed like the program's output.
+    ///
+    /// ```no_run
+    /// use scriptty::Engine;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let captured = Arc::new(Mutex::new(Vec::new()));
+    /// let sink = captured.clone();
+    /// let mut engine = Engine::spawn("bash", &[] as &[&str])?;
+    /// engine.add_handler(move |data| sink.lock().unwrap().extend_from_slice(data));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_handler(&mut self, handler: impl Fn(&[u8]) + Send + Sync + 'static) {
+        let transcript = self.ctx.transcript.lock().unwrap();
+        if !transcript.is_empty() {
+            handler(transcript.as_bytes());
+        }
+        self.ctx
+            .sinks
+            .lock()
+            .unwrap()
+            .handlers
+            .push(Arc::new(handler));
+    }
+
+    /// Pass `handler` what happens in the session from now on as typed
+    /// [`OutputEvent`]s: the program's output, the input sent to it, the
+    /// start and end of each command, and the program's exit. Output the
+    /// program printed before this call arrives first, as one
+    /// [`OutputEvent::ProgramOutput`].
+    ///
+    /// The raw output handler is still called; spawn with a no-op handler
+    /// (see [`EngineBuilder::handler`](crate::EngineBuilder::handler)) to
+    /// only get events. The handler is also called from the thread reading
+    /// the program's output, so it should return quickly.
+    pub fn on_event(&mut self, handler: impl Fn(&OutputEvent) + Send + Sync + 'static) {
+        let transcript = self.ctx.transcript.lock().unwrap();
+        if !transcript.is_empty() {
+            handler(&OutputEvent::ProgramOutput(transcript.as_bytes().to_vec()));
+        }
+        self.ctx.sinks.lock().unwrap().observer = Some(Arc::new(handler));
+    }
+
+    /// Call `handler` with the name of each `marker` the script reaches, e.g.
+    /// to report progress through a long recording.
+    pub fn on_marker(&mut self, handler: impl FnMut(&str) + 'static) {
+        self.ctx.marker_handler = Some(Box::new(handler));
+    }
+
+    /// Call `hook` before and after each top-level command of a run, with its
+    /// name, arguments, and position, and afterwards how long it took and
+    /// whether it failed. Commands inside blocks such as `if` or `repeat`
+    /// run as part of their block. Hooks added earlier stay: each is called
+    /// in the order it was added. Suits progress displays, logging, and a
+    /// screenshot of each step:
+    ///
+    /// ```no_run
+    /// use scriptty::Engine;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut engine = Engine::spawn("bash", &[] as &[&str])?;
+    /// engine.on_command(|hook| {
+    ///     if let Some(duration) = hook.duration {
+    ///         eprintln!("#{} {} {} took {:?}", hook.index, hook.name, hook.args, duration);
+    ///     }
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn on_command(&mut self, hook: impl FnMut(CommandHook<'_>) + 'static) {
+        self.command_hooks.push(Box::new(hook));
+    }
+
+    /// What [`Engine::execute`] and [`Engine::run_script_file`] wait for
+    /// before the first command of the first run, for at most the default
+    /// timeout of `expect` (scaled by
+    /// [`set_timeout_scale`](Self::set_timeout_scale)). Later runs continue
+    /// with the program as the previous one left it. By default they don't
+    /// wait.
+    pub fn set_startup_policy(&mut self, policy: StartupPolicy) {
+        self.startup = policy;
+    }
+
+    /// The program's output from before the first command of the first run,
+    /// without escape sequences; `None` until a run has started.
+    ///
+    /// Scripts read it as `${banner}`. The output stays in the buffer
+    /// `expect` matches against as well. Set a [`StartupPolicy`] to wait for
+    /// the whole banner; otherwise the first command may start before much of
+    /// it arrives.
+    pub fn banner(&self) -> Option<&str> {
+        self.banner.as_deref()
+    }
+
+    /// Call `hook` when [`Engine::execute`] or [`Engine::run_script_file`]
+    /// fails, with the path of a report of the failure: the error, the
+    /// failing command, the screen, and the program's output as plain text.
+    /// The report is left in the temp directory for the hook to collect.
+    pub fn on_failure(&mut self, hook: impl FnMut(&Path) + 'static) {
+        self.failure_hook = Some(Box::new(hook));
+    }
+
+    /// Keep the workspace directory after a failed [`Engine::execute`] so its
+    /// contents can be inspected. Successful runs always clean up.
+    pub fn keep_workspace_on_failure(&mut self, keep: bool) {
+        self.keep_workspace_on_failure = keep;
+    }
+
+    pub(crate) fn set_output_queue(&mut self, queue: OutputQueue) {
+        self.ctx.emit_handler = queue.emitter();
+        self.output_queue = Some(queue);
+    }
+
+    pub(crate) fn set_workspace(&mut self, workspace: Workspace) {
+        self.set_var("WORKSPACE", workspace.path().to_string_lossy());
+        self.workspace = Some(workspace);
+    }
+
+    /// The run's workspace, if the engine was spawned with one.
+    pub fn workspace(&self) -> Option<&Workspace> {
+        self.workspace.as_ref()
+    }
+
+    /// Listen for control commands on a unix socket at `path`.
+    ///
+    /// While the engine is executing, clients connected to the socket can
+    /// `pause`, `resume`, and `step` through the script, or `inject` extra
+    /// script lines. See [`crate::control`] for the protocol. The socket file
+    /// is removed when the engine is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the socket cannot be bound (e.g. the path exists).
+    pub fn listen_control(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.control = Some(ControlServer::bind(path.as_ref())?);
+        Ok(())
+    }
+
+    /// Honor pending control requests before the next scripted command:
+    /// run injected commands and block while paused.
+    ///
+    /// The client hears how each injected command went. One that doesn't
+    /// parse is skipped; one that fails fails the run, like a scripted
+    /// command.
+    async fn run_control_actions(&mut self) -> Result<()> {
+        loop {
+            let Some(control) = self.control.as_mut() else {
+                return Ok(());
+            };
+            let Some(injected) = control.controller.next_action().await else {
+                return Ok(());
+            };
+            let result = match parse_line(&injected.line) {
+                Ok(cmd) => self.dispatch(cmd.as_ref()).await,
+                Err(e) => {
+                    injected.answer(&Err(e));
+                    continue;
+                }
+            };
+            injected.answer(&result);
+            result?;
+        }
+    }
+
+    /// Start recording everything written to and printed by the program, for
+    /// [`Engine::accessible_transcript`]. Call this before [`Engine::execute`].
+    pub fn record_interactions(&mut self) {
+        self.ctx.interactions.lock().unwrap().enable();
+    }
+
+    /// A screen-reader friendly description of the session recorded since
+    /// [`Engine::record_interactions`]: the commands typed, keys pressed, and
+    /// the program's responses as plain text, without escape sequences, box
+    /// drawing, or echoed input. See [`crate::accessible`].
+    pub fn accessible_transcript(&self) -> String {
+        accessible::describe(self.ctx.interactions.lock().unwrap().entries())
+    }
+
+    /// The session so far as a standalone HTML page with the program's
+    /// colors, for embedding a walkthrough in documentation: everything that
+    /// scrolled past followed by the current screen.
+    ///
+    /// It covers the current program instance (since the last
+    /// [`Engine::respawn`]), up to the last megabyte of output.
+    pub fn session_html(&self) -> String {
+        let (rows, cols) = self.ctx.screen().size();
+        let mut parser = vt100::Parser::new(rows, cols, HTML_SCROLLBACK);
+        parser.process(self.ctx.transcript.lock().unwrap().as_bytes());
+        crate::render::render_html(&mut parser, &self.ctx.spawn.command_line())
+    }
+
+    /// Wait-time statistics for every pattern waited on so far.
+    pub fn metrics(&self) -> &ExpectMetrics {
+        &self.ctx.metrics
+    }
+
+    /// Which branches of branching commands (e.g. `expect_any`) were taken so far.
+    pub fn coverage(&self) -> &BranchCoverage {
+        &self.ctx.coverage
+    }
+
+    /// Record the program's output as an asciinema v2 cast at `path`; see
+    /// [`Recorder`].
+    ///
+    /// Output the program printed before this call (e.g. while it started
+    /// up) opens the cast.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be created.
+    pub fn record_cast(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let (rows, cols) = self.ctx.screen().size();
+        self.start_cast(Recorder::create(path, cols, rows)?)
+    }
+
+    /// Like [`Engine::record_cast`], but write the cast to `out`, e.g. a
+    /// buffer to compare the run against an earlier recording.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header or the output printed so far cannot be
+    /// written.
+    pub fn record_cast_to(&mut self, out: impl Write + Send + 'static) -> Result<()> {
+        let (rows, cols) = self.ctx.screen().size();
+        self.start_cast(Recorder::new(out, cols, rows)?)
+    }
+
+    fn start_cast(&mut self, mut recorder: Recorder) -> Result<()> {
+        let transcript = self.ctx.transcript.lock().unwrap();
+        recorder.output(transcript.as_bytes())?;
+        self.ctx.sinks.lock().unwrap().cast = Some(recorder);
+        Ok(())
+    }
+
+    /// Log each script command run from now on, with the output it produced
+    /// and how long it took, and every chunk of output, input, and wait, to
+    /// `path` as JSON Lines; see [`crate::events`].
+    ///
+    /// Output the program printed before the first command counts towards
+    /// the first command.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be created.
+    pub fn record_events(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.start_events(EventLog::create(path.as_ref())?)
+    }
+
+    /// Like [`Engine::record_events`], but write the log to `out`, e.g. a
+    /// pipe to a tool that follows the run live.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the output printed so far cannot be written.
+    pub fn record_events_to(&mut self, out: impl Write + Send + 'static) -> Result<()> {
+        self.start_events(EventLog::new(out))
+    }
+
+    fn start_events(&mut self, mut events: EventLog) -> Result<()> {
+        let transcript = self.ctx.transcript.lock().unwrap();
+        events.output(transcript.as_bytes())?;
+        self.ctx.sinks.lock().unwrap().events = Some(events);
+        Ok(())
+    }
+
+    /// Write the program's output from now on to `path` as plain text with a
+    /// timestamp per line, and with `include_input`, the lines sent to the
+    /// program too; see [`TranscriptWriter`].
+    ///
+    /// Output the program printed before this call opens the transcript.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be created.
+    pub fn record_transcript(&mut self, path: impl AsRef<Path>, include_input: bool) -> Result<()> {
+        let transcript = self.ctx.transcript.lock().unwrap();
+        let mut writer = TranscriptWriter::create(path, include_input)?;
+        writer.output(transcript.as_bytes())?;
+        self.ctx.sinks.lock().unwrap().transcript = Some(writer);
+        Ok(())
+    }
+
+    /// Save the images the program draws (sixel, kitty, iTerm2) as files in
+    /// `dir`, including those it drew before this call; see
+    /// [`ImageWriter`]. A transcript being written notes where each image
+    /// was saved.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created or an image
+    /// drawn earlier cannot be written.
+    pub fn save_images(&mut self, dir: impl AsRef<Path>) -> Result<()> {
+        let transcript = self.ctx.transcript.lock().unwrap();
+        let mut writer = ImageWriter::create(dir)?;
+        for image in ImageScanner::new().feed(transcript.as_bytes()) {
+            writer.save(&image)?;
         }
-        sleep(Duration::from_millis(300)).await;
+        self.ctx.sinks.lock().unwrap().images = Some(writer);
         Ok(())
     }
 
-    /// Wait for the child process to exit.
+    /// Limit the output, duration, and input of runs from now on; see
+    /// [`crate::quota`]. The duration counts from the start of
+    /// [`Engine::execute`].
+    pub fn set_quotas(&mut self, quotas: Quotas) {
+        self.ctx.quotas = quotas;
+    }
+
+    /// Record a span for every script command and every wait from now on;
+    /// see [`crate::trace`]. Call this before [`Engine::execute`].
+    pub fn enable_tracing(&mut self) {
+        self.ctx.trace = Some(Trace::new());
+    }
+
+    /// The spans recorded since [`Engine::enable_tracing`], if it was called.
+    pub fn trace(&self) -> Option<&Trace> {
+        self.ctx.trace.as_ref()
+    }
+
+    /// Memory and file descriptor usage after each `loop` iteration so far.
+    pub fn soak_report(&self) -> &SoakReport {
+        &self.ctx.soak
+    }
+
+    /// Wait until the program exits and return its exit code, also
+    /// available afterwards from [`Engine::exit_code`].
+    ///
+    /// Waits as long as it takes; wrap it in [`tokio::time::timeout`] to
+    /// bound the wait.
+    ///
+    /// ```no_run
+    /// use scriptty::Engine;
+    /// use std::time::Duration;
+    ///
+    /// # async fn example() -> anyhow::Result<()> {
+    /// let mut engine = Engine::builder("make").arg("test").spawn()?;
+    /// let code = tokio::time::timeout(Duration::from_secs(60), engine.wait()).await??;
+    /// assert_eq!(code, 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the child's status cannot be queried.
+    pub async fn wait(&mut self) -> Result<u32> {
+        loop {
+            if let Some(code) = self.try_status()? {
+                return Ok(code);
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Wait for the child process to exit, blocking the thread.
+    ///
+    /// The exit code is available afterwards from [`Engine::exit_code`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the child's status cannot be queried.
+    #[deprecated(note = "use the async `Engine::wait`, which also returns the exit code")]
     pub fn wait_for_exit(&mut self) -> Result<()> {
-        self.ctx.pty.wait()
+        while self.try_status()?.is_none() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        Ok(())
+    }
+
+    /// Stop the program gracefully: SIGTERM, then SIGKILL for the whole
+    /// process group if it hasn't exited after `grace`. Returns its exit
+    /// code, also available afterwards from [`Engine::exit_code`].
+    ///
+    /// The output the program printed while exiting is kept. Use
+    /// [`Engine::respawn`] to start it again.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the program's status cannot be read.
+    pub async fn kill(&mut self, grace: Duration) -> Result<u32> {
+        self.ctx.kill(grace).await
+    }
+
+    /// Kill the program, if it is still running, and reap it.
+    pub(crate) fn stop(&mut self) {
+        if self.ctx.pty.is_running() {
+            // Best effort: the program may exit on its own in the meantime.
+            let _ = self.ctx.pty.kill();
+            let _ = self.ctx.pty.wait();
+        }
+    }
+
+    /// The child's exit code, once it has been observed to exit (by
+    /// `expect_eof`, `assert_exit_code`, [`Engine::wait`], or
+    /// [`Engine::try_status`]).
+    pub fn exit_code(&self) -> Option<u32> {
+        self.ctx.exit_code()
+    }
+
+    /// Kill the program and start it again with the same command line.
+    ///
+    /// Output of the old session is discarded, except that with
+    /// `Some(label)` its transcript is kept as a snapshot retrievable through
+    /// [`Engine::snapshot`] and the `assert_snapshot` command.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the program cannot be spawned again.
+    pub async fn respawn(&mut self, snapshot: Option<&str>) -> Result<()> {
+        self.ctx.respawn(snapshot).await
+    }
+
+    /// Resize the terminal to `rows` x `cols`; the program receives SIGWINCH
+    /// and sees the new size, e.g. to reflow a TUI layout.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the PTY cannot be resized.
+    pub fn resize(&mut self, rows: u16, cols: u16) -> Result<()> {
+        self.ctx.resize(rows, cols)
+    }
+
+    /// Send the POSIX signal called `signal` (`"INT"`, `"SIGTSTP"`, ...) to
+    /// the program.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for unsupported signal names or if the signal
+    /// cannot be delivered.
+    pub fn send_signal(&mut self, signal: &str) -> Result<()> {
+        self.ctx.pty.signal(crate::commands::signal_number(signal)?)
+    }
+
+    /// The terminal screen as rendered from the program's output so far. See
+    /// [`Context::screen`].
+    pub fn screen(&self) -> vt100::Screen {
+        self.ctx.screen()
+    }
+
+    /// The session since the program was (re)spawned as timestamped events:
+    /// its output (and `show` text), the script's input, resizes, and the
+    /// `marker`s reached. Only the last 1 MiB of output is kept.
+    ///
+    /// Save it with [`Transcript::save`] to replay or inspect it later.
+    pub fn transcript(&self) -> Transcript {
+        self.ctx.sinks.lock().unwrap().log.transcript()
+    }
+
+    /// Everything the program printed since it was (re)spawned, escape
+    /// sequences included.
+    pub fn output(&self) -> String {
+        self.ctx.transcript.lock().unwrap().clone()
+    }
+
+    /// The transcript saved under `label` by a previous respawn.
+    pub fn snapshot(&self, label: &str) -> Option<&str> {
+        self.ctx.snapshot(label)
+    }
+
+    /// Check, without blocking, whether the child has exited, and return its
+    /// exit code if so.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the child's status cannot be queried.
+    pub fn try_status(&mut self) -> Result<Option<u32>> {
+        if self.ctx.exit_code.is_none()
+            && let Some(code) = self.ctx.pty.try_wait()?
+        {
+            self.ctx.set_exit_code(code);
+        }
+        Ok(self.ctx.exit_code)
+    }
+}
+
+/// Maximum bytes of output kept in a session's transcript.
+const TRANSCRIPT_LIMIT: usize = 1 << 20;
+
+/// Rows of scrollback kept when rendering the session as HTML.
+const HTML_SCROLLBACK: usize = 100_000;
+
+/// Maximum images kept waiting for `expect_image`; older ones are dropped.
+const MAX_PENDING_IMAGES: usize = 16;
+
+/// Maximum bells and notifications kept waiting for `expect_notification`;
+/// older ones are dropped, so a program ringing the bell in a loop cannot
+/// grow the queue without bound.
+const MAX_PENDING_NOTIFICATIONS: usize = 256;
+
+/// How often the output and duration quotas are checked while a command runs.
+const QUOTA_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Where the program's output is recorded, besides the buffers every session
+/// has; each sink is enabled through its `Engine::record_*` method.
+#[derive(Default)]
+pub(crate) struct Sinks {
+    pub cast: Option<Recorder>,
+    pub events: Option<EventLog>,
+    pub transcript: Option<TranscriptWriter>,
+    pub images: Option<ImageWriter>,
+    /// Handler set with [`Engine::on_event`].
+    pub observer: Option<EventHandler>,
+    /// Handlers added with [`Engine::add_handler`], after the one the
+    /// engine was spawned with.
+    pub handlers: Vec<OutputHandler>,
+    /// The session so far, for [`Engine::transcript`]; started over when the
+    /// program is respawned.
+    pub log: TranscriptLog,
+    /// Bytes the program printed in total, for the output quota.
+    pub output_bytes: u64,
+    /// Values masked in the recorded output; shared with [`Context`].
+    pub secrets: Secrets,
+    /// Set when the program was stopped for exceeding a quota: output still
+    /// queued from it is dropped instead of processed.
+    pub closed: bool,
+}
+
+impl Sinks {
+    /// Feed a chunk of the program's output to every enabled sink, and
+    /// count it towards the output quota.
+    pub(crate) fn output(&mut self, data: &[u8]) {
+        self.output_bytes += data.len() as u64;
+        self.display(data);
+    }
+
+    /// Feed a chunk of what the viewer sees to every enabled sink: the
+    /// program's output or text the script shows with [`Context::emit`].
+    ///
+    /// Handlers see the text as it was printed; the recordings see it with
+    /// secrets masked.
+    pub(crate) fn display(&mut self, data: &[u8]) {
+        for handler in &self.handlers {
+            handler(data);
+        }
+        let data = self.secrets.mask_bytes(data);
+        self.log.output(&data);
+        // Best effort: a full disk should not stop the run.
+        if let Some(cast) = self.cast.as_mut() {
+            let _ = cast.output(&data);
+        }
+        if let Some(events) = self.events.as_mut() {
+            let _ = events.output(&data);
+        }
+        if let Some(transcript) = self.transcript.as_mut() {
+            let _ = transcript.output(&data);
+        }
+        if let Some(observer) = &self.observer {
+            observer(&OutputEvent::ProgramOutput(data.to_vec()));
+        }
+    }
+
+    /// Pass `event` to the handler set with [`Engine::on_event`], if any.
+    pub(crate) fn event(&self, event: &OutputEvent) {
+        if let Some(observer) = &self.observer {
+            observer(event);
+        }
+    }
+
+    /// Save an image the program drew, if images are being saved.
+    pub(crate) fn image(&mut self, image: &Image) {
+        if let Some(Ok(path)) = self.images.as_mut().map(|images| images.save(image))
+            && let Some(transcript) = self.transcript.as_mut()
+        {
+            let _ = transcript.note(&format!("[image: {}]", path.display()));
+        }
+    }
+}
+
+/// A running child process and the task pumping its output into shared buffers.
+pub(crate) struct Session {
+    pub pty: PtySession,
+    pub output_buffer: Arc<Mutex<String>>,
+    pub notifications: Arc<Mutex<Vec<Notification>>>,
+    pub images: Arc<Mutex<Vec<Image>>>,
+    pub transcript: Arc<Mutex<String>>,
+    pub last_output: Arc<Mutex<Instant>>,
+    pub screen: Arc<Mutex<vt100::Parser>>,
+    pub output_task: OutputTask,
+}
+
+/// The task forwarding a session's output to its buffers and sinks.
+pub(crate) struct OutputTask {
+    handle: JoinHandle<()>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl OutputTask {
+    /// Whether the task has ended, after the end of the program's output.
+    pub(crate) fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Stop forwarding output. The task runs on a blocking thread and cannot
+    /// be cancelled outright, so it drops whatever it receives from now on
+    /// and ends with its next chunk.
+    pub(crate) fn abort(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        self.handle.abort();
+    }
+}
+
+impl Session {
+    /// Spawn the child described by `spawn` and start forwarding its output to
+    /// `handler`, fresh buffers, `interactions`, and `sinks`.
+    pub(crate) fn start(
+        spawn: &SpawnSpec,
+        handler: OutputHandler,
+        interactions: Arc<Mutex<InteractionLog>>,
+        sinks: Arc<Mutex<Sinks>>,
+        behavior: Arc<Mutex<Behavior>>,
+    ) -> Result<Self> {
+        let (pty, reader) = PtySession::spawn(&spawn.command, &spawn.args, &spawn.options)?;
+        let output_rx = crate::pty_reader::spawn_reader(reader);
+        let output_buffer = Arc::new(Mutex::new(String::new()));
+        let buffer_clone = output_buffer.clone();
+        let notifications = Arc::new(Mutex::new(Vec::new()));
+        let notifications_clone = notifications.clone();
+        let images = Arc::new(Mutex::new(Vec::new()));
+        let images_clone = images.clone();
+        let transcript = Arc::new(Mutex::new(String::new()));
+        let transcript_clone = transcript.clone();
+        let last_output = Arc::new(Mutex::new(Instant::now()));
+        let last_output_clone = last_output.clone();
+        let (rows, cols) = spawn.options.size.unwrap_or((24, 80));
+        let screen = Arc::new(Mutex::new(vt100::Parser::new(rows, cols, 0)));
+        let screen_clone = screen.clone();
+
+        let secrets = {
+            let mut sinks = sinks.lock().unwrap();
+            sinks.closed = false;
+            sinks.log = TranscriptLog::new(rows, cols);
+            sinks.secrets.clone()
+        };
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stopped_clone = stopped.clone();
+        let handle = tokio::task::spawn_blocking(move || {
+            let mut scanner = NotificationScanner::new();
+            let mut image_scanner = ImageScanner::new();
+            while let Ok(data) = output_rx.recv() {
+                if stopped_clone.load(Ordering::Relaxed) {
+                    break;
+                }
+                if sinks.lock().is_ok_and(|sinks| sinks.closed) {
+                    break;
+                }
+                if let Ok(mut last) = last_output_clone.lock() {
+                    *last = Instant::now();
+                }
+                handler(&data);
+                if let Ok(mut log) = interactions.lock() {
+                    log.record_output(&secrets.mask_bytes(&data));
+                }
+                if let Ok(mut screen) = screen_clone.lock() {
+                    screen.process(&data);
+                }
+                let found = scanner.feed(&data);
+                if !found.is_empty()
+                    && let Ok(mut pending) = notifications_clone.lock()
+                {
+                    pending.extend(found);
+                    let excess = pending.len().saturating_sub(MAX_PENDING_NOTIFICATIONS);
+                    pending.drain(..excess);
+                }
+                let found_images = image_scanner.feed(&data);
+                if !found_images.is_empty()
+                    && let Ok(mut pending) = images_clone.lock()
+                {
+                    pending.extend(found_images.iter().cloned());
+                    let excess = pending.len().saturating_sub(MAX_PENDING_IMAGES);
+                    pending.drain(..excess);
+                }
+                let text = String::from_utf8_lossy(&data);
+                if let Ok(mut buffer) = buffer_clone.lock() {
+                    let behavior = behavior.lock().map_or(Behavior::LATEST, |b| *b);
+                    buffer.push_str(&text);
+                    if buffer.len() > behavior.buffer_limit() {
+                        let cut = behavior.buffer_cut(buffer.len());
+                        drain_front(&mut buffer, cut);
+                    }
+                }
+                if let Ok(mut transcript) = transcript_clone.lock() {
+                    push_capped(&mut transcript, &text, TRANSCRIPT_LIMIT);
+                    // Under the transcript lock, so a sink started now (and
+                    // seeded with the transcript) sees each chunk once.
+                    if let Ok(mut sinks) = sinks.lock() {
+                        sinks.output(&data);
+                        for image in &found_images {
+                            sinks.image(image);
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(Session {
+            pty,
+            output_buffer,
+            notifications,
+            images,
+            transcript,
+            last_output,
+            screen,
+            output_task: OutputTask { handle, stopped },
+        })
+    }
+}
+
+/// Append `text` to `buffer`; once it grows past `limit`, drop the oldest half.
+fn push_capped(buffer: &mut String, text: &str, limit: usize) {
+    buffer.push_str(text);
+    if buffer.len() > limit {
+        drain_front(buffer, buffer.len() - limit / 2);
+    }
+}
+
+/// Drop the first `cut` bytes of `buffer`, and the rest of a character
+/// they end in.
+fn drain_front(buffer: &mut String, mut cut: usize) {
+    cut = cut.min(buffer.len());
+    while !buffer.is_char_boundary(cut) {
+        cut += 1;
+    }
+    buffer.drain(..cut);
+}
+
+/// Default output handler: write PTY output straight to stdout.
+pub(crate) fn write_stdout(data: &[u8]) {
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    stdout.write_all(data).ok();
+    stdout.flush().ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait() {
+        let mut engine = Engine::builder("sh")
+            .args(["-c", "sleep 0.2; exit 3"])
+            .handler(|_| {})
+            .spawn()
+            .unwrap();
+        assert_eq!(engine.try_status().unwrap(), None);
+        assert_eq!(engine.wait().await.unwrap(), 3);
+        assert_eq!(engine.try_status().unwrap(), Some(3));
+        assert_eq!(engine.exit_code(), Some(3));
+    }
+
+    #[tokio::test]
+    #[allow(deprecated)]
+    async fn test_wait_for_exit() {
+        let mut engine = Engine::builder("sh")
+            .args(["-c", "exit 4"])
+            .handler(|_| {})
+            .spawn()
+            .unwrap();
+        engine.wait_for_exit().unwrap();
+        assert_eq!(engine.exit_code(), Some(4));
+        assert_eq!(engine.try_status().unwrap(), Some(4));
+    }
+
+    #[tokio::test]
+    async fn test_on_event() {
+        let mut engine = Engine::builder("sh")
+            .args(["-c", "echo ready; read line; echo \"got $line\""])
+            .handler(|_| {})
+            .spawn()
+            .unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        engine.on_event(move |event| sink.lock().unwrap().push(event.clone()));
+        let script = "expect \"ready\"\nsend \"hi\"\nkey Enter\nexpect_eof\nshow \"shown\"";
+        engine
+            .execute(crate::parse_str(script).unwrap())
+            .await
+            .unwrap();
+
+        let events = events.lock().unwrap();
+        let position = |wanted: &OutputEvent| events.iter().position(|e| e == wanted).unwrap();
+        let started = position(&OutputEvent::CommandStarted {
+            name: "send",
+            index: 1,
+        });
+        let input = position(&OutputEvent::InputEcho(b"hi".to_vec()));
+        let exited = position(&OutputEvent::ProcessExited(0));
+        assert!(started < input && input < exited);
+        assert!(events.iter().any(|e| matches!(
+            e,
+            OutputEvent::CommandFinished {
+                name: "expect_eof",
+                index: 3,
+                error: None,
+                ..
+            }
+        )));
+        let output: Vec<u8> = events
+            .iter()
+            .filter_map(|e| match e {
+                OutputEvent::ProgramOutput(data) => Some(data.clone()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        let output = String::from_utf8_lossy(&output);
+        assert!(output.contains("got hi"), "got: {output}");
+        // Text the script shows reaches every sink, like the program's output.
+        assert!(output.contains("shown"), "got: {output}");
+        assert!(engine.transcript().to_cast().contains("shown"));
+    }
+
+    #[tokio::test]
+    async fn test_injected_commands_are_reported() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let path =
+            std::env::temp_dir().join(format!("scriptty-inject-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut engine = Engine::builder("sh")
+            .args(["-c", "sleep 2"])
+            .handler(|_| {})
+            .spawn()
+            .unwrap();
+        engine.listen_control(&path).unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        engine.on_event(move |event| sink.lock().unwrap().push(event.clone()));
+
+        let client = tokio::spawn({
+            let path = path.clone();
+            async move {
+                let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                let mut replies = Vec::new();
+                for line in ["inject show \"hi\"\n", "inject expect \"never\" 10ms\n"] {
+                    writer.write_all(line.as_bytes()).await.unwrap();
+                    replies.push(lines.next_line().await.unwrap().unwrap());
+                }
+                replies
+            }
+        });
+        let script = "wait 300ms\nwait 300ms\nshow \"last\"";
+        let result = engine.execute(crate::parse_str(script).unwrap()).await;
+        let replies = client.await.unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(replies[0], "ok");
+        assert!(replies[1].starts_with("error: "), "got: {}", replies[1]);
+        // A failing injected command fails the run, like a scripted one.
+        assert!(result.is_err());
+        let events = events.lock().unwrap();
+        let shows = events
+            .iter()
+            .filter(|e| matches!(e, OutputEvent::CommandFinished { name: "show", .. }))
+            .count();
+        assert_eq!(shows, 1);
+        assert!(events.iter().any(|e| matches!(
+            e,
+            OutputEvent::CommandFinished {
+                name: "expect",
+                error: Some(_),
+                ..
+            }
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_on_command() {
+        let mut engine = Engine::builder("sh")
+            .args(["-c", "echo ready; sleep 5"])
+            .handler(|_| {})
+            .spawn()
+            .unwrap();
+        let hooks = Arc::new(Mutex::new(Vec::new()));
+        let sink = hooks.clone();
+        engine.on_command(move |hook| {
+            let screen = hook.screen().contents();
+            sink.lock().unwrap().push((
+                hook.index,
+                hook.name,
+                hook.args.to_string(),
+                hook.is_finished(),
+                hook.error.is_some(),
+                screen.contains("ready"),
+            ));
+        });
+        let calls = Arc::new(Mutex::new(0));
+        let counter = calls.clone();
+        engine.on_command(move |_| *counter.lock().unwrap() += 1);
+        let script = "expect \"ready\"\nexpect \"missing\" 100ms";
+        assert!(
+            engine
+                .execute(crate::parse_str(script).unwrap())
+                .await
+                .is_err()
+        );
+
+        let hooks = hooks.lock().unwrap();
+        assert_eq!(hooks.len(), 4);
+        let (index, name, args, finished, failed, _) = &hooks[0];
+        assert_eq!((*index, *name, args.as_str()), (0, "expect", "\"ready\""));
+        assert!(!finished && !failed);
+        // The screen is taken after the command has matched.
+        assert!(hooks[1].3 && hooks[1].5);
+        assert_eq!(hooks[3].1, "expect");
+        assert_eq!(hooks[3].2, "\"missing\" 100ms");
+        assert!(hooks[3].3 && hooks[3].4);
+        assert_eq!(*calls.lock().unwrap(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_transcript() {
+        use crate::transcript::TranscriptEvent;
+
+        let mut engine = Engine::builder("sh")
+            .args(["-c", "echo ready; read line; echo \"got $line\""])
+            .handler(|_| {})
+            .spawn()
+            .unwrap();
+        let script = "expect \"ready\"\nmarker \"answer\"\nsend \"hi\"\nkey Enter\nexpect_eof";
+        engine
+            .execute(crate::parse_str(script).unwrap())
+            .await
+            .unwrap();
+
+        let transcript = engine.transcript();
+        let events = &transcript.events;
+        let marker = events
+            .iter()
+            .position(|e| matches!(e, TranscriptEvent::Marker { name, .. } if name == "answer"));
+        let input = events
+            .iter()
+            .position(|e| matches!(e, TranscriptEvent::Input { data, .. } if data == "hi"));
+        assert!(marker.is_some() && marker < input);
+        assert!(events.windows(2).all(|w| w[0].time() <= w[1].time()));
+        let screen = transcript.render(transcript.height, transcript.width);
+        assert!(screen.contents().contains("got hi"));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_scale_saturates() {
+        let mut engine = Engine::builder("true").handler(|_| {}).spawn().unwrap();
+        engine.set_timeout_scale(1e300).unwrap();
+        let scaled = engine.ctx.scale_timeout(Duration::from_secs(5));
+        assert!(scaled > Duration::from_secs(1 << 30));
+        let _deadline = tokio::time::Instant::now() + scaled;
+        assert_eq!(
+            engine.ctx.scale_timeout(Duration::MAX),
+            engine.ctx.scale_timeout(Duration::from_secs(5))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expect_regex() {
+        let mut engine = Engine::builder("sh")
+            .args(["-c", "echo version 4.2; sleep 1"])
+            .handler(|_| {})
+            .spawn()
+            .unwrap();
+        let script = r#"expect --regex "version (?<MAJOR>\d+)\.(\d+)""#;
+        engine
+            .execute(crate::parse_str(script).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(engine.ctx.var("MATCH"), Some("version 4.2"));
+        assert_eq!(engine.ctx.var("MAJOR"), Some("4"));
+        assert_eq!(engine.ctx.var("MATCH_1"), Some("4"));
+        assert_eq!(engine.ctx.var("MATCH_2"), Some("2"));
+    }
+
+    #[tokio::test]
+    async fn test_pending_notifications_capped() {
+        let mut engine = Engine::builder("sh")
+            .args([
+                "-c",
+                "i=0; while [ $i -lt 300 ]; do printf '\\a'; i=$((i+1)); done; echo done; sleep 1",
+            ])
+            .handler(|_| {})
+            .spawn()
+            .unwrap();
+        engine
+            .execute(crate::parse_str(r#"expect "done""#).unwrap())
+            .await
+            .unwrap();
+        let pending = engine.ctx.notifications.lock().unwrap().len();
+        assert_eq!(pending, MAX_PENDING_NOTIFICATIONS);
+    }
+
+    #[tokio::test]
+    async fn test_startup_policy_first_run_only() {
+        let mut engine = Engine::builder("sh")
+            .args(["-c", "echo ready; cat"])
+            .handler(|_| {})
+            .spawn()
+            .unwrap();
+        engine.set_startup_policy(StartupPolicy::Pattern("ready".to_string()));
+        engine.set_timeout_scale(0.1).unwrap();
+        engine
+            .execute(crate::parse_str(r#"expect "ready""#).unwrap())
+            .await
+            .unwrap();
+        // "ready" is gone from the buffer; a second wait would time out.
+        engine
+            .execute(crate::parse_str(r#"send "again""#).unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_behavior_profiles() {
+        let shown = Arc::new(Mutex::new(Vec::new()));
+        let sink = shown.clone();
+        let mut engine = Engine::builder("sh")
+            .args(["-c", "cat"])
+            .handler(move |data| sink.lock().unwrap().extend_from_slice(data))
+            .spawn()
+            .unwrap();
+        let script = "set X \"expanded\"\nsend \"${X}\\n\"\nexpect \"expanded\"\nshow \"note\"\n";
+        engine
+            .execute(crate::parse_str(script).unwrap())
+            .await
+            .unwrap();
+        assert!(String::from_utf8_lossy(&shown.lock().unwrap()).contains("note\n"));
+
+        // 0.1 had no variables: `${X}` reaches the program as written.
+        let script = "set behavior 0.1\nsend \"${X}\\n\"\nexpect \"${X}\"\n";
+        engine
+            .execute(crate::parse_str(script).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(engine.ctx.behavior(), Behavior::V0_1);
+    }
+
+    #[tokio::test]
+    async fn test_respawn() {
+        let mut engine = Engine::builder("sh")
+            .args(["-c", "echo started; sleep 5"])
+            .handler(|_| {})
+            .spawn()
+            .unwrap();
+        engine
+            .execute(crate::parse_str(r#"expect "started""#).unwrap())
+            .await
+            .unwrap();
+        let start = std::time::Instant::now();
+        engine.respawn(Some("first")).await.unwrap();
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(engine.snapshot("first").unwrap().contains("started"));
+        engine
+            .execute(crate::parse_str(r#"expect "started""#).unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sudo_masks_password() {
+        let dir = std::env::temp_dir().join(format!("scriptty-sudo-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sudo = dir.join("sudo");
+        std::fs::write(
+            &sudo,
+            "#!/bin/sh\nprintf 'Password: '\nread -r pw\n\
+             if [ \"$pw\" = hunter2 ]; then \"$@\"; else echo 'Sorry, try again'; fi\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&sudo, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+            .unwrap();
+        let path = format!("{}:{}", dir.display(), std::env::var("PATH").unwrap());
+        let spawn = |password: &str| {
+            let mut engine = Engine::builder("sh")
+                .env("PATH", path.as_str())
+                .env("PS1", "prompt> ")
+                .env("ENV", "")
+                .args(["-i"])
+                .handler(|_| {})
+                .spawn()
+                .unwrap();
+            engine.record_interactions();
+            engine.set_var("SUDO_PASSWORD", password);
+            engine
+        };
+
+        let mut engine = spawn("hunter2");
+        let script = "expect \"prompt> \"\nsudo \"echo done\" 2s\nsudo \"sleep 5\" 300ms\n";
+        let err = engine
+            .execute(crate::parse_str(script).unwrap())
+            .await
+            .unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("sudo command did not finish"),
+            "got: {err:#}"
+        );
+        assert!(!engine.accessible_transcript().contains("hunter2"));
+        assert!(!engine.transcript().to_cast().contains("hunter2"));
+
+        let mut engine = spawn("wrong");
+        let script = "expect \"prompt> \"\nsudo \"echo done\" 2s\n";
+        let err = engine
+            .execute(crate::parse_str(script).unwrap())
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("Sorry"), "got: {err:#}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_secrets_masked_in_reports() {
+        let mut engine = Engine::builder("sh")
+            .args(["-c", "echo ready; sleep 1"])
+            .handler(|_| {})
+            .spawn()
+            .unwrap();
+        let transcript = std::env::temp_dir().join(format!(
+            "scriptty-secrets-transcript-{}.txt",
+            std::process::id()
+        ));
+        engine.record_transcript(&transcript, false).unwrap();
+        engine.set_var("SUDO_PASSWORD", "hunter2");
+        let report = Arc::new(Mutex::new(None));
+        let report_path = report.clone();
+        engine.on_failure(move |path| *report_path.lock().unwrap() = Some(path.to_path_buf()));
+        let script = "debug_state\nexpect \"hunter2\" 100ms\n";
+        assert!(
+            engine
+                .execute(crate::parse_str(script).unwrap())
+                .await
+                .is_err()
+        );
+        engine.ctx.sinks.lock().unwrap().transcript = None;
+        let output = std::fs::read_to_string(&transcript).unwrap();
+        std::fs::remove_file(&transcript).unwrap();
+        assert!(
+            output.contains("SUDO_PASSWORD = \"********\"") && !output.contains("hunter2"),
+            "got: {output}"
+        );
+        let path = report.lock().unwrap().take().unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions();
+        std::fs::remove_file(&path).unwrap();
+        assert!(
+            text.contains("'********'") && !text.contains("hunter2"),
+            "got: {text}"
+        );
+        assert_eq!(
+            std::os::unix::fs::PermissionsExt::mode(&mode) & 0o777,
+            0o600
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_handler() {
+        let primary = Arc::new(Mutex::new(Vec::new()));
+        let sink = primary.clone();
+        let mut engine = Engine::builder("sh")
+            .args(["-c", "echo first; read line; echo second"])
+            .handler(move |data| sink.lock().unwrap().extend_from_slice(data))
+            .spawn()
+            .unwrap();
+        engine
+            .execute(crate::parse_str("expect \"first\"").unwrap())
+            .await
+            .unwrap();
+        let captures: Vec<_> = (0..2).map(|_| Arc::new(Mutex::new(Vec::new()))).collect();
+        for capture in &captures {
+            let sink = capture.clone();
+            engine.add_handler(move |data| sink.lock().unwrap().extend_from_slice(data));
+        }
+        let script = "show \"shown\"\nkey Enter\nexpect_eof";
+        engine
+            .execute(crate::parse_str(script).unwrap())
+            .await
+            .unwrap();
+
+        for output in captures.iter().chain([&primary]) {
+            let output = String::from_utf8_lossy(&output.lock().unwrap()).into_owned();
+            for text in ["first", "shown", "second"] {
+                assert!(output.contains(text), "{text} missing from {output:?}");
+            }
+        }
     }
 }
@@ -0,0 +1,249 @@
+//! A machine-readable log of a run.
+//!
+//! The event log (see [`crate::Engine::record_events`]) has one JSON object
+//! per line (JSON Lines), with the kind of event in `"event"`. Each top-level
+//! script command produces a `command_start` event when it starts and a
+//! `command` event once it has finished:
+//!
+//! ```json
+//! {"event":"command_start","index":3,"command":"expect","time":1.204}
+//! {"event":"command","index":3,"command":"expect","start":1.204,"duration":0.311,"output":"$ ","error":null}
+//! ```
+//!
+//! `index` counts the commands from 0, `start` and `duration` are in seconds
+//! from the start of the run, `output` is what the program printed while the
+//! command ran, and `error` is the command's error message if it failed.
+//!
+//! In between, the log has an event for every chunk of output, every write
+//! to the program, and every wait for a pattern, with its `time` in seconds
+//! from the start of the run:
+//!
+//! ```json
+//! {"event":"output","time":1.398,"data":"$ "}
+//! {"event":"input","time":1.52,"data":"ls\r"}
+//! {"event":"expect","time":1.515,"pattern":"$ ","wait":0.311,"matched":true}
+//! ```
+//!
+//! Output the program printed before the run started has time 0. Logs of two
+//! runs can be compared with [`crate::compare`].
+
+use anyhow::{Context as _, Result};
+use serde_json::json;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Writes the events of a run as JSON Lines.
+pub(crate) struct EventLog {
+    out: Box<dyn Write + Send>,
+    /// When the run started; event times count from here.
+    run_start: Option<Instant>,
+    /// Output received since the last command finished.
+    output: Vec<u8>,
+    /// Bytes of a UTF-8 character split across output chunks, held back
+    /// from the `output` events until the rest arrives.
+    pending: Vec<u8>,
+    /// Number of commands logged so far.
+    commands: usize,
+}
+
+impl EventLog {
+    /// Create the log file at `path`.
+    pub(crate) fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create event log: {}", path.display()))?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+
+    pub(crate) fn new(out: impl Write + Send + 'static) -> Self {
+        Self {
+            out: Box::new(out),
+            run_start: None,
+            output: Vec::new(),
+            pending: Vec::new(),
+            commands: 0,
+        }
+    }
+
+    /// Count event times from `start`, when the run started.
+    pub(crate) fn set_run_start(&mut self, start: Instant) {
+        self.run_start = Some(start);
+    }
+
+    /// Log a chunk of program output and collect it for the running command.
+    pub(crate) fn output(&mut self, data: &[u8]) -> Result<()> {
+        self.output.extend_from_slice(data);
+        match crate::recorder::take_complete_utf8(&mut self.pending, data) {
+            Some(text) => self.write(json!({
+                "event": "output",
+                "time": self.time(),
+                "data": text,
+            })),
+            None => Ok(()),
+        }
+    }
+
+    /// Log bytes sent to the program.
+    pub(crate) fn input(&mut self, data: &[u8]) -> Result<()> {
+        self.write(json!({
+            "event": "input",
+            "time": self.time(),
+            "data": String::from_utf8_lossy(data),
+        }))
+    }
+
+    /// Log a wait for `pattern` that took `wait` and found it if `matched`.
+    pub(crate) fn expect(&mut self, pattern: &str, wait: Duration, matched: bool) -> Result<()> {
+        self.write(json!({
+            "event": "expect",
+            "time": self.time(),
+            "pattern": pattern,
+            "wait": seconds(wait),
+            "matched": matched,
+        }))
+    }
+
+    /// Log that the command `name` starts.
+    pub(crate) fn command_start(&mut self, name: &str) -> Result<()> {
+        self.write(json!({
+            "event": "command_start",
+            "index": self.commands,
+            "command": name,
+            "time": self.time(),
+        }))
+    }
+
+    /// Log that the command `name` ran from `start` for `duration` (both
+    /// measured from the start of the run) with `result`.
+    pub(crate) fn command(
+        &mut self,
+        name: &str,
+        start: Duration,
+        duration: Duration,
+        result: &Result<()>,
+    ) -> Result<()> {
+        let output = String::from_utf8_lossy(&std::mem::take(&mut self.output)).into_owned();
+        let event = json!({
+            "event": "command",
+            "index": self.commands,
+            "command": name,
+            "start": seconds(start),
+            "duration": seconds(duration),
+            "output": output,
+            "error": result.as_ref().err().map(|e| format!("{:#}", e)),
+        });
+        self.commands += 1;
+        self.write(event)
+    }
+
+    /// Seconds since the start of the run; 0 before it started.
+    fn time(&self) -> f64 {
+        self.run_start.map_or(0.0, |start| seconds(start.elapsed()))
+    }
+
+    fn write(&mut self, event: serde_json::Value) -> Result<()> {
+        writeln!(self.out, "{}", event).context("Failed to write event log")?;
+        // Flush every event so the log is usable even if the run is killed.
+        self.out.flush().context("Failed to write event log")
+    }
+}
+
+/// `duration` in seconds, rounded to microseconds.
+fn seconds(duration: Duration) -> f64 {
+    duration.as_micros() as f64 / 1e6
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use serde_json::Value;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_command_events() {
+        let out = Shared::default();
+        let mut log = EventLog::new(out.clone());
+        log.output(b"$ ").unwrap();
+        log.command(
+            "expect",
+            Duration::from_millis(100),
+            Duration::from_millis(250),
+            &Ok(()),
+        )
+        .unwrap();
+        log.command(
+            "expect",
+            Duration::from_millis(350),
+            Duration::from_secs(1),
+            &Err(anyhow!("Timeout waiting for pattern: 'ok'")),
+        )
+        .unwrap();
+
+        let text = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events[0]["event"], "output");
+        assert_eq!(events[1]["event"], "command");
+        assert_eq!(events[1]["index"], 0);
+        assert_eq!(events[1]["command"], "expect");
+        assert_eq!(events[1]["start"], 0.1);
+        assert_eq!(events[1]["duration"], 0.25);
+        assert_eq!(events[1]["output"], "$ ");
+        assert_eq!(events[1]["error"], Value::Null);
+        assert_eq!(events[2]["index"], 1);
+        assert_eq!(events[2]["output"], "");
+        assert_eq!(events[2]["error"], "Timeout waiting for pattern: 'ok'");
+    }
+
+    #[test]
+    fn test_stream_events() {
+        let out = Shared::default();
+        let mut log = EventLog::new(out.clone());
+        // Output from before the run has time 0.
+        log.output(b"login: ").unwrap();
+        log.set_run_start(Instant::now() + Duration::from_secs(60));
+        log.command_start("type").unwrap();
+        log.input(b"alice\r").unwrap();
+        // "é" split across two chunks.
+        log.output(b"caf\xc3").unwrap();
+        log.output(b"\xa9").unwrap();
+        log.expect("caf", Duration::from_millis(120), true).unwrap();
+
+        let text = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events.len(), 6);
+        assert_eq!(events[0]["time"], 0.0);
+        assert_eq!(events[1]["event"], "command_start");
+        assert_eq!(events[1]["index"], 0);
+        assert_eq!(events[1]["command"], "type");
+        assert_eq!(events[1]["time"], 0.0);
+        assert_eq!(events[2]["event"], "input");
+        assert_eq!(events[2]["data"], "alice\r");
+        assert_eq!(events[3]["data"], "caf");
+        assert_eq!(events[4]["data"], "é");
+        assert_eq!(events[5]["event"], "expect");
+        assert_eq!(events[5]["pattern"], "caf");
+        assert_eq!(events[5]["wait"], 0.12);
+        assert_eq!(events[5]["matched"], true);
+    }
+}
@@ -0,0 +1,35 @@
+//! Fuzz mode: seeded randomization of script timing and ordering.
+//!
+//! Running the same script many times with different seeds shakes out timing
+//! assumptions in the program under test. Every random choice the engine makes
+//! is drawn from one seeded generator, so a failing seed reproduces the run.
+
+use std::time::Duration;
+
+/// Randomization settings for a fuzzed run.
+///
+/// When enabled on an [`crate::Engine`]:
+/// - each `type` command runs at a random speed within `typing_speed`,
+/// - a random pause of up to `max_extra_wait` is inserted before each command,
+/// - `any_order` blocks run their commands in a random order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fuzz {
+    /// Seed for every random choice made during the run.
+    pub seed: u64,
+    /// Range of typing speed multipliers (`0.5` types at half speed).
+    pub typing_speed: (f64, f64),
+    /// Upper bound of the random pause inserted before each command.
+    pub max_extra_wait: Duration,
+}
+
+impl Fuzz {
+    /// Fuzz settings with default ranges (typing 0.25–4× speed, up to 200 ms
+    /// extra wait) and the given seed.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            typing_speed: (0.25, 4.0),
+            max_extra_wait: Duration::from_millis(200),
+        }
+    }
+}
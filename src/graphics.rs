@@ -0,0 +1,600 @@
+//! Images drawn by the program with terminal graphics protocols.
+//!
+//! Image viewers and plotting tools for the terminal (`viu`, `chafa`,
+//! `timg`, ...) draw with escape sequences that carry a whole image:
+//!
+//! - sixel: `ESC P ... q <sixel data> ESC \`
+//! - the kitty graphics protocol: `ESC _G <keys> ; <base64> ESC \`, possibly
+//!   split into chunks
+//! - iTerm2 inline images: `ESC ] 1337 ; File = <keys> : <base64> BEL`
+//!
+//! The output handler and casts get these sequences unchanged, so a terminal
+//! or player that supports the protocol shows the image. Plain-text outputs
+//! (transcripts, expects) drop them. An [`ImageWriter`] decodes each image
+//! and saves it as a file, sixel and raw pixel data as PNG.
+
+use anyhow::{Context as _, Result, anyhow};
+use std::path::{Path, PathBuf};
+
+/// Longest escape sequence collected; longer ones are skipped.
+const MAX_SEQUENCE_LEN: usize = 32 << 20;
+
+/// Largest sixel image decoded, in pixels along each side.
+const MAX_SIXEL_SIDE: usize = 8192;
+
+/// The protocol an image was drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageProtocol {
+    Sixel,
+    Kitty,
+    Iterm2,
+}
+
+/// A decoded image: the contents of an image file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub protocol: ImageProtocol,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    /// The file extension for the image's format: `png`, `jpg`, `gif`, or
+    /// `bin` when the format is not recognized.
+    pub fn extension(&self) -> &'static str {
+        if self.data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            "png"
+        } else if self.data.starts_with(&[0xff, 0xd8, 0xff]) {
+            "jpg"
+        } else if self.data.starts_with(b"GIF8") {
+            "gif"
+        } else {
+            "bin"
+        }
+    }
+
+    /// How alike this image and the PNG file `expected` look, from 0.0 to
+    /// 1.0: one minus the mean difference of their color and alpha channels.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either image is not a PNG or their sizes differ.
+    pub fn similarity(&self, expected: &[u8]) -> Result<f64> {
+        let (width, height, actual) =
+            crate::png::decode(&self.data).context("Failed to decode the program's image")?;
+        let (expected_width, expected_height, expected) =
+            crate::png::decode(expected).context("Failed to decode the expected image")?;
+        if (width, height) != (expected_width, expected_height) {
+            return Err(anyhow!(
+                "Image is {}x{} pixels, expected {}x{}",
+                width,
+                height,
+                expected_width,
+                expected_height
+            ));
+        }
+        if actual.is_empty() {
+            return Ok(1.0);
+        }
+        let total: u64 = actual
+            .iter()
+            .zip(&expected)
+            .map(|(a, b)| u64::from(a.abs_diff(*b)))
+            .sum();
+        Ok(1.0 - total as f64 / (actual.len() as f64 * 255.0))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Ground,
+    Escape,
+    /// Inside a string sequence introduced by `ESC` and this byte.
+    Sequence(u8),
+    /// An `ESC` inside a string sequence, possibly starting its terminator.
+    SequenceEscape(u8),
+}
+
+/// Incremental scanner that finds images in a byte stream, including
+/// sequences split across chunks.
+#[derive(Debug)]
+pub(crate) struct ImageScanner {
+    state: State,
+    payload: Vec<u8>,
+    /// Keys and base64 data of a kitty image whose chunks are still arriving.
+    kitty: Option<(String, Vec<u8>)>,
+}
+
+impl ImageScanner {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: State::Ground,
+            payload: Vec::new(),
+            kitty: None,
+        }
+    }
+
+    /// Scan the next chunk of output and return the images it completes.
+    pub(crate) fn feed(&mut self, data: &[u8]) -> Vec<Image> {
+        let mut found = Vec::new();
+        for &byte in data {
+            self.state = match (self.state, byte) {
+                (State::Ground, 0x1b) => State::Escape,
+                (State::Ground, _) => State::Ground,
+                (State::Escape, b'P' | b'_' | b']') => {
+                    self.payload.clear();
+                    State::Sequence(byte)
+                }
+                (State::Escape, 0x1b) => State::Escape,
+                (State::Escape, _) => State::Ground,
+                (State::Sequence(b']'), 0x07) => {
+                    found.extend(self.finish(b']'));
+                    State::Ground
+                }
+                (State::Sequence(kind), 0x1b) => State::SequenceEscape(kind),
+                (State::Sequence(kind), _) => {
+                    if self.payload.len() <= MAX_SEQUENCE_LEN {
+                        self.payload.push(byte);
+                    }
+                    State::Sequence(kind)
+                }
+                (State::SequenceEscape(kind), b'\\') => {
+                    found.extend(self.finish(kind));
+                    State::Ground
+                }
+                // A new escape sequence interrupted this one; drop it.
+                (State::SequenceEscape(_), b'P' | b'_' | b']') => {
+                    self.payload.clear();
+                    State::Sequence(byte)
+                }
+                (State::SequenceEscape(_), _) => State::Ground,
+            };
+        }
+        found
+    }
+
+    fn finish(&mut self, kind: u8) -> Option<Image> {
+        if self.payload.len() > MAX_SEQUENCE_LEN {
+            return None;
+        }
+        let payload = std::mem::take(&mut self.payload);
+        match kind {
+            b'P' => decode_sixel(&payload).map(|data| Image {
+                protocol: ImageProtocol::Sixel,
+                data,
+            }),
+            b'_' => self.kitty_chunk(payload.strip_prefix(b"G")?),
+            _ => decode_iterm2(&payload).map(|data| Image {
+                protocol: ImageProtocol::Iterm2,
+                data,
+            }),
+        }
+    }
+
+    /// Collect a kitty graphics command; the image is complete once a chunk
+    /// without `m=1` arrives.
+    fn kitty_chunk(&mut self, command: &[u8]) -> Option<Image> {
+        let command = String::from_utf8_lossy(command);
+        let (keys, data) = command.split_once(';').unwrap_or((&command, ""));
+        let more = keys.split(',').any(|key| key == "m=1");
+        // Later chunks only carry `m`; the first one has the image's keys.
+        self.kitty
+            .get_or_insert_with(|| (keys.to_string(), Vec::new()))
+            .1
+            .extend_from_slice(data.as_bytes());
+        if more {
+            return None;
+        }
+        let (keys, collected) = self.kitty.take()?;
+        decode_kitty(&keys, &collected).map(|data| Image {
+            protocol: ImageProtocol::Kitty,
+            data,
+        })
+    }
+}
+
+/// Saves the images the program draws into a directory, numbered in the
+/// order they appear (`image-001.png`, ...).
+pub struct ImageWriter {
+    dir: PathBuf,
+    count: usize,
+}
+
+impl ImageWriter {
+    /// Save images into `dir`, creating it if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created.
+    pub fn create(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create image directory: {}", dir.display()))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            count: 0,
+        })
+    }
+
+    /// Save the next image, returning its path.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn save(&mut self, image: &Image) -> Result<PathBuf> {
+        self.count += 1;
+        let path = self
+            .dir
+            .join(format!("image-{:03}.{}", self.count, image.extension()));
+        std::fs::write(&path, &image.data)
+            .with_context(|| format!("Failed to write image: {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// Decode an iTerm2 `1337;File=...:<base64>` payload to the file it carries.
+fn decode_iterm2(payload: &[u8]) -> Option<Vec<u8>> {
+    let rest = payload.strip_prefix(b"1337;File=")?;
+    let colon = rest.iter().position(|&b| b == b':')?;
+    let keys = String::from_utf8_lossy(&rest[..colon]);
+    // Only inline images are drawn; other files are downloads.
+    if !keys.split(';').any(|key| key == "inline=1") {
+        return None;
+    }
+    base64_decode(&rest[colon + 1..])
+}
+
+/// Decode a kitty graphics image sent directly (not by file name) as PNG
+/// (`f=100`) or as uncompressed RGB or RGBA pixels (`f=24`, `f=32`).
+fn decode_kitty(keys: &str, data: &[u8]) -> Option<Vec<u8>> {
+    let key = |name: &str| {
+        keys.split(',')
+            .find_map(|key| key.strip_prefix(name)?.strip_prefix('='))
+    };
+    // Queries (`a=q`) and placements of images sent earlier carry no image.
+    if !matches!(key("a").unwrap_or("t"), "t" | "T")
+        || key("t").is_some_and(|t| t != "d")
+        || key("o").is_some()
+    {
+        return None;
+    }
+    let data = base64_decode(data)?;
+    let channels = match key("f").unwrap_or("32") {
+        "100" => return Some(data),
+        "24" => 3,
+        "32" => 4,
+        _ => return None,
+    };
+    let width: usize = key("s")?.parse().ok()?;
+    let height: usize = key("v")?.parse().ok()?;
+    if data.len() != width.checked_mul(height)?.checked_mul(channels)? {
+        return None;
+    }
+    let rgba: Vec<u8> = if channels == 4 {
+        data
+    } else {
+        data.chunks(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 0xff])
+            .collect()
+    };
+    crate::png::encode(
+        u32::try_from(width).ok()?,
+        u32::try_from(height).ok()?,
+        &rgba,
+    )
+    .ok()
+}
+
+/// The VT340's default sixel palette.
+const SIXEL_PALETTE: [[u8; 3]; 16] = [
+    [0, 0, 0],
+    [51, 51, 204],
+    [204, 36, 36],
+    [51, 204, 51],
+    [204, 51, 204],
+    [51, 204, 204],
+    [204, 204, 51],
+    [135, 135, 135],
+    [66, 66, 66],
+    [84, 84, 153],
+    [153, 66, 66],
+    [84, 153, 84],
+    [153, 84, 153],
+    [84, 153, 153],
+    [153, 153, 84],
+    [204, 204, 204],
+];
+
+/// Decode a sixel DCS payload (`P1;P2;P3 q` and the sixel data) to a PNG.
+///
+/// Pixels the image does not paint are transparent when `P2` is 1 and
+/// black otherwise.
+fn decode_sixel(payload: &[u8]) -> Option<Vec<u8>> {
+    let q = payload.iter().position(|&b| b == b'q')?;
+    let params = &payload[..q];
+    if !params.iter().all(|b| b.is_ascii_digit() || *b == b';') {
+        return None;
+    }
+    let transparent = params.split(|&b| b == b';').nth(1) == Some(b"1");
+    let data = &payload[q + 1..];
+
+    let mut palette: Vec<[u8; 3]> = SIXEL_PALETTE.to_vec();
+    palette.resize(256, [0, 0, 0]);
+    let mut color = 0usize;
+    let (mut x, mut y) = (0usize, 0usize);
+    let (mut width, mut height) = (0usize, 0usize);
+    // Painted pixels as palette colors, row by row; grown as needed.
+    let mut pixels: Vec<Vec<Option<[u8; 3]>>> = Vec::new();
+
+    let mut i = 0;
+    // The numeric parameters following position `i`.
+    let numbers = |i: &mut usize| -> Vec<usize> {
+        let mut numbers = vec![0usize];
+        while let Some(&b) = data.get(*i) {
+            match b {
+                b'0'..=b'9' => {
+                    let last = numbers.last_mut().expect("never empty");
+                    *last = last.saturating_mul(10).saturating_add((b - b'0') as usize);
+                }
+                b';' => numbers.push(0),
+                _ => break,
+            }
+            *i += 1;
+        }
+        numbers
+    };
+    while i < data.len() {
+        let byte = data[i];
+        i += 1;
+        match byte {
+            b'"' => {
+                let raster = numbers(&mut i);
+                width = width.max(raster.get(2).copied().unwrap_or(0).min(MAX_SIXEL_SIDE));
+                height = height.max(raster.get(3).copied().unwrap_or(0).min(MAX_SIXEL_SIDE));
+            }
+            b'#' => {
+                let args = numbers(&mut i);
+                color = args[0] % palette.len();
+                if let [_, system, a, b, c, ..] = args[..] {
+                    palette[color] = match system {
+                        1 => hls_to_rgb(a, b, c),
+                        _ => [a, b, c].map(|v| (v.min(100) * 255 / 100) as u8),
+                    };
+                }
+            }
+            b'!' => {
+                let count = numbers(&mut i)[0].max(1);
+                if let Some(&sixel @ b'?'..=b'~') = data.get(i) {
+                    i += 1;
+                    paint(&mut pixels, &mut x, y, count, sixel, palette[color])?;
+                }
+            }
+            b'$' => x = 0,
+            b'-' => {
+                x = 0;
+                y += 6;
+            }
+            b'?'..=b'~' => paint(&mut pixels, &mut x, y, 1, byte, palette[color])?,
+            _ => {}
+        }
+    }
+    let height = height.max(pixels.len());
+    let width = width.max(pixels.iter().map(Vec::len).max().unwrap_or(0));
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let background = if transparent {
+        [0, 0, 0, 0]
+    } else {
+        [0, 0, 0, 0xff]
+    };
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for row in 0..height {
+        for col in 0..width {
+            rgba.extend_from_slice(&match pixels
+                .get(row)
+                .and_then(|r| r.get(col))
+                .copied()
+                .flatten()
+            {
+                Some([r, g, b]) => [r, g, b, 0xff],
+                None => background,
+            });
+        }
+    }
+    crate::png::encode(
+        u32::try_from(width).ok()?,
+        u32::try_from(height).ok()?,
+        &rgba,
+    )
+    .ok()
+}
+
+/// Paint `count` columns of the six-pixel `sixel` at `x`, `y` and advance `x`;
+/// `None` if the image grows beyond [`MAX_SIXEL_SIDE`].
+fn paint(
+    pixels: &mut Vec<Vec<Option<[u8; 3]>>>,
+    x: &mut usize,
+    y: usize,
+    count: usize,
+    sixel: u8,
+    color: [u8; 3],
+) -> Option<()> {
+    let end = x.checked_add(count)?;
+    if end > MAX_SIXEL_SIDE || y + 6 > MAX_SIXEL_SIDE {
+        return None;
+    }
+    let bits = sixel - b'?';
+    for bit in 0..6 {
+        if bits & (1 << bit) == 0 {
+            continue;
+        }
+        let row = y + bit;
+        if pixels.len() <= row {
+            pixels.resize(row + 1, Vec::new());
+        }
+        let row = &mut pixels[row];
+        if row.len() < end {
+            row.resize(end, None);
+        }
+        row[*x..end].fill(Some(color));
+    }
+    *x = end;
+    Some(())
+}
+
+/// Convert a sixel HLS color (hue in degrees with blue at 0, lightness and
+/// saturation in percent) to RGB.
+fn hls_to_rgb(hue: usize, lightness: usize, saturation: usize) -> [u8; 3] {
+    // Sixel hues start at blue; the usual HSL wheel starts at red.
+    let h = ((hue + 240) % 360) as f64 / 360.0;
+    let l = lightness.min(100) as f64 / 100.0;
+    let s = saturation.min(100) as f64 / 100.0;
+    let q = if l < 0.5 {
+        l * (1.0 + s)
+    } else {
+        l + s - l * s
+    };
+    let p = 2.0 * l - q;
+    let channel = |t: f64| {
+        let t = t.rem_euclid(1.0);
+        let v = if t < 1.0 / 6.0 {
+            p + (q - p) * 6.0 * t
+        } else if t < 0.5 {
+            q
+        } else if t < 2.0 / 3.0 {
+            p + (q - p) * (2.0 / 3.0 - t) * 6.0
+        } else {
+            p
+        };
+        (v * 255.0).round() as u8
+    };
+    [channel(h + 1.0 / 3.0), channel(h), channel(h - 1.0 / 3.0)]
+}
+
+/// Decode standard base64, ignoring whitespace; `None` if `data` is not
+/// base64.
+fn base64_decode(data: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for &byte in data {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            b if b.is_ascii_whitespace() => continue,
+            _ => return None,
+        };
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The width, height, and first pixel's RGBA of a PNG.
+    fn png_info(png: &[u8]) -> (u32, u32, [u8; 4]) {
+        let (width, height, rgba) = crate::png::decode(png).unwrap();
+        (width, height, rgba[..4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_base64_decode() {
+        assert_eq!(base64_decode(b"aGVsbG8=").unwrap(), b"hello");
+        assert_eq!(base64_decode(b"aGVs\nbG8").unwrap(), b"hello");
+        assert!(base64_decode(b"a*b").is_none());
+    }
+
+    #[test]
+    fn test_sixel() {
+        let mut scanner = ImageScanner::new();
+        // A red 2x6 column pair, drawn with an RGB color; the sequence is
+        // split across chunks.
+        assert!(
+            scanner
+                .feed(b"text\x1bP0;1;0q\"1;1;2;6#1;2;100;0;0")
+                .is_empty()
+        );
+        let images = scanner.feed(b"#1!2~\x1b\\more");
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].protocol, ImageProtocol::Sixel);
+        assert_eq!(images[0].extension(), "png");
+        assert_eq!(png_info(&images[0].data), (2, 6, [255, 0, 0, 255]));
+        assert_eq!(hls_to_rgb(120, 50, 100), [255, 0, 0]);
+        // Other DCS strings are not images.
+        assert!(scanner.feed(b"\x1bP$qm\x1b\\").is_empty());
+    }
+
+    #[test]
+    fn test_kitty() {
+        let mut scanner = ImageScanner::new();
+        // A 1x1 RGB pixel, in two chunks.
+        assert!(
+            scanner
+                .feed(b"\x1b_Ga=T,f=24,s=1,v=1,m=1;AP8\x1b\\")
+                .is_empty()
+        );
+        let images = scanner.feed(b"\x1b_Gm=0;A\x1b\\");
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].protocol, ImageProtocol::Kitty);
+        assert_eq!(png_info(&images[0].data), (1, 1, [0, 255, 0, 255]));
+        // A PNG is passed on as is; queries are not images.
+        let images = scanner.feed(b"\x1b_Ga=T,f=100;iVBORw0KGgo=\x1b\\\x1b_Ga=q,i=1;AAAA\x1b\\");
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].data, b"\x89PNG\r\n\x1a\n");
+    }
+
+    #[test]
+    fn test_iterm2() {
+        let mut scanner = ImageScanner::new();
+        let images = scanner.feed(b"\x1b]1337;File=name=eC5naWY=;inline=1:R0lGODlh\x07");
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].protocol, ImageProtocol::Iterm2);
+        assert_eq!(images[0].extension(), "gif");
+        assert!(
+            scanner
+                .feed(b"\x1b]1337;File=name=eC5naWY=:R0lGODlh\x07")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_image_writer() {
+        let dir = std::env::temp_dir().join(format!("scriptty-images-{}", std::process::id()));
+        let mut writer = ImageWriter::create(&dir).unwrap();
+        let images = ImageScanner::new().feed(b"\x1bPq#0;2;0;100;0~\x1b\\");
+        let saved = writer.save(&images[0]).unwrap();
+        assert_eq!(saved, dir.join("image-001.png"));
+        assert!(std::fs::read(&saved).unwrap().starts_with(b"\x89PNG"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_similarity() {
+        let image = |rgba: &[u8]| Image {
+            protocol: ImageProtocol::Kitty,
+            data: crate::png::encode(2, 1, rgba).unwrap(),
+        };
+        let red = image(&[255, 0, 0, 255, 255, 0, 0, 255]);
+        let half = image(&[255, 0, 0, 255, 0, 0, 0, 255]);
+        assert_eq!(red.similarity(&red.data).unwrap(), 1.0);
+        assert_eq!(
+            red.similarity(&half.data).unwrap(),
+            1.0 - 255.0 / (8.0 * 255.0)
+        );
+        let small = crate::png::encode(1, 1, &[255, 0, 0, 255]).unwrap();
+        let err = red.similarity(&small).unwrap_err().to_string();
+        assert_eq!(err, "Image is 2x1 pixels, expected 1x1");
+        assert!(red.similarity(b"GIF89a").is_err());
+    }
+}
@@ -0,0 +1,122 @@
+//! `${NAME}` variable interpolation for script strings.
+
+use anyhow::{Result, anyhow};
+use std::collections::HashMap;
+
+/// Resolve a reference name: `env:NAME` reads the host environment, anything
+/// else is looked up in the script variables.
+pub(crate) fn resolve(name: &str, variables: &HashMap<String, String>) -> Option<String> {
+    match name.strip_prefix("env:") {
+        Some(env_name) => std::env::var(env_name).ok(),
+        None => variables.get(name).cloned(),
+    }
+}
+
+/// Replace every `${NAME}` in `text` with the value returned by `lookup`.
+///
+/// `$${` produces a literal `${`. A name for which `lookup` returns `None`
+/// is left as it was written, like a `${` without a closing `}`, so shell
+/// text such as `echo ${HOME}` passes through to the program.
+pub(crate) fn interpolate<F>(text: &str, lookup: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    expand(text, lookup, false).expect("lenient expansion can't fail")
+}
+
+/// Like [`interpolate`], but referencing a name for which `lookup` returns
+/// `None` is an error, as is a `${` without a closing `}`. For values such as
+/// passwords, where sending the reference itself would be wrong.
+pub(crate) fn interpolate_strict<F>(text: &str, lookup: F) -> Result<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    expand(text, lookup, true)
+}
+
+fn expand<F>(text: &str, lookup: F, strict: bool) -> Result<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(idx) = rest.find("${") {
+        if rest[..idx].ends_with('$') {
+            out.push_str(&rest[..idx - 1]);
+            out.push_str("${");
+            rest = &rest[idx + 2..];
+            continue;
+        }
+        out.push_str(&rest[..idx]);
+        let after = &rest[idx + 2..];
+        let Some(end) = after.find('}') else {
+            if strict {
+                return Err(anyhow!("Unclosed '${{' in: {}", text));
+            }
+            out.push_str(&rest[idx..]);
+            return Ok(out);
+        };
+        let name = &after[..end];
+        match lookup(name) {
+            Some(value) => out.push_str(&value),
+            None if strict => return Err(anyhow!("Undefined variable: {}", name)),
+            None => out.push_str(&rest[idx..idx + end + 3]),
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "USER" => Some("bob".to_string()),
+            "HOST" => Some("db1".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_interpolate() {
+        assert_eq!(interpolate("ssh ${USER}@${HOST}", lookup), "ssh bob@db1");
+        assert_eq!(interpolate("no vars", lookup), "no vars");
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            interpolate("echo $${USER} is ${USER}", lookup),
+            "echo ${USER} is bob"
+        );
+    }
+
+    #[test]
+    fn test_resolve_env() {
+        let vars = HashMap::from([("HOME".to_string(), "script".to_string())]);
+        let path = std::env::var("PATH").unwrap();
+        assert_eq!(resolve("env:PATH", &vars), Some(path));
+        assert_eq!(resolve("HOME", &vars), Some("script".to_string()));
+        assert_eq!(resolve("env:SCRIPTTY_SURELY_UNSET_VAR", &vars), None);
+    }
+
+    #[test]
+    fn test_undefined_left_as_written() {
+        assert_eq!(
+            interpolate("echo ${HOME} ${USER}", lookup),
+            "echo ${HOME} bob"
+        );
+        assert_eq!(interpolate("`${a} ${USER", lookup), "`${a} ${USER");
+        assert_eq!(interpolate("x ${USER} ${", lookup), "x bob ${");
+    }
+
+    #[test]
+    fn test_strict_errors() {
+        assert_eq!(interpolate_strict("${USER}", lookup).unwrap(), "bob");
+        assert!(interpolate_strict("${MISSING}", lookup).is_err());
+        assert!(interpolate_strict("${USER", lookup).is_err());
+    }
+}
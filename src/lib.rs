@@ -35,20 +35,85 @@
 //! Use [`parse_str`] to parse a script from an in-memory string, or
 //! [`parse_file`] to read one from a file path. Both return a
 //! `Vec<Box<dyn `[`ScripttyCommand`]`>>` ready to pass to [`Engine::execute`].
+//! For very large generated scripts, [`Engine::run_script_file`] parses and
+//! runs a file one command at a time instead.
 //!
 //! ## Script syntax
 //!
 //! | Command | Description |
 //! |---------|-------------|
 //! | `type "text"` | Simulate typing with per-character delays |
+//! | `complete "git ch" expect_menu` | Type a prefix, press Tab (twice for the menu), and wait for the completion |
+//! | `history_up 2 expect "make test"` | Recall an earlier command with Up, check it, and run it |
+//! | `search_history "docker run"` | Find an earlier command with Ctrl+R and run it; optional `expect "line"` and timeout |
+//! | `type_edit "git sttus" fix 6 "a"` | Type text, then fix it with arrow keys and Backspace (`fix`, `delete`, `replace`) |
 //! | `send "text"` | Send text to the program immediately (no typing simulation) |
-//! | `key Enter` | Send a key press (supports `Ctrl+`, `Alt+`, `Shift+` modifiers) |
+//! | `paste "text"` | Send text as a bracketed paste (`ESC [200~` ... `ESC [201~`) |
+//! | `@profile python` | Select the REPL profile (`python`, `node`, `psql`, `mysql`, `gdb`) for `expect_prompt` and `eval` |
+//! | `expect_prompt` | Wait for the REPL profile's prompt; optional timeout |
+//! | `eval "print(1)"` | Type code into the REPL line by line and wait for the next prompt; optional timeout |
+//! | `gdb break main.c:42` | Drive gdb in MI mode: `break`, `run`, `continue`, `next`, `step`, `finish`, `expect_stopped [at LOCATION]` |
+//! | `signal INT` | Send a POSIX signal (`INT`, `TERM`, `TSTP`, `CONT`, ...) to the program |
+//! | `kill 5s` | Stop the program: SIGTERM, then SIGKILL after the grace period |
+//! | `screenshot "step4.svg"` | Save the current terminal screen as an SVG or PNG image |
+//! | `snapshot "main_menu"` | Save the screen's text as a golden snapshot, or compare against it in check mode |
+//! | `resize 40 120` | Resize the terminal to 40 rows by 120 columns and send SIGWINCH |
+//! | `mouse click 10 42` | Click at row 10, column 42 (also `press`, `release`, `scroll up`/`down`, `drag`) |
+//! | `sudo "apt install jq"` | Run a command with sudo, answering the password prompt from `${SUDO_PASSWORD}` |
+//! | `login user=${U} password_env=PW prompt="$ "` | Answer login and password prompts until the shell prompt appears |
+//! | `key Enter` | Send a key press (supports `Ctrl+`, `Alt+`, `Shift+` modifiers); `key Ctrl+A Ctrl+K` sends several in order, `key Down x10` repeats one |
 //! | `show "text"` | Write text directly to the output handler |
+//! | `debug_state` | Write the buffered output, variables, and elapsed time to stderr and the transcript |
 //! | `expect "pattern"` | Wait until `pattern` appears in the program output |
 //! | `expect "pattern" 5s` | Wait up to 5 seconds for the pattern |
+//! | `expect --plain "pattern"` | Wait for the pattern, ignoring ANSI escape sequences in the output |
+//! | `expect --regex "v(\d+)"` | Wait for a regex and store its groups as `${MATCH_1}`, ... (named groups under their names) |
+//! | `expect_any "a" "b" 10s` | Wait until the first of several patterns appears |
+//! | `expect_bell 10s` | Wait until the program rings the terminal bell |
+//! | `expect_eof 30s` | Wait until the program exits; its exit code is stored in `${EXIT_CODE}` |
+//! | `expect_idle 500ms 10s` | Wait until the program has been silent for 500ms (default timeout 5s) |
+//! | `expect_not "panic" 2s` | Fail if the pattern shows up in the output within 2s (default window 5s) |
+//! | `expect_screen row 3 contains "OK"` | Wait until the rendered screen matches (`contains`, `row N contains`/`is`, `cursor ROW COL`) |
+//! | `assert_exit_code 0` | Wait for the program to exit and fail unless it exited with this code |
+//! | `respawn snapshot before` | Restart the program, keeping the old session's output as snapshot `before` |
+//! | `assert_snapshot before "count: 3"` | Fail unless snapshot `before` contains the text |
+//! | `expect_notification "Done"` | Wait for an OSC 9/777 desktop notification containing the text |
+//! | `expect_image 10s` | Wait until the program draws an inline image (sixel, kitty, iTerm2) |
+//! | `assert_image_similar "golden.png" 0.98` | Fail unless the program's last image looks like the PNG file |
+//! | `capture "v(\d+)" into VER` | Wait for a regex and store the matched group in a variable |
+//! | `set NAME "value"` | Assign a script variable |
+//! | `set default_timeout 10s` | Change the timeout of later `expect`-style commands that don't give one |
+//! | `set typing_speed 20ms..60ms` | Change the per-character delay of later `type` commands |
+//! | `set typo_rate 3%` | Make later `type` commands mistype and correct that share of letters and digits |
+//! | `set plain_expect on` | Ignore ANSI escape sequences when later commands match output |
+//! | `set key_delay 100ms` | Change the pause between the presses of later `key` commands |
+//! | `set key_table linux` | Use the Home/End/F-key sequences of this `TERM` instead of the program's |
+//! | `set vendor cisco` | Pick the device CLI (`cisco` or `juniper`) for `enable`, `config_mode`, and `save_config` (`network` feature) |
+//! | `set crash_loop 3 10s` | Make `respawn` fail once the program has exited on its own 3 times within 10s (`off` to disable) |
+//! | `set behavior 0.1` | Keep the defaults of the 0.1 releases after upgrading (see [`Behavior`]) |
 //! | `wait 500ms` | Pause for a duration (`ms` or `s` units, floats allowed) |
+//! | `at 00:01:30 <command>` | Run a command at a fixed offset from the start of the run |
+//! | `sync_audio "narration.json"` | Load narration marker timestamps for `marker` |
+//! | `marker "install"` | Wait for, or catch up with, the narration's `install` marker |
+//! | `wait_file "/tmp/go"` | Pause until a file exists (optional timeout, e.g. `30s`) |
+//! | `wait_signal USR1` | Pause until scriptty receives a signal (optional timeout) |
+//! | `if expect "pattern" 2s` … `else` … `end` | Run a block depending on whether the pattern appears |
+//! | `repeat 5` … `end` | Run a block a fixed number of times |
+//! | `loop until ${iterations} == 1000` … `end` | Run a block until the condition holds; `${iterations}` counts completed iterations |
+//! | `while expect "pattern" 1s` … `end` | Run a block each time the pattern appears, until it times out |
+//! | `any_order` … `end` | Commands that may run in any order (shuffled in fuzz mode) |
+//! | `def login(user, pass)` … `end` | Define a macro; parameters are variables inside the body |
+//! | `call login("bob", "hunter2")` | Run a macro defined earlier with the given arguments |
+//! | `include "common/login.script"` | Splice in another script (path relative to the including file) |
+//! | `env NO_COLOR=1` | Set an environment variable for the program; only at the top of the script (see [`script_env`]) |
+//! | `@meta ssh.user=deploy` | Configure how the program is started, e.g. the `ssh` connection; only at the top of the script (see [`script_meta`]) |
 //! | `# comment` | Full-line or inline comment |
 //!
+//! Strings passed to `type`, `send`, `show`, `expect`, and `expect_any` may
+//! reference script variables as `${NAME}`; they are expanded when the command
+//! runs. `${env:NAME}` reads an environment variable instead. References to
+//! undefined names are left as written; write `$${` for a literal `${`.
+//!
 //! ## Custom output handling
 //!
 //! By default [`Engine::spawn`] writes all output to stdout. Use
@@ -74,6 +139,55 @@
 //! }
 //! ```
 //!
+//! The handler is called synchronously as output arrives. To stream output
+//! into a tokio file, socket, or channel instead, spawn with
+//! [`EngineBuilder::writer`] or [`EngineBuilder::async_handler`], which
+//! queue it for a background task.
+//!
+//! To send the output to more than one place, e.g. stdout and a capture
+//! buffer, add more handlers with [`Engine::add_handler`].
+//!
+//! When several engines share one sink, wrap each handler with
+//! [`output::prefix_lines`] to tag every line with a (optionally colored)
+//! session name.
+//!
+//! The handler only sees bytes. To tell the program's output apart from the
+//! input the script sent, or to follow which command is running, register a
+//! handler for typed [`OutputEvent`]s with [`Engine::on_event`]. To act on
+//! each command with its arguments, e.g. to take a screenshot of every step,
+//! use [`Engine::on_command`]. [`Engine::transcript`] returns the whole
+//! session as a replayable [`transcript::Transcript`].
+//!
+//! ## Configuring the program
+//!
+//! [`Engine::builder`] controls how the program starts: its arguments,
+//! environment, working directory, terminal size and type, and more.
+//! [`Engine::spawn`] and [`Engine::spawn_with_handler`] are shortcuts for
+//! the defaults.
+//!
+//! ```no_run
+//! use scriptty::Engine;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let engine = Engine::builder("bash")
+//!     .arg("--norc")
+//!     .env("PS1", "$ ")
+//!     .size(40, 120)
+//!     .term("xterm-256color")
+//!     .handler(|_| {})
+//!     .spawn()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Inspecting the screen
+//!
+//! Program output is also fed through a terminal emulator (the [`vt100`]
+//! crate). [`Context::screen`] and [`Engine::screen`] return the rendered
+//! grid, including cell contents, colors, and the cursor position, for checks
+//! that raw output matching can't express, such as "the status bar is red"
+//! or "the cursor is on the last line".
+//!
 //! ## Implementing a custom command
 //!
 //! Implement [`ScripttyCommand`] to add new commands to the engine:
@@ -104,14 +218,60 @@
 //! }
 //! ```
 
+pub mod accessible;
+pub(crate) mod ansi;
+pub mod behavior;
+pub mod builder;
 pub mod command;
 pub mod commands;
+pub mod compare;
+pub mod control;
+pub mod coverage;
+pub mod diff;
 pub mod engine;
+pub mod events;
+pub mod fuzz;
+pub mod graphics;
+pub(crate) mod interpolate;
+#[cfg(feature = "lsp")]
+pub mod lsp;
+pub mod markdown;
+pub mod metrics;
+pub mod notify;
+#[cfg(feature = "otlp")]
+pub mod otlp;
+pub mod output;
 pub mod parser;
+pub(crate) mod png;
 pub(crate) mod pty;
 pub(crate) mod pty_reader;
+pub mod quota;
+pub mod recorder;
+pub(crate) mod render;
+pub mod sandbox;
+pub mod secrets;
+pub mod soak;
+pub mod ssh;
+pub mod stress;
+pub mod suite;
+pub mod supervisor;
+pub mod syntax;
+#[cfg(feature = "templates")]
+pub mod template;
+pub mod terminal;
+pub mod testing;
+pub mod trace;
+pub mod transcript;
+pub mod workspace;
 
-pub use command::{Context, ScripttyCommand};
-pub use commands::{Expect, KeyPress, SendInput, Show, TypeText, Wait};
-pub use engine::Engine;
-pub use parser::{parse_file, parse_str};
+pub use behavior::Behavior;
+pub use builder::EngineBuilder;
+pub use command::{CommandHook, Context, OutputEvent, ScripttyCommand};
+pub use commands::{
+    AnyOrder, AssertExitCode, At, Call, Capture, Expect, ExpectAny, ExpectBell, ExpectEof,
+    ExpectNotification, If, KeyPress, Macro, Repeat, SendInput, Set, Setting, Show, TypeText, Wait,
+    WaitFile, WaitSignal, While,
+};
+pub use engine::{Engine, StartupPolicy};
+pub use parser::{parse_file, parse_source, parse_str, script_env, script_meta};
+pub use vt100;
@@ -0,0 +1,806 @@
+//! Language server for the script DSL (requires the `lsp` feature).
+//!
+//! `scriptty lsp` speaks the Language Server Protocol over stdin/stdout, so
+//! any LSP-capable editor (VS Code, Neovim, Helix, …) gets:
+//!
+//! - diagnostics: the parse errors in the script, on open and on change,
+//! - completion of command names, key names after `key`, and macro names after `call`,
+//! - hover documentation for commands,
+//! - go-to-definition from `call` to its `def` and from `include` to the file.
+//!
+//! Documents are synchronized in full on every change; scripts are small.
+
+use crate::commands::KEY_NAMES;
+use crate::parser::{check_source, keywords};
+use anyhow::{Context, Result, anyhow};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Syntax and a one-line description of each keyword, shown on hover.
+const DOCS: &[(&str, &str, &str)] = &[
+    (
+        "type",
+        "type \"text\"",
+        "Simulate typing text character by character",
+    ),
+    (
+        "type_edit",
+        "type_edit \"text\" (fix POS \"TEXT\" | delete POS [COUNT] | replace POS \"TEXT\")...",
+        "Type text, then correct it with arrow keys and Backspace like a person fixing a typo",
+    ),
+    (
+        "complete",
+        "complete \"prefix\" [expect \"text\" | expect_menu [\"text\"]] [TIMEOUT]",
+        "Type a prefix, press Tab (twice with expect_menu), and wait for the completion",
+    ),
+    (
+        "history_up",
+        "history_up COUNT [expect \"line\"] [TIMEOUT]",
+        "Recall an earlier command with Up, check it, and press Enter",
+    ),
+    (
+        "search_history",
+        "search_history \"query\" [expect \"line\"] [TIMEOUT]",
+        "Find an earlier command with Ctrl+R, check it, and press Enter",
+    ),
+    (
+        "send",
+        "send \"text\"",
+        "Send text to the program instantly",
+    ),
+    (
+        "paste",
+        "paste \"text\"",
+        "Send text as a bracketed paste, like pasting from the clipboard",
+    ),
+    (
+        "show",
+        "show \"text\"",
+        "Display text to the viewer without sending it to the program",
+    ),
+    (
+        "debug_state",
+        "debug_state",
+        "Print the unmatched output, variables, elapsed time, and pending notifications and images",
+    ),
+    ("wait", "wait 500ms", "Pause for a duration"),
+    (
+        "expect",
+        "expect [--plain] \"pattern\" [timeout]",
+        "Wait for a pattern in the output (default 5s timeout)",
+    ),
+    (
+        "expect_any",
+        "expect_any [--plain] \"a\" \"b\" [timeout]",
+        "Wait for whichever pattern appears first",
+    ),
+    (
+        "expect_bell",
+        "expect_bell [timeout]",
+        "Wait until the program rings the terminal bell",
+    ),
+    (
+        "expect_eof",
+        "expect_eof [timeout]",
+        "Wait until the program exits; its exit code is stored in ${EXIT_CODE}",
+    ),
+    (
+        "expect_idle",
+        "expect_idle quiet [timeout]",
+        "Wait until the program has produced no output for the quiet period",
+    ),
+    (
+        "expect_not",
+        "expect_not [--plain] \"pattern\" [window]",
+        "Fail if the pattern appears in the output within the window",
+    ),
+    (
+        "assert_exit_code",
+        "assert_exit_code CODE [timeout]",
+        "Wait for the program to exit and fail unless it exited with this code",
+    ),
+    (
+        "respawn",
+        "respawn [snapshot LABEL]",
+        "Kill the program and start it again; with a label, keep the old session's output as a snapshot",
+    ),
+    (
+        "assert_snapshot",
+        "assert_snapshot LABEL \"text\"",
+        "Fail unless the snapshot saved by `respawn snapshot LABEL` contains the text",
+    ),
+    (
+        "expect_notification",
+        "expect_notification \"text\" [timeout]",
+        "Wait for an OSC 9/777 desktop notification containing the text",
+    ),
+    (
+        "expect_image",
+        "expect_image [timeout]",
+        "Wait until the program draws an inline image (sixel, kitty, iTerm2)",
+    ),
+    (
+        "assert_image_similar",
+        "assert_image_similar \"golden.png\" [threshold]",
+        "Fail unless the program's last image looks like the PNG file (similarity 0 to 1, default 0.99)",
+    ),
+    (
+        "capture",
+        "capture \"regex\" [into NAME] [timeout]",
+        "Wait for a regex and store the matched group in a variable",
+    ),
+    (
+        "set",
+        "set NAME \"value\" | set default_timeout 10s | set typing_speed 20ms..60ms | set typo_rate 3% | set key_delay 100ms | set key_table linux | set vendor cisco | set crash_loop 3 10s | set behavior 0.1",
+        "Assign a script variable, or change a script-wide default",
+    ),
+    (
+        "sudo",
+        "sudo \"command\" [until \"pattern\"] [timeout]",
+        "Run a command with sudo, answering the password prompt from ${SUDO_PASSWORD}",
+    ),
+    (
+        "login",
+        "login user=NAME password_env=VAR prompt=\"$ \" [login_prompt=..] [password_prompt=..] [retries=N] [timeout=..]",
+        "Answer login and password prompts until the shell prompt appears",
+    ),
+    (
+        "enable",
+        "enable",
+        "Enter the device's privileged mode, answering a password prompt from ${ENABLE_SECRET} (network feature)",
+    ),
+    (
+        "config_mode",
+        "config_mode",
+        "Enter the device's configuration mode (network feature)",
+    ),
+    (
+        "save_config",
+        "save_config",
+        "Leave configuration mode and save or commit the configuration (network feature)",
+    ),
+    (
+        "key",
+        "key [Ctrl+][Alt+][Shift+]<key> [xN] ...",
+        "Send one or more key presses in order; xN repeats the previous key",
+    ),
+    (
+        "mouse",
+        "mouse click|press|release ROW COL [left|middle|right] | mouse scroll up|down ROW COL | mouse drag ROW COL ROW COL [button]",
+        "Send xterm SGR mouse events",
+    ),
+    (
+        "@profile",
+        "@profile python|node|psql|mysql|gdb",
+        "Select the REPL profile used by expect_prompt and eval",
+    ),
+    (
+        "expect_prompt",
+        "expect_prompt [TIMEOUT]",
+        "Wait for the REPL profile's primary prompt",
+    ),
+    (
+        "eval",
+        "eval \"CODE\" [TIMEOUT]",
+        "Type CODE into the REPL line by line and wait for the next prompt",
+    ),
+    (
+        "gdb",
+        "gdb break LOCATION | gdb run|continue|next|step|finish | gdb expect_stopped [at LOCATION] [TIMEOUT]",
+        "Drive gdb running with --interpreter=mi",
+    ),
+    (
+        "resize",
+        "resize ROWS COLS",
+        "Resize the terminal and send SIGWINCH to the program",
+    ),
+    (
+        "signal",
+        "signal INT|TERM|HUP|QUIT|KILL|TSTP|STOP|CONT|USR1|USR2|...",
+        "Send a POSIX signal to the program",
+    ),
+    (
+        "kill",
+        "kill [grace]",
+        "Stop the program: SIGTERM, then SIGKILL after the grace period (default 2s)",
+    ),
+    (
+        "screenshot",
+        "screenshot \"file.svg|file.png\"",
+        "Save the current terminal screen as an SVG or PNG image",
+    ),
+    (
+        "sync_audio",
+        "sync_audio \"narration.json\"",
+        "Load narration marker timestamps for `marker`",
+    ),
+    (
+        "marker",
+        "marker \"name\"",
+        "Line up this point of the script with a narration marker, and report it to --notify/--on-event",
+    ),
+    (
+        "expect_screen",
+        "expect_screen contains \"text\" | row N contains|is \"text\" | cursor ROW COL [timeout]",
+        "Wait until the rendered terminal screen matches",
+    ),
+    (
+        "snapshot",
+        "snapshot \"name\"",
+        "Compare the screen with a golden snapshot, or save it with --update-snapshots",
+    ),
+    (
+        "at",
+        "at 00:01:30 <command>",
+        "Run a command at a fixed offset from the start of the run",
+    ),
+    (
+        "wait_file",
+        "wait_file \"path\" [timeout]",
+        "Pause until a file exists",
+    ),
+    (
+        "wait_signal",
+        "wait_signal USR1 [timeout]",
+        "Pause until scriptty receives a signal",
+    ),
+    (
+        "call",
+        "call name(\"arg\", ...)",
+        "Run a macro defined earlier with `def`",
+    ),
+    (
+        "if",
+        "if expect \"pattern\" [timeout] … else … end",
+        "Run a block depending on whether the pattern appears",
+    ),
+    (
+        "any_order",
+        "any_order … end",
+        "Commands that may run in any order (shuffled in fuzz mode)",
+    ),
+    (
+        "repeat",
+        "repeat N … end",
+        "Run a block a fixed number of times",
+    ),
+    (
+        "loop",
+        "loop [until VALUE OP VALUE] … end",
+        "Run a block until the condition holds after an iteration (forever without one)",
+    ),
+    (
+        "while",
+        "while expect \"pattern\" [timeout] … end",
+        "Run a block each time the pattern appears, until it times out",
+    ),
+    (
+        "def",
+        "def name(params) … end",
+        "Define a macro; parameters are variables inside the body",
+    ),
+    (
+        "include",
+        "include \"path\"",
+        "Splice in another script (path relative to the including file)",
+    ),
+    (
+        "env",
+        "env NAME=VALUE",
+        "Set an environment variable for the program; only at the top of the script",
+    ),
+    (
+        "@meta",
+        "@meta ssh.KEY=VALUE",
+        "Configure how the program is started, e.g. the ssh connection; only at the top of the script",
+    ),
+];
+
+/// Serve the language server protocol on stdin/stdout until the client exits.
+///
+/// # Errors
+///
+/// Returns an error if reading from stdin or writing to stdout fails, or if the
+/// client sends a malformed message.
+pub fn run_stdio() -> Result<()> {
+    let stdin = std::io::stdin();
+    let mut input = stdin.lock();
+    let stdout = std::io::stdout();
+    let mut output = stdout.lock();
+    let mut server = Server::default();
+    while let Some(message) = read_message(&mut input)? {
+        for reply in server.handle(&message) {
+            write_message(&mut output, &reply)?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Read one `Content-Length`-framed JSON-RPC message; `None` at end of input.
+fn read_message(input: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = Some(
+                value
+                    .trim()
+                    .parse::<usize>()
+                    .context("Invalid Content-Length")?,
+            );
+        }
+    }
+    let length = length.ok_or_else(|| anyhow!("Message without Content-Length header"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(
+        serde_json::from_slice(&body).context("Invalid JSON-RPC message")?,
+    ))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Protocol state: the open documents, keyed by URI.
+#[derive(Default)]
+struct Server {
+    documents: HashMap<String, String>,
+    exited: bool,
+}
+
+impl Server {
+    /// Handle one client message and return the responses and notifications to send.
+    fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let id = message.get("id").cloned();
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "completionProvider": { "triggerCharacters": [" ", "+"] },
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                },
+                "serverInfo": { "name": "scriptty", "version": env!("CARGO_PKG_VERSION") },
+            }),
+            "shutdown" => Value::Null,
+            "exit" => {
+                self.exited = true;
+                return Vec::new();
+            }
+            "textDocument/didOpen" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.to_string(), text.to_string());
+                return vec![self.diagnostics(uri)];
+            }
+            "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                if let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                }
+                return vec![self.diagnostics(uri)];
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                self.documents.remove(uri);
+                return vec![publish(uri, Vec::new())];
+            }
+            "textDocument/completion" => self.completion(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/definition" => self.definition(params),
+            _ => {
+                return match id {
+                    Some(id) => vec![json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": -32601, "message": format!("Unsupported method: {}", method) },
+                    })],
+                    None => Vec::new(),
+                };
+            }
+        };
+        match id {
+            Some(id) => vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })],
+            None => Vec::new(),
+        }
+    }
+
+    /// A `publishDiagnostics` notification with the document's parse errors.
+    fn diagnostics(&self, uri: &str) -> Value {
+        let text = self
+            .documents
+            .get(uri)
+            .map(String::as_str)
+            .unwrap_or_default();
+        let path = uri_to_path(uri).filter(|p| p.exists());
+        let diagnostics = check_source(text, path.as_deref())
+            .into_iter()
+            .map(|(line, error)| {
+                let line = line.saturating_sub(1);
+                let width = text.lines().nth(line).map_or(0, |l| l.chars().count());
+                json!({
+                    "range": range(line, 0, width),
+                    "severity": 1,
+                    "source": "scriptty",
+                    "message": format!("{:#}", error),
+                })
+            })
+            .collect();
+        publish(uri, diagnostics)
+    }
+
+    /// The document line at the request's position and the cursor column.
+    fn line_at<'a>(&'a self, params: &Value) -> Option<(&'a str, usize)> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        let line = params["position"]["line"].as_u64()? as usize;
+        let character = params["position"]["character"].as_u64()? as usize;
+        let text = self.documents.get(uri)?.lines().nth(line)?;
+        Some((text, character))
+    }
+
+    fn completion(&self, params: &Value) -> Value {
+        let Some((line, character)) = self.line_at(params) else {
+            return json!([]);
+        };
+        let prefix: String = line.chars().take(character).collect();
+        let prefix = prefix.trim_start();
+        let (items, kind): (Vec<String>, u8) = match prefix.split_once(' ') {
+            None => (keywords().map(str::to_string).collect(), 14),
+            Some(("key", _)) => (KEY_NAMES.iter().map(|k| k.to_string()).collect(), 12),
+            Some(("call", _)) => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                let text = self
+                    .documents
+                    .get(uri)
+                    .map(String::as_str)
+                    .unwrap_or_default();
+                (macro_names(text), 3)
+            }
+            Some(_) => (Vec::new(), 1),
+        };
+        items
+            .into_iter()
+            .map(|label| {
+                let detail = doc(&label).map(|(syntax, _)| syntax);
+                json!({ "label": label, "kind": kind, "detail": detail })
+            })
+            .collect()
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let Some((line, character)) = self.line_at(params) else {
+            return Value::Null;
+        };
+        let keyword = line.trim_start();
+        let keyword = keyword.split(' ').next().unwrap_or_default();
+        let start = line.len() - line.trim_start().len();
+        if character < start || character > start + keyword.len() {
+            return Value::Null;
+        }
+        match doc(keyword) {
+            Some((syntax, description)) => json!({
+                "contents": {
+                    "kind": "markdown",
+                    "value": format!("```\n{}\n```\n{}", syntax, description),
+                },
+            }),
+            None => Value::Null,
+        }
+    }
+
+    fn definition(&self, params: &Value) -> Value {
+        let Some((line, _)) = self.line_at(params) else {
+            return Value::Null;
+        };
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let base = uri_to_path(uri);
+        match line.trim().split_once(' ') {
+            Some(("include", target)) => resolve_include(base.as_deref(), target)
+                .map_or(Value::Null, |path| location(&path, 0, 0)),
+            Some(("call", call)) => {
+                let name = call.split('(').next().unwrap_or_default().trim();
+                let text = self
+                    .documents
+                    .get(uri)
+                    .map(String::as_str)
+                    .unwrap_or_default();
+                let mut visited = Vec::new();
+                find_def(text, base.as_deref(), name, &mut visited).map_or(
+                    Value::Null,
+                    |(path, line)| match path {
+                        Some(path) => location(&path, line, 0),
+                        None => json!({ "uri": uri, "range": range(line, 0, 0) }),
+                    },
+                )
+            }
+            _ => Value::Null,
+        }
+    }
+}
+
+fn doc(keyword: &str) -> Option<(&'static str, &'static str)> {
+    DOCS.iter()
+        .find(|(name, _, _)| *name == keyword)
+        .map(|(_, syntax, description)| (*syntax, *description))
+}
+
+/// Names of the macros defined with `def` in `text`.
+fn macro_names(text: &str) -> Vec<String> {
+    text.lines()
+        .filter_map(|l| l.trim().strip_prefix("def "))
+        .filter_map(|def| def.split('(').next())
+        .map(|name| name.trim().to_string())
+        .collect()
+}
+
+/// Find the `def` of macro `name` in `text` or, recursively, in the files it
+/// includes. Returns the file (`None` for `text` itself) and the 0-based line.
+fn find_def(
+    text: &str,
+    path: Option<&Path>,
+    name: &str,
+    visited: &mut Vec<PathBuf>,
+) -> Option<(Option<PathBuf>, usize)> {
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if let Some(def) = line.strip_prefix("def ")
+            && def.split('(').next().map(str::trim) == Some(name)
+        {
+            return Some((None, index));
+        }
+        if let Some(target) = line.strip_prefix("include ")
+            && let Some(target) = resolve_include(path, target)
+            && !visited.contains(&target)
+        {
+            visited.push(target.clone());
+            let Ok(included) = std::fs::read_to_string(&target) else {
+                continue;
+            };
+            if let Some((file, line)) = find_def(&included, Some(&target), name, visited) {
+                return Some((Some(file.unwrap_or(target)), line));
+            }
+        }
+    }
+    None
+}
+
+/// Resolve the quoted path of an `include` relative to the including file.
+fn resolve_include(base: Option<&Path>, target: &str) -> Option<PathBuf> {
+    let target = target.trim().strip_prefix('"')?.strip_suffix('"')?;
+    let dir = base.and_then(Path::parent).unwrap_or(Path::new("."));
+    dir.join(target).canonicalize().ok()
+}
+
+fn publish(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+fn range(line: usize, start: usize, end: usize) -> Value {
+    json!({
+        "start": { "line": line, "character": start },
+        "end": { "line": line, "character": end },
+    })
+}
+
+fn location(path: &Path, line: usize, character: usize) -> Value {
+    json!({ "uri": path_to_uri(path), "range": range(line, character, character) })
+}
+
+/// Convert a `file://` URI to a path, decoding `%XX` escapes.
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?;
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut rest = encoded.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let decoded = match (b, tail) {
+            (b'%', [hi, lo, ..]) => std::str::from_utf8(&[*hi, *lo])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match decoded {
+            Some(byte) => {
+                bytes.push(byte);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
+
+/// Convert a path to a `file://` URI, escaping characters unsafe in URIs.
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for b in path.to_string_lossy().bytes() {
+        if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", b));
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(server: &mut Server, uri: &str, text: &str) -> Vec<Value> {
+        server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": uri, "text": text } },
+        }))
+    }
+
+    fn request(
+        server: &mut Server,
+        method: &str,
+        uri: &str,
+        line: usize,
+        character: usize,
+    ) -> Value {
+        let replies = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": {
+                "textDocument": { "uri": uri },
+                "position": { "line": line, "character": character },
+            },
+        }));
+        replies[0]["result"].clone()
+    }
+
+    #[test]
+    fn test_framing() {
+        let mut out = Vec::new();
+        write_message(&mut out, &json!({ "id": 1 })).unwrap();
+        let mut input = std::io::Cursor::new(out);
+        assert_eq!(read_message(&mut input).unwrap(), Some(json!({ "id": 1 })));
+        assert_eq!(read_message(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let mut server = Server::default();
+        let replies = open(&mut server, "untitled:a", "wait 1s\nexpect \"x\nwait 1s\n");
+        let diagnostic = &replies[0]["params"]["diagnostics"][0];
+        assert_eq!(diagnostic["range"]["start"]["line"], 1);
+
+        let replies = open(
+            &mut server,
+            "untitled:c",
+            "bogus\nrepeat 2\nwait 5minutes\nend\nexpect \"x\n",
+        );
+        let lines: Vec<_> = replies[0]["params"]["diagnostics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|d| d["range"]["start"]["line"].clone())
+            .collect();
+        assert_eq!(lines, [json!(0), json!(2), json!(4)]);
+
+        let replies = open(&mut server, "untitled:b", "wait 1s\n");
+        assert_eq!(replies[0]["params"]["diagnostics"], json!([]));
+    }
+
+    #[test]
+    fn test_completion() {
+        let mut server = Server::default();
+        open(
+            &mut server,
+            "untitled:a",
+            "def login(u)\nend\nexp\nkey \ncall \n",
+        );
+        let labels = |v: Value| -> Vec<String> {
+            v.as_array()
+                .unwrap()
+                .iter()
+                .map(|i| i["label"].as_str().unwrap().to_string())
+                .collect()
+        };
+        assert!(
+            labels(request(
+                &mut server,
+                "textDocument/completion",
+                "untitled:a",
+                2,
+                3
+            ))
+            .contains(&"expect_any".to_string())
+        );
+        assert!(
+            labels(request(
+                &mut server,
+                "textDocument/completion",
+                "untitled:a",
+                3,
+                4
+            ))
+            .contains(&"PageDown".to_string())
+        );
+        assert_eq!(
+            labels(request(
+                &mut server,
+                "textDocument/completion",
+                "untitled:a",
+                4,
+                5
+            )),
+            vec!["login"]
+        );
+    }
+
+    #[test]
+    fn test_hover() {
+        let mut server = Server::default();
+        open(&mut server, "untitled:a", "  expect \"$ \"\n");
+        let hover = request(&mut server, "textDocument/hover", "untitled:a", 0, 4);
+        assert!(
+            hover["contents"]["value"]
+                .as_str()
+                .unwrap()
+                .contains("expect [--plain] \"pattern\"")
+        );
+        assert_eq!(
+            request(&mut server, "textDocument/hover", "untitled:a", 0, 11),
+            Value::Null
+        );
+    }
+
+    #[test]
+    fn test_definition() {
+        let mut server = Server::default();
+        open(
+            &mut server,
+            "untitled:a",
+            "wait 1s\ndef go()\nend\ncall go()\n",
+        );
+        let location = request(&mut server, "textDocument/definition", "untitled:a", 3, 6);
+        assert_eq!(location["range"]["start"]["line"], 1);
+    }
+
+    #[test]
+    fn test_every_keyword_documented() {
+        for keyword in keywords() {
+            assert!(doc(keyword).is_some(), "missing hover docs for '{keyword}'");
+        }
+    }
+
+    #[test]
+    fn test_uri_round_trip() {
+        let path = Path::new("/tmp/my scripts/demo.script");
+        assert_eq!(uri_to_path(&path_to_uri(path)).unwrap(), path);
+    }
+}
@@ -1,50 +1,1082 @@
-use anyhow::{Context, Result};
-use clap::Parser;
-use scriptty::{Engine, parse_file};
+use anyhow::{Context, Result, anyhow};
+use clap::{Parser, Subcommand, ValueEnum};
+use scriptty::commands::SnapshotMode;
+use scriptty::coverage::BranchCoverage;
+use scriptty::fuzz::Fuzz;
+use scriptty::markdown;
+use scriptty::metrics::ExpectMetrics;
+use scriptty::quota::{self, Quotas};
+use scriptty::recorder::Cast;
+use scriptty::sandbox::Sandbox;
+use scriptty::soak::SoakReport;
+use scriptty::ssh::SshOptions;
+use scriptty::stress::{self, StressOptions};
+use scriptty::suite::{self, SuiteOptions, SuiteReport};
+#[cfg(feature = "templates")]
+use scriptty::template;
+use scriptty::terminal::{self, TerminalSettings};
+use scriptty::{
+    Behavior, Engine, EngineBuilder, ScripttyCommand, StartupPolicy, compare, diff, parse_source,
+    parse_str, script_env, script_meta, syntax,
+};
 use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
 #[command(
     name = "scriptty",
     about = "Run a scriptty script against an interactive terminal program",
-    version
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
 )]
 struct Args {
-    /// Path to the script file
-    #[arg(short, long)]
-    script: String,
+    #[command(subcommand)]
+    mode: Option<Mode>,
+
+    /// Path to the script file, or - to read it from stdin
+    #[arg(short, long, required = true)]
+    script: Option<String>,
+
+    /// Run the ```scriptty NAME block of the script file, a Markdown document
+    #[arg(long, value_name = "NAME")]
+    block: Option<String>,
+
+    /// The script read from stdin with `--script -`, kept for reruns
+    #[arg(skip)]
+    stdin_script: Option<String>,
 
     /// Command to run in the PTY
-    #[arg(short, long)]
-    command: String,
+    #[arg(short, long, required = true)]
+    command: Option<String>,
+
+    /// Render the script as a template with values from this YAML file before parsing
+    #[cfg(feature = "templates")]
+    #[arg(long, value_name = "PATH")]
+    data: Option<String>,
+
+    /// Define a script variable (repeatable)
+    #[arg(short = 'D', long = "define", value_name = "NAME=VALUE")]
+    defines: Vec<String>,
+
+    #[command(flatten)]
+    session: SessionArgs,
+
+    /// Listen for pause/resume/step/inject requests on this unix socket path
+    #[arg(long, value_name = "PATH")]
+    control: Option<String>,
+
+    /// Stop the program and fail once it has printed more than this (e.g. 10M)
+    #[arg(long, value_name = "SIZE", value_parser = quota::parse_size)]
+    max_output: Option<u64>,
+
+    /// Stop the program and fail once the script has run longer than this (e.g. 10m)
+    #[arg(long, value_name = "DURATION", value_parser = stress::parse_test_duration)]
+    max_duration: Option<Duration>,
+
+    /// Fail once the script has sent more than this to the program (e.g. 64K)
+    #[arg(long, value_name = "SIZE", value_parser = quota::parse_size)]
+    max_input: Option<u64>,
+
+    /// Print expect latency statistics (p50/p95) to stderr after the run
+    #[arg(long)]
+    stats: bool,
+
+    /// Print which expect_any branches were taken to stderr after the run
+    #[arg(long)]
+    coverage: bool,
+
+    /// Store `snapshot` files in this directory
+    #[arg(long, value_name = "DIR", default_value = "snapshots")]
+    snapshot_dir: String,
+
+    /// Write the screen to the stored files at each `snapshot` instead of comparing against them (also SCRIPTTY_UPDATE_SNAPSHOTS=1)
+    #[arg(long)]
+    update_snapshots: bool,
+
+    /// Log each command, output chunk, input write, and expect to this file as JSON Lines (see `scriptty compare`)
+    #[arg(long, value_name = "PATH")]
+    events: Option<String>,
+
+    /// Send a span per command and per expect to this OpenTelemetry collector (OTLP/HTTP, e.g. http://localhost:4318; default: $OTEL_EXPORTER_OTLP_ENDPOINT)
+    #[cfg(feature = "otlp")]
+    #[arg(long, value_name = "URL")]
+    otlp_endpoint: Option<String>,
+
+    /// Service name reported with the spans (default: $OTEL_SERVICE_NAME, or scriptty)
+    #[cfg(feature = "otlp")]
+    #[arg(long, value_name = "NAME")]
+    otlp_service: Option<String>,
+
+    /// Record the run as an asciinema v2 cast to this file
+    #[arg(long, value_name = "PATH")]
+    record: Option<String>,
+
+    /// Fail unless the program prints the same lines as in this recorded cast (timing and colors are ignored)
+    #[arg(long, value_name = "CAST", conflicts_with = "record")]
+    verify: Option<String>,
+
+    /// Write a screen-reader friendly transcript of the run (commands typed, then responses) to this file
+    #[arg(long, value_name = "PATH")]
+    accessible_transcript: Option<String>,
+
+    /// Write the session, with colors, to this file as a standalone HTML page after the run
+    #[arg(long, value_name = "PATH")]
+    html: Option<String>,
+
+    /// Write the program's output as plain text with a timestamp per line to this file
+    #[arg(long, value_name = "PATH")]
+    transcript: Option<String>,
+
+    /// Save images the program draws (sixel, kitty, iTerm2) as files in this directory
+    #[arg(long, value_name = "DIR")]
+    images: Option<String>,
+
+    /// Include the lines sent to the program in the --transcript file
+    #[arg(long, requires = "transcript")]
+    transcript_input: bool,
+
+    /// Re-run a failing script up to this many times; a later pass is reported as flaky
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    retries: u32,
+
+    /// Run the script this many times with randomized timing and ordering, stopping at the first failure
+    #[arg(long, value_name = "RUNS", conflicts_with = "retries")]
+    fuzz: Option<u32>,
+
+    /// Run the script over and over (at most N times) until it fails, for soak testing
+    #[arg(
+        long = "loop",
+        value_name = "N",
+        num_args = 0..=1,
+        conflicts_with_all = ["retries", "fuzz"]
+    )]
+    loop_runs: Option<Option<u32>>,
+
+    /// Print memory and file descriptor growth across `loop` iterations to stderr after the run
+    #[arg(long)]
+    leaks: bool,
+
+    /// Ring the bell and show a desktop notification (OSC 9) when the script finishes or fails, and at each `marker`
+    #[arg(long)]
+    notify: bool,
+
+    /// Run this shell command when the script finishes or fails, and at each `marker`; it gets $SCRIPTTY_EVENT (finished, failed, or marker) and $SCRIPTTY_MESSAGE
+    #[arg(long, value_name = "COMMAND")]
+    on_event: Option<String>,
+
+    /// Run this shell command with the path of a failure report (error, screen, and output) when a run fails
+    #[arg(long, value_name = "COMMAND")]
+    on_fail: Option<String>,
+
+    /// Seed for the first fuzz run (later runs use consecutive seeds)
+    #[arg(long, requires = "fuzz")]
+    seed: Option<u64>,
 
     /// Arguments to pass to the command
     #[arg(trailing_var_arg = true)]
     args: Vec<String>,
 }
 
+/// How the program is started and driven; shared by script runs and the
+/// `test`, `doctest`, and `exec` modes.
+#[derive(clap::Args, Debug)]
+struct SessionArgs {
+    /// Set an environment variable for the command, overriding the script's env header (repeatable)
+    #[arg(long = "env", value_name = "NAME=VALUE")]
+    env: Vec<String>,
+
+    /// Start the command in a fresh temporary directory, available to the script as ${WORKSPACE}
+    #[arg(long)]
+    workspace: bool,
+
+    /// Start the command in this directory instead of the current one
+    #[arg(long, value_name = "DIR", conflicts_with = "workspace")]
+    cwd: Option<String>,
+
+    /// Keep the workspace directory when the script fails
+    #[arg(long, requires = "workspace")]
+    keep_workspace: bool,
+
+    /// Run the command without network access, in its own network namespace (Linux)
+    #[arg(long)]
+    no_network: bool,
+
+    /// Limit the address space of the command's processes (e.g. 2G; Linux)
+    #[arg(long, value_name = "SIZE", value_parser = quota::parse_size)]
+    limit_memory: Option<u64>,
+
+    /// Limit the number of files each of the command's processes may open (Linux)
+    #[arg(long, value_name = "N")]
+    limit_files: Option<u64>,
+
+    /// Limit the number of processes of the user running the command (Linux)
+    #[arg(long, value_name = "N")]
+    limit_procs: Option<u64>,
+
+    /// Limit the CPU time of each of the command's processes (e.g. 30s; Linux)
+    #[arg(long, value_name = "DURATION", value_parser = stress::parse_test_duration)]
+    limit_cpu: Option<Duration>,
+
+    /// Terminal line settings for the command, as for stty (e.g. "-echo -onlcr" or "raw")
+    #[arg(long, value_name = "SETTINGS", allow_hyphen_values = true)]
+    termios: Option<String>,
+
+    /// Size of a character cell in pixels, so the window's pixel size is reported (e.g. 10x20)
+    #[arg(long, value_name = "WxH", value_parser = terminal::parse_cell_size)]
+    cell_size: Option<(u16, u16)>,
+
+    /// Run the command as this user, with their groups and environment (needs root; Linux)
+    #[arg(long, value_name = "NAME")]
+    user: Option<String>,
+
+    /// Start the script once the program prints this text, e.g. its prompt (the script can still expect it)
+    #[arg(long, value_name = "TEXT", conflicts_with = "ready_idle")]
+    ready_pattern: Option<String>,
+
+    /// Start the script once the program has printed nothing for this long (e.g. 200ms)
+    #[arg(long, value_name = "DURATION", value_parser = stress::parse_test_duration)]
+    ready_idle: Option<Duration>,
+
+    /// Multiply every expect timeout and wait by this factor (e.g. 3.0 on slow CI)
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0)]
+    timeout_scale: f64,
+
+    /// Type and press keys without delays and skip waits, e.g. to check a demo script quickly
+    #[arg(long)]
+    instant: bool,
+
+    /// Keep the defaults of an older release (e.g. 0.1), as `set behavior` does
+    #[arg(long, value_name = "VERSION")]
+    behavior: Option<Behavior>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Mode {
+    /// Run a language server for script files on stdin/stdout
+    #[cfg(feature = "lsp")]
+    Lsp,
+    /// Compare two scripts command by command, ignoring comments, whitespace, and duration spelling
+    Diff {
+        /// The original script
+        old: String,
+        /// The changed script
+        new: String,
+    },
+    /// Compare the event logs (--events) of two runs command by command: output, failures, and timing
+    Compare {
+        /// Event log of the first run
+        a: String,
+        /// Event log of the second run
+        b: String,
+    },
+    /// Run many sessions of a script at once and report failures and latencies
+    Stress {
+        /// Path to the script file
+        script: String,
+        /// Command to run in each session's PTY
+        #[arg(short, long)]
+        command: String,
+        /// Number of sessions running at the same time
+        #[arg(long, value_name = "N", default_value_t = 10)]
+        instances: usize,
+        /// Keep starting new sessions until this much time has passed (e.g. 10m); without it each session runs once
+        #[arg(long, value_name = "DURATION", value_parser = stress::parse_test_duration)]
+        duration: Option<Duration>,
+        /// Define a script variable (repeatable); ${INSTANCE} is the session's number
+        #[arg(short = 'D', long = "define", value_name = "NAME=VALUE")]
+        defines: Vec<String>,
+        /// CSV file of per-session variables: a header row of names, then one row per session (reused in turn)
+        #[arg(long, value_name = "PATH")]
+        matrix: Option<String>,
+        /// Re-run a failing session up to this many times; a later pass is counted as flaky
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        retries: u32,
+        /// Arguments to pass to the command
+        #[arg(trailing_var_arg = true)]
+        args: Vec<String>,
+    },
+    /// Run every *.scriptty file under a directory against the command and report which pass
+    Test {
+        /// Directory to search for scripts, including subdirectories
+        #[arg(default_value = ".")]
+        dir: String,
+        /// Command to run in the PTY for each script
+        #[arg(short, long)]
+        command: String,
+        /// Define a script variable (repeatable)
+        #[arg(short = 'D', long = "define", value_name = "NAME=VALUE")]
+        defines: Vec<String>,
+        /// Also write the results to this file as JUnit XML
+        #[arg(long, value_name = "PATH")]
+        junit: Option<String>,
+        /// Also write the results to this file in the Test Anything Protocol
+        #[arg(long, value_name = "PATH")]
+        tap: Option<String>,
+        /// Re-run a failing script up to this many times; a later pass is reported as flaky
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        retries: u32,
+        #[command(flatten)]
+        session: SessionArgs,
+        /// Arguments to pass to the command
+        #[arg(trailing_var_arg = true)]
+        args: Vec<String>,
+    },
+    /// Run every ```scriptty block in the Markdown files under a directory, without pauses
+    Doctest {
+        /// Directory to search for *.md files, including subdirectories
+        #[arg(default_value = ".")]
+        dir: String,
+        /// Command to run in the PTY for each block
+        #[arg(short, long)]
+        command: String,
+        /// Define a script variable (repeatable)
+        #[arg(short = 'D', long = "define", value_name = "NAME=VALUE")]
+        defines: Vec<String>,
+        /// Also write the results to this file as JUnit XML
+        #[arg(long, value_name = "PATH")]
+        junit: Option<String>,
+        /// Also write the results to this file in the Test Anything Protocol
+        #[arg(long, value_name = "PATH")]
+        tap: Option<String>,
+        /// Re-run a failing block up to this many times; a later pass is reported as flaky
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        retries: u32,
+        #[command(flatten)]
+        session: SessionArgs,
+        /// Arguments to pass to the command
+        #[arg(trailing_var_arg = true)]
+        args: Vec<String>,
+    },
+    /// Run script commands given with -e (or read from stdin) without a script file
+    Exec {
+        /// Command to run in the PTY
+        #[arg(short, long)]
+        command: String,
+        /// A script line to run (repeatable); without any, the script is read from stdin
+        #[arg(short = 'e', long = "eval", value_name = "LINE")]
+        lines: Vec<String>,
+        /// Define a script variable (repeatable)
+        #[arg(short = 'D', long = "define", value_name = "NAME=VALUE")]
+        defines: Vec<String>,
+        #[command(flatten)]
+        session: SessionArgs,
+        /// Arguments to pass to the command
+        #[arg(trailing_var_arg = true)]
+        args: Vec<String>,
+    },
+    /// Print a syntax highlighting definition for script files
+    EmitSyntax {
+        /// Grammar format to generate
+        #[arg(long, value_enum)]
+        format: SyntaxFormat,
+    },
+    /// Play a recorded cast (from --record) in this terminal
+    Replay {
+        /// Cast file to play
+        cast: String,
+        /// Playback speed (2.0 plays twice as fast)
+        #[arg(long, default_value_t = 1.0)]
+        speed: f64,
+    },
+    /// Print a recorded cast (from --record) as an animated SVG image
+    Svg {
+        /// Cast file to render
+        cast: String,
+        /// Render the screen at this time from the start as a still image
+        #[arg(long, value_name = "TIME", value_parser = stress::parse_test_duration)]
+        at: Option<Duration>,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum SyntaxFormat {
+    /// TextMate grammar (XML plist), e.g. for VS Code or Sublime Text
+    #[value(name = "tmLanguage")]
+    TmLanguage,
+    /// tree-sitter grammar.js
+    TreeSitter,
+    /// tree-sitter queries/highlights.scm for the generated grammar
+    TreeSitterHighlights,
+}
+
+impl Args {
+    fn script(&self) -> &str {
+        self.script.as_deref().expect("--script is required")
+    }
+
+    fn command(&self) -> &str {
+        self.command.as_deref().expect("--command is required")
+    }
+
+    fn alerts(&self) -> Alerts {
+        Alerts {
+            notify: self.notify,
+            hook: self.on_event.clone(),
+        }
+    }
+}
+
+impl SessionArgs {
+    fn terminal(&self) -> Result<TerminalSettings> {
+        Ok(TerminalSettings {
+            termios: terminal::parse_termios(self.termios.as_deref().unwrap_or_default())?,
+            cell_size: self.cell_size,
+        })
+    }
+
+    fn startup(&self) -> StartupPolicy {
+        match (&self.ready_pattern, self.ready_idle) {
+            (Some(pattern), _) => StartupPolicy::Pattern(pattern.clone()),
+            (None, Some(quiet)) => StartupPolicy::Idle(quiet),
+            (None, None) => StartupPolicy::Immediate,
+        }
+    }
+
+    fn sandbox(&self) -> Sandbox {
+        Sandbox {
+            no_network: self.no_network,
+            max_memory: self.limit_memory,
+            max_open_files: self.limit_files,
+            max_processes: self.limit_procs,
+            max_cpu_time: self.limit_cpu,
+            user: self.user.clone(),
+        }
+    }
+
+    /// A builder for `command` with `args`, set up as these options say.
+    /// `script_env` comes from the script's `env` header; the --env flags
+    /// override it.
+    fn builder<I, S>(
+        &self,
+        command: &str,
+        args: I,
+        script_env: Vec<(String, String)>,
+    ) -> Result<EngineBuilder>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut builder = Engine::builder(command)
+            .args(args)
+            .sandbox(self.sandbox())
+            .terminal(self.terminal()?);
+        if self.workspace {
+            builder = builder.workspace();
+        }
+        if let Some(dir) = &self.cwd {
+            builder = builder.cwd(dir);
+        }
+        for (name, value) in script_env {
+            builder = builder.env(name, value);
+        }
+        for env in &self.env {
+            let (name, value) = parse_env(env)?;
+            builder = builder.env(name, value);
+        }
+        Ok(builder)
+    }
+
+    /// Start `command` for one script of a suite, discarding its output.
+    fn spawn_quiet(
+        &self,
+        command: &str,
+        args: &[String],
+        script_env: Vec<(String, String)>,
+    ) -> Result<Engine> {
+        let mut engine = self
+            .builder(command, args, script_env)?
+            .handler(|_| {})
+            .spawn()
+            .context("Failed to spawn engine")?;
+        self.configure(&mut engine)?;
+        Ok(engine)
+    }
+
+    /// Apply the options that take effect once the program is running.
+    fn configure(&self, engine: &mut Engine) -> Result<()> {
+        engine.keep_workspace_on_failure(self.keep_workspace);
+        engine.set_timeout_scale(self.timeout_scale)?;
+        engine.set_instant(self.instant);
+        if let Some(behavior) = self.behavior {
+            engine.set_behavior(behavior);
+        }
+        engine.set_startup_policy(self.startup());
+        Ok(())
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::parse();
+    let mut args = Args::parse();
+    match args.mode {
+        #[cfg(feature = "lsp")]
+        Some(Mode::Lsp) => return scriptty::lsp::run_stdio(),
+        Some(Mode::Diff { old, new }) => {
+            let read = |path: &str| {
+                std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read script file: {}", path))
+            };
+            let changes = diff::diff(&read(&old)?, &read(&new)?);
+            print!("{}", changes);
+            if !changes.is_empty() {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Mode::Compare { a, b }) => {
+            let read = |path: &str| {
+                std::fs::read_to_string(path)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|text| compare::parse_log(&text))
+                    .with_context(|| format!("Failed to read event log: {}", path))
+            };
+            let comparison = compare::compare(&read(&a)?, &read(&b)?);
+            print!("{}", comparison);
+            if !comparison.is_empty() {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Mode::Stress {
+            script,
+            command,
+            instances,
+            duration,
+            defines,
+            matrix,
+            retries,
+            args,
+        }) => {
+            let matrix = match matrix {
+                Some(path) => stress::parse_matrix(
+                    &std::fs::read_to_string(&path)
+                        .with_context(|| format!("Failed to read matrix file: {}", path))?,
+                )
+                .with_context(|| format!("Invalid matrix file: {}", path))?,
+                None => Vec::new(),
+            };
+            let options = StressOptions {
+                script: script.into(),
+                command,
+                args,
+                instances,
+                duration,
+                variables: defines
+                    .iter()
+                    .map(|define| parse_define(define))
+                    .collect::<Result<_>>()?,
+                matrix,
+                retries,
+            };
+            let report = stress::run(&options).await?;
+            eprint!("{}", report.report());
+            if report.failed() > 0 {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Mode::Test {
+            dir,
+            command,
+            defines,
+            junit,
+            tap,
+            retries,
+            session,
+            args,
+        }) => {
+            let options = SuiteOptions {
+                dir: dir.into(),
+                variables: defines
+                    .iter()
+                    .map(|define| parse_define(define))
+                    .collect::<Result<_>>()?,
+                retries,
+            };
+            let spawn = |env| session.spawn_quiet(&command, &args, env);
+            let report =
+                suite::run(&options, spawn, |result| eprint!("{}", result.summary())).await?;
+            if report.results.is_empty() {
+                return Err(anyhow!(
+                    "No *.{} files found in {}",
+                    suite::EXTENSION,
+                    options.dir.display()
+                ));
+            }
+            return finish_suite(&report, junit.as_deref(), tap.as_deref());
+        }
+        Some(Mode::Doctest {
+            dir,
+            command,
+            defines,
+            junit,
+            tap,
+            retries,
+            mut session,
+            args,
+        }) => {
+            let options = SuiteOptions {
+                dir: dir.into(),
+                variables: defines
+                    .iter()
+                    .map(|define| parse_define(define))
+                    .collect::<Result<_>>()?,
+                retries,
+            };
+            session.instant = true;
+            let spawn = |env| session.spawn_quiet(&command, &args, env);
+            let report =
+                suite::run_markdown(&options, spawn, |result| eprint!("{}", result.summary()))
+                    .await?;
+            if report.results.is_empty() {
+                return Err(anyhow!(
+                    "No ```{} blocks found in the *.md files in {}",
+                    markdown::LANGUAGE,
+                    options.dir.display()
+                ));
+            }
+            return finish_suite(&report, junit.as_deref(), tap.as_deref());
+        }
+        Some(Mode::Exec {
+            command,
+            lines,
+            defines,
+            session,
+            args,
+        }) => {
+            let source = if lines.is_empty() {
+                std::io::read_to_string(std::io::stdin()).context("Failed to read stdin")?
+            } else {
+                lines.join("\n")
+            };
+            let commands = parse_str(&source).context("Failed to parse script")?;
+            let mut engine = session
+                .builder(&command, args, Vec::new())?
+                .spawn()
+                .context("Failed to spawn engine")?;
+            session.configure(&mut engine)?;
+            for define in &defines {
+                let (name, value) = parse_define(define)?;
+                engine.set_var(name, value);
+            }
+            return engine
+                .execute(commands)
+                .await
+                .context("Failed to execute script");
+        }
+        Some(Mode::EmitSyntax { format }) => {
+            match format {
+                SyntaxFormat::TmLanguage => print!("{}", syntax::tm_language()),
+                SyntaxFormat::TreeSitter => print!("{}", syntax::tree_sitter_grammar()),
+                SyntaxFormat::TreeSitterHighlights => {
+                    print!("{}", syntax::tree_sitter_highlights())
+                }
+            }
+            return Ok(());
+        }
+        Some(Mode::Replay { cast, speed }) => {
+            let cast = Cast::load(&cast)?;
+            clear_screen()?;
+            cast.replay(speed, |data| {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(data).ok();
+                stdout.flush().ok();
+            })
+            .await?;
+            return Ok(());
+        }
+        Some(Mode::Svg { cast, at }) => {
+            print!("{}", Cast::load(&cast)?.to_svg(at));
+            return Ok(());
+        }
+        None => {}
+    }
+    if args.script() == "-" {
+        // Retries and reruns parse the script again, but stdin can only be
+        // read once.
+        args.stdin_script =
+            Some(std::io::read_to_string(std::io::stdin()).context("Failed to read stdin")?);
+    }
+
+    let mut metrics = ExpectMetrics::default();
+    let mut coverage = BranchCoverage::default();
+    let mut soak = SoakReport::default();
+    let mut reports = Reports {
+        metrics: &mut metrics,
+        coverage: &mut coverage,
+        soak: &mut soak,
+    };
+    let result = match (args.fuzz, args.loop_runs) {
+        (Some(runs), _) => run_fuzz(&args, runs, &mut reports).await,
+        (None, Some(limit)) => run_loop(&args, limit, &mut reports).await,
+        (None, None) => run_with_retries(&args, &mut reports).await,
+    };
+
+    if args.stats {
+        eprint!("{}", metrics.report());
+    }
+    if args.coverage {
+        eprint!("{}", coverage.report());
+    }
+    if args.leaks && !soak.is_empty() {
+        eprint!("{}", soak.report());
+    }
+    match &result {
+        Ok(()) => args.alerts().send("finished", "Script finished"),
+        Err(e) => args
+            .alerts()
+            .send("failed", &format!("Script failed: {:#}", e)),
+    }
+    result
+}
+
+/// Where `--notify` and `--on-event` report the run's progress.
+#[derive(Clone)]
+struct Alerts {
+    notify: bool,
+    hook: Option<String>,
+}
+
+impl Alerts {
+    fn is_enabled(&self) -> bool {
+        self.notify || self.hook.is_some()
+    }
+
+    /// Report `event` (`finished`, `failed`, or `marker`). The run waits for
+    /// the hook; a failing hook is reported but does not fail the run.
+    fn send(&self, event: &str, message: &str) {
+        if self.notify {
+            // Escape sequences in the message would end the notification early.
+            let text: String = message
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .chars()
+                .filter(|c| !c.is_control())
+                .collect();
+            eprint!("\x1b]9;scriptty: {}\x07\x07", text);
+        }
+        if let Some(hook) = &self.hook {
+            let status = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(hook)
+                .env("SCRIPTTY_EVENT", event)
+                .env("SCRIPTTY_MESSAGE", message)
+                .status();
+            match status {
+                Ok(status) if status.success() => {}
+                Ok(status) => eprintln!("scriptty: --on-event hook failed: {status}"),
+                Err(e) => eprintln!("scriptty: --on-event hook failed: {e}"),
+            }
+        }
+    }
+}
+
+/// Where each run's reports are collected.
+struct Reports<'a> {
+    metrics: &'a mut ExpectMetrics,
+    coverage: &'a mut BranchCoverage,
+    /// Replaced by each run, since its samples only compare within one process.
+    soak: &'a mut SoakReport,
+}
+
+/// Run the script, retrying up to `--retries` times and reporting a pass after
+/// a failure as flaky.
+async fn run_with_retries(args: &Args, reports: &mut Reports<'_>) -> Result<()> {
+    let attempts = args.retries + 1;
+    let mut result = Ok(());
+    for attempt in 1..=attempts {
+        let script = parse_script(args)?;
+        result = run(args, script, None, reports).await;
+        match &result {
+            Ok(()) if attempt > 1 => {
+                eprintln!("scriptty: flaky: passed on attempt {attempt} of {attempts}");
+                break;
+            }
+            Ok(()) => break,
+            Err(e) if attempt < attempts => {
+                eprintln!("scriptty: attempt {attempt} of {attempts} failed, retrying: {e:#}");
+            }
+            Err(_) => {}
+        }
+    }
+    result
+}
+
+/// Run the script `runs` times in fuzz mode, reporting the seed of the first
+/// failing run so it can be reproduced.
+async fn run_fuzz(args: &Args, runs: u32, reports: &mut Reports<'_>) -> Result<()> {
+    let first_seed = args.seed.unwrap_or_else(rand::random);
+    for run_index in 0..runs {
+        let seed = first_seed.wrapping_add(run_index as u64);
+        let script = parse_script(args)?;
+        if let Err(e) = run(args, script, Some(Fuzz::new(seed)), reports).await {
+            eprintln!(
+                "scriptty: fuzz run {} of {} failed with seed {seed}; reproduce with --fuzz 1 --seed {seed}",
+                run_index + 1,
+                runs
+            );
+            return Err(e);
+        }
+    }
+    eprintln!("scriptty: {runs} fuzz runs passed (seeds {first_seed}..)");
+    Ok(())
+}
 
-    let events = parse_file(&args.script)
-        .with_context(|| format!("Failed to parse script file: {}", args.script))?;
+/// Run the script again and again, up to `limit` times or until it fails,
+/// reporting the failing run.
+async fn run_loop(args: &Args, limit: Option<u32>, reports: &mut Reports<'_>) -> Result<()> {
+    let mut completed: u32 = 0;
+    while limit.is_none_or(|limit| completed < limit) {
+        let script = parse_script(args)?;
+        if let Err(e) = run(args, script, None, reports).await {
+            eprintln!(
+                "scriptty: loop run {} failed after {completed} passing runs",
+                completed + 1
+            );
+            return Err(e);
+        }
+        completed += 1;
+    }
+    eprintln!("scriptty: {completed} loop runs passed");
+    Ok(())
+}
 
-    let mut engine = Engine::spawn(&args.command, &args.args).context("Failed to spawn engine")?;
+/// A parsed script file.
+struct Script {
+    /// Environment set by the script's `env` header.
+    env: Vec<(String, String)>,
+    /// Settings of the script's `@meta` header.
+    meta: Vec<(String, String)>,
+    commands: Vec<Box<dyn ScripttyCommand>>,
+}
 
-    // Give the program time to start up before executing events.
-    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+fn parse_script(args: &Args) -> Result<Script> {
+    let script = args.script();
+    let source = match &args.stdin_script {
+        Some(source) => source.clone(),
+        None => std::fs::read_to_string(script)
+            .with_context(|| format!("Failed to read script file: {}", script))?,
+    };
+    let source = match &args.block {
+        Some(name) => markdown::extract(&source, name)
+            .with_context(|| format!("Failed to extract script from {}", script))?,
+        None => source,
+    };
+    #[cfg(feature = "templates")]
+    let source = match &args.data {
+        Some(data) => template::render(&source, &template::load_data(data)?)
+            .with_context(|| format!("Failed to render script template: {}", script))?,
+        None => source,
+    };
+    let parsed = script_env(&source).and_then(|env| {
+        Ok(Script {
+            env,
+            meta: script_meta(&source)?,
+            commands: match args.stdin_script {
+                Some(_) => parse_str(&source)?,
+                None => parse_source(&source, script)?,
+            },
+        })
+    });
+    parsed.with_context(|| format!("Failed to parse script file: {}", script))
+}
 
+/// Spawn the program and run one attempt of the script, folding the attempt's
+/// expect statistics, branch coverage, and resource usage into `reports`.
+async fn run(
+    args: &Args,
+    script: Script,
+    fuzz: Option<Fuzz>,
+    reports: &mut Reports<'_>,
+) -> Result<()> {
+    let expected = args.verify.as_deref().map(Cast::load).transpose()?;
+    // Clear the terminal before the program starts, so its first output
+    // (a banner, a prompt) stays visible.
     clear_screen()?;
+    // A script with `@meta ssh.*` settings describes its own connection; the
+    // command's arguments follow as the remote command.
+    let ssh = match args.command() {
+        "ssh" => SshOptions::from_meta(&script.meta)?,
+        _ => None,
+    };
+    let ssh_args = ssh.iter().flat_map(SshOptions::args);
+    let mut engine = args
+        .session
+        .builder(
+            args.command(),
+            ssh_args.chain(args.args.iter().cloned()),
+            script.env,
+        )?
+        .spawn()
+        .context("Failed to spawn engine")?;
+    args.session.configure(&mut engine)?;
+    engine.set_quotas(Quotas {
+        max_output: args.max_output,
+        max_duration: args.max_duration,
+        max_input: args.max_input,
+    });
+    if let Some(fuzz) = fuzz {
+        engine.set_fuzz(fuzz);
+    }
+    for define in &args.defines {
+        let (name, value) = parse_define(define)?;
+        engine.set_var(name, value);
+    }
+    engine.set_snapshot_dir(&args.snapshot_dir);
+    if args.update_snapshots {
+        engine.set_snapshot_mode(SnapshotMode::Record);
+    }
+    if args.accessible_transcript.is_some() {
+        engine.record_interactions();
+    }
+    if let Some(path) = &args.record {
+        engine.record_cast(path)?;
+    }
+    let recording = SharedBuffer::default();
+    if expected.is_some() {
+        engine.record_cast_to(recording.clone())?;
+    }
+    if let Some(path) = &args.events {
+        engine.record_events(path)?;
+    }
+    if let Some(path) = &args.transcript {
+        engine.record_transcript(path, args.transcript_input)?;
+    }
+    if let Some(dir) = &args.images {
+        engine.save_images(dir)?;
+    }
+    if let Some(hook) = args.on_fail.clone() {
+        engine.on_failure(move |report| run_failure_hook(&hook, report));
+    }
+    let alerts = args.alerts();
+    if alerts.is_enabled() {
+        engine.on_marker(move |name| alerts.send("marker", name));
+    }
+    #[cfg(feature = "otlp")]
+    let exporter = match &args.otlp_endpoint {
+        Some(endpoint) => Some(scriptty::otlp::Exporter::new(endpoint)?),
+        None => scriptty::otlp::Exporter::from_env()?,
+    };
+    #[cfg(feature = "otlp")]
+    if exporter.is_some() {
+        engine.enable_tracing();
+    }
+    if let Some(path) = &args.control {
+        engine
+            .listen_control(path)
+            .context("Failed to start control socket")?;
+    }
 
-    engine
-        .execute(events)
-        .await
-        .context("Failed to execute script")?;
+    let result = engine.execute(script.commands).await;
+    reports.metrics.merge(engine.metrics());
+    reports.coverage.merge(engine.coverage());
+    #[cfg(feature = "otlp")]
+    if let (Some(exporter), Some(trace)) = (&exporter, engine.trace()) {
+        let service = args
+            .otlp_service
+            .clone()
+            .unwrap_or_else(scriptty::otlp::service_name);
+        // Losing the spans should not fail an otherwise passing run.
+        if let Err(e) = exporter.export(trace, &service) {
+            eprintln!("scriptty: {e:#}");
+        }
+    }
+    *reports.soak = engine.soak_report().clone();
+    if let Some(path) = &args.accessible_transcript {
+        std::fs::write(path, engine.accessible_transcript())
+            .with_context(|| format!("Failed to write accessible transcript: {}", path))?;
+    }
+    if let Some(path) = &args.html {
+        std::fs::write(path, engine.session_html())
+            .with_context(|| format!("Failed to write HTML export: {}", path))?;
+    }
+    if let Some(workspace) = engine.workspace().filter(|w| w.is_kept()) {
+        eprintln!("scriptty: workspace kept at {}", workspace.path().display());
+    }
+    result.context("Failed to execute script")?;
+    if let (Some(path), Some(expected)) = (&args.verify, expected) {
+        let actual = Cast::parse(&String::from_utf8_lossy(&recording.0.lock().unwrap()))?;
+        let diff = expected.diff_output(&actual);
+        if !diff.is_empty() {
+            return Err(anyhow!(
+                "Output differs from the recorded cast {}:\n{}",
+                path,
+                diff.to_string().trim_end()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Run the `--on-fail` command with the report's path as its last argument
+/// and in `$SCRIPTTY_REPORT`. A failing hook is reported but does not change
+/// the run's result.
+fn run_failure_hook(hook: &str, report: &std::path::Path) {
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", hook))
+        .arg("sh")
+        .arg(report)
+        .env("SCRIPTTY_REPORT", report)
+        .status();
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => eprintln!("scriptty: --on-fail hook failed: {status}"),
+        Err(e) => eprintln!("scriptty: --on-fail hook failed: {e}"),
+    }
+}
+
+/// A cast being recorded in memory, for `--verify`.
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
 
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Print the summary of a suite run, write the requested reports, and exit
+/// with status 1 if anything failed.
+fn finish_suite(report: &SuiteReport, junit: Option<&str>, tap: Option<&str>) -> Result<()> {
+    eprint!("{}", report.report());
+    if let Some(path) = junit {
+        std::fs::write(path, report.junit())
+            .with_context(|| format!("Failed to write JUnit report: {}", path))?;
+    }
+    if let Some(path) = tap {
+        std::fs::write(path, report.tap())
+            .with_context(|| format!("Failed to write TAP report: {}", path))?;
+    }
+    if report.failed() > 0 {
+        std::process::exit(1);
+    }
     Ok(())
 }
 
+/// Split a `-D NAME=VALUE` definition.
+fn parse_define(define: &str) -> Result<(String, String)> {
+    let (name, value) = define
+        .split_once('=')
+        .with_context(|| format!("Invalid definition, expected NAME=VALUE: {}", define))?;
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// Split an `--env NAME=VALUE` setting.
+fn parse_env(env: &str) -> Result<(String, String)> {
+    let (name, value) = env
+        .split_once('=')
+        .with_context(|| format!("Invalid environment variable, expected NAME=VALUE: {}", env))?;
+    Ok((name.to_string(), value.to_string()))
+}
+
 fn clear_screen() -> Result<()> {
     print!("\x1B[2J\x1B[1;1H");
     std::io::stdout().flush()?;
@@ -0,0 +1,163 @@
+//! Scripts embedded in Markdown documents.
+//!
+//! A fenced code block whose info string is `scriptty NAME` holds the script
+//! called `NAME`, so the examples in a README can run (and be tested in CI)
+//! as they are written:
+//!
+//! ````text
+//! ```scriptty login
+//! expect "login:"
+//! send "bob"
+//! key Enter
+//! ```
+//! ````
+//!
+//! Blocks fenced with backticks or tildes are recognized; other blocks and
+//! the prose around them are ignored.
+
+use anyhow::{Result, anyhow};
+
+/// Info string word that marks a code block as a script.
+pub const LANGUAGE: &str = "scriptty";
+
+/// A script block found in a Markdown document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    /// The word after `scriptty` in the info string, if any.
+    pub name: Option<String>,
+    /// 1-based line number of the first line inside the fence.
+    pub line: usize,
+    pub content: String,
+}
+
+impl Block {
+    /// The script, preceded by blank lines so that line numbers in parse
+    /// errors match the Markdown document.
+    pub fn script(&self) -> String {
+        "\n".repeat(self.line - 1) + &self.content
+    }
+}
+
+/// The `scriptty` blocks of `markdown`, in document order. A block that is
+/// never closed runs to the end of the document, as in CommonMark.
+pub fn blocks(markdown: &str) -> Vec<Block> {
+    let mut found = Vec::new();
+    let mut lines = markdown.lines().enumerate();
+    while let Some((_, line)) = lines.next() {
+        let Some((fence, info)) = opening_fence(line) else {
+            continue;
+        };
+        let mut words = info.split_whitespace();
+        let is_script = words.next() == Some(LANGUAGE);
+        let name = words.next().map(str::to_string);
+        let mut block = Block {
+            name,
+            line: 0,
+            content: String::new(),
+        };
+        for (index, line) in lines.by_ref() {
+            if block.line == 0 {
+                block.line = index + 1;
+            }
+            if is_closing_fence(line, fence) {
+                break;
+            }
+            block.content.push_str(line);
+            block.content.push('\n');
+        }
+        if is_script {
+            found.push(block);
+        }
+    }
+    found
+}
+
+/// The script in the block called `name`; see [`Block::script`].
+///
+/// # Errors
+///
+/// Returns an error naming the available blocks if there is no block called
+/// `name`.
+pub fn extract(markdown: &str, name: &str) -> Result<String> {
+    let blocks = blocks(markdown);
+    match blocks.iter().find(|b| b.name.as_deref() == Some(name)) {
+        Some(block) => Ok(block.script()),
+        None => {
+            let names: Vec<_> = blocks.iter().filter_map(|b| b.name.as_deref()).collect();
+            Err(anyhow!(
+                "No ```{} block named '{}' (found: {})",
+                LANGUAGE,
+                name,
+                if names.is_empty() {
+                    "none".to_string()
+                } else {
+                    names.join(", ")
+                }
+            ))
+        }
+    }
+}
+
+/// The fence (e.g. "```") and info string of a line that opens a code block.
+fn opening_fence(line: &str) -> Option<(&str, &str)> {
+    let trimmed = line.trim_start();
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let marker = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = trimmed.len() - trimmed.trim_start_matches(marker).len();
+    let (fence, info) = trimmed.split_at(len);
+    // A backtick fence's info string may not contain backticks.
+    if len < 3 || (marker == '`' && info.contains('`')) {
+        return None;
+    }
+    Some((fence, info.trim()))
+}
+
+/// Whether `line` closes a block opened with `fence`.
+fn is_closing_fence(line: &str, fence: &str) -> bool {
+    let trimmed = line.trim();
+    let marker = fence.chars().next().unwrap_or('`');
+    trimmed.len() >= fence.len() && trimmed.chars().all(|c| c == marker)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const README: &str = "# Demo\n\
+        \n\
+        ```bash\n\
+        cargo run\n\
+        ```\n\
+        \n\
+        ```scriptty login\n\
+        expect \"login:\"\n\
+        ```\n\
+        \n\
+        ~~~~scriptty nested\n\
+        show \"```\"\n\
+        ~~~~\n\
+        ```scriptty\n\
+        wait 1s\n";
+
+    #[test]
+    fn test_blocks() {
+        let blocks = blocks(README);
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0].name.as_deref(), Some("login"));
+        assert_eq!(blocks[0].line, 8);
+        assert_eq!(blocks[0].content, "expect \"login:\"\n");
+        assert_eq!(blocks[1].content, "show \"```\"\n");
+        assert_eq!(blocks[2].name, None);
+        assert_eq!(blocks[2].content, "wait 1s\n");
+    }
+
+    #[test]
+    fn test_extract() {
+        let script = extract(README, "login").unwrap();
+        assert_eq!(script.lines().nth(7), Some("expect \"login:\""));
+        let err = extract(README, "logout").unwrap_err().to_string();
+        assert!(err.contains("found: login, nested"), "got: {err}");
+    }
+}
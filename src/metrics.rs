@@ -0,0 +1,165 @@
+//! Wait-time statistics for pattern-matching commands.
+//!
+//! Every `expect`-style wait records how long it took for its pattern to show
+//! up. Looking at the p50/p95 over a run (or several runs merged together)
+//! makes it possible to pick timeouts from data rather than by guessing.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default)]
+struct Samples {
+    waits: Vec<Duration>,
+    timeouts: usize,
+}
+
+/// Per-pattern wait times collected during a run.
+#[derive(Debug, Clone, Default)]
+pub struct ExpectMetrics {
+    patterns: BTreeMap<String, Samples>,
+}
+
+/// Summary statistics for one pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternStats {
+    pub pattern: String,
+    /// Number of successful waits.
+    pub count: usize,
+    /// Number of waits that timed out.
+    pub timeouts: usize,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+}
+
+impl ExpectMetrics {
+    /// Record a successful wait for `pattern`.
+    pub(crate) fn record(&mut self, pattern: &str, wait: Duration) {
+        self.entry(pattern).waits.push(wait);
+    }
+
+    /// Record a wait for `pattern` that timed out.
+    pub(crate) fn record_timeout(&mut self, pattern: &str) {
+        self.entry(pattern).timeouts += 1;
+    }
+
+    fn entry(&mut self, pattern: &str) -> &mut Samples {
+        self.patterns.entry(pattern.to_string()).or_default()
+    }
+
+    /// Fold the samples from `other` into `self`, e.g. to aggregate a suite.
+    pub fn merge(&mut self, other: &ExpectMetrics) {
+        for (pattern, samples) in &other.patterns {
+            let entry = self.entry(pattern);
+            entry.waits.extend_from_slice(&samples.waits);
+            entry.timeouts += samples.timeouts;
+        }
+    }
+
+    /// Whether no waits have been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Summary statistics per pattern, ordered by pattern.
+    pub fn stats(&self) -> Vec<PatternStats> {
+        self.patterns
+            .iter()
+            .map(|(pattern, samples)| {
+                let mut waits = samples.waits.clone();
+                waits.sort();
+                PatternStats {
+                    pattern: pattern.clone(),
+                    count: waits.len(),
+                    timeouts: samples.timeouts,
+                    p50: percentile(&waits, 0.50),
+                    p95: percentile(&waits, 0.95),
+                    max: waits.last().copied().unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+
+    /// Render the statistics as a human-readable table.
+    pub fn report(&self) -> String {
+        let mut out = String::from("expect latency:\n");
+        let _ = writeln!(
+            out,
+            "  {:>5} {:>9} {:>9} {:>9} {:>8}  pattern",
+            "n", "p50", "p95", "max", "timeouts"
+        );
+        for s in self.stats() {
+            let _ = writeln!(
+                out,
+                "  {:>5} {:>9} {:>9} {:>9} {:>8}  {:?}",
+                s.count,
+                format_ms(s.p50),
+                format_ms(s.p95),
+                format_ms(s.max),
+                s.timeouts,
+                s.pattern
+            );
+        }
+        out
+    }
+}
+
+/// Nearest-rank percentile of an ascending slice; zero when empty.
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn format_ms(d: Duration) -> String {
+    format!("{}ms", d.as_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let mut metrics = ExpectMetrics::default();
+        for n in 1..=20 {
+            metrics.record("$ ", ms(n * 10));
+        }
+        let stats = &metrics.stats()[0];
+        assert_eq!(stats.count, 20);
+        assert_eq!(stats.p50, ms(100));
+        assert_eq!(stats.p95, ms(190));
+        assert_eq!(stats.max, ms(200));
+    }
+
+    #[test]
+    fn test_timeouts_only() {
+        let mut metrics = ExpectMetrics::default();
+        metrics.record_timeout("never");
+        let stats = &metrics.stats()[0];
+        assert_eq!(stats.count, 0);
+        assert_eq!(stats.timeouts, 1);
+        assert_eq!(stats.p95, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = ExpectMetrics::default();
+        a.record("x", ms(5));
+        let mut b = ExpectMetrics::default();
+        b.record("x", ms(15));
+        b.record("y", ms(1));
+        a.merge(&b);
+        let stats = a.stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].count, 2);
+        assert_eq!(stats[0].max, ms(15));
+    }
+}
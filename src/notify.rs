@@ -0,0 +1,178 @@
+//! Detection of terminal bells and desktop notifications in program output.
+//!
+//! Programs signal completion with a BEL character or with OSC notification
+//! sequences: `ESC ] 9 ; message BEL` (iTerm2/ConEmu) and
+//! `ESC ] 777 ; notify ; title ; body BEL` (rxvt/VTE). The output pipeline
+//! runs every chunk through a `NotificationScanner` so commands like
+//! `expect_bell` and `expect_notification` can wait on them.
+
+/// A bell or notification emitted by the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notification {
+    /// A BEL character outside of any escape sequence.
+    Bell,
+    /// An OSC 9 or OSC 777 desktop notification.
+    Message { title: String, body: String },
+}
+
+impl Notification {
+    /// Whether `text` occurs in the notification's title or body.
+    pub fn contains(&self, text: &str) -> bool {
+        match self {
+            Notification::Bell => false,
+            Notification::Message { title, body } => title.contains(text) || body.contains(text),
+        }
+    }
+}
+
+/// Longest OSC payload kept; longer sequences are discarded.
+const MAX_OSC_LEN: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Ground,
+    Escape,
+    Osc,
+    OscEscape,
+}
+
+/// Incremental scanner that finds notifications in a byte stream, including
+/// sequences split across chunks.
+#[derive(Debug)]
+pub(crate) struct NotificationScanner {
+    state: State,
+    payload: Vec<u8>,
+}
+
+impl NotificationScanner {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: State::Ground,
+            payload: Vec::new(),
+        }
+    }
+
+    /// Scan the next chunk of output and return the notifications it completes.
+    pub(crate) fn feed(&mut self, data: &[u8]) -> Vec<Notification> {
+        let mut found = Vec::new();
+        for &byte in data {
+            self.state = match (self.state, byte) {
+                (State::Ground, 0x07) => {
+                    found.push(Notification::Bell);
+                    State::Ground
+                }
+                (State::Ground, 0x1b) => State::Escape,
+                (State::Ground, _) => State::Ground,
+                (State::Escape, b']') => {
+                    self.payload.clear();
+                    State::Osc
+                }
+                (State::Escape, 0x1b) => State::Escape,
+                (State::Escape, 0x07) => {
+                    found.push(Notification::Bell);
+                    State::Ground
+                }
+                (State::Escape, _) => State::Ground,
+                (State::Osc, 0x07) => {
+                    found.extend(self.finish_osc());
+                    State::Ground
+                }
+                (State::Osc, 0x1b) => State::OscEscape,
+                (State::Osc, _) => {
+                    if self.payload.len() < MAX_OSC_LEN {
+                        self.payload.push(byte);
+                    }
+                    State::Osc
+                }
+                (State::OscEscape, b'\\') => {
+                    found.extend(self.finish_osc());
+                    State::Ground
+                }
+                // A new escape sequence interrupted the OSC; drop it.
+                (State::OscEscape, b']') => {
+                    self.payload.clear();
+                    State::Osc
+                }
+                (State::OscEscape, _) => State::Ground,
+            };
+        }
+        found
+    }
+
+    fn finish_osc(&mut self) -> Option<Notification> {
+        if self.payload.len() >= MAX_OSC_LEN {
+            return None;
+        }
+        let payload = String::from_utf8_lossy(&self.payload);
+        if let Some(message) = payload.strip_prefix("9;") {
+            // `OSC 9 ; 4 ; …` is ConEmu's progress report, not a notification.
+            if message.starts_with("4;") {
+                return None;
+            }
+            return Some(Notification::Message {
+                title: String::new(),
+                body: message.to_string(),
+            });
+        }
+        if let Some(rest) = payload.strip_prefix("777;notify;") {
+            let (title, body) = rest.split_once(';').unwrap_or((rest, ""));
+            return Some(Notification::Message {
+                title: title.to_string(),
+                body: body.to_string(),
+            });
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(title: &str, body: &str) -> Notification {
+        Notification::Message {
+            title: title.into(),
+            body: body.into(),
+        }
+    }
+
+    #[test]
+    fn test_bell() {
+        let mut scanner = NotificationScanner::new();
+        assert_eq!(scanner.feed(b"done\x07"), vec![Notification::Bell]);
+    }
+
+    #[test]
+    fn test_osc_terminator_is_not_a_bell() {
+        let mut scanner = NotificationScanner::new();
+        // Window title update terminated by BEL.
+        assert!(scanner.feed(b"\x1b]0;my title\x07").is_empty());
+    }
+
+    #[test]
+    fn test_osc9() {
+        let mut scanner = NotificationScanner::new();
+        assert_eq!(
+            scanner.feed(b"\x1b]9;Build finished\x07"),
+            vec![message("", "Build finished")]
+        );
+        assert!(scanner.feed(b"\x1b]9;4;1;50\x07").is_empty());
+    }
+
+    #[test]
+    fn test_osc777_with_st_split_across_chunks() {
+        let mut scanner = NotificationScanner::new();
+        assert!(scanner.feed(b"\x1b]777;notify;CI;tests pa").is_empty());
+        assert_eq!(
+            scanner.feed(b"ssed\x1b\\"),
+            vec![message("CI", "tests passed")]
+        );
+    }
+
+    #[test]
+    fn test_contains() {
+        assert!(message("CI", "tests passed").contains("passed"));
+        assert!(message("CI", "").contains("CI"));
+        assert!(!Notification::Bell.contains(""));
+    }
+}
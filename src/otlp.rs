@@ -0,0 +1,406 @@
+//! Sending a run's [`Trace`] to an OpenTelemetry collector.
+//!
+//! Spans are exported with OTLP over HTTP (or HTTPS), JSON-encoded, in a
+//! single request after the run. The standard `OTEL_EXPORTER_OTLP_ENDPOINT`,
+//! `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`, `OTEL_EXPORTER_OTLP_HEADERS`,
+//! `OTEL_EXPORTER_OTLP_TIMEOUT` (and their `TRACES_` variants) and
+//! `OTEL_SERVICE_NAME` variables are honored, so the same settings as for
+//! other instrumented programs work, e.g. an API key for a hosted backend.
+
+use crate::trace::{AttributeValue, Span, Trace};
+use anyhow::{Context as _, Result, anyhow};
+use serde_json::{Value, json};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long to wait for the collector to accept and answer the request.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait for the connection to the collector.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The OTLP/JSON request body for `trace`, with `service_name` as the
+/// `service.name` resource attribute.
+pub fn to_json(trace: &Trace, service_name: &str) -> Value {
+    let trace_id = hex(&trace.trace_id);
+    let spans: Vec<Value> = trace
+        .spans()
+        .iter()
+        .map(|span| span_json(span, &trace_id))
+        .collect();
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [attribute("service.name", &AttributeValue::String(service_name.to_string()))],
+            },
+            "scopeSpans": [{
+                "scope": {"name": "scriptty", "version": env!("CARGO_PKG_VERSION")},
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+fn span_json(span: &Span, trace_id: &str) -> Value {
+    let mut value = json!({
+        "traceId": trace_id,
+        "spanId": hex(&span.span_id),
+        "name": span.name,
+        // SPAN_KIND_INTERNAL
+        "kind": 1,
+        "startTimeUnixNano": unix_nanos(span.start),
+        "endTimeUnixNano": unix_nanos(span.end),
+        "attributes": span
+            .attributes
+            .iter()
+            .map(|(key, value)| attribute(key, value))
+            .collect::<Vec<_>>(),
+        "status": match &span.error {
+            // STATUS_CODE_OK and STATUS_CODE_ERROR
+            None => json!({"code": 1}),
+            Some(message) => json!({"code": 2, "message": message}),
+        },
+    });
+    if let Some(parent) = span.parent_id {
+        value["parentSpanId"] = json!(hex(&parent));
+    }
+    value
+}
+
+fn attribute(key: &str, value: &AttributeValue) -> Value {
+    let value = match value {
+        AttributeValue::String(s) => json!({"stringValue": s}),
+        // 64-bit integers are strings in OTLP/JSON.
+        AttributeValue::Int(n) => json!({"intValue": n.to_string()}),
+    };
+    json!({"key": key, "value": value})
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos())
+        .to_string()
+}
+
+/// Environment variable naming the collector; `/v1/traces` is appended.
+pub const ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// Environment variable naming the full traces URL; takes precedence over
+/// [`ENDPOINT_ENV`].
+pub const TRACES_ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT";
+
+/// Request headers, as `key=value` pairs separated by commas.
+const HEADER_VARS: &[&str] = &[
+    "OTEL_EXPORTER_OTLP_HEADERS",
+    "OTEL_EXPORTER_OTLP_TRACES_HEADERS",
+];
+
+/// Request timeout in milliseconds; the traces variable wins.
+const TIMEOUT_VARS: &[&str] = &[
+    "OTEL_EXPORTER_OTLP_TRACES_TIMEOUT",
+    "OTEL_EXPORTER_OTLP_TIMEOUT",
+];
+
+/// Sends traces to one OTLP/HTTP collector.
+///
+/// Headers and the timeout are read from the standard `OTEL_EXPORTER_OTLP_*`
+/// variables when the exporter is created; [`Exporter::header`] adds more.
+#[derive(Debug, Clone)]
+pub struct Exporter {
+    url: String,
+    headers: Vec<(String, String)>,
+    timeout: Duration,
+}
+
+impl Exporter {
+    /// An exporter for the collector at `endpoint` (`http://` or `https://`,
+    /// with `/v1/traces` appended unless the URL has a path).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the endpoint is not an `http://` or `https://`
+    /// URL, or an `OTEL_EXPORTER_OTLP_*` variable is malformed.
+    pub fn new(endpoint: &str) -> Result<Self> {
+        Self::configure(Some(endpoint), env_var)
+            .map(|exporter| exporter.expect("an endpoint was given"))
+    }
+
+    /// An exporter for the collector named by [`TRACES_ENDPOINT_ENV`] or
+    /// [`ENDPOINT_ENV`], or `None` if neither is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a variable is malformed.
+    pub fn from_env() -> Result<Option<Self>> {
+        Self::configure(None, env_var)
+    }
+
+    fn configure(
+        endpoint: Option<&str>,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Option<Self>> {
+        let url = match (endpoint, var(TRACES_ENDPOINT_ENV), var(ENDPOINT_ENV)) {
+            (Some(endpoint), _, _) => traces_url(endpoint, false)?,
+            (None, Some(url), _) => traces_url(&url, true)?,
+            (None, None, Some(base)) => {
+                traces_url(&format!("{}/v1/traces", base.trim_end_matches('/')), true)?
+            }
+            (None, None, None) => return Ok(None),
+        };
+        let mut headers = Vec::new();
+        for name in HEADER_VARS {
+            if let Some(value) = var(name) {
+                headers.extend(parse_headers(&value).with_context(|| format!("Invalid {}", name))?);
+            }
+        }
+        let timeout = match TIMEOUT_VARS
+            .iter()
+            .find_map(|name| var(name).map(|v| (name, v)))
+        {
+            Some((name, millis)) => Duration::from_millis(
+                millis
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("Invalid {}: {}", name, millis))?,
+            ),
+            None => TIMEOUT,
+        };
+        Ok(Some(Self {
+            url,
+            headers,
+            timeout,
+        }))
+    }
+
+    /// Send `name: value` with every request, e.g. an API key.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// The URL spans are posted to.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Send `trace` with `service_name` as its `service.name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the collector cannot be reached in time or
+    /// rejects the spans.
+    pub fn export(&self, trace: &Trace, service_name: &str) -> Result<()> {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(CONNECT_TIMEOUT.min(self.timeout))
+            .timeout(self.timeout)
+            .build();
+        let mut request = agent
+            .post(&self.url)
+            .set("Content-Type", "application/json");
+        for (name, value) in &self.headers {
+            request = request.set(name, value);
+        }
+        match request.send_string(&to_json(trace, service_name).to_string()) {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(code, response)) => Err(anyhow!(
+                "OTLP endpoint {} rejected the spans: {} {}",
+                self.url,
+                code,
+                response.status_text()
+            )),
+            Err(e) => Err(anyhow!(e))
+                .with_context(|| format!("Failed to send spans to OTLP endpoint: {}", self.url)),
+        }
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// The service name for spans: `OTEL_SERVICE_NAME`, or `scriptty`.
+pub fn service_name() -> String {
+    env_var("OTEL_SERVICE_NAME").unwrap_or_else(|| "scriptty".to_string())
+}
+
+/// Send `trace` to the collector at `endpoint`; see [`Exporter::new`].
+///
+/// # Errors
+///
+/// Returns an error if the endpoint is invalid, the collector cannot be
+/// reached, or it rejects the spans.
+pub fn export(trace: &Trace, endpoint: &str, service_name: &str) -> Result<()> {
+    Exporter::new(endpoint)?.export(trace, service_name)
+}
+
+/// Check `endpoint` and, unless `exact`, add `/v1/traces` when it has no
+/// path.
+fn traces_url(endpoint: &str, exact: bool) -> Result<String> {
+    let rest = endpoint
+        .strip_prefix("http://")
+        .or_else(|| endpoint.strip_prefix("https://"))
+        .ok_or_else(|| {
+            anyhow!(
+                "Unsupported OTLP endpoint (expected http:// or https://): {}",
+                endpoint
+            )
+        })?;
+    let host = rest.split('/').next().unwrap_or_default();
+    if host.is_empty() {
+        return Err(anyhow!("OTLP endpoint without a host: {}", endpoint));
+    }
+    if exact || rest.trim_end_matches('/') != host {
+        return Ok(endpoint.to_string());
+    }
+    Ok(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+}
+
+/// Parse `key=value,key2=value2`, with percent-encoded values.
+fn parse_headers(value: &str) -> Result<Vec<(String, String)>> {
+    value
+        .split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Expected key=value, got: {}", pair))?;
+            Ok((key.trim().to_string(), percent_decode(value.trim())?))
+        })
+        .collect()
+}
+
+fn percent_decode(text: &str) -> Result<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let code = tail
+                .get(..2)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| anyhow!("Invalid percent escape in: {}", text))?;
+            bytes.push(code);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).with_context(|| format!("Invalid UTF-8 in: {}", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_traces_url() {
+        assert_eq!(
+            traces_url("http://localhost:4318", false).unwrap(),
+            "http://localhost:4318/v1/traces"
+        );
+        assert_eq!(
+            traces_url("https://collector/otlp/v1/traces", false).unwrap(),
+            "https://collector/otlp/v1/traces"
+        );
+        assert_eq!(
+            traces_url("https://collector", true).unwrap(),
+            "https://collector"
+        );
+        assert!(traces_url("ftp://localhost:4318", false).is_err());
+        assert!(traces_url("http:///v1/traces", false).is_err());
+    }
+
+    #[test]
+    fn test_configure_from_env() {
+        let vars: HashMap<&str, &str> = [
+            (ENDPOINT_ENV, "https://otlp.example.com/"),
+            ("OTEL_EXPORTER_OTLP_HEADERS", "api-key=s%3Dcret, x-team=ci"),
+            ("OTEL_EXPORTER_OTLP_TIMEOUT", "2500"),
+        ]
+        .into();
+        let var = |name: &str| vars.get(name).map(|v| v.to_string());
+        let exporter = Exporter::configure(None, var).unwrap().unwrap();
+        assert_eq!(exporter.url(), "https://otlp.example.com/v1/traces");
+        assert_eq!(
+            exporter.headers,
+            vec![
+                ("api-key".to_string(), "s=cret".to_string()),
+                ("x-team".to_string(), "ci".to_string())
+            ]
+        );
+        assert_eq!(exporter.timeout, Duration::from_millis(2500));
+
+        let exporter = Exporter::configure(Some("http://localhost:4318"), var).unwrap();
+        assert_eq!(exporter.unwrap().url(), "http://localhost:4318/v1/traces");
+        assert!(Exporter::configure(None, |_| None).unwrap().is_none());
+        assert!(parse_headers("novalue").is_err());
+        assert!(parse_headers("k=%zz").is_err());
+    }
+
+    #[test]
+    fn test_to_json() {
+        let mut trace = Trace::new();
+        trace.begin_command();
+        trace.record_wait("$ ", Duration::from_millis(20), true);
+        trace.end_command("expect", Duration::from_millis(30), None);
+        trace.end_run("scriptty run", "bash", Some("boom".into()));
+
+        let json = to_json(&trace, "my-tests");
+        let resource = &json["resourceSpans"][0];
+        assert_eq!(
+            resource["resource"]["attributes"][0]["value"]["stringValue"],
+            "my-tests"
+        );
+        let spans = resource["scopeSpans"][0]["spans"].as_array().unwrap();
+        assert_eq!(spans.len(), 3);
+        let trace_id = spans[0]["traceId"].as_str().unwrap();
+        assert_eq!(trace_id.len(), 32);
+        assert_eq!(spans[0]["name"], "expect");
+        assert_eq!(spans[0]["parentSpanId"], spans[1]["spanId"]);
+        assert_eq!(spans[0]["attributes"][1]["value"]["intValue"], "20");
+        assert_eq!(spans[1]["status"]["code"], 1);
+        assert_eq!(spans[2]["parentSpanId"], Value::Null);
+        assert_eq!(spans[2]["status"]["code"], 2);
+        assert_eq!(spans[2]["status"]["message"], "boom");
+        assert_eq!(spans[1]["parentSpanId"], spans[2]["spanId"]);
+    }
+
+    #[test]
+    fn test_export() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            while !String::from_utf8_lossy(&request).contains("\"resourceSpans\"")
+                || !request.ends_with(b"}")
+            {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}")
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        let mut trace = Trace::new();
+        trace.end_run("scriptty run", "bash", None);
+        Exporter::new(&endpoint)
+            .unwrap()
+            .header("Authorization", "Bearer t0ken")
+            .export(&trace, "scriptty")
+            .unwrap();
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /v1/traces HTTP/1.1\r\n"));
+        assert!(request.contains("Content-Type: application/json"));
+        assert!(request.contains("Authorization: Bearer t0ken"));
+    }
+}
@@ -0,0 +1,138 @@
+//! Output handler adapters.
+//!
+//! These wrap a user-supplied output handler to make several engines writing to
+//! the same sink distinguishable — for example when recording two sessions
+//! (client and server) into a single terminal.
+
+use std::sync::Mutex;
+
+/// ANSI foreground colors usable for session prefixes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+}
+
+impl Color {
+    /// The SGR parameter selecting this foreground color.
+    fn sgr(self) -> u8 {
+        match self {
+            Color::Red => 31,
+            Color::Green => 32,
+            Color::Yellow => 33,
+            Color::Blue => 34,
+            Color::Magenta => 35,
+            Color::Cyan => 36,
+        }
+    }
+}
+
+/// Wrap `handler` so that every output line is prefixed with `[name] `.
+///
+/// When `color` is set the prefix is rendered in that color and the style is
+/// reset right after it, leaving the program's own colors untouched. Line
+/// boundaries are tracked across calls, so a line split over several PTY
+/// chunks is prefixed only once.
+///
+/// # Example
+///
+/// ```no_run
+/// use scriptty::Engine;
+/// use scriptty::output::{Color, prefix_lines};
+/// use std::io::Write;
+///
+/// fn stdout(data: &[u8]) {
+///     let mut out = std::io::stdout().lock();
+///     out.write_all(data).ok();
+///     out.flush().ok();
+/// }
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let server = Engine::spawn_with_handler(
+///     "redis-server",
+///     &[] as &[&str],
+///     prefix_lines("server", Some(Color::Cyan), stdout),
+/// )?;
+/// let client = Engine::spawn_with_handler(
+///     "redis-cli",
+///     &[] as &[&str],
+///     prefix_lines("client", Some(Color::Yellow), stdout),
+/// )?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn prefix_lines<F>(
+    name: &str,
+    color: Option<Color>,
+    handler: F,
+) -> impl Fn(&[u8]) + Send + Sync + 'static
+where
+    F: Fn(&[u8]) + Send + Sync + 'static,
+{
+    let prefix = match color {
+        Some(c) => format!("\x1b[{}m[{}]\x1b[0m ", c.sgr(), name),
+        None => format!("[{}] ", name),
+    }
+    .into_bytes();
+    let at_line_start = Mutex::new(true);
+
+    move |data: &[u8]| {
+        let mut at_start = at_line_start.lock().unwrap();
+        let mut out = Vec::with_capacity(data.len() + prefix.len());
+        for &byte in data {
+            if *at_start {
+                out.extend_from_slice(&prefix);
+                *at_start = false;
+            }
+            out.push(byte);
+            if byte == b'\n' {
+                *at_start = true;
+            }
+        }
+        handler(&out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    type Captured = Arc<Mutex<Vec<u8>>>;
+
+    fn capture(color: Option<Color>) -> (impl Fn(&[u8]), Captured) {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let sink = captured.clone();
+        let handler = prefix_lines("db", color, move |data| {
+            sink.lock().unwrap().extend_from_slice(data);
+        });
+        (handler, captured)
+    }
+
+    #[test]
+    fn test_prefix_each_line() {
+        let (handler, captured) = capture(None);
+        handler(b"one\ntwo\n");
+        assert_eq!(&*captured.lock().unwrap(), b"[db] one\n[db] two\n");
+    }
+
+    #[test]
+    fn test_line_split_across_chunks() {
+        let (handler, captured) = capture(None);
+        handler(b"hel");
+        handler(b"lo\nwor");
+        handler(b"ld");
+        assert_eq!(&*captured.lock().unwrap(), b"[db] hello\n[db] world");
+    }
+
+    #[test]
+    fn test_colored_prefix() {
+        let (handler, captured) = capture(Some(Color::Green));
+        handler(b"ok\n");
+        assert_eq!(&*captured.lock().unwrap(), b"\x1b[32m[db]\x1b[0m ok\n");
+    }
+}
@@ -2,10 +2,22 @@
 //!
 //! The top-level entry points are [`parse_str`] and [`parse_file`].
 
-use crate::command::ScripttyCommand;
-use crate::commands::{Expect, KeyPress, SendInput, Show, TypeText, Wait};
+use crate::command::{Context, ScripttyCommand};
+use crate::commands::{
+    AnyOrder, AssertExitCode, AssertImageSimilar, AssertSnapshot, At, Call, Capture, Complete,
+    DebugState, Eval, Expect, ExpectAny, ExpectBell, ExpectEof, ExpectIdle, ExpectImage, ExpectNot,
+    ExpectNotification, ExpectPrompt, ExpectScreen, Gdb, HistoryUp, If, KeyPress, Kill, Login,
+    Loop, Macro, Marker, Mouse, Paste, Repeat, Resize, Respawn, Screenshot, SearchHistory,
+    SendInput, SendSignal, Set, Show, Snapshot, Sudo, SyncAudio, TypeEdit, TypeText, UseProfile,
+    Wait, WaitFile, WaitSignal, While,
+};
+#[cfg(feature = "network")]
+use crate::commands::{ConfigMode, Enable, SaveConfig};
 use anyhow::{Context as _, Result, anyhow};
-use std::path::Path;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::time::Duration;
 
 /// Parse a scriptty script from a string slice and return the resulting commands.
@@ -27,23 +39,16 @@ use std::time::Duration;
 /// assert_eq!(commands.len(), 2);
 /// ```
 pub fn parse_str(content: &str) -> Result<Vec<Box<dyn ScripttyCommand>>> {
-    let mut commands = Vec::new();
-    for (line_num, line) in content.lines().enumerate() {
-        let line = line.trim();
-        if line.is_empty() || line.starts_with('#') {
-            continue;
-        }
-        let line = strip_inline_comment(line);
-        let cmd = parse_line(line)
-            .with_context(|| format!("Failed to parse line {}: {}", line_num + 1, line))?;
-        commands.push(cmd);
-    }
+    let mut reader = ScriptReader::new(content);
+    let (commands, terminator) = reader.parse_block(&[])?;
+    debug_assert!(terminator.is_none());
     Ok(commands)
 }
 
 /// Parse a scriptty script from a file and return the resulting commands.
 ///
-/// Reads the entire file into memory and delegates to [`parse_str`].
+/// Reads the entire file into memory and parses it like [`parse_str`].
+/// Paths in `include` directives are resolved relative to the including file.
 ///
 /// # Errors
 ///
@@ -60,25 +65,565 @@ pub fn parse_file(path: impl AsRef<Path>) -> Result<Vec<Box<dyn ScripttyCommand>
     let path = path.as_ref();
     let content = std::fs::read_to_string(path)
         .with_context(|| format!("Failed to read script file: {}", path.display()))?;
-    parse_str(&content)
+    parse_source(&content, path)
+}
+
+/// Parse `content` only to find its errors, each reported together with the
+/// 1-based line where it was found (the `include` line for errors inside
+/// included files).
+///
+/// Parsing goes on after a malformed statement, so every broken line is
+/// reported, not just the first. `path` resolves relative includes like in
+/// [`parse_source`].
+#[cfg(feature = "lsp")]
+pub(crate) fn check_source(content: &str, path: Option<&Path>) -> Vec<(usize, anyhow::Error)> {
+    let includes = path
+        .and_then(|p| p.canonicalize().ok())
+        .into_iter()
+        .collect();
+    let mut reader = ScriptReader::with_includes(content, includes);
+    reader.errors = Some(Vec::new());
+    let result = reader.parse_block(&[]);
+    let mut errors = reader.errors.take().unwrap_or_default();
+    if let Err(e) = result {
+        errors.push((reader.line, e));
+    }
+    errors
+}
+
+/// Keywords that can start a script line: commands and parse-time directives.
+pub(crate) fn keywords() -> impl Iterator<Item = &'static str> {
+    command_keywords().chain(control_keywords())
+}
+
+/// Names of the single-line commands in [`REGISTRY`].
+pub(crate) fn command_keywords() -> impl Iterator<Item = &'static str> {
+    REGISTRY.iter().map(|(name, _)| *name)
+}
+
+/// Names of the block commands in [`BLOCK_REGISTRY`] and the parse-time
+/// directives (`def`, `include`, `env`, `@meta`).
+pub(crate) fn control_keywords() -> impl Iterator<Item = &'static str> {
+    BLOCK_REGISTRY
+        .iter()
+        .map(|(name, _)| *name)
+        .chain(["def", "include", "env", META])
+}
+
+/// Keyword of the header lines that configure how the program is started.
+const META: &str = "@meta";
+
+/// Namespaces of the `@meta` settings; see [`script_meta`].
+const META_NAMESPACES: &[&str] = &["ssh"];
+
+/// The environment variables set by `env NAME=VALUE` lines at the top of a
+/// script, before its first command, in order.
+///
+/// The program starts before the script runs, so these only take effect when
+/// passed to [`EngineBuilder::env`](crate::EngineBuilder::env) before
+/// spawning; the parser otherwise skips the lines.
+///
+/// # Errors
+///
+/// Returns an error for an `env` line that is not `NAME=VALUE`.
+///
+/// # Example
+///
+/// ```
+/// use scriptty::parser::script_env;
+///
+/// let env = script_env("# setup\nenv NO_COLOR=1\nenv LANG=\"C.UTF-8\"\nexpect \"$ \"\n").unwrap();
+/// assert_eq!(env, [("NO_COLOR".into(), "1".into()), ("LANG".into(), "C.UTF-8".into())]);
+/// ```
+pub fn script_env(content: &str) -> Result<Vec<(String, String)>> {
+    header(content, "env", parse_env)
+}
+
+/// The settings of `@meta KEY=VALUE` lines at the top of a script, before its
+/// first command, in order.
+///
+/// Keys are namespaced; `ssh.*` settings describe the connection for the
+/// `ssh` program and are read by [`SshOptions::from_meta`]. Like `env`
+/// lines, they only take effect when applied before spawning.
+///
+/// [`SshOptions::from_meta`]: crate::ssh::SshOptions::from_meta
+///
+/// # Errors
+///
+/// Returns an error for a `@meta` line that is not `KEY=VALUE` or whose key
+/// is not in a known namespace.
+///
+/// # Example
+///
+/// ```
+/// use scriptty::parser::script_meta;
+///
+/// let meta = script_meta("@meta ssh.host=db1\n@meta ssh.user=deploy\nexpect \"$ \"\n").unwrap();
+/// assert_eq!(meta, [("ssh.host".into(), "db1".into()), ("ssh.user".into(), "deploy".into())]);
+/// ```
+pub fn script_meta(content: &str) -> Result<Vec<(String, String)>> {
+    header(content, META, parse_meta)
+}
+
+/// The header lines (`env` and `@meta`) of `content` with keyword `keyword`,
+/// each parsed with `parse`.
+fn header(
+    content: &str,
+    keyword: &str,
+    parse: fn(&str) -> Result<(String, String)>,
+) -> Result<Vec<(String, String)>> {
+    let mut reader = ScriptReader::new(content);
+    let mut settings = Vec::new();
+    while let Some((line_num, line)) = reader.next_line() {
+        let (name, args) = line.split_once(' ').unwrap_or((line, ""));
+        if name == keyword {
+            settings.push(
+                parse(args)
+                    .with_context(|| format!("Failed to parse line {}: {}", line_num, line))?,
+            );
+        } else if name != "env" && name != META {
+            break;
+        }
+    }
+    Ok(settings)
+}
+
+/// Parse the `NAME=VALUE` of an `env` line; the value may be quoted.
+fn parse_env(args: &str) -> Result<(String, String)> {
+    let (name, value) = split_setting(args)?;
+    Ok((parse_identifier(name)?.to_string(), value))
+}
+
+/// Parse the `NAMESPACE.KEY=VALUE` of a `@meta` line; the value may be quoted.
+fn parse_meta(args: &str) -> Result<(String, String)> {
+    let (key, value) = split_setting(args)?;
+    let (namespace, name) = key
+        .split_once('.')
+        .filter(|(namespace, _)| META_NAMESPACES.contains(namespace))
+        .ok_or_else(|| anyhow!("Unknown @meta key: '{}'", key))?;
+    parse_identifier(name).map_err(|_| anyhow!("Unknown @meta key: '{}'", key))?;
+    Ok((format!("{}.{}", namespace, name), value))
+}
+
+/// Split `NAME=VALUE` into the trimmed name and the value, unquoting a
+/// quoted value.
+fn split_setting(args: &str) -> Result<(&str, String)> {
+    let (name, value) = args
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Expected NAME=VALUE: {}", args.trim()))?;
+    let value = value.trim();
+    let value = if value.starts_with('"') {
+        parse_quoted_string(value)?
+    } else {
+        value.to_string()
+    };
+    Ok((name.trim(), value))
+}
+
+/// Parse script `content` that was read from (or generated for) the file at
+/// `path`, e.g. a rendered template.
+///
+/// `path` only serves to resolve relative `include` paths and detect include
+/// cycles; it is not read.
+///
+/// # Errors
+///
+/// Returns an error if `path` does not exist or if the script is malformed.
+pub fn parse_source(
+    content: &str,
+    path: impl AsRef<Path>,
+) -> Result<Vec<Box<dyn ScripttyCommand>>> {
+    let mut reader = ScriptReader::for_file(content, path.as_ref())?;
+    let (commands, _) = reader.parse_block(&[])?;
+    Ok(commands)
+}
+
+type Commands = Vec<Box<dyn ScripttyCommand>>;
+
+/// A command parsed from a script line, which remembers the arguments it was
+/// parsed from for [`ScripttyCommand::args`].
+struct Parsed {
+    command: Box<dyn ScripttyCommand>,
+    args: String,
+}
+
+#[async_trait(?Send)]
+impl ScripttyCommand for Parsed {
+    fn name(&self) -> &'static str {
+        self.command.name()
+    }
+
+    /// Parse a whole line, keyword included.
+    fn parse(line: &str) -> Result<Self> {
+        Ok(Self {
+            command: parse_line(line)?,
+            args: line.split_once(' ').map_or("", |(_, a)| a).to_string(),
+        })
+    }
+
+    fn args(&self) -> &str {
+        &self.args
+    }
+
+    async fn execute(&self, ctx: &mut Context) -> Result<()> {
+        self.command.execute(ctx).await
+    }
+}
+
+/// Line-by-line reader over a script, used to parse nested blocks.
+///
+/// Block commands (see [`BLOCK_REGISTRY`]) receive the reader so they can
+/// parse their own bodies with [`ScriptReader::parse_block`].
+pub(crate) struct ScriptReader<'a> {
+    lines: std::iter::Enumerate<std::str::Lines<'a>>,
+    /// 1-based number of the line most recently read.
+    #[cfg_attr(not(feature = "lsp"), allow(dead_code))]
+    line: usize,
+    /// Canonical paths of the files being parsed, outermost first; empty when
+    /// parsing a string. Used to resolve and detect cycles in `include`.
+    includes: Vec<PathBuf>,
+    /// Macros defined so far with `def`, shared with included files.
+    macros: HashMap<String, Rc<Macro>>,
+    /// Whether no statement but `env` has been read yet, so `env` is still
+    /// allowed; always false in included files.
+    header: bool,
+    /// Errors collected with their line numbers instead of stopping at the
+    /// first one; `None` stops at the first.
+    #[cfg_attr(not(feature = "lsp"), allow(dead_code))]
+    errors: Option<Vec<(usize, anyhow::Error)>>,
+}
+
+impl<'a> ScriptReader<'a> {
+    pub(crate) fn new(content: &'a str) -> Self {
+        Self::with_includes(content, Vec::new())
+    }
+
+    /// A reader for `content` read from (or generated for) the file at `path`,
+    /// which resolves relative `include` paths.
+    pub(crate) fn for_file(content: &'a str, path: &Path) -> Result<Self> {
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve script file: {}", path.display()))?;
+        Ok(Self::with_includes(content, vec![path]))
+    }
+
+    fn with_includes(content: &'a str, includes: Vec<PathBuf>) -> Self {
+        Self {
+            lines: content.lines().enumerate(),
+            line: 0,
+            includes,
+            macros: HashMap::new(),
+            header: true,
+            errors: None,
+        }
+    }
+
+    /// The macro defined as `name`, if any.
+    pub(crate) fn macro_named(&self, name: &str) -> Option<Rc<Macro>> {
+        self.macros.get(name).cloned()
+    }
+
+    /// Next line with surrounding whitespace and comments removed, skipping
+    /// empty and comment-only lines, together with its 1-based line number.
+    fn next_line(&mut self) -> Option<(usize, &'a str)> {
+        for (line_num, line) in self.lines.by_ref() {
+            self.line = line_num + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            return Some((line_num + 1, strip_inline_comment(line)));
+        }
+        None
+    }
+
+    /// Parse commands until a line whose keyword is one of `terminators`, and
+    /// return them along with that terminator.
+    ///
+    /// With no terminators the whole remaining script is parsed and `None` is
+    /// returned as the terminator; otherwise reaching the end of the script is
+    /// an error. A terminator line must hold only its keyword, so a typo such
+    /// as `end repeat` is an error rather than silently ignored.
+    pub(crate) fn parse_block(
+        &mut self,
+        terminators: &[&str],
+    ) -> Result<(Commands, Option<&'a str>)> {
+        let mut commands = Vec::new();
+        while let Some((line_num, line)) = self.next_line() {
+            let (name, rest) = line.split_once(' ').unwrap_or((line, ""));
+            if terminators.contains(&name) {
+                if !rest.trim().is_empty() {
+                    let error = anyhow!("Unexpected text after '{}': {}", name, rest.trim())
+                        .context(format!("Failed to parse line {}: {}", line_num, line));
+                    self.recover(error)?;
+                }
+                return Ok((commands, Some(name)));
+            }
+            if let Err(e) = self.parse_statement(line_num, line, &mut commands) {
+                self.recover(e)?;
+            }
+        }
+        match terminators {
+            [] => Ok((commands, None)),
+            _ => Err(anyhow!(
+                "Unexpected end of script, expected '{}'",
+                terminators.join("' or '")
+            )),
+        }
+    }
+}
+
+impl ScriptReader<'_> {
+    /// Record `error` if errors are being collected, or return it.
+    fn recover(&mut self, error: anyhow::Error) -> Result<()> {
+        match &mut self.errors {
+            Some(errors) => {
+                errors.push((self.line, error));
+                Ok(())
+            }
+            None => Err(error),
+        }
+    }
+
+    /// Parse the next top-level statement of the script, or return `None` at
+    /// the end of the script.
+    ///
+    /// A statement is usually one command, or a whole block such as
+    /// `repeat ... end`. `def` yields no commands and `include` yields all
+    /// commands of the included file.
+    pub(crate) fn next_statement(&mut self) -> Result<Option<Commands>> {
+        let Some((line_num, line)) = self.next_line() else {
+            return Ok(None);
+        };
+        let mut commands = Vec::new();
+        self.parse_statement(line_num, line, &mut commands)?;
+        Ok(Some(commands))
+    }
+
+    /// Parse the statement starting at `line` (including the body of a block
+    /// or heredoc) and append its commands to `commands`.
+    fn parse_statement(
+        &mut self,
+        line_num: usize,
+        line: &str,
+        commands: &mut Commands,
+    ) -> Result<()> {
+        let (name, args) = line.split_once(' ').unwrap_or((line, ""));
+        let expanded = self
+            .expand_heredoc(line)
+            .with_context(|| format!("Failed to parse line {}: {}", line_num, line))?;
+        let (line, args) = match &expanded {
+            Some(expanded) => (
+                expanded.as_str(),
+                expanded.split_once(' ').map_or("", |(_, a)| a),
+            ),
+            None => (line, args),
+        };
+        if name == "env" || name == META {
+            if !self.header {
+                return Err(anyhow!(
+                    "Failed to parse line {}: {}: {} must come before the first command of the script",
+                    line_num,
+                    line,
+                    name
+                ));
+            }
+            let parse = if name == META { parse_meta } else { parse_env };
+            return parse(args)
+                .map(|_| ())
+                .with_context(|| format!("Failed to parse line {}: {}", line_num, line));
+        }
+        self.header = false;
+        if name == "include" {
+            let included = self
+                .include(args)
+                .with_context(|| format!("Failed to parse line {}: {}", line_num, line))?;
+            commands.extend(included);
+            return Ok(());
+        }
+        if name == "def" {
+            return self
+                .define(args)
+                .with_context(|| format!("Failed to parse line {}: {}", line_num, line));
+        }
+        let block = BLOCK_REGISTRY
+            .iter()
+            .find(|(block_name, _)| *block_name == name);
+        let command = match block {
+            Some((_, parse)) => parse(args, self),
+            None => parse_line(line),
+        }
+        .with_context(|| format!("Failed to parse line {}: {}", line_num, line))?;
+        commands.push(Box::new(Parsed {
+            command,
+            args: args.to_string(),
+        }));
+        Ok(())
+    }
+
+    /// If `line` ends with a heredoc marker (`<<TAG`), read the following raw
+    /// lines up to the one consisting of `TAG` and return `line` with the
+    /// marker replaced by an equivalent quoted string.
+    ///
+    /// Every body line ends with a newline, and the closing tag's indentation
+    /// is removed from each of them so heredocs can be indented with their
+    /// block. Body lines are taken literally: `#` does not start a comment and
+    /// backslashes are not escapes (`${...}` is still interpolated by the
+    /// command).
+    fn expand_heredoc(&mut self, line: &str) -> Result<Option<String>> {
+        let Some(tag) = heredoc_tag(line) else {
+            return Ok(None);
+        };
+        let mut body = Vec::new();
+        let indent = loop {
+            let Some((line_num, raw)) = self.lines.next() else {
+                return Err(anyhow!("Unterminated heredoc, expected '{}'", tag));
+            };
+            self.line = line_num + 1;
+            if raw.trim() == tag {
+                break &raw[..raw.len() - raw.trim_start().len()];
+            }
+            body.push(raw);
+        };
+        let mut quoted = String::from("\"");
+        for raw in body {
+            let raw = raw.strip_prefix(indent).unwrap_or(raw.trim_start());
+            for ch in raw.chars() {
+                match ch {
+                    '\\' => quoted.push_str("\\\\"),
+                    '"' => quoted.push_str("\\\""),
+                    _ => quoted.push(ch),
+                }
+            }
+            quoted.push_str("\\n");
+        }
+        quoted.push('"');
+        let marker = line.len() - tag.len() - 2;
+        Ok(Some(format!("{}{}", &line[..marker], quoted)))
+    }
+
+    /// Parse the script named by an `include "path"` directive.
+    ///
+    /// Relative paths are resolved against the directory of the including
+    /// file (or the working directory when parsing a string).
+    fn include(&mut self, args: &str) -> Result<Commands> {
+        let target = PathBuf::from(parse_quoted_string(args)?);
+        let target = match self.includes.last().and_then(|file| file.parent()) {
+            Some(dir) => dir.join(target),
+            None => target,
+        };
+        let target = target
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve included file: {}", target.display()))?;
+        if self.includes.contains(&target) {
+            let chain: Vec<String> = self
+                .includes
+                .iter()
+                .chain([&target])
+                .map(|p| p.display().to_string())
+                .collect();
+            return Err(anyhow!("Include cycle: {}", chain.join(" -> ")));
+        }
+        let content = std::fs::read_to_string(&target)
+            .with_context(|| format!("Failed to read included file: {}", target.display()))?;
+        let mut includes = self.includes.clone();
+        includes.push(target.clone());
+        let mut reader = ScriptReader::with_includes(&content, includes);
+        reader.macros = std::mem::take(&mut self.macros);
+        reader.header = false;
+        let parsed = reader
+            .parse_block(&[])
+            .with_context(|| format!("In included file: {}", target.display()));
+        self.macros = reader.macros;
+        Ok(parsed?.0)
+    }
+
+    /// Parse a `def` block and make the macro available to later lines.
+    fn define(&mut self, args: &str) -> Result<()> {
+        let definition = Macro::parse_definition(args, self)?;
+        if self.macros.contains_key(&definition.name) {
+            return Err(anyhow!("Macro '{}' is already defined", definition.name));
+        }
+        self.macros
+            .insert(definition.name.clone(), Rc::new(definition));
+        Ok(())
+    }
 }
 
 type ParseFn = fn(&str) -> Result<Box<dyn ScripttyCommand>>;
+type BlockParseFn = fn(&str, &mut ScriptReader) -> Result<Box<dyn ScripttyCommand>>;
+
+/// Commands that need the [`ScriptReader`]: blocks that own a body of nested
+/// lines closed by `end`, and `call`, which looks up earlier `def`s.
+///
+/// Each entry receives the arguments of its opening line and the
+/// [`ScriptReader`] positioned at the following line.
+static BLOCK_REGISTRY: &[(&str, BlockParseFn)] = &[
+    (Call::NAME, Call::parse_call),
+    (If::NAME, If::parse_block),
+    (AnyOrder::NAME, AnyOrder::parse_block),
+    (Loop::NAME, Loop::parse_block),
+    (Repeat::NAME, Repeat::parse_block),
+    (While::NAME, While::parse_block),
+];
 
 static REGISTRY: &[(&str, ParseFn)] = &[
     (TypeText::NAME, TypeText::parse_boxed),
+    (TypeEdit::NAME, TypeEdit::parse_boxed),
+    (Complete::NAME, Complete::parse_boxed),
+    (HistoryUp::NAME, HistoryUp::parse_boxed),
+    (SearchHistory::NAME, SearchHistory::parse_boxed),
     (SendInput::NAME, SendInput::parse_boxed),
+    (Paste::NAME, Paste::parse_boxed),
     (Show::NAME, Show::parse_boxed),
+    (DebugState::NAME, DebugState::parse_boxed),
     (Wait::NAME, Wait::parse_boxed),
     (Expect::NAME, Expect::parse_boxed),
+    (ExpectAny::NAME, ExpectAny::parse_boxed),
+    (ExpectBell::NAME, ExpectBell::parse_boxed),
+    (ExpectEof::NAME, ExpectEof::parse_boxed),
+    (ExpectIdle::NAME, ExpectIdle::parse_boxed),
+    (ExpectNot::NAME, ExpectNot::parse_boxed),
+    (ExpectScreen::NAME, ExpectScreen::parse_boxed),
+    (AssertExitCode::NAME, AssertExitCode::parse_boxed),
+    (Respawn::NAME, Respawn::parse_boxed),
+    (Resize::NAME, Resize::parse_boxed),
+    (SendSignal::NAME, SendSignal::parse_boxed),
+    (Kill::NAME, Kill::parse_boxed),
+    (AssertSnapshot::NAME, AssertSnapshot::parse_boxed),
+    (Screenshot::NAME, Screenshot::parse_boxed),
+    (Snapshot::NAME, Snapshot::parse_boxed),
+    (ExpectNotification::NAME, ExpectNotification::parse_boxed),
+    (ExpectImage::NAME, ExpectImage::parse_boxed),
+    (AssertImageSimilar::NAME, AssertImageSimilar::parse_boxed),
+    (Capture::NAME, Capture::parse_boxed),
+    (Set::NAME, Set::parse_boxed),
+    (Sudo::NAME, Sudo::parse_boxed),
+    (Login::NAME, Login::parse_boxed),
     (KeyPress::NAME, KeyPress::parse_boxed),
+    (Mouse::NAME, Mouse::parse_boxed),
+    (UseProfile::NAME, UseProfile::parse_boxed),
+    (ExpectPrompt::NAME, ExpectPrompt::parse_boxed),
+    (Eval::NAME, Eval::parse_boxed),
+    (Gdb::NAME, Gdb::parse_boxed),
+    (At::NAME, At::parse_boxed),
+    (SyncAudio::NAME, SyncAudio::parse_boxed),
+    (Marker::NAME, Marker::parse_boxed),
+    (WaitFile::NAME, WaitFile::parse_boxed),
+    (WaitSignal::NAME, WaitSignal::parse_boxed),
+    #[cfg(feature = "network")]
+    (Enable::NAME, Enable::parse_boxed),
+    #[cfg(feature = "network")]
+    (ConfigMode::NAME, ConfigMode::parse_boxed),
+    #[cfg(feature = "network")]
+    (SaveConfig::NAME, SaveConfig::parse_boxed),
 ];
 
 /// Dispatch a single non-empty, non-comment line to the matching command's parser.
 ///
 /// To add a new command, add one entry to [`REGISTRY`] using the command's
 /// `NAME` constant and `parse_boxed` function pointer.
-fn parse_line(line: &str) -> Result<Box<dyn ScripttyCommand>> {
+pub(crate) fn parse_line(line: &str) -> Result<Box<dyn ScripttyCommand>> {
     let (name, args) = line.split_once(' ').unwrap_or((line, ""));
     REGISTRY
         .iter()
@@ -88,7 +633,7 @@ fn parse_line(line: &str) -> Result<Box<dyn ScripttyCommand>> {
 }
 
 /// Strip inline comments from a line, preserving `#` inside quoted strings.
-fn strip_inline_comment(line: &str) -> &str {
+pub(crate) fn strip_inline_comment(line: &str) -> &str {
     let mut in_quotes = false;
     let mut escaped = false;
     for (i, ch) in line.char_indices() {
@@ -111,6 +656,75 @@ fn strip_inline_comment(line: &str) -> &str {
     line
 }
 
+/// The tag of a heredoc opened at the end of `line` (`type <<EOF`), if any.
+///
+/// Tags are identifiers (letters, digits, and `_`, not starting with a digit).
+pub(crate) fn heredoc_tag(line: &str) -> Option<&str> {
+    let last = line.rsplit(char::is_whitespace).next()?;
+    let tag = last.strip_prefix("<<")?;
+    parse_identifier(tag).ok()
+}
+
+/// Split `line` at whitespace outside double-quoted strings. Quotes are kept
+/// in the tokens.
+pub(crate) fn split_args(line: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (i, ch) in line.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if ch == '\\' {
+            escaped = true;
+        } else if ch == '"' {
+            in_quotes = !in_quotes;
+        } else if ch.is_whitespace() && !in_quotes {
+            if let Some(s) = start.take() {
+                tokens.push(&line[s..i]);
+            }
+            continue;
+        }
+        start.get_or_insert(i);
+    }
+    if let Some(s) = start {
+        tokens.push(&line[s..]);
+    }
+    tokens
+}
+
+/// Parse `key=value` options, where values are bare words or quoted strings
+/// (see [`parse_quoted_string`]).
+///
+/// # Errors
+///
+/// Returns an error for a token without `=`, an unknown key (one not in
+/// `allowed`), a repeated key, or a malformed quoted value.
+pub(crate) fn parse_options(args: &str, allowed: &[&str]) -> Result<HashMap<String, String>> {
+    let mut options = HashMap::new();
+    for token in split_args(args) {
+        let (key, value) = token
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected key=value, got: {}", token))?;
+        if !allowed.contains(&key) {
+            return Err(anyhow!(
+                "Unknown option '{}', expected one of: {}",
+                key,
+                allowed.join(", ")
+            ));
+        }
+        let value = if value.starts_with('"') {
+            parse_quoted_string(value)?
+        } else {
+            value.to_string()
+        };
+        if options.insert(key.to_string(), value).is_some() {
+            return Err(anyhow!("Option '{}' given more than once", key));
+        }
+    }
+    Ok(options)
+}
+
 /// Parse a duration string: `1s`, `500ms`, `1.5s`.
 pub(crate) fn parse_duration(s: &str) -> Result<Duration> {
     let s = s.trim();
@@ -122,13 +736,59 @@ pub(crate) fn parse_duration(s: &str) -> Result<Duration> {
         Ok(Duration::from_millis(ms))
     } else if let Some(s_str) = s.strip_suffix('s') {
         let secs: f64 = s_str.trim().parse().context("Invalid seconds value")?;
-        Ok(Duration::from_secs_f64(secs))
+        Duration::try_from_secs_f64(secs).map_err(|_| anyhow!("Invalid duration: {}", s))
     } else {
         Err(anyhow!("Duration must end with 's' or 'ms', got: {}", s))
     }
 }
 
-/// Parse a double-quoted string, processing `\n`, `\t`, `\"`, and `\\`.
+/// Validate a script variable name: ASCII letters, digits, and `_`, not
+/// starting with a digit.
+pub(crate) fn parse_identifier(s: &str) -> Result<&str> {
+    let s = s.trim();
+    let mut chars = s.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(s)
+    } else {
+        Err(anyhow!("Invalid variable name: '{}'", s))
+    }
+}
+
+/// Split a leading double-quoted string off `s`.
+///
+/// Returns the unescaped string (see [`parse_quoted_string`]) and the trimmed
+/// remainder after the closing quote. Backslash-escaped quotes do not close the
+/// string.
+pub(crate) fn split_quoted(s: &str) -> Result<(String, &str)> {
+    let s = s.trim();
+    if !s.starts_with('"') {
+        return Err(anyhow!("Expected quoted string"));
+    }
+    let mut escaped = false;
+    for (i, ch) in s.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        if ch == '\\' {
+            escaped = true;
+            continue;
+        }
+        if ch == '"' {
+            return Ok((parse_quoted_string(&s[..=i])?, s[i + 1..].trim()));
+        }
+    }
+    Err(anyhow!("Unclosed quote"))
+}
+
+/// Parse a double-quoted string, processing `\n`, `\t`, `\"`, `\\`, `\0`,
+/// `\xNN` (ASCII only, `00`–`7f`), and `\u{NNNN}` escapes. Other backslash
+/// sequences, including malformed `\x` and `\u` escapes, are kept as
+/// written.
 pub(crate) fn parse_quoted_string(s: &str) -> Result<String> {
     let s = s.trim();
     if !s.starts_with('"') {
@@ -137,11 +797,61 @@ pub(crate) fn parse_quoted_string(s: &str) -> Result<String> {
     if !s.ends_with('"') {
         return Err(anyhow!("Expected string to end with '\"'"));
     }
-    Ok(s[1..s.len() - 1]
-        .replace("\\n", "\n")
-        .replace("\\t", "\t")
-        .replace("\\\"", "\"")
-        .replace("\\\\", "\\"))
+    let mut out = String::new();
+    let mut chars = s[1..s.len() - 1].chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('"') => out.push('"'),
+            Some('\\') => out.push('\\'),
+            Some('0') => out.push('\0'),
+            Some('x') => {
+                let rest = chars.as_str();
+                match rest
+                    .get(..2)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .filter(u8::is_ascii)
+                {
+                    Some(code) => {
+                        out.push(char::from(code));
+                        chars = rest[2..].chars();
+                    }
+                    None => out.push_str("\\x"),
+                }
+            }
+            Some('u') => {
+                let rest = chars.as_str();
+                let escape = rest
+                    .strip_prefix('{')
+                    .and_then(|r| r.split_once('}'))
+                    .map(|(digits, _)| digits)
+                    .filter(|digits| (1..=6).contains(&digits.len()))
+                    .and_then(|digits| {
+                        let ch = char::from_u32(u32::from_str_radix(digits, 16).ok()?)?;
+                        Some((ch, digits.len()))
+                    });
+                match escape {
+                    Some((ch, len)) => {
+                        out.push(ch);
+                        chars = rest[len + 2..].chars();
+                    }
+                    None => out.push_str("\\u"),
+                }
+            }
+            // Unknown escapes are kept as written.
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    Ok(out)
 }
 
 #[cfg(test)]
@@ -156,6 +866,9 @@ mod tests {
             parse_duration("1.5s").unwrap(),
             Duration::from_secs_f64(1.5)
         );
+        assert!(parse_duration("-5s").is_err());
+        assert!(parse_duration("infs").is_err());
+        assert!(parse_duration("NaNs").is_err());
     }
 
     #[test]
@@ -171,6 +884,99 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_quoted_string_escapes() {
+        assert_eq!(parse_quoted_string(r#""a\\nb""#).unwrap(), r"a\nb");
+        assert_eq!(
+            parse_quoted_string(r#""say \"hi\"""#).unwrap(),
+            r#"say "hi""#
+        );
+        assert_eq!(parse_quoted_string(r#""C:\dir""#).unwrap(), r"C:\dir");
+    }
+
+    #[test]
+    fn test_parse_quoted_string_code_escapes() {
+        assert_eq!(parse_quoted_string(r#""\x1b[0m""#).unwrap(), "\x1b[0m");
+        assert_eq!(parse_quoted_string(r#""a\0b""#).unwrap(), "a\0b");
+        assert_eq!(
+            parse_quoted_string(r#""\u{1F600}!""#).unwrap(),
+            "\u{1F600}!"
+        );
+        assert_eq!(parse_quoted_string(r#""\u{e9}""#).unwrap(), "é");
+        // Malformed escapes are kept as written, as they always were.
+        for kept in [
+            r"\x1",
+            r"\xff",
+            r"\xZZ",
+            r"\xé",
+            r"\u1F600",
+            r"\u{D800}",
+            r"\u{}",
+        ] {
+            let quoted = format!("\"{}\"", kept);
+            assert_eq!(parse_quoted_string(&quoted).unwrap(), kept);
+        }
+    }
+
+    #[test]
+    fn test_heredoc() {
+        let script =
+            "    SELECT \"name\" # not a comment\n      FROM t; -- \\n\n    EOF\nwait 1s\n";
+        let mut reader = ScriptReader::new(script);
+        let line = reader.expand_heredoc("type <<EOF").unwrap().unwrap();
+        let (text, rest) = split_quoted(line.strip_prefix("type ").unwrap()).unwrap();
+        assert_eq!(text, "SELECT \"name\" # not a comment\n  FROM t; -- \\n\n");
+        assert_eq!(rest, "");
+
+        let cmds =
+            parse_str("repeat 2\n  send <<END\n  one\n  two\n  END\nend\nkey Enter\n").unwrap();
+        assert_eq!(cmds.len(), 2);
+    }
+
+    #[test]
+    fn test_heredoc_unterminated() {
+        assert!(parse_str("type <<EOF\nhello\n").is_err());
+        assert_eq!(heredoc_tag("type <<EOF"), Some("EOF"));
+        assert_eq!(heredoc_tag(r#"show "a <<b""#), None);
+        assert_eq!(heredoc_tag("type <<1"), None);
+    }
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(
+            split_args(r#"  a  "b c"   d="e \" f" "#),
+            vec!["a", r#""b c""#, r#"d="e \" f""#]
+        );
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = parse_options(r#"user=${U} prompt="$ ""#, &["user", "prompt"]).unwrap();
+        assert_eq!(options["user"], "${U}");
+        assert_eq!(options["prompt"], "$ ");
+        assert!(parse_options("user", &["user"]).is_err());
+        assert!(parse_options("other=1", &["user"]).is_err());
+        assert!(parse_options("user=a user=b", &["user"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_identifier() {
+        assert_eq!(parse_identifier(" VER ").unwrap(), "VER");
+        assert_eq!(parse_identifier("_a1").unwrap(), "_a1");
+        assert!(parse_identifier("1abc").is_err());
+        assert!(parse_identifier("a-b").is_err());
+        assert!(parse_identifier("").is_err());
+    }
+
+    #[test]
+    fn test_split_quoted() {
+        let (text, rest) = split_quoted(r#""a \"b\" c" 5s"#).unwrap();
+        assert_eq!(text, r#"a "b" c"#);
+        assert_eq!(rest, "5s");
+        assert!(split_quoted("bare").is_err());
+        assert!(split_quoted(r#""open"#).is_err());
+    }
+
     #[test]
     fn test_parse_str() {
         let cmds = parse_str("wait 1s\ntype \"hello\"\nwait 500ms\nsend \"cmd\"\n").unwrap();
@@ -223,6 +1029,49 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_if_block() {
+        let cmds = parse_str(
+            "if expect \"yes/no\" 2s\n  send \"yes\"\n  key Enter\nelse\n  show \"skip\"\nend\nwait 1s\n",
+        )
+        .unwrap();
+        assert_eq!(cmds.len(), 2);
+        assert_eq!(cmds[0].name(), "if");
+        assert_eq!(cmds[1].name(), "wait");
+    }
+
+    #[test]
+    fn test_parse_nested_blocks() {
+        let script = "if expect \"a\"\nif expect \"b\"\nkey Enter\nend\nend\n";
+        assert_eq!(parse_str(script).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_parse_unterminated_block() {
+        let err = format!(
+            "{:#}",
+            parse_str("if expect \"a\"\nkey Enter\n").err().unwrap()
+        );
+        assert!(err.contains("expected 'else' or 'end'"), "got: {err}");
+    }
+
+    #[test]
+    fn test_parse_text_after_end() {
+        for script in [
+            "repeat 2\nkey Enter\nend junk\n",
+            "if expect \"a\"\nkey Enter\nelse junk\nend\n",
+            "if expect \"a\"\nkey Enter\nelse\nkey Enter\nend x\n",
+        ] {
+            let err = format!("{:#}", parse_str(script).err().unwrap());
+            assert!(err.contains("Unexpected text after"), "got: {err}");
+        }
+    }
+
+    #[test]
+    fn test_parse_stray_end() {
+        assert!(parse_str("key Enter\nend\n").is_err());
+    }
+
     #[test]
     fn test_parse_invalid_duration() {
         assert!(parse_str("wait 5minutes").is_err());
@@ -255,4 +1104,102 @@ mod tests {
         let cmds = parse_str("wait 1s # delay\ntype \"hi\" # greet\nexpect \"ok\" 2s\n").unwrap();
         assert_eq!(cmds.len(), 3);
     }
+
+    #[test]
+    fn test_next_statement() {
+        let mut reader = ScriptReader::new(
+            "# setup\ndef greet()\n  type \"hi\"\nend\nrepeat 2\n  call greet()\nend\nwait 1s\nbogus\n",
+        );
+        assert_eq!(reader.next_statement().unwrap().unwrap().len(), 0);
+        let block = reader.next_statement().unwrap().unwrap();
+        assert_eq!(block.len(), 1);
+        assert_eq!(block[0].name(), "repeat");
+        assert_eq!(reader.next_statement().unwrap().unwrap()[0].name(), "wait");
+        let err = reader.next_statement().err().unwrap();
+        assert!(format!("{:#}", err).contains("line 9"));
+        assert!(reader.next_statement().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_include() {
+        let dir = std::env::temp_dir().join(format!("scriptty-include-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("common")).unwrap();
+        std::fs::write(
+            dir.join("common/login.script"),
+            "expect \"login:\"\ninclude \"password.script\"\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("common/password.script"), "send \"secret\"\n").unwrap();
+        std::fs::write(
+            dir.join("main.script"),
+            "include \"common/login.script\"\nwait 1s\n",
+        )
+        .unwrap();
+
+        let cmds = parse_file(dir.join("main.script")).unwrap();
+        let names: Vec<_> = cmds.iter().map(|c| c.name()).collect();
+        assert_eq!(names, vec!["expect", "send", "wait"]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_meta() {
+        let source = "env NO_COLOR=1\n@meta ssh.host=db1\n@meta ssh.jump_host=\"bastion\"\nenv LANG=C\nwait 1s\n";
+        assert_eq!(
+            script_meta(source).unwrap(),
+            [
+                ("ssh.host".to_string(), "db1".to_string()),
+                ("ssh.jump_host".to_string(), "bastion".to_string())
+            ]
+        );
+        assert_eq!(script_env(source).unwrap().len(), 2);
+        assert_eq!(parse_str(source).unwrap().len(), 1);
+        assert!(script_meta("@meta shh.host=db1\n").is_err());
+        assert!(script_meta("@meta host=db1\n").is_err());
+        let err = format!(
+            "{:#}",
+            parse_str("wait 1s\n@meta ssh.host=db1\n").err().unwrap()
+        );
+        assert!(err.contains("@meta must come before"), "got: {err}");
+    }
+
+    #[test]
+    fn test_env() {
+        let source =
+            "# setup\nenv NO_COLOR=1\nenv GREETING=\"hello world\"\nexpect \"$ \"\nenv LATE=1\n";
+        assert_eq!(
+            script_env(source).unwrap(),
+            [
+                ("NO_COLOR".to_string(), "1".to_string()),
+                ("GREETING".to_string(), "hello world".to_string())
+            ]
+        );
+        let err = format!("{:#}", parse_str(source).err().unwrap());
+        assert!(err.contains("env must come before"), "got: {err}");
+        let names: Vec<_> = parse_str("env A=1\nenv B=2\nwait 1s\n")
+            .unwrap()
+            .iter()
+            .map(|c| c.name())
+            .collect();
+        assert_eq!(names, vec!["wait"]);
+        assert!(script_env("env 1A=x\n").is_err());
+        assert!(script_env("env NAME\n").is_err());
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let dir = std::env::temp_dir().join(format!("scriptty-cycle-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.script"), "include \"b.script\"\n").unwrap();
+        std::fs::write(dir.join("b.script"), "wait 1s\ninclude \"a.script\"\n").unwrap();
+
+        let err = match parse_file(dir.join("a.script")) {
+            Ok(_) => panic!("include cycle should be rejected"),
+            Err(e) => format!("{e:#}"),
+        };
+        assert!(err.contains("Include cycle"), "got: {err}");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
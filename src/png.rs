@@ -0,0 +1,134 @@
+//! Reading and writing PNG images, through the `png` crate.
+//!
+//! Images are passed around as 8-bit RGBA pixels. The decoder accepts every
+//! PNG file programs and people produce, and converts it to that; it refuses
+//! images too large to hold in memory comfortably.
+
+use anyhow::{Context as _, Result, anyhow, bail};
+
+/// Largest width or height the decoder accepts, in pixels.
+const MAX_SIDE: u32 = 1 << 14;
+
+/// Largest decoded image the decoder accepts, in bytes.
+const MAX_BYTES: usize = 1 << 28;
+
+/// Encode `width` x `height` RGBA pixels as a PNG file.
+///
+/// # Errors
+///
+/// Returns an error if the image is empty or `rgba` does not hold exactly
+/// `width` x `height` pixels.
+pub(crate) fn encode(width: u32, height: u32, rgba: &[u8]) -> Result<Vec<u8>> {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_compression(png::Compression::Best);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(rgba))
+        .context("Failed to encode PNG image")?;
+    Ok(png)
+}
+
+/// Decode a PNG file to its width, height, and RGBA pixels.
+///
+/// # Errors
+///
+/// Returns an error if `data` is not a PNG file, is damaged, is empty, or
+/// is larger than [`MAX_SIDE`] pixels on a side or [`MAX_BYTES`] in all.
+pub(crate) fn decode(data: &[u8]) -> Result<(u32, u32, Vec<u8>)> {
+    let mut decoder = png::Decoder::new_with_limits(data, png::Limits { bytes: MAX_BYTES });
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| anyhow!("Not a PNG image or damaged: {}", e))?;
+    let (width, height) = reader.info().size();
+    if width == 0 || height == 0 {
+        bail!("Damaged PNG image: {}x{} pixels", width, height);
+    }
+    if width > MAX_SIDE || height > MAX_SIDE {
+        bail!("PNG image too large: {}x{}", width, height);
+    }
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let frame = reader
+        .next_frame(&mut buf)
+        .map_err(|e| anyhow!("Damaged PNG image: {}", e))?;
+    let pixels = &buf[..frame.buffer_size()];
+    let rgba = match frame.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 0xff])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|&v| [v, v, v, 0xff]).collect(),
+        png::ColorType::Indexed => bail!("Damaged PNG image: palette not expanded"),
+    };
+    Ok((width, height, rgba))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let rgba = [255, 0, 0, 255, 0, 0, 255, 128, 1, 2, 3, 4, 5, 6, 7, 8];
+        let png = encode(2, 2, &rgba).unwrap();
+        assert_eq!(decode(&png).unwrap(), (2, 2, rgba.to_vec()));
+        assert!(encode(0, 1, &[]).is_err());
+        assert!(encode(2, 2, &rgba[..4]).is_err());
+        assert!(decode(b"GIF89a").is_err());
+    }
+
+    #[test]
+    fn test_decode_color_types() {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, 3, 1);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Four);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[0x0f, 0x70]).unwrap();
+        writer.finish().unwrap();
+        let (width, height, rgba) = decode(&png).unwrap();
+        assert_eq!((width, height), (3, 1));
+        assert_eq!(rgba, [0, 0, 0, 255, 255, 255, 255, 255, 119, 119, 119, 255]);
+    }
+
+    #[test]
+    fn test_decode_limits() {
+        let wide = encode(MAX_SIDE + 1, 1, &vec![0; (MAX_SIDE as usize + 1) * 4]).unwrap();
+        let err = decode(&wide).unwrap_err().to_string();
+        assert!(err.contains("too large"), "got: {err}");
+
+        let mut empty = encode(1, 1, &[0; 4]).unwrap();
+        // Zero the width in the IHDR chunk and fix up its CRC.
+        empty[16..20].copy_from_slice(&0u32.to_be_bytes());
+        let crc = crc32(&empty[12..29]);
+        empty[29..33].copy_from_slice(&crc.to_be_bytes());
+        let err = decode(&empty).unwrap_err().to_string();
+        assert!(
+            err.contains("dimension") || err.contains("0x1"),
+            "got: {err}"
+        );
+    }
+
+    fn crc32(data: &[u8]) -> u32 {
+        let mut crc = !0u32;
+        for &byte in data {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 {
+                    (crc >> 1) ^ 0xedb8_8320
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        !crc
+    }
+}
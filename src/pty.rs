@@ -1,35 +1,101 @@
+use crate::terminal::TerminalSettings;
 use anyhow::{Context, Result};
 use portable_pty::{Child, CommandBuilder, MasterPty, PtySize};
 use std::io::{Read, Write};
+use std::path::PathBuf;
+
+/// How to start the child process beyond its command line.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct SpawnOptions {
+    /// Working directory for the child (inherits ours when `None`).
+    pub cwd: Option<PathBuf>,
+    /// Extra environment variables for the child.
+    pub env: Vec<(String, String)>,
+    /// Terminal size as `(rows, cols)`; 24x80 when `None`.
+    pub size: Option<(u16, u16)>,
+    /// Line settings and pixel size of the terminal.
+    pub terminal: TerminalSettings,
+}
+
+/// Everything needed to (re)start the child process.
+#[derive(Debug, Clone)]
+pub(crate) struct SpawnSpec {
+    pub command: String,
+    pub args: Vec<String>,
+    pub options: SpawnOptions,
+}
+
+impl SpawnSpec {
+    /// The command and its arguments, for display.
+    pub(crate) fn command_line(&self) -> String {
+        std::iter::once(&self.command)
+            .chain(&self.args)
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// The state letter of a process from its `/proc/PID/stat` line: `R`, `S`,
+/// `T` (stopped), `Z`, ...
+fn process_state(stat: &str) -> Option<char> {
+    // The command name in parentheses may itself contain spaces and ")".
+    let (_, rest) = stat.rsplit_once(") ")?;
+    rest.chars().next()
+}
+
+/// The process group id in a `/proc/<pid>/stat` line.
+fn process_group(stat: &str) -> Option<libc::pid_t> {
+    let (_, rest) = stat.rsplit_once(") ")?;
+    // State, parent pid, then the process group.
+    rest.split_whitespace().nth(2)?.parse().ok()
+}
 
 /// Manages a program running inside a PTY
 pub struct PtySession {
-    #[allow(dead_code)]
     master: Box<dyn MasterPty + Send>,
     child: Box<dyn Child + Send + Sync>,
     writer: Box<dyn Write + Send>,
+    terminal: TerminalSettings,
 }
 
 impl PtySession {
     /// Spawn a new program in a PTY, returning the session and reader separately
-    pub fn spawn(command: &str, args: &[String]) -> Result<(Self, Box<dyn Read + Send>)> {
+    pub(crate) fn spawn(
+        command: &str,
+        args: &[String],
+        options: &SpawnOptions,
+    ) -> Result<(Self, Box<dyn Read + Send>)> {
         let pty_system = portable_pty::native_pty_system();
 
         // Create PTY with reasonable defaults
+        let (rows, cols) = options.size.unwrap_or((24, 80));
+        let (pixel_width, pixel_height) = options.terminal.pixel_size(rows, cols);
         let pty_size = PtySize {
-            rows: 24,
-            cols: 80,
-            pixel_width: 0,
-            pixel_height: 0,
+            rows,
+            cols,
+            pixel_width,
+            pixel_height,
         };
 
         let pair = pty_system.openpty(pty_size).context("Failed to open PTY")?;
+        // Settings made through the master apply to the terminal the child
+        // gets, so set them up before it starts.
+        if let Some(fd) = pair.master.as_raw_fd() {
+            options.terminal.apply_termios(fd)?;
+        }
 
         // Build the command
         let mut cmd = CommandBuilder::new(command);
         for arg in args {
             cmd.arg(arg);
         }
+        if let Some(cwd) = &options.cwd {
+            cmd.cwd(cwd);
+        }
+        for (key, value) in &options.env {
+            cmd.env(key, value);
+        }
 
         // Spawn the child process
         let child = pair
@@ -52,6 +118,7 @@ impl PtySession {
             master: pair.master,
             child,
             writer,
+            terminal: options.terminal.clone(),
         };
 
         Ok((session, reader))
@@ -65,27 +132,187 @@ impl PtySession {
     }
 
     /// Check if the child process is still running
-    #[allow(dead_code)]
     pub fn is_running(&mut self) -> bool {
         self.child.try_wait().ok().flatten().is_none()
     }
 
-    /// Wait for the child process to exit
-    pub fn wait(&mut self) -> Result<()> {
-        self.child.wait()?;
+    /// Wait for the child process to exit and return its exit code
+    pub fn wait(&mut self) -> Result<u32> {
+        Ok(self.child.wait()?.exit_code())
+    }
+
+    /// Kill the child process and the rest of its process group, such as
+    /// background jobs it started without job control
+    pub fn kill(&mut self) -> Result<()> {
+        if let Some(pid) = self.child.process_id() {
+            // The child leads its own process group (portable-pty calls
+            // setsid), so the group id is its pid. Best effort: the group
+            // may be gone already.
+            // SAFETY: kill(2) has no memory-safety preconditions.
+            unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) };
+        }
+        self.child.kill()?;
         Ok(())
     }
 
-    /// Resize the PTY
-    #[allow(dead_code)]
+    /// Whether the child, or a process in the terminal's foreground process
+    /// group, is stopped by a signal (SIGSTOP, or SIGTSTP and friends), so
+    /// the program cannot make progress until it gets SIGCONT.
+    ///
+    /// The foreground group covers programs the child started, e.g. the
+    /// command a non-interactive shell is waiting for. Always false where the
+    /// process state cannot be read (outside Linux).
+    pub fn is_stopped(&self) -> bool {
+        let stat = |path: PathBuf| std::fs::read_to_string(path).ok();
+        if let Some(pid) = self.child.process_id()
+            && stat(PathBuf::from(format!("/proc/{}/stat", pid)))
+                .and_then(|stat| process_state(&stat))
+                == Some('T')
+        {
+            return true;
+        }
+        let Some(group) = self.master.process_group_leader() else {
+            return false;
+        };
+        let Ok(entries) = std::fs::read_dir("/proc") else {
+            return false;
+        };
+        entries
+            .flatten()
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| name.bytes().all(|b| b.is_ascii_digit()))
+            })
+            .filter_map(|entry| stat(entry.path().join("stat")))
+            .any(|stat| process_group(&stat) == Some(group) && process_state(&stat) == Some('T'))
+    }
+
+    /// Return the child's exit code if it has exited, without blocking
+    pub fn try_wait(&mut self) -> Result<Option<u32>> {
+        Ok(self.child.try_wait()?.map(|status| status.exit_code()))
+    }
+
+    /// Resize the PTY and send SIGWINCH to the child
+    ///
+    /// The kernel already signals the terminal's foreground process group;
+    /// the child is signaled as well in case it runs in another group.
     pub fn resize(&self, rows: u16, cols: u16) -> Result<()> {
+        let (pixel_width, pixel_height) = self.terminal.pixel_size(rows, cols);
         let size = PtySize {
             rows,
             cols,
-            pixel_width: 0,
-            pixel_height: 0,
+            pixel_width,
+            pixel_height,
         };
         self.master.resize(size)?;
+        // Best effort: the program may already have exited.
+        let _ = self.signal(libc::SIGWINCH);
+        Ok(())
+    }
+
+    /// The child's process id, if it is still known.
+    pub fn pid(&self) -> Option<u32> {
+        self.child.process_id()
+    }
+
+    /// Send a POSIX signal to the child process
+    pub fn signal(&self, signal: libc::c_int) -> Result<()> {
+        let pid = self
+            .child
+            .process_id()
+            .context("The program has no process id")?;
+        // SAFETY: kill(2) has no memory-safety preconditions.
+        if unsafe { libc::kill(pid as libc::pid_t, signal) } != 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to send signal");
+        }
         Ok(())
     }
 }
+
+impl Drop for PtySession {
+    /// Kill and reap a program that is still running, so that an engine
+    /// dropped early (by an error or a panic) does not leave it behind
+    /// holding the terminal open.
+    fn drop(&mut self) {
+        if self.is_running() {
+            let _ = self.kill();
+        }
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_state() {
+        assert_eq!(
+            process_state("1234 (vim) T 1 1234 1234 34816 ..."),
+            Some('T')
+        );
+        assert_eq!(process_state("1 (a) b) S 0 1 1"), Some('S'));
+        assert_eq!(process_state("garbage"), None);
+        assert_eq!(
+            process_group("1234 (vim) T 1 1200 1234 34816 ..."),
+            Some(1200)
+        );
+    }
+
+    #[test]
+    fn test_stopped_grandchild() {
+        // The inner shell stops itself; the outer one just waits for it.
+        let args = [
+            "-c".to_string(),
+            "sh -c 'kill -STOP $$'; echo done".to_string(),
+        ];
+        let (mut session, _reader) =
+            PtySession::spawn("sh", &args, &SpawnOptions::default()).unwrap();
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while !session.is_stopped() {
+            assert!(std::time::Instant::now() < deadline, "never stopped");
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        session.kill().unwrap();
+    }
+
+    #[test]
+    fn test_terminal_settings() {
+        let options = SpawnOptions {
+            size: Some((24, 80)),
+            terminal: TerminalSettings {
+                termios: crate::terminal::parse_termios("-echo").unwrap(),
+                cell_size: Some((10, 20)),
+            },
+            ..SpawnOptions::default()
+        };
+        let (mut session, _reader) =
+            PtySession::spawn("sleep", &["5".to_string()], &options).unwrap();
+        let size = session.master.get_size().unwrap();
+        assert_eq!((size.pixel_width, size.pixel_height), (800, 480));
+        session.resize(40, 100).unwrap();
+        let size = session.master.get_size().unwrap();
+        assert_eq!((size.pixel_width, size.pixel_height), (1000, 800));
+
+        let fd = session.master.as_raw_fd().unwrap();
+        // SAFETY: termios is plain data; tcgetattr fills it in.
+        let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+        // SAFETY: fd is the open master, termios is writable.
+        assert_eq!(unsafe { libc::tcgetattr(fd, &mut termios) }, 0);
+        assert_eq!(termios.c_lflag & libc::ECHO, 0);
+        assert_ne!(termios.c_lflag & libc::ICANON, 0);
+        session.kill().unwrap();
+    }
+
+    #[test]
+    fn test_drop_kills_child() {
+        let (session, _reader) =
+            PtySession::spawn("sleep", &["30".to_string()], &SpawnOptions::default()).unwrap();
+        let pid = session.pid().unwrap() as libc::pid_t;
+        drop(session);
+        // SAFETY: kill(2) with signal 0 only checks that the process exists.
+        assert_ne!(unsafe { libc::kill(pid, 0) }, 0, "child should be reaped");
+    }
+}
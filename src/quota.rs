@@ -0,0 +1,149 @@
+//! Limits on what a run may consume.
+//!
+//! A script driving a runaway program (one that prints without end, or never
+//! reaches the prompt the script waits for in a loop) can tie up a shared CI
+//! machine for hours. [`Quotas`] cap the run: how much output the program
+//! may print, how long the run may take, and how much input the script may
+//! send. When a quota is exceeded, the engine stops the program and fails the
+//! run with an error naming the command that was running.
+
+use anyhow::{Context as _, Result, anyhow};
+use std::fmt;
+use std::time::Duration;
+
+/// Limits for one run; `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Quotas {
+    /// Bytes the program may print, including while it starts up.
+    pub max_output: Option<u64>,
+    /// How long the script may run, from the start of its first command.
+    pub max_duration: Option<Duration>,
+    /// Bytes the script may send to the program.
+    pub max_input: Option<u64>,
+}
+
+/// A quota that was exceeded, with its limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quota {
+    Output(u64),
+    Duration(Duration),
+    Input(u64),
+}
+
+impl fmt::Display for Quota {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Quota::Output(limit) => write!(f, "the program printed more than {} bytes", limit),
+            Quota::Duration(limit) => write!(f, "the run took longer than {:?}", limit),
+            Quota::Input(limit) => write!(f, "the script sent more than {} bytes", limit),
+        }
+    }
+}
+
+impl Quotas {
+    /// Whether no quota is set.
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The output or duration quota exceeded after `elapsed` with `output`
+    /// bytes printed, if any.
+    pub(crate) fn exceeded(&self, elapsed: Duration, output: u64) -> Option<Quota> {
+        if let Some(limit) = self.max_output
+            && output > limit
+        {
+            return Some(Quota::Output(limit));
+        }
+        if let Some(limit) = self.max_duration
+            && elapsed > limit
+        {
+            return Some(Quota::Duration(limit));
+        }
+        None
+    }
+
+    /// The input quota, if sending `len` more bytes after `sent` exceeds it.
+    pub(crate) fn input_exceeded(&self, sent: u64, len: usize) -> Option<Quota> {
+        self.max_input
+            .filter(|limit| sent + len as u64 > *limit)
+            .map(Quota::Input)
+    }
+}
+
+/// The error a run fails with when `quota` is exceeded during `command`.
+pub(crate) fn error(quota: Quota, command: &str) -> anyhow::Error {
+    anyhow!(
+        "Quota exceeded during '{}': {}; the program was stopped",
+        command,
+        quota
+    )
+}
+
+/// Parse a byte size: a number of bytes, optionally with a `K`, `M`, or `G`
+/// suffix (powers of 1024), e.g. `512`, `64K`, `10M`.
+///
+/// # Errors
+///
+/// Returns an error if `s` is not a size.
+pub fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let (number, unit) = match s.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&s[..i], 1 << 10),
+        Some((i, 'M' | 'm')) => (&s[..i], 1 << 20),
+        Some((i, 'G' | 'g')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    let number: u64 = number
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid size: {}", s))?;
+    number
+        .checked_mul(unit)
+        .ok_or_else(|| anyhow!("Size too large: {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exceeded() {
+        let quotas = Quotas {
+            max_output: Some(100),
+            max_duration: Some(Duration::from_secs(1)),
+            max_input: Some(10),
+        };
+        assert_eq!(quotas.exceeded(Duration::from_millis(500), 100), None);
+        assert_eq!(
+            quotas.exceeded(Duration::from_millis(500), 101),
+            Some(Quota::Output(100))
+        );
+        assert_eq!(
+            quotas.exceeded(Duration::from_secs(2), 0),
+            Some(Quota::Duration(Duration::from_secs(1)))
+        );
+        assert_eq!(quotas.input_exceeded(5, 5), None);
+        assert_eq!(quotas.input_exceeded(5, 6), Some(Quota::Input(10)));
+        assert!(Quotas::default().is_unlimited());
+        assert_eq!(Quotas::default().exceeded(Duration::MAX, u64::MAX), None);
+    }
+
+    #[test]
+    fn test_error_message() {
+        assert_eq!(
+            error(Quota::Output(1024), "wait").to_string(),
+            "Quota exceeded during 'wait': the program printed more than 1024 bytes; the program was stopped"
+        );
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("64K").unwrap(), 64 * 1024);
+        assert_eq!(parse_size("10M").unwrap(), 10 * 1024 * 1024);
+        assert_eq!(parse_size("1g").unwrap(), 1 << 30);
+        assert!(parse_size("ten").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("99999999999G").is_err());
+    }
+}
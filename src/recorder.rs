@@ -0,0 +1,472 @@
+//! Recording runs as asciinema casts, and reading them back.
+//!
+//! A [`Recorder`] writes the program's output in the [asciinema v2
+//! format](https://docs.asciinema.org/manual/asciicast/v2/): a JSON header
+//! line with the terminal size, followed by one `[time, "o", data]` line per
+//! chunk of output and `[time, "r", "COLSxROWS"]` for resizes. The file can
+//! be played with `asciinema play`, uploaded, or embedded with the asciinema
+//! player.
+//!
+//! A [`Cast`] is a cast file read back: to render it as an SVG image, to
+//! replay it through an output handler, or to check that a new run of the
+//! script printed the same as the recorded one.
+
+use crate::diff::{ScriptDiff, ScriptLine, diff_lines};
+use crate::transcript::{Transcript, TranscriptEvent, is_valid_size};
+use anyhow::{Context as _, Result, anyhow};
+use serde_json::json;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Output events closer together than this share one frame of an animation.
+const FRAME_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long an animation shows its last frame before starting over.
+const FINAL_FRAME_HOLD: Duration = Duration::from_secs(3);
+
+/// Writes an asciinema v2 cast, timing events from its creation.
+pub struct Recorder {
+    out: Box<dyn Write + Send>,
+    start: Instant,
+    /// Bytes of a UTF-8 character split across output chunks, held back
+    /// until the rest arrives.
+    pending: Vec<u8>,
+}
+
+impl Recorder {
+    /// Create the cast file at `path` for a `cols` x `rows` terminal.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be created.
+    pub fn create(path: impl AsRef<Path>, cols: u16, rows: u16) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create cast file: {}", path.display()))?;
+        Self::new(BufWriter::new(file), cols, rows)
+    }
+
+    /// Write a cast for a `cols` x `rows` terminal to `out`, starting with
+    /// its header.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header cannot be written.
+    pub fn new(out: impl Write + Send + 'static, cols: u16, rows: u16) -> Result<Self> {
+        let mut recorder = Self {
+            out: Box::new(out),
+            start: Instant::now(),
+            pending: Vec::new(),
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let mut header = json!({
+            "version": 2,
+            "width": cols,
+            "height": rows,
+            "timestamp": timestamp,
+        });
+        if let Ok(term) = std::env::var("TERM") {
+            header["env"] = json!({ "TERM": term });
+        }
+        recorder.write_line(&header)?;
+        Ok(recorder)
+    }
+
+    /// Record a chunk of program output.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event cannot be written.
+    pub fn output(&mut self, data: &[u8]) -> Result<()> {
+        match take_complete_utf8(&mut self.pending, data) {
+            Some(text) => self.event("o", &text),
+            None => Ok(()),
+        }
+    }
+
+    /// Record that the terminal was resized to `cols` x `rows`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event cannot be written.
+    pub fn resize(&mut self, cols: u16, rows: u16) -> Result<()> {
+        self.event("r", &format!("{}x{}", cols, rows))
+    }
+
+    fn event(&mut self, code: &str, data: &str) -> Result<()> {
+        let time = self.start.elapsed().as_micros() as f64 / 1e6;
+        self.write_line(&json!([time, code, data]))
+    }
+
+    fn write_line(&mut self, value: &serde_json::Value) -> Result<()> {
+        writeln!(self.out, "{}", value).context("Failed to write cast file")?;
+        // Flush every event so the cast is usable even if the run is killed.
+        self.out.flush().context("Failed to write cast file")
+    }
+}
+
+/// An event of a [`Cast`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum CastEvent {
+    /// The program printed `data` at `time` from the start.
+    Output { time: Duration, data: String },
+    /// The terminal was resized to `cols` x `rows` at `time`.
+    Resize {
+        time: Duration,
+        cols: u16,
+        rows: u16,
+    },
+}
+
+impl CastEvent {
+    /// When the event happened, from the start of the cast.
+    pub fn time(&self) -> Duration {
+        match self {
+            CastEvent::Output { time, .. } | CastEvent::Resize { time, .. } => *time,
+        }
+    }
+}
+
+/// An asciinema v2 cast: the terminal size and the output and resize events.
+/// Other events (input, markers) are skipped; a [`Transcript`] keeps them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cast {
+    pub width: u16,
+    pub height: u16,
+    pub events: Vec<CastEvent>,
+}
+
+impl Cast {
+    /// Read the cast file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a v2 cast.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read cast file: {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid cast file: {}", path.display()))
+    }
+
+    /// Parse the text of a cast file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header is missing or not version 2, or an
+    /// event line is malformed.
+    pub fn parse(text: &str) -> Result<Self> {
+        let transcript = Transcript::parse(text)?;
+        let events = transcript
+            .events
+            .into_iter()
+            .filter_map(|event| match event {
+                TranscriptEvent::Output { time, data } => Some(CastEvent::Output { time, data }),
+                TranscriptEvent::Resize { time, cols, rows } => {
+                    Some(CastEvent::Resize { time, cols, rows })
+                }
+                TranscriptEvent::Input { .. } | TranscriptEvent::Marker { .. } => None,
+            })
+            .collect();
+        Ok(Self {
+            width: transcript.width,
+            height: transcript.height,
+            events,
+        })
+    }
+
+    /// How long the cast plays: the time of its last event.
+    pub fn duration(&self) -> Duration {
+        self.events.last().map_or(Duration::ZERO, CastEvent::time)
+    }
+
+    /// Everything the program printed, in one string.
+    pub fn output(&self) -> String {
+        self.events
+            .iter()
+            .filter_map(|event| match event {
+                CastEvent::Output { data, .. } => Some(data.as_str()),
+                CastEvent::Resize { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Compare the output of this cast with that of `other`, line by line
+    /// without escape sequences, carriage returns, and blank lines; timing
+    /// is not compared. Lines are numbered among the non-blank lines.
+    pub fn diff_output(&self, other: &Cast) -> ScriptDiff {
+        let lines = |cast: &Cast| -> Vec<ScriptLine> {
+            crate::compare::plain_lines(&cast.output())
+                .into_iter()
+                .enumerate()
+                .map(|(i, text)| ScriptLine { line: i + 1, text })
+                .collect()
+        };
+        diff_lines(&lines(self), &lines(other))
+    }
+
+    /// Pass the output to `handler` with the timing it was recorded with,
+    /// sped up by `speed` (2.0 plays twice as fast).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `speed` is not a positive number.
+    pub async fn replay(&self, speed: f64, mut handler: impl FnMut(&[u8])) -> Result<()> {
+        if !(speed.is_finite() && speed > 0.0) {
+            return Err(anyhow!(
+                "Replay speed must be a positive number, got {}",
+                speed
+            ));
+        }
+        let start = tokio::time::Instant::now();
+        for event in &self.events {
+            tokio::time::sleep_until(start + event.time().div_f64(speed)).await;
+            if let CastEvent::Output { data, .. } = event {
+                handler(data.as_bytes());
+            }
+        }
+        Ok(())
+    }
+
+    /// The terminal screen after the events up to `at`.
+    pub fn screen_at(&self, at: Duration) -> vt100::Screen {
+        let mut parser = vt100::Parser::new(self.height, self.width, 0);
+        for event in self.events.iter().take_while(|e| e.time() <= at) {
+            apply(&mut parser, event);
+        }
+        parser.screen().clone()
+    }
+
+    /// Render the screen at `at` as a static SVG image, or with `None`, the
+    /// whole cast as an animated SVG image that loops.
+    pub fn to_svg(&self, at: Option<Duration>) -> String {
+        match at {
+            Some(at) => crate::render::render_svg(&self.screen_at(at)),
+            None => crate::render::render_animated_svg(
+                &self.frames(),
+                (self.height, self.width),
+                FINAL_FRAME_HOLD,
+            ),
+        }
+    }
+
+    /// The screens the cast shows, each with the time it appears; bursts of
+    /// output within [`FRAME_INTERVAL`] make one frame.
+    fn frames(&self) -> Vec<(Duration, vt100::Screen)> {
+        let mut parser = vt100::Parser::new(self.height, self.width, 0);
+        let mut frames = vec![(Duration::ZERO, parser.screen().clone())];
+        let mut burst_start = None;
+        for (i, event) in self.events.iter().enumerate() {
+            apply(&mut parser, event);
+            let start = *burst_start.get_or_insert(event.time());
+            let burst_over = self
+                .events
+                .get(i + 1)
+                .is_none_or(|next| next.time() >= start + FRAME_INTERVAL);
+            if burst_over {
+                burst_start = None;
+                if start.is_zero() {
+                    frames[0].1 = parser.screen().clone();
+                } else {
+                    frames.push((start, parser.screen().clone()));
+                }
+            }
+        }
+        frames
+    }
+}
+
+fn apply(parser: &mut vt100::Parser, event: &CastEvent) {
+    match event {
+        CastEvent::Output { data, .. } => parser.process(data.as_bytes()),
+        CastEvent::Resize { cols, rows, .. } if is_valid_size(*cols, *rows) => {
+            parser.screen_mut().set_size(*rows, *cols)
+        }
+        CastEvent::Resize { .. } => {}
+    }
+}
+
+/// Append `data` to `pending` and take the text it completes, holding back
+/// a character split at the end until the rest arrives. Invalid bytes
+/// elsewhere become U+FFFD. Returns `None` if there is no complete text yet.
+pub(crate) fn take_complete_utf8(pending: &mut Vec<u8>, data: &[u8]) -> Option<String> {
+    pending.extend_from_slice(data);
+    let complete = match std::str::from_utf8(pending) {
+        Ok(_) => pending.len(),
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        Err(_) => pending.len(),
+    };
+    if complete == 0 {
+        return None;
+    }
+    let rest = pending.split_off(complete);
+    let text = String::from_utf8_lossy(pending).into_owned();
+    *pending = rest;
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::sync::{Arc, Mutex};
+
+    /// A writer whose contents stay readable after the recorder takes it.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_cast() {
+        let out = Shared::default();
+        let mut recorder = Recorder::new(out.clone(), 80, 24).unwrap();
+        recorder.output(b"$ echo \"hi\"\r\n").unwrap();
+        // "é" split across two chunks.
+        recorder.output(b"caf\xc3").unwrap();
+        recorder.output(b"\xa9\x1b[0m").unwrap();
+        recorder.resize(100, 30).unwrap();
+
+        let text = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0]["version"], 2);
+        assert_eq!(lines[0]["width"], 80);
+        assert_eq!(lines[0]["height"], 24);
+        assert_eq!(lines[1][1], "o");
+        assert_eq!(lines[1][2], "$ echo \"hi\"\r\n");
+        assert_eq!(lines[2][2], "caf");
+        assert_eq!(lines[3][2], "é\x1b[0m");
+        assert_eq!(lines[4][1], "r");
+        assert_eq!(lines[4][2], "100x30");
+        assert!(lines[4][0].as_f64().unwrap() >= lines[1][0].as_f64().unwrap());
+
+        let cast = Cast::parse(&text).unwrap();
+        assert_eq!((cast.width, cast.height), (80, 24));
+        assert_eq!(cast.events.len(), 4);
+        assert!(matches!(
+            &cast.events[2],
+            CastEvent::Output { data, .. } if data == "é\x1b[0m"
+        ));
+        assert!(matches!(
+            cast.events[3],
+            CastEvent::Resize {
+                cols: 100,
+                rows: 30,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_parse_cast_errors() {
+        assert!(Cast::parse("").is_err());
+        assert!(Cast::parse(r#"{"version":1,"width":80,"height":24}"#).is_err());
+        let header = r#"{"version":2,"width":80,"height":24}"#;
+        assert!(Cast::parse(&format!("{}\n[0.5, \"o\"]", header)).is_err());
+        assert!(Cast::parse(&format!("{}\n[0.5, \"r\", \"wide\"]", header)).is_err());
+        let cast = Cast::parse(&format!("{}\n[0.5, \"i\", \"ls\"]\n", header)).unwrap();
+        assert!(cast.events.is_empty());
+    }
+
+    #[test]
+    fn test_diff_output() {
+        let header = r#"{"version":2,"width":80,"height":24}"#;
+        let old = Cast::parse(&format!(
+            "{}\n{}\n{}",
+            header, r#"[0.1, "o", "$ ls\r\n"]"#, r#"[0.2, "o", "\u001b[1ma.txt\u001b[0m\r\n$ "]"#
+        ))
+        .unwrap();
+        let same = Cast::parse(&format!(
+            "{}\n{}",
+            header, r#"[3.0, "o", "$ ls\r\na.txt\r\n\r\n$ "]"#
+        ))
+        .unwrap();
+        assert!(old.diff_output(&same).is_empty());
+        let new = Cast::parse(&format!(
+            "{}\n{}",
+            header, r#"[0.1, "o", "$ ls\r\nb.txt\r\n$ "]"#
+        ))
+        .unwrap();
+        assert_eq!(
+            old.diff_output(&new).to_string(),
+            "-    2: a.txt\n+    2: b.txt\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replay() {
+        let cast = Cast::parse(concat!(
+            r#"{"version":2,"width":80,"height":24}"#,
+            "\n",
+            r#"[1.0, "o", "a"]"#,
+            "\n",
+            r#"[1.0, "r", "100x30"]"#,
+            "\n",
+            r#"[3.0, "o", "b"]"#,
+        ))
+        .unwrap();
+        let start = tokio::time::Instant::now();
+        let mut seen = Vec::new();
+        cast.replay(10.0, |data| seen.push((data.to_vec(), start.elapsed())))
+            .await
+            .unwrap();
+        assert_eq!(seen.len(), 2);
+        assert_eq!(seen[0].0, b"a");
+        assert!(seen[0].1 >= Duration::from_millis(100));
+        assert_eq!(seen[1].0, b"b");
+        assert!(seen[1].1 >= Duration::from_millis(300));
+        assert!(seen[1].1 < Duration::from_secs(3));
+        assert!(cast.replay(0.0, |_| {}).await.is_err());
+    }
+
+    #[test]
+    fn test_cast_frames() {
+        let cast = Cast::parse(concat!(
+            r#"{"version":2,"width":10,"height":2}"#,
+            "\n",
+            r#"[0.0, "o", "$ "]"#,
+            "\n",
+            r#"[1.0, "o", "l"]"#,
+            "\n",
+            r#"[1.02, "o", "s"]"#,
+            "\n",
+            r#"[2.5, "o", "\r\nok"]"#,
+        ))
+        .unwrap();
+        assert_eq!(cast.duration(), Duration::from_millis(2500));
+        assert_eq!(cast.screen_at(Duration::from_secs(1)).contents(), "$ l");
+        let frames = cast.frames();
+        let times: Vec<Duration> = frames.iter().map(|(time, _)| *time).collect();
+        assert_eq!(
+            times,
+            [
+                Duration::ZERO,
+                Duration::from_secs(1),
+                Duration::from_millis(2500)
+            ]
+        );
+        assert_eq!(frames[0].1.contents(), "$ ");
+        assert_eq!(frames[1].1.contents(), "$ ls");
+        assert!(cast.to_svg(None).contains("@keyframes f2"));
+        assert!(
+            cast.to_svg(Some(Duration::from_secs(2)))
+                .contains(">$ ls</text>")
+        );
+    }
+}
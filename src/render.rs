@@ -0,0 +1,641 @@
+//! Rendering of the emulated terminal screen to images and HTML.
+
+use embedded_graphics::mono_font::MonoTextStyleBuilder;
+use embedded_graphics::mono_font::iso_8859_1::{FONT_8X13, FONT_8X13_BOLD, FONT_8X13_ITALIC};
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use embedded_graphics::text::{Baseline, Text};
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Width of one character cell in pixels.
+const CELL_WIDTH: f32 = 8.4;
+
+/// Height of one character cell in pixels.
+const CELL_HEIGHT: f32 = 17.0;
+
+/// Font size in pixels.
+const FONT_SIZE: f32 = 14.0;
+
+/// Padding around the cell grid in pixels.
+const PADDING: f32 = 8.0;
+
+const DEFAULT_FG: &str = "#d4d4d4";
+const DEFAULT_BG: &str = "#1e1e1e";
+
+/// The 16 ANSI colors, as xterm shows them.
+const ANSI_COLORS: [&str; 16] = [
+    "#000000", "#cd0000", "#00cd00", "#cdcd00", "#0000ee", "#cd00cd", "#00cdcd", "#e5e5e5",
+    "#7f7f7f", "#ff0000", "#00ff00", "#ffff00", "#5c5cff", "#ff00ff", "#00ffff", "#ffffff",
+];
+
+/// CSS color for a palette index: 16 ANSI colors, a 6x6x6 cube, and 24 grays.
+fn indexed_color(index: u8) -> String {
+    match index {
+        0..16 => ANSI_COLORS[index as usize].to_string(),
+        16..232 => {
+            let i = index - 16;
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            format!(
+                "#{:02x}{:02x}{:02x}",
+                level(i / 36),
+                level(i / 6 % 6),
+                level(i % 6)
+            )
+        }
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+            format!("#{:02x}{:02x}{:02x}", gray, gray, gray)
+        }
+    }
+}
+
+fn css_color(color: vt100::Color, default: &str) -> String {
+    match color {
+        vt100::Color::Default => default.to_string(),
+        vt100::Color::Idx(index) => indexed_color(index),
+        vt100::Color::Rgb(r, g, b) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+    }
+}
+
+/// Format a pixel coordinate with at most one decimal.
+fn px(value: f32) -> String {
+    let text = format!("{:.1}", value);
+    match text.strip_suffix(".0") {
+        Some(whole) => whole.to_string(),
+        None => text,
+    }
+}
+
+/// Escape text for use in SVG and HTML content and attribute values.
+pub(crate) fn escape_xml(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// How a cell is drawn; neighbouring cells with the same style share one
+/// `<text>` element.
+#[derive(PartialEq)]
+struct Style {
+    fg: String,
+    bg: String,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    dim: bool,
+}
+
+impl Style {
+    fn of(cell: &vt100::Cell) -> Self {
+        let mut fg = css_color(cell.fgcolor(), DEFAULT_FG);
+        let mut bg = css_color(cell.bgcolor(), DEFAULT_BG);
+        if cell.inverse() {
+            std::mem::swap(&mut fg, &mut bg);
+        }
+        Self {
+            fg,
+            bg,
+            bold: cell.bold(),
+            italic: cell.italic(),
+            underline: cell.underline(),
+            dim: cell.dim(),
+        }
+    }
+}
+
+/// Neighbouring cells of a row with the same style.
+struct Run {
+    start: u16,
+    end: u16,
+    style: Style,
+    /// The cells' contents, a blank for each empty cell.
+    text: String,
+}
+
+/// Split row `row` of `screen` into runs of equally styled cells.
+fn runs(screen: &vt100::Screen, row: u16) -> Vec<Run> {
+    let (_, cols) = screen.size();
+    let mut runs = Vec::new();
+    let mut col = 0;
+    while col < cols {
+        let Some(cell) = screen.cell(row, col) else {
+            break;
+        };
+        let style = Style::of(cell);
+        let start = col;
+        let mut text = String::new();
+        while col < cols {
+            let Some(cell) = screen.cell(row, col) else {
+                break;
+            };
+            if cell.is_wide_continuation() {
+                col += 1;
+                continue;
+            }
+            if Style::of(cell) != style {
+                break;
+            }
+            text.push_str(if cell.has_contents() {
+                cell.contents()
+            } else {
+                " "
+            });
+            col += 1;
+        }
+        runs.push(Run {
+            start,
+            end: col,
+            style,
+            text,
+        });
+    }
+    runs
+}
+
+/// Render `screen` as a standalone SVG image: every cell with its colors and
+/// attributes, and the cursor unless the program hid it.
+pub(crate) fn render_svg(screen: &vt100::Screen) -> String {
+    let mut svg = svg_header(screen.size());
+    push_svg_screen(&mut svg, screen);
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// The opening `<svg>` tag and background for a `(rows, cols)` terminal.
+fn svg_header((rows, cols): (u16, u16)) -> String {
+    let width = cols as f32 * CELL_WIDTH + 2.0 * PADDING;
+    let height = rows as f32 * CELL_HEIGHT + 2.0 * PADDING;
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = px(width),
+        h = px(height)
+    );
+    let _ = writeln!(
+        svg,
+        r#"<rect width="100%" height="100%" fill="{}"/>"#,
+        DEFAULT_BG
+    );
+    svg
+}
+
+/// Append the cells of `screen` and its cursor to `svg`.
+fn push_svg_screen(svg: &mut String, screen: &vt100::Screen) {
+    let (rows, _) = screen.size();
+    let _ = writeln!(
+        svg,
+        r#"<g font-family="ui-monospace, Menlo, Consolas, 'DejaVu Sans Mono', monospace" font-size="{}" xml:space="preserve">"#,
+        px(FONT_SIZE)
+    );
+    for row in 0..rows {
+        let y = PADDING + row as f32 * CELL_HEIGHT;
+        for Run {
+            start,
+            end,
+            style,
+            text,
+        } in runs(screen, row)
+        {
+            let x = PADDING + start as f32 * CELL_WIDTH;
+            let span = (end - start) as f32 * CELL_WIDTH;
+            if style.bg != DEFAULT_BG {
+                let _ = writeln!(
+                    svg,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+                    px(x),
+                    px(y),
+                    px(span),
+                    px(CELL_HEIGHT),
+                    style.bg
+                );
+            }
+            if text.trim().is_empty() {
+                continue;
+            }
+            // Trailing blanks are one cell each; leave them out of the text.
+            let trimmed = text.trim_end_matches(' ');
+            let text_span = span - (text.len() - trimmed.len()) as f32 * CELL_WIDTH;
+            let mut attrs = format!(r#" fill="{}""#, style.fg);
+            if style.bold {
+                attrs.push_str(r#" font-weight="bold""#);
+            }
+            if style.italic {
+                attrs.push_str(r#" font-style="italic""#);
+            }
+            if style.underline {
+                attrs.push_str(r#" text-decoration="underline""#);
+            }
+            if style.dim {
+                attrs.push_str(r#" opacity="0.6""#);
+            }
+            let _ = writeln!(
+                svg,
+                r#"<text x="{}" y="{}" textLength="{}" lengthAdjust="spacingAndGlyphs"{}>{}</text>"#,
+                px(x),
+                px(y + CELL_HEIGHT * 0.8),
+                px(text_span),
+                attrs,
+                escape_xml(trimmed)
+            );
+        }
+    }
+    svg.push_str("</g>\n");
+    if !screen.hide_cursor() {
+        let (row, col) = screen.cursor_position();
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" opacity="0.7"/>"#,
+            px(PADDING + col as f32 * CELL_WIDTH),
+            px(PADDING + row as f32 * CELL_HEIGHT),
+            px(CELL_WIDTH),
+            px(CELL_HEIGHT),
+            DEFAULT_FG
+        );
+    }
+}
+
+/// Width of one character cell in PNG screenshots, in pixels.
+const PNG_CELL_WIDTH: u32 = 8;
+
+/// Height of one character cell in PNG screenshots, in pixels.
+const PNG_CELL_HEIGHT: u32 = 16;
+
+/// Padding around the cell grid in PNG screenshots, in pixels.
+const PNG_PADDING: u32 = 8;
+
+/// RGB value of a `#rrggbb` color.
+fn rgb(css: &str) -> Rgb888 {
+    let hex = u32::from_str_radix(css.trim_start_matches('#'), 16).unwrap_or(0);
+    Rgb888::new((hex >> 16) as u8, (hex >> 8) as u8, hex as u8)
+}
+
+/// `color` mixed with `other`, keeping `alpha` of `color`.
+fn blend(color: Rgb888, other: Rgb888, alpha: f32) -> Rgb888 {
+    let mix = |a: u8, b: u8| (a as f32 * alpha + b as f32 * (1.0 - alpha)).round() as u8;
+    Rgb888::new(
+        mix(color.r(), other.r()),
+        mix(color.g(), other.g()),
+        mix(color.b(), other.b()),
+    )
+}
+
+/// An RGBA pixel buffer that text and rectangles are drawn on.
+struct Canvas {
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32, background: Rgb888) -> Self {
+        let pixel = [background.r(), background.g(), background.b(), 255];
+        Self {
+            width,
+            height,
+            rgba: pixel.repeat((width * height) as usize),
+        }
+    }
+
+    fn pixel(&self, x: u32, y: u32) -> Rgb888 {
+        let i = ((y * self.width + x) * 4) as usize;
+        Rgb888::new(self.rgba[i], self.rgba[i + 1], self.rgba[i + 2])
+    }
+}
+
+impl OriginDimensions for Canvas {
+    fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+}
+
+impl DrawTarget for Canvas {
+    type Color = Rgb888;
+    type Error = std::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Rgb888>>,
+    {
+        for Pixel(point, color) in pixels {
+            let (Ok(x), Ok(y)) = (u32::try_from(point.x), u32::try_from(point.y)) else {
+                continue;
+            };
+            if x < self.width && y < self.height {
+                let i = ((y * self.width + x) * 4) as usize;
+                self.rgba[i..i + 3].copy_from_slice(&[color.r(), color.g(), color.b()]);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Render `screen` as a PNG image: every cell with its colors and
+/// attributes in a built-in bitmap font, and the cursor unless the program
+/// hid it.
+///
+/// Characters outside Latin-1 show as `?`, since the font has no glyphs for
+/// them; use an SVG screenshot for those.
+pub(crate) fn render_png(screen: &vt100::Screen) -> Vec<u8> {
+    let (rows, cols) = screen.size();
+    let width = cols as u32 * PNG_CELL_WIDTH + 2 * PNG_PADDING;
+    let height = rows as u32 * PNG_CELL_HEIGHT + 2 * PNG_PADDING;
+    let mut canvas = Canvas::new(width, height, rgb(DEFAULT_BG));
+    let cell_origin = |row: u16, col: u16| {
+        Point::new(
+            (PNG_PADDING + col as u32 * PNG_CELL_WIDTH) as i32,
+            (PNG_PADDING + row as u32 * PNG_CELL_HEIGHT) as i32,
+        )
+    };
+    for row in 0..rows {
+        for col in 0..cols {
+            let Some(cell) = screen.cell(row, col) else {
+                continue;
+            };
+            if cell.is_wide_continuation() {
+                continue;
+            }
+            let style = Style::of(cell);
+            let origin = cell_origin(row, col);
+            let span = if cell.is_wide() { 2 } else { 1 };
+            let area = Rectangle::new(origin, Size::new(span * PNG_CELL_WIDTH, PNG_CELL_HEIGHT));
+            let _ = canvas.fill_solid(&area, rgb(&style.bg));
+            if !cell.has_contents() || cell.contents() == " " {
+                continue;
+            }
+            let mut fg = rgb(&style.fg);
+            if style.dim {
+                fg = blend(fg, rgb(&style.bg), 0.6);
+            }
+            let font = if style.bold {
+                &FONT_8X13_BOLD
+            } else if style.italic {
+                &FONT_8X13_ITALIC
+            } else {
+                &FONT_8X13
+            };
+            let mut text_style = MonoTextStyleBuilder::new().font(font).text_color(fg);
+            if style.underline {
+                text_style = text_style.underline();
+            }
+            // Center the 13 pixel glyphs in the taller cell.
+            let position = origin + Point::new(0, 1);
+            let _ =
+                Text::with_baseline(cell.contents(), position, text_style.build(), Baseline::Top)
+                    .draw(&mut canvas);
+        }
+    }
+    if !screen.hide_cursor() {
+        let (row, col) = screen.cursor_position();
+        let origin = cell_origin(row, col);
+        let cursor = rgb(DEFAULT_FG);
+        for y in 0..PNG_CELL_HEIGHT {
+            for x in 0..PNG_CELL_WIDTH {
+                let (px, py) = (origin.x as u32 + x, origin.y as u32 + y);
+                if px < width && py < height {
+                    let color = blend(cursor, canvas.pixel(px, py), 0.7);
+                    let _ = Pixel(Point::new(px as i32, py as i32), color).draw(&mut canvas);
+                }
+            }
+        }
+    }
+    // The padding keeps the image from being empty, and the canvas holds
+    // exactly its pixels.
+    crate::png::encode(width, height, &canvas.rgba).expect("the canvas is a valid image")
+}
+
+/// Render `frames`, each a screen and the time it appears, as an animated
+/// SVG image of a `size` (rows, cols) terminal that loops forever, holding
+/// the last frame for `hold` before starting over.
+///
+/// Each frame is a group of its own, shown in turn with CSS animations, so
+/// the image plays in browsers (and on GitHub) without scripts.
+pub(crate) fn render_animated_svg(
+    frames: &[(Duration, vt100::Screen)],
+    size: (u16, u16),
+    hold: Duration,
+) -> String {
+    let mut svg = svg_header(size);
+    let total = frames.last().map_or(Duration::ZERO, |(time, _)| *time) + hold;
+    let percent = |time: Duration| 100.0 * time.as_secs_f64() / total.as_secs_f64();
+    svg.push_str("<style>\n");
+    let _ = writeln!(
+        svg,
+        ".frame {{ opacity: 0; animation-duration: {:.3}s; animation-iteration-count: infinite; animation-timing-function: step-end; }}",
+        total.as_secs_f64()
+    );
+    for (i, (start, _)) in frames.iter().enumerate() {
+        let end = frames.get(i + 1).map(|(time, _)| percent(*time));
+        if start.is_zero() {
+            let _ = write!(svg, "@keyframes f{i} {{ 0% {{ opacity: 1 }}");
+        } else {
+            let _ = write!(
+                svg,
+                "@keyframes f{i} {{ 0% {{ opacity: 0 }} {:.3}% {{ opacity: 1 }}",
+                percent(*start)
+            );
+        }
+        match end {
+            Some(end) => {
+                let _ = write!(svg, " {:.3}% {{ opacity: 0 }} 100% {{ opacity: 0 }}", end);
+            }
+            None => svg.push_str(" 100% { opacity: 1 }"),
+        }
+        let _ = writeln!(svg, " }}\n#f{i} {{ animation-name: f{i}; }}");
+    }
+    svg.push_str("</style>\n");
+    for (i, (_, screen)) in frames.iter().enumerate() {
+        let _ = writeln!(svg, r#"<g id="f{}" class="frame">"#, i);
+        push_svg_screen(&mut svg, screen);
+        svg.push_str("</g>\n");
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Render everything `parser` has shown, its scrollback followed by the
+/// screen, as a standalone HTML page titled `title`, with colors and
+/// attributes. Rows the program wrapped are joined into one line.
+pub(crate) fn render_html(parser: &mut vt100::Parser, title: &str) -> String {
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        concat!(
+            "<!DOCTYPE html>\n",
+            "<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n",
+            "<style>\n",
+            "body {{ margin: 0; background: {bg}; }}\n",
+            "pre {{ margin: 0; padding: {padding}px; color: {fg}; background: {bg}; ",
+            "font: {font}px/{line}px ui-monospace, Menlo, Consolas, 'DejaVu Sans Mono', monospace; }}\n",
+            "</style>\n</head>\n<body>\n<pre>"
+        ),
+        title = escape_xml(title),
+        bg = DEFAULT_BG,
+        fg = DEFAULT_FG,
+        padding = px(PADDING),
+        font = px(FONT_SIZE),
+        line = px(CELL_HEIGHT),
+    );
+    let (rows, _) = parser.screen().size();
+    parser.screen_mut().set_scrollback(usize::MAX);
+    let history = parser.screen().scrollback();
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    // Scrollback rows one at a time at the top of the view, then the screen.
+    let visible = (1..=history)
+        .rev()
+        .map(|offset| (offset, 0))
+        .chain((0..rows).map(|row| (0, row)));
+    for (offset, row) in visible {
+        parser.screen_mut().set_scrollback(offset);
+        let screen = parser.screen();
+        push_html_row(&mut line, screen, row);
+        if !screen.row_wrapped(row) {
+            lines.push(std::mem::take(&mut line));
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    // Rows below the last output are blank, not part of the session.
+    while lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+    html.push_str(&lines.join("\n"));
+    html.push_str("</pre>\n</body>\n</html>\n");
+    html
+}
+
+/// Append row `row` of `screen` to `html` as escaped text, styled runs in
+/// `<span>`s, without trailing blanks.
+fn push_html_row(html: &mut String, screen: &vt100::Screen, row: u16) {
+    let mut runs = runs(screen, row);
+    // Trailing default blanks are the empty rest of the row.
+    while let Some(last) = runs.last_mut() {
+        if last.style.bg != DEFAULT_BG || last.style.underline {
+            break;
+        }
+        let trimmed = last.text.trim_end_matches(' ').len();
+        last.text.truncate(trimmed);
+        if !last.text.is_empty() {
+            break;
+        }
+        runs.pop();
+    }
+    for run in runs {
+        let style = &run.style;
+        let mut css = Vec::new();
+        if style.fg != DEFAULT_FG {
+            css.push(format!("color:{}", style.fg));
+        }
+        if style.bg != DEFAULT_BG {
+            css.push(format!("background:{}", style.bg));
+        }
+        if style.bold {
+            css.push("font-weight:bold".to_string());
+        }
+        if style.italic {
+            css.push("font-style:italic".to_string());
+        }
+        if style.underline {
+            css.push("text-decoration:underline".to_string());
+        }
+        if style.dim {
+            css.push("opacity:0.6".to_string());
+        }
+        if css.is_empty() {
+            html.push_str(&escape_xml(&run.text));
+        } else {
+            let _ = write!(
+                html,
+                r#"<span style="{}">{}</span>"#,
+                css.join(";"),
+                escape_xml(&run.text)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indexed_color() {
+        assert_eq!(indexed_color(1), "#cd0000");
+        assert_eq!(indexed_color(16), "#000000");
+        assert_eq!(indexed_color(231), "#ffffff");
+        assert_eq!(indexed_color(232), "#080808");
+    }
+
+    #[test]
+    fn test_render_svg() {
+        let mut parser = vt100::Parser::new(2, 10, 0);
+        parser.process(b"a<b \x1b[1;31mred\x1b[0m\r\n\x1b[44m  \x1b[0m");
+        let svg = render_svg(parser.screen());
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(
+            r##"textLength="25.2" lengthAdjust="spacingAndGlyphs" fill="#d4d4d4">a&lt;b</text>"##
+        ));
+        assert!(svg.contains(r##"fill="#cd0000" font-weight="bold">red</text>"##));
+        assert!(svg.contains(r##"fill="#0000ee"/>"##));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn test_render_animated_svg() {
+        let mut parser = vt100::Parser::new(2, 10, 0);
+        parser.process(b"$ ");
+        let first = parser.screen().clone();
+        parser.process(b"ls");
+        let frames = [
+            (Duration::ZERO, first),
+            (Duration::from_secs(1), parser.screen().clone()),
+        ];
+        let svg = render_animated_svg(&frames, (2, 10), Duration::from_secs(3));
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("animation-duration: 4.000s;"));
+        assert!(svg.contains(
+            "@keyframes f0 { 0% { opacity: 1 } 25.000% { opacity: 0 } 100% { opacity: 0 } }"
+        ));
+        assert!(svg.contains(
+            "@keyframes f1 { 0% { opacity: 0 } 25.000% { opacity: 1 } 100% { opacity: 1 } }"
+        ));
+        assert_eq!(svg.matches(r#"class="frame""#).count(), 2);
+        assert!(svg.contains(">$ ls</text>"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn test_render_html() {
+        let mut parser = vt100::Parser::new(3, 10, 100);
+        parser.process(b"first\r\n");
+        parser.process(b"a<b \x1b[1;31mred\x1b[0m\r\n");
+        parser.process(b"0123456789wrapped\r\n\x1b[44m  \x1b[0m\r\nlast");
+        let html = render_html(&mut parser, "demo & co");
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>demo &amp; co</title>"));
+        let body = html.split("<pre>").nth(1).unwrap();
+        let body = body.split("</pre>").next().unwrap();
+        assert_eq!(
+            body,
+            "first\n\
+             a&lt;b <span style=\"color:#cd0000;font-weight:bold\">red</span>\n\
+             0123456789wrapped\n\
+             <span style=\"background:#0000ee\">  </span>\n\
+             last"
+        );
+        // The view is back on the screen afterwards.
+        assert_eq!(parser.screen().scrollback(), 0);
+    }
+}
@@ -0,0 +1,267 @@
+//! Restrictions for the program under test (Linux).
+//!
+//! A test script drives whatever program it is pointed at, and a buggy one
+//! can eat the machine's memory, fork without end, or talk to production
+//! services. A [`Sandbox`] starts the program with resource limits and,
+//! optionally, without network access.
+//!
+//! The program always runs as the leader of its own session and process
+//! group, with the PTY as its controlling terminal, so terminal signals from
+//! scriptty's own terminal never reach it. The sandbox adds to that by
+//! starting the program through util-linux tools, which must be installed:
+//! `prlimit` applies the limits and `unshare` gives it a network namespace
+//! with only a loopback interface. With a [`Sandbox::user`], `setpriv` switches
+//! to that user's ids and groups and resets the environment to theirs (`HOME`,
+//! `USER`, `SHELL`, `PATH`), much like `su -`; this needs root. The variables
+//! set for the program (`--env`, `env` lines, `WORKSPACE`) are then set again
+//! on top with `env`. All of these replace themselves with the program, so it
+//! keeps the process id scriptty started.
+
+use anyhow::{Result, anyhow};
+use std::ffi::CString;
+use std::time::Duration;
+
+/// Limits and isolation for the program; all off by default.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sandbox {
+    /// Run the program in its own network namespace, without network access.
+    /// Without root, this needs unprivileged user namespaces and util-linux
+    /// 2.38 or newer; the program keeps scriptty's user and group ids.
+    pub no_network: bool,
+    /// Maximum address space of each process, in bytes.
+    pub max_memory: Option<u64>,
+    /// Maximum number of open files of each process.
+    pub max_open_files: Option<u64>,
+    /// Maximum number of processes of the user running the program.
+    pub max_processes: Option<u64>,
+    /// Maximum CPU time of each process; it is killed after that.
+    pub max_cpu_time: Option<Duration>,
+    /// Run the program as this user, e.g. to test what an unprivileged
+    /// account sees. Needs root.
+    pub user: Option<String>,
+}
+
+impl Sandbox {
+    /// Whether the sandbox restricts nothing.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The command line that starts `command` with `args` in this sandbox,
+    /// as a command and its arguments.
+    ///
+    /// `env` is the environment set for the program. It passes through the
+    /// sandbox unchanged, except that running as a [`Sandbox::user`] resets
+    /// the environment, so there it is set again after the switch.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sandbox restricts anything on a platform other
+    /// than Linux, or the user is unknown or scriptty is not running as root.
+    pub fn command<S: AsRef<str>>(
+        &self,
+        command: &str,
+        args: &[S],
+        env: &[(String, String)],
+    ) -> Result<(String, Vec<String>)> {
+        if !self.is_empty() && !cfg!(target_os = "linux") {
+            return Err(anyhow!("Sandboxing is only supported on Linux"));
+        }
+        let mut argv: Vec<String> = Vec::new();
+        if self.no_network {
+            // SAFETY: geteuid and getegid have no preconditions and cannot fail.
+            let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+            argv.extend(unshare_args(uid, gid));
+        }
+        let limits: Vec<String> = [
+            ("as", self.max_memory),
+            ("nofile", self.max_open_files),
+            ("nproc", self.max_processes),
+            (
+                "cpu",
+                self.max_cpu_time.map(|t| t.as_secs_f64().ceil() as u64),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, limit)| limit.map(|limit| format!("--{}={}", name, limit)))
+        .collect();
+        if !limits.is_empty() {
+            argv.push("prlimit".to_string());
+            argv.extend(limits);
+            argv.push("--".to_string());
+        }
+        if let Some(user) = &self.user {
+            // SAFETY: geteuid has no preconditions and cannot fail.
+            if unsafe { libc::geteuid() } != 0 {
+                return Err(anyhow!("Running the program as '{}' needs root", user));
+            }
+            let (uid, gid) = lookup_user(user)?;
+            argv.extend([
+                "setpriv".to_string(),
+                format!("--reuid={}", uid),
+                format!("--regid={}", gid),
+                "--init-groups".to_string(),
+                "--reset-env".to_string(),
+                "--".to_string(),
+            ]);
+            if !env.is_empty() {
+                argv.push("env".to_string());
+                argv.extend(env.iter().map(|(key, value)| format!("{}={}", key, value)));
+            }
+        }
+        argv.push(command.to_string());
+        argv.extend(args.iter().map(|arg| arg.as_ref().to_string()));
+        let command = argv.remove(0);
+        Ok((command, argv))
+    }
+}
+
+/// The `unshare` command line that gives the program its own network
+/// namespace when scriptty runs as `uid` and `gid`.
+///
+/// Without root, the namespace needs a user namespace too. The program is
+/// mapped to the same ids inside it, not to root, so it sees the user it
+/// would see without the sandbox (e.g. a `$` shell prompt, not `#`).
+fn unshare_args(uid: libc::uid_t, gid: libc::gid_t) -> Vec<String> {
+    let mut argv = vec!["unshare".to_string(), "--net".to_string()];
+    if uid != 0 {
+        argv.push(format!("--map-user={}", uid));
+        argv.push(format!("--map-group={}", gid));
+    }
+    argv.push("--".to_string());
+    argv
+}
+
+/// The user and primary group id of the user named `name`.
+fn lookup_user(name: &str) -> Result<(libc::uid_t, libc::gid_t)> {
+    let unknown = || anyhow!("Unknown user: {}", name);
+    let c_name = CString::new(name).map_err(|_| unknown())?;
+    // SAFETY: passwd is plain data; getpwnam_r fills it in.
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+    let mut buf = vec![0 as libc::c_char; 4096];
+    loop {
+        // SAFETY: all pointers are valid for the duration of the call, and
+        // buf.len() is the size of buf.
+        let err = unsafe {
+            libc::getpwnam_r(
+                c_name.as_ptr(),
+                &mut passwd,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            )
+        };
+        if err == libc::ERANGE && buf.len() < 1 << 20 {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+        if result.is_null() {
+            return Err(unknown());
+        }
+        return Ok((passwd.pw_uid, passwd.pw_gid));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_sandbox() {
+        let (command, args) = Sandbox::default().command("bash", &["-i"], &[]).unwrap();
+        assert_eq!(command, "bash");
+        assert_eq!(args, ["-i"]);
+    }
+
+    #[test]
+    fn test_limits() {
+        let sandbox = Sandbox {
+            max_memory: Some(1 << 30),
+            max_open_files: Some(64),
+            max_cpu_time: Some(Duration::from_millis(1500)),
+            ..Sandbox::default()
+        };
+        let (command, args) = sandbox.command("bash", &["-i"], &[]).unwrap();
+        assert_eq!(command, "prlimit");
+        assert_eq!(
+            args,
+            [
+                "--as=1073741824",
+                "--nofile=64",
+                "--cpu=2",
+                "--",
+                "bash",
+                "-i"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_network() {
+        let sandbox = Sandbox {
+            no_network: true,
+            max_processes: Some(100),
+            ..Sandbox::default()
+        };
+        let (command, args) = sandbox.command("bash", &[] as &[&str], &[]).unwrap();
+        assert_eq!(command, "unshare");
+        assert_eq!(args[0], "--net");
+        let rest: Vec<&str> = args
+            .iter()
+            .map(String::as_str)
+            .skip_while(|a| *a != "--")
+            .collect();
+        assert_eq!(rest, ["--", "prlimit", "--nproc=100", "--", "bash"]);
+    }
+
+    #[test]
+    fn test_unshare_args() {
+        assert_eq!(unshare_args(0, 0), ["unshare", "--net", "--"]);
+        assert_eq!(
+            unshare_args(1000, 100),
+            [
+                "unshare",
+                "--net",
+                "--map-user=1000",
+                "--map-group=100",
+                "--"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_user() {
+        let sandbox = Sandbox {
+            user: Some("root".to_string()),
+            ..Sandbox::default()
+        };
+        // SAFETY: geteuid has no preconditions and cannot fail.
+        if unsafe { libc::geteuid() } != 0 {
+            assert!(sandbox.command("id", &["-u"], &[]).is_err());
+            return;
+        }
+        let (command, args) = sandbox.command("id", &["-u"], &[]).unwrap();
+        assert_eq!(command, "setpriv");
+        assert_eq!(
+            args,
+            [
+                "--reuid=0",
+                "--regid=0",
+                "--init-groups",
+                "--reset-env",
+                "--",
+                "id",
+                "-u"
+            ]
+        );
+        let env = [("LANG".to_string(), "C.UTF-8".to_string())];
+        let (_, args) = sandbox.command("id", &["-u"], &env).unwrap();
+        assert_eq!(args[5..], ["env", "LANG=C.UTF-8", "id", "-u"]);
+        let unknown = Sandbox {
+            user: Some("no-such-user-here".to_string()),
+            ..Sandbox::default()
+        };
+        assert!(unknown.command("id", &[] as &[&str], &[]).is_err());
+    }
+}
@@ -0,0 +1,123 @@
+//! Keeping passwords out of logs and reports.
+//!
+//! Commands that send a password (`sudo`, `login`, `enable`) register it
+//! with the run's [`Secrets`], and so do the variables holding one (see
+//! [`SECRET_VARS`]). Every place the run is recorded — the event log, the
+//! transcript, the accessible transcript, input events, `debug_state`, and
+//! the failure report — shows registered values as [`MASK`] instead. The
+//! program itself still receives the real value, and `expect` still sees the
+//! real output.
+
+use std::borrow::Cow;
+use std::sync::{Arc, RwLock};
+
+/// Text shown in place of a secret.
+pub const MASK: &str = "********";
+
+/// Variables whose values are secrets as soon as they are set.
+pub const SECRET_VARS: &[&str] = &["SUDO_PASSWORD", "ENABLE_SECRET"];
+
+/// The secret values of a run, shared by everything that records it.
+///
+/// Clones share the same registry, so a secret registered through one is
+/// masked by all.
+#[derive(Debug, Clone, Default)]
+pub struct Secrets {
+    values: Arc<RwLock<Vec<String>>>,
+}
+
+impl Secrets {
+    /// Mask `value` from now on. Empty values are ignored.
+    pub fn register(&self, value: impl Into<String>) {
+        let value = value.into();
+        if value.is_empty() {
+            return;
+        }
+        let mut values = self.values.write().unwrap();
+        if !values.contains(&value) {
+            values.push(value);
+            // Longest first, so a secret containing another is masked whole.
+            values.sort_by_key(|v| std::cmp::Reverse(v.len()));
+        }
+    }
+
+    /// Whether no secret has been registered.
+    pub fn is_empty(&self) -> bool {
+        self.values.read().unwrap().is_empty()
+    }
+
+    /// `text` with every registered secret replaced by [`MASK`].
+    pub fn mask<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let values = self.values.read().unwrap();
+        let mut text = Cow::Borrowed(text);
+        for value in values.iter() {
+            if text.contains(value.as_str()) {
+                text = Cow::Owned(text.replace(value.as_str(), MASK));
+            }
+        }
+        text
+    }
+
+    /// `data` with every registered secret replaced by [`MASK`].
+    pub fn mask_bytes<'a>(&self, data: &'a [u8]) -> Cow<'a, [u8]> {
+        let values = self.values.read().unwrap();
+        let mut data = Cow::Borrowed(data);
+        for value in values.iter() {
+            let value = value.as_bytes();
+            if data.windows(value.len()).any(|w| w == value) {
+                data = Cow::Owned(replace_bytes(&data, value, MASK.as_bytes()));
+            }
+        }
+        data
+    }
+}
+
+/// `data` with every occurrence of `from` replaced by `to`.
+fn replace_bytes(data: &[u8], from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut rest = data;
+    while !rest.is_empty() {
+        if rest.starts_with(from) {
+            out.extend_from_slice(to);
+            rest = &rest[from.len()..];
+        } else {
+            out.push(rest[0]);
+            rest = &rest[1..];
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask() {
+        let secrets = Secrets::default();
+        assert_eq!(secrets.mask("hunter2"), "hunter2");
+        secrets.register("hunter2");
+        secrets.register("");
+        assert_eq!(secrets.mask("pw hunter2!"), "pw ********!");
+        assert_eq!(
+            secrets.mask_bytes(b"hunter2\rhunter2").as_ref(),
+            b"********\r********"
+        );
+        assert!(matches!(secrets.mask("nothing"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_longest_first() {
+        let secrets = Secrets::default();
+        secrets.register("abc");
+        secrets.register("abcdef");
+        assert_eq!(secrets.mask("abcdef abc"), "******** ********");
+    }
+
+    #[test]
+    fn test_clones_share() {
+        let secrets = Secrets::default();
+        secrets.clone().register("s3cret");
+        assert_eq!(secrets.mask("s3cret"), MASK);
+    }
+}
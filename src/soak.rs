@@ -0,0 +1,146 @@
+//! Resource usage of the program across `loop` iterations.
+//!
+//! After each iteration of a `loop` block, the program's resident memory and
+//! number of open file descriptors are sampled. Steady growth over a long
+//! (soak) run points at a memory or descriptor leak. Samples are read from
+//! `/proc`, so they are only available on Linux, and they describe the
+//! spawned process itself, not its children.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+/// The program's resource usage at one point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// Resident set size in bytes.
+    pub rss_bytes: u64,
+    /// Number of open file descriptors.
+    pub open_fds: usize,
+}
+
+impl ResourceUsage {
+    /// Sample the usage of process `pid`, or `None` if it cannot be read
+    /// (the process has exited, or there is no `/proc`).
+    pub(crate) fn sample(pid: u32) -> Option<Self> {
+        let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+        let rss_kb: u64 = status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))?
+            .trim()
+            .trim_end_matches("kB")
+            .trim()
+            .parse()
+            .ok()?;
+        let open_fds = std::fs::read_dir(format!("/proc/{}/fd", pid)).ok()?.count();
+        Some(Self {
+            rss_bytes: rss_kb * 1024,
+            open_fds,
+        })
+    }
+}
+
+/// Per-iteration samples keyed by loop site.
+#[derive(Debug, Clone, Default)]
+pub struct SoakReport {
+    loops: BTreeMap<String, Vec<ResourceUsage>>,
+}
+
+impl SoakReport {
+    /// Record the usage after an iteration of the loop at `site`.
+    pub(crate) fn record(&mut self, site: &str, usage: ResourceUsage) {
+        self.loops.entry(site.to_string()).or_default().push(usage);
+    }
+
+    /// Whether no samples have been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.loops.is_empty()
+    }
+
+    /// Samples per loop site, one per iteration, ordered by site description.
+    pub fn loops(&self) -> impl Iterator<Item = (&str, &[ResourceUsage])> {
+        self.loops
+            .iter()
+            .map(|(site, samples)| (site.as_str(), samples.as_slice()))
+    }
+
+    /// Render the growth of memory and descriptors per loop as a
+    /// human-readable report.
+    pub fn report(&self) -> String {
+        let mut out = String::from("resource usage per loop iteration:\n");
+        for (site, samples) in self.loops() {
+            let (Some(first), Some(last)) = (samples.first(), samples.last()) else {
+                continue;
+            };
+            let iterations = samples.len();
+            let rss_growth = last.rss_bytes as i64 - first.rss_bytes as i64;
+            let fd_growth = last.open_fds as i64 - first.open_fds as i64;
+            let per_iteration = match iterations {
+                1 => 0,
+                n => rss_growth / (n as i64 - 1),
+            };
+            let max_rss = samples.iter().map(|s| s.rss_bytes).max().unwrap_or(0);
+            let max_fds = samples.iter().map(|s| s.open_fds).max().unwrap_or(0);
+            let _ = writeln!(out, "  {}: {} iterations", site, iterations);
+            let _ = writeln!(
+                out,
+                "    rss: {} -> {} ({}, {}/iteration, max {})",
+                kib(first.rss_bytes as i64),
+                kib(last.rss_bytes as i64),
+                signed_kib(rss_growth),
+                signed_kib(per_iteration),
+                kib(max_rss as i64)
+            );
+            let _ = writeln!(
+                out,
+                "    fds: {} -> {} ({:+}, max {})",
+                first.open_fds, last.open_fds, fd_growth, max_fds
+            );
+        }
+        out
+    }
+}
+
+fn kib(bytes: i64) -> String {
+    format!("{} KiB", bytes / 1024)
+}
+
+fn signed_kib(bytes: i64) -> String {
+    format!("{:+} KiB", bytes / 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(rss_kb: u64, open_fds: usize) -> ResourceUsage {
+        ResourceUsage {
+            rss_bytes: rss_kb * 1024,
+            open_fds,
+        }
+    }
+
+    #[test]
+    fn test_report() {
+        let mut report = SoakReport::default();
+        assert!(report.is_empty());
+        report.record("loop until a", usage(1000, 5));
+        report.record("loop until a", usage(1100, 6));
+        report.record("loop until a", usage(1200, 7));
+        let text = report.report();
+        assert!(text.contains("loop until a: 3 iterations"), "got: {text}");
+        assert!(
+            text.contains("rss: 1000 KiB -> 1200 KiB (+200 KiB, +100 KiB/iteration, max 1200 KiB)"),
+            "got: {text}"
+        );
+        assert!(text.contains("fds: 5 -> 7 (+2, max 7)"), "got: {text}");
+    }
+
+    #[test]
+    fn test_sample_self() {
+        if cfg!(target_os = "linux") {
+            let usage = ResourceUsage::sample(std::process::id()).unwrap();
+            assert!(usage.rss_bytes > 0);
+            assert!(usage.open_fds > 0);
+        }
+    }
+}
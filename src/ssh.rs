@@ -0,0 +1,351 @@
+//! Connecting to remote hosts through the OpenSSH client.
+//!
+//! [`SshOptions`] builds an `ssh` command line from connection settings —
+//! host key verification, authentication, jump hosts, and timeouts — so
+//! scripts can run against remote machines by passing [`SshOptions::args`] to
+//! [`crate::Engine::builder`]`("ssh")`.
+//! A script can carry its own settings as `@meta ssh.*` header lines, read
+//! with [`SshOptions::from_meta`]. Settings not covered here can still come
+//! from `~/.ssh/config`.
+
+use crate::parser::parse_duration;
+use anyhow::{Context as _, Result, anyhow};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How the remote host key is verified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HostKeyPolicy {
+    /// Only connect to hosts already in the known hosts file.
+    Strict,
+    /// Add keys of unknown hosts, but refuse hosts whose key changed
+    /// (OpenSSH's default for new setups).
+    #[default]
+    AcceptNew,
+    /// Do not verify or record host keys, e.g. for throwaway test VMs.
+    Insecure,
+}
+
+impl FromStr for HostKeyPolicy {
+    type Err = anyhow::Error;
+
+    /// Parse `strict`, `accept-new`, or `insecure`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "strict" => Ok(Self::Strict),
+            "accept-new" => Ok(Self::AcceptNew),
+            "insecure" => Ok(Self::Insecure),
+            _ => Err(anyhow!(
+                "Unknown host key policy '{}', expected strict, accept-new, or insecure",
+                s
+            )),
+        }
+    }
+}
+
+/// Connection settings for an SSH session.
+///
+/// ```
+/// use scriptty::ssh::{HostKeyPolicy, SshOptions};
+/// use std::time::Duration;
+///
+/// let options = SshOptions::new("db1.example.com")
+///     .user("deploy")
+///     .host_key_policy(HostKeyPolicy::Strict)
+///     .identity_file("/home/deploy/.ssh/id_ed25519")
+///     .jump_host("bastion.example.com")
+///     .connect_timeout(Duration::from_secs(10));
+/// assert!(options.args().contains(&"deploy@db1.example.com".to_string()));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SshOptions {
+    pub host: String,
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    pub host_key_policy: HostKeyPolicy,
+    /// Known hosts file to use instead of `~/.ssh/known_hosts`.
+    pub known_hosts: Option<PathBuf>,
+    /// Private keys to offer; when any are given, only these are offered.
+    pub identity_files: Vec<PathBuf>,
+    /// Whether keys held by `ssh-agent` may be used.
+    pub use_agent: bool,
+    /// Hosts to hop through, in order (`-J`).
+    pub jump_hosts: Vec<String>,
+    pub connect_timeout: Option<Duration>,
+}
+
+impl SshOptions {
+    /// Settings for connecting to `host` with OpenSSH's defaults, except
+    /// that unknown host keys are accepted (see [`HostKeyPolicy::AcceptNew`]).
+    pub fn new(host: impl Into<String>) -> Self {
+        Self {
+            host: host.into(),
+            user: None,
+            port: None,
+            host_key_policy: HostKeyPolicy::default(),
+            known_hosts: None,
+            identity_files: Vec::new(),
+            use_agent: true,
+            jump_hosts: Vec::new(),
+            connect_timeout: None,
+        }
+    }
+
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    pub fn host_key_policy(mut self, policy: HostKeyPolicy) -> Self {
+        self.host_key_policy = policy;
+        self
+    }
+
+    pub fn known_hosts(mut self, path: impl Into<PathBuf>) -> Self {
+        self.known_hosts = Some(path.into());
+        self
+    }
+
+    /// Offer this private key; may be called several times.
+    pub fn identity_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.identity_files.push(path.into());
+        self
+    }
+
+    pub fn use_agent(mut self, use_agent: bool) -> Self {
+        self.use_agent = use_agent;
+        self
+    }
+
+    /// Connect through `host` first; may be called several times to chain hops.
+    pub fn jump_host(mut self, host: impl Into<String>) -> Self {
+        self.jump_hosts.push(host.into());
+        self
+    }
+
+    /// Give up if the TCP connection is not established within `timeout`
+    /// (rounded up to whole seconds).
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Settings from a script's `@meta ssh.*` header lines (see
+    /// [`crate::script_meta`]), or `None` if there are none.
+    ///
+    /// `ssh.host` is required; `ssh.user`, `ssh.port`, `ssh.host_key_policy`
+    /// (`strict`, `accept-new`, `insecure`), `ssh.known_hosts`,
+    /// `ssh.use_agent` (`on`/`off`), and `ssh.connect_timeout` (e.g. `10s`)
+    /// set the matching option, and `ssh.identity_file` and `ssh.jump_host`
+    /// may be repeated. Settings of other namespaces are ignored.
+    ///
+    /// ```
+    /// use scriptty::script_meta;
+    /// use scriptty::ssh::SshOptions;
+    ///
+    /// let meta = script_meta("@meta ssh.host=db1\n@meta ssh.user=deploy\n").unwrap();
+    /// let options = SshOptions::from_meta(&meta).unwrap().unwrap();
+    /// assert_eq!(options, SshOptions::new("db1").user("deploy"));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for an unknown `ssh.*` key, an invalid value, or
+    /// settings without `ssh.host`.
+    pub fn from_meta(meta: &[(String, String)]) -> Result<Option<Self>> {
+        let settings: Vec<_> = meta
+            .iter()
+            .filter_map(|(key, value)| Some((key.strip_prefix("ssh.")?, value.as_str())))
+            .collect();
+        if settings.is_empty() {
+            return Ok(None);
+        }
+        let host = settings
+            .iter()
+            .rev()
+            .find(|(key, _)| *key == "host")
+            .map(|(_, host)| *host)
+            .ok_or_else(|| anyhow!("@meta ssh settings require ssh.host"))?;
+        let mut options = Self::new(host);
+        for (key, value) in settings {
+            options = options
+                .apply(key, value)
+                .with_context(|| format!("Invalid @meta ssh.{}: {}", key, value))?;
+        }
+        Ok(Some(options))
+    }
+
+    /// Apply the `@meta ssh.<key>` setting `value`.
+    fn apply(self, key: &str, value: &str) -> Result<Self> {
+        Ok(match key {
+            "host" => self,
+            "user" => self.user(value),
+            "port" => self.port(value.parse()?),
+            "host_key_policy" => self.host_key_policy(value.parse()?),
+            "known_hosts" => self.known_hosts(value),
+            "identity_file" => self.identity_file(value),
+            "use_agent" => match value {
+                "on" => self.use_agent(true),
+                "off" => self.use_agent(false),
+                _ => return Err(anyhow!("expected on or off")),
+            },
+            "jump_host" => self.jump_host(value),
+            "connect_timeout" => self.connect_timeout(parse_duration(value)?),
+            _ => return Err(anyhow!("unknown setting")),
+        })
+    }
+
+    /// Arguments for the `ssh` program, ending with `--` and the destination
+    /// so that a host name starting with `-` cannot be taken for an option.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        let mut option = |value: String| {
+            args.push("-o".to_string());
+            args.push(value);
+        };
+        match self.host_key_policy {
+            HostKeyPolicy::Strict => option("StrictHostKeyChecking=yes".into()),
+            HostKeyPolicy::AcceptNew => option("StrictHostKeyChecking=accept-new".into()),
+            HostKeyPolicy::Insecure => {
+                option("StrictHostKeyChecking=no".into());
+                option("UserKnownHostsFile=/dev/null".into());
+                option("LogLevel=ERROR".into());
+            }
+        }
+        if let Some(path) = &self.known_hosts
+            && self.host_key_policy != HostKeyPolicy::Insecure
+        {
+            option(format!("UserKnownHostsFile={}", path.display()));
+        }
+        if !self.identity_files.is_empty() {
+            option("IdentitiesOnly=yes".into());
+        }
+        if !self.use_agent {
+            option("IdentityAgent=none".into());
+        }
+        if let Some(timeout) = self.connect_timeout {
+            let secs = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+            option(format!("ConnectTimeout={}", secs.max(1)));
+        }
+        for path in &self.identity_files {
+            args.push("-i".to_string());
+            args.push(path.display().to_string());
+        }
+        if !self.jump_hosts.is_empty() {
+            args.push("-J".to_string());
+            args.push(self.jump_hosts.join(","));
+        }
+        if let Some(port) = self.port {
+            args.push("-p".to_string());
+            args.push(port.to_string());
+        }
+        args.push("--".to_string());
+        args.push(match &self.user {
+            Some(user) => format!("{}@{}", user, self.host),
+            None => self.host.clone(),
+        });
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_args() {
+        assert_eq!(
+            SshOptions::new("host").args(),
+            vec!["-o", "StrictHostKeyChecking=accept-new", "--", "host"]
+        );
+    }
+
+    #[test]
+    fn test_full_args() {
+        let args = SshOptions::new("db1")
+            .user("deploy")
+            .port(2222)
+            .host_key_policy(HostKeyPolicy::Strict)
+            .known_hosts("/etc/fleet/known_hosts")
+            .identity_file("/keys/id_ed25519")
+            .use_agent(false)
+            .jump_host("bastion1")
+            .jump_host("bastion2")
+            .connect_timeout(Duration::from_millis(2500))
+            .args();
+        assert_eq!(
+            args,
+            vec![
+                "-o",
+                "StrictHostKeyChecking=yes",
+                "-o",
+                "UserKnownHostsFile=/etc/fleet/known_hosts",
+                "-o",
+                "IdentitiesOnly=yes",
+                "-o",
+                "IdentityAgent=none",
+                "-o",
+                "ConnectTimeout=3",
+                "-i",
+                "/keys/id_ed25519",
+                "-J",
+                "bastion1,bastion2",
+                "-p",
+                "2222",
+                "--",
+                "deploy@db1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_insecure_ignores_known_hosts() {
+        let args = SshOptions::new("vm")
+            .host_key_policy(HostKeyPolicy::Insecure)
+            .known_hosts("/tmp/kh")
+            .args();
+        assert!(args.contains(&"UserKnownHostsFile=/dev/null".to_string()));
+        assert!(!args.contains(&"UserKnownHostsFile=/tmp/kh".to_string()));
+    }
+
+    #[test]
+    fn test_from_meta() {
+        let meta = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        let options = SshOptions::from_meta(&meta(&[
+            ("ssh.host", "db1"),
+            ("ssh.host_key_policy", "strict"),
+            ("ssh.port", "2222"),
+            ("ssh.use_agent", "off"),
+            ("ssh.jump_host", "bastion1"),
+            ("ssh.jump_host", "bastion2"),
+            ("ssh.connect_timeout", "10s"),
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            options,
+            SshOptions::new("db1")
+                .host_key_policy(HostKeyPolicy::Strict)
+                .port(2222)
+                .use_agent(false)
+                .jump_host("bastion1")
+                .jump_host("bastion2")
+                .connect_timeout(Duration::from_secs(10))
+        );
+        assert_eq!(SshOptions::from_meta(&[]).unwrap(), None);
+        assert!(SshOptions::from_meta(&meta(&[("ssh.user", "deploy")])).is_err());
+        assert!(SshOptions::from_meta(&meta(&[("ssh.host", "a"), ("ssh.port", "x")])).is_err());
+        assert!(SshOptions::from_meta(&meta(&[("ssh.host", "a"), ("ssh.color", "x")])).is_err());
+    }
+}
@@ -0,0 +1,416 @@
+//! Running many sessions of one script at once, for load testing.
+//!
+//! [`run`] starts `instances` copies of the program, each driven by its own
+//! run of the script, and keeps restarting them until the test duration is
+//! up. The outcome of every run is folded into a [`StressReport`]: how many
+//! runs passed (and how many of those only on a retry), which errors the
+//! failing ones hit, and how long runs and
+//! their `expect` waits took. Pointed at a client such as `ssh` or `telnet`,
+//! this loads the server behind it.
+
+use crate::engine::Engine;
+use crate::metrics::ExpectMetrics;
+use crate::parser::{parse_duration, parse_file, parse_identifier, script_env};
+use anyhow::{Context as _, Result, anyhow};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// What to run and how hard.
+#[derive(Debug, Clone)]
+pub struct StressOptions {
+    pub script: PathBuf,
+    pub command: String,
+    pub args: Vec<String>,
+    /// Number of sessions running at the same time.
+    pub instances: usize,
+    /// Keep starting new runs until this much time has passed; `None` runs
+    /// each instance once.
+    pub duration: Option<Duration>,
+    /// Script variables set in every session, before `INSTANCE` (the
+    /// session's number, starting at 1).
+    pub variables: Vec<(String, String)>,
+    /// Variable sets for individual sessions: session N gets set N, wrapping
+    /// around when there are more sessions than sets. They override
+    /// [`variables`](Self::variables).
+    pub matrix: Vec<Vec<(String, String)>>,
+    /// Run a failing session again up to this many times before counting it
+    /// as failed.
+    pub retries: u32,
+}
+
+/// Parse a variable matrix from CSV: a header row of variable names, then
+/// one row of values per variable set.
+///
+/// Fields may be double-quoted to contain commas; `""` inside quotes is a
+/// literal quote. Blank lines are skipped.
+///
+/// # Errors
+///
+/// Returns an error for an invalid variable name, a row with the wrong
+/// number of fields, or an unterminated quote.
+pub fn parse_matrix(csv: &str) -> Result<Vec<Vec<(String, String)>>> {
+    let mut rows = csv
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let Some((_, header)) = rows.next() else {
+        return Ok(Vec::new());
+    };
+    let names = split_csv_row(header)?
+        .into_iter()
+        .map(|name| parse_identifier(&name).map(str::to_string))
+        .collect::<Result<Vec<_>>>()?;
+    rows.map(|(index, line)| {
+        let values = split_csv_row(line)?;
+        if values.len() != names.len() {
+            return Err(anyhow!(
+                "Line {}: expected {} values, got {}",
+                index + 1,
+                names.len(),
+                values.len()
+            ));
+        }
+        Ok(names.iter().cloned().zip(values).collect())
+    })
+    .collect()
+}
+
+/// Split one CSV line into its fields.
+fn split_csv_row(line: &str) -> Result<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut chars = line.trim_end_matches('\r').chars().peekable();
+    loop {
+        let mut field = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err(anyhow!("Unterminated quote in: {}", line)),
+                }
+            }
+        }
+        while let Some(c) = chars.next_if(|c| *c != ',') {
+            field.push(c);
+        }
+        fields.push(field.trim().to_string());
+        if chars.next().is_none() {
+            return Ok(fields);
+        }
+    }
+}
+
+/// Results of all runs of a stress test.
+#[derive(Debug, Clone, Default)]
+pub struct StressReport {
+    passed: usize,
+    /// Number of passing runs that failed at least once before.
+    flaky: usize,
+    /// Number of failing runs per error message.
+    failures: BTreeMap<String, usize>,
+    /// How long each run took, passing or not.
+    durations: Vec<Duration>,
+    metrics: ExpectMetrics,
+}
+
+impl StressReport {
+    /// Record a run that took `duration` and ended with `result` on its
+    /// `attempts`-th attempt.
+    pub(crate) fn record(&mut self, duration: Duration, result: &Result<()>, attempts: u32) {
+        self.durations.push(duration);
+        match result {
+            Ok(()) => {
+                self.passed += 1;
+                if attempts > 1 {
+                    self.flaky += 1;
+                }
+            }
+            Err(e) => *self.failures.entry(format!("{:#}", e)).or_default() += 1,
+        }
+    }
+
+    /// Number of runs, passing and failing.
+    pub fn runs(&self) -> usize {
+        self.durations.len()
+    }
+
+    /// Number of failing runs.
+    pub fn failed(&self) -> usize {
+        self.failures.values().sum()
+    }
+
+    /// Number of runs that passed only after being retried.
+    pub fn flaky(&self) -> usize {
+        self.flaky
+    }
+
+    /// Failing runs grouped by error message, ordered by message.
+    pub fn failures(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.failures.iter().map(|(error, n)| (error.as_str(), *n))
+    }
+
+    /// Wait-time statistics of all runs together.
+    pub fn metrics(&self) -> &ExpectMetrics {
+        &self.metrics
+    }
+
+    /// Render the counts, run durations, failures, and expect latencies as a
+    /// human-readable report.
+    pub fn report(&self) -> String {
+        let mut durations = self.durations.clone();
+        durations.sort();
+        let at = |p: f64| match durations.len() {
+            0 => Duration::ZERO,
+            n => durations[((p * n as f64).ceil() as usize).clamp(1, n) - 1],
+        };
+        let mut out = String::from("stress test:\n");
+        let flaky = match self.flaky {
+            0 => String::new(),
+            n => format!(" ({} flaky)", n),
+        };
+        let _ = writeln!(
+            out,
+            "  {} runs, {} passed{}, {} failed",
+            self.runs(),
+            self.passed,
+            flaky,
+            self.failed()
+        );
+        let _ = writeln!(
+            out,
+            "  run time: p50 {}ms, p95 {}ms, max {}ms",
+            at(0.50).as_millis(),
+            at(0.95).as_millis(),
+            durations.last().copied().unwrap_or_default().as_millis()
+        );
+        if !self.failures.is_empty() {
+            out.push_str("failures:\n");
+            for (error, n) in self.failures() {
+                let _ = writeln!(out, "  {:>5}  {}", n, error);
+            }
+        }
+        if !self.metrics.is_empty() {
+            out.push_str(&self.metrics.report());
+        }
+        out
+    }
+}
+
+/// Parse a test duration: `10m` and `2h` on top of the script durations
+/// (`30s`, `500ms`).
+pub fn parse_test_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let (number, unit) = if s.ends_with("ms") {
+        return parse_duration(s);
+    } else if let Some(minutes) = s.strip_suffix('m') {
+        (minutes, 60.0)
+    } else if let Some(hours) = s.strip_suffix('h') {
+        (hours, 3600.0)
+    } else {
+        return parse_duration(s);
+    };
+    let number: f64 = number
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid duration: {}", s))?;
+    Duration::try_from_secs_f64(number * unit).map_err(|_| anyhow!("Invalid duration: {}", s))
+}
+
+/// Run the stress test described by `options` and collect the results.
+///
+/// Program output is discarded. Sessions run concurrently on the current
+/// thread.
+///
+/// # Errors
+///
+/// Returns an error if the script cannot be parsed; failures of individual
+/// runs are only counted in the report.
+pub async fn run(options: &StressOptions) -> Result<StressReport> {
+    // Fail fast on a broken script instead of once per run.
+    parse_file(&options.script)
+        .with_context(|| format!("Failed to parse script file: {}", options.script.display()))?;
+    let report = Rc::new(RefCell::new(StressReport::default()));
+    let deadline = options.duration.map(|d| tokio::time::Instant::now() + d);
+    let local = tokio::task::LocalSet::new();
+    for instance in 1..=options.instances {
+        let options = options.clone();
+        let report = Rc::clone(&report);
+        local.spawn_local(async move {
+            loop {
+                let start = tokio::time::Instant::now();
+                let mut attempts = 1;
+                let (mut result, metrics) = run_once(&options, instance).await;
+                report.borrow_mut().metrics.merge(&metrics);
+                while result.is_err() && attempts <= options.retries {
+                    attempts += 1;
+                    let (retry, metrics) = run_once(&options, instance).await;
+                    result = retry;
+                    report.borrow_mut().metrics.merge(&metrics);
+                }
+                let mut report = report.borrow_mut();
+                report.record(start.elapsed(), &result, attempts);
+                if deadline.is_none_or(|deadline| tokio::time::Instant::now() >= deadline) {
+                    break;
+                }
+            }
+        });
+    }
+    local.await;
+    Ok(Rc::try_unwrap(report)
+        .map(RefCell::into_inner)
+        .unwrap_or_else(|report| report.borrow().clone()))
+}
+
+/// One session: spawn the program, run the script, and stop the program.
+async fn run_once(options: &StressOptions, instance: usize) -> (Result<()>, ExpectMetrics) {
+    // An unreadable script is reported by `parse_file` below.
+    let env = match script_env(&std::fs::read_to_string(&options.script).unwrap_or_default()) {
+        Ok(env) => env,
+        Err(e) => return (Err(e), ExpectMetrics::default()),
+    };
+    let mut builder = Engine::builder(&options.command)
+        .args(&options.args)
+        .handler(|_| {});
+    for (name, value) in env {
+        builder = builder.env(name, value);
+    }
+    let mut engine = match builder.spawn() {
+        Ok(engine) => engine,
+        Err(e) => {
+            return (
+                Err(e.context("Failed to spawn engine")),
+                ExpectMetrics::default(),
+            );
+        }
+    };
+    let row = match options.matrix.len() {
+        0 => &[][..],
+        n => &options.matrix[(instance - 1) % n][..],
+    };
+    for (name, value) in options.variables.iter().chain(row) {
+        engine.set_var(name, value);
+    }
+    engine.set_var("INSTANCE", instance.to_string());
+    let result = match parse_file(&options.script) {
+        Ok(commands) => engine.execute(commands).await,
+        Err(e) => Err(e),
+    };
+    engine.stop();
+    (result, engine.metrics().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let mut report = StressReport::default();
+        report.record(Duration::from_millis(100), &Ok(()), 1);
+        report.record(Duration::from_millis(300), &Err(anyhow!("Timeout")), 1);
+        report.record(Duration::from_millis(200), &Err(anyhow!("Timeout")), 1);
+        assert_eq!(report.runs(), 3);
+        assert_eq!(report.failed(), 2);
+        assert_eq!(report.flaky(), 0);
+        assert_eq!(report.failures().collect::<Vec<_>>(), [("Timeout", 2)]);
+        let text = report.report();
+        assert!(text.contains("3 runs, 1 passed, 2 failed"), "got: {text}");
+        assert!(
+            text.contains("run time: p50 200ms, p95 300ms, max 300ms"),
+            "got: {text}"
+        );
+        assert!(text.contains("      2  Timeout"), "got: {text}");
+    }
+
+    #[test]
+    fn test_report_flaky() {
+        let mut report = StressReport::default();
+        report.record(Duration::from_millis(100), &Ok(()), 2);
+        report.record(Duration::from_millis(100), &Ok(()), 1);
+        assert_eq!(report.flaky(), 1);
+        let text = report.report();
+        assert!(
+            text.contains("2 runs, 2 passed (1 flaky), 0 failed"),
+            "got: {text}"
+        );
+    }
+
+    #[test]
+    fn test_parse_test_duration() {
+        assert_eq!(
+            parse_test_duration("10m").unwrap(),
+            Duration::from_secs(600)
+        );
+        assert_eq!(
+            parse_test_duration("1.5h").unwrap(),
+            Duration::from_secs(5400)
+        );
+        assert_eq!(parse_test_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(
+            parse_test_duration("500ms").unwrap(),
+            Duration::from_millis(500)
+        );
+        assert!(parse_test_duration("10").is_err());
+        assert!(parse_test_duration("xm").is_err());
+        assert!(parse_test_duration("-5m").is_err());
+        assert!(parse_test_duration("infm").is_err());
+        assert!(parse_test_duration("nanh").is_err());
+        assert!(parse_test_duration("1e300h").is_err());
+    }
+
+    #[test]
+    fn test_parse_matrix() {
+        let matrix =
+            parse_matrix("USER, HOST\nalice,a.example\n\n\"bob, jr\",\"say \"\"hi\"\"\"\n")
+                .unwrap();
+        assert_eq!(
+            matrix,
+            [
+                vec![
+                    ("USER".to_string(), "alice".to_string()),
+                    ("HOST".to_string(), "a.example".to_string())
+                ],
+                vec![
+                    ("USER".to_string(), "bob, jr".to_string()),
+                    ("HOST".to_string(), "say \"hi\"".to_string())
+                ],
+            ]
+        );
+        assert!(parse_matrix("USER,HOST\nalice\n").is_err());
+        assert!(parse_matrix("1USER\nalice\n").is_err());
+        assert!(parse_matrix("USER\n\"alice\n").is_err());
+        assert!(parse_matrix("").unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_run() {
+        let dir = std::env::temp_dir().join(format!("scriptty-stress-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("echo.script");
+        std::fs::write(&script, "expect \"ready\"\nexpect \"${WORD}\" 100ms\n").unwrap();
+        let options = StressOptions {
+            script,
+            command: "sh".into(),
+            args: vec!["-c".into(), "echo ready one; sleep 1".into()],
+            instances: 3,
+            duration: None,
+            variables: Vec::new(),
+            matrix: parse_matrix("WORD\none\ntwo\n").unwrap(),
+            retries: 0,
+        };
+        let report = run(&options).await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(report.runs(), 3);
+        // The third instance wraps around to the first variable set.
+        assert_eq!(report.failed(), 1);
+    }
+}
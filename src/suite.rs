@@ -0,0 +1,477 @@
+//! Running a directory of scripts as a test suite.
+//!
+//! [`discover`] finds the `*.scriptty` files under a directory, and [`run`]
+//! runs each against a fresh copy of the program, one after the other. The
+//! caller starts each copy, so a suite runs the program exactly as a single
+//! script would (environment, sandbox, timeouts). A
+//! script passes when it runs to the end without an error; with
+//! [`SuiteOptions::retries`], a failing script is run again, and one that
+//! passes on a later attempt is reported as flaky. The results are
+//! collected in a [`SuiteReport`] with how long each script took, for a CI
+//! job to print and fail on. CI systems that show test results natively
+//! (GitLab, Jenkins) can read the report as JUnit XML or TAP.
+//!
+//! [`run_markdown`] does the same for the scripts embedded in Markdown
+//! documents (see [`crate::markdown`]), so that documentation examples keep
+//! working.
+
+use crate::engine::Engine;
+use crate::markdown;
+use crate::parser::{parse_source, script_env};
+use anyhow::{Context as _, Result};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// File extension of the scripts a suite runs.
+pub const EXTENSION: &str = "scriptty";
+
+/// Where to find the scripts, and what to do around each one.
+#[derive(Debug, Clone, Default)]
+pub struct SuiteOptions {
+    /// Directory searched for scripts, including subdirectories.
+    pub dir: PathBuf,
+    /// Script variables set before each script runs.
+    pub variables: Vec<(String, String)>,
+    /// Run a failing script again up to this many times.
+    pub retries: u32,
+}
+
+/// The outcome of one script.
+#[derive(Debug)]
+pub struct ScriptResult {
+    pub path: PathBuf,
+    /// Time taken by all attempts together.
+    pub duration: Duration,
+    /// The result of the last attempt.
+    pub result: Result<()>,
+    /// The errors of the attempts before the last, which were retried.
+    pub retried: Vec<String>,
+}
+
+impl ScriptResult {
+    /// Whether the script ran to the end without an error.
+    pub fn passed(&self) -> bool {
+        self.result.is_ok()
+    }
+
+    /// Whether the script passed only after failing at least once.
+    pub fn flaky(&self) -> bool {
+        self.passed() && !self.retried.is_empty()
+    }
+
+    /// Number of times the script ran.
+    pub fn attempts(&self) -> usize {
+        self.retried.len() + 1
+    }
+
+    /// One line for the report: status, path, and time taken, followed by
+    /// the error of a failing script, indented. A flaky script's line notes
+    /// the attempt it passed on.
+    pub fn summary(&self) -> String {
+        let mut out = match (self.passed(), self.flaky()) {
+            (true, true) => format!(
+                "FLAKY {} ({}ms, passed on attempt {})\n",
+                self.path.display(),
+                self.duration.as_millis(),
+                self.attempts()
+            ),
+            (passed, _) => format!(
+                "{}  {} ({}ms)\n",
+                if passed { "PASS" } else { "FAIL" },
+                self.path.display(),
+                self.duration.as_millis()
+            ),
+        };
+        if let Err(e) = &self.result {
+            for line in format!("{:#}", e).lines() {
+                let _ = writeln!(out, "      {}", line);
+            }
+        }
+        out
+    }
+}
+
+/// Results of all scripts of a suite, in the order they ran.
+#[derive(Debug, Default)]
+pub struct SuiteReport {
+    pub results: Vec<ScriptResult>,
+}
+
+impl SuiteReport {
+    /// Number of failing scripts.
+    pub fn failed(&self) -> usize {
+        self.results.iter().filter(|r| !r.passed()).count()
+    }
+
+    /// Number of scripts that passed only after being retried.
+    pub fn flaky(&self) -> usize {
+        self.results.iter().filter(|r| r.flaky()).count()
+    }
+
+    /// The counts and total time, and the flaky and failing scripts again so
+    /// they are easy to find at the end of a long log.
+    pub fn report(&self) -> String {
+        let total: Duration = self.results.iter().map(|r| r.duration).sum();
+        let flaky = match self.flaky() {
+            0 => String::new(),
+            n => format!(" ({} flaky)", n),
+        };
+        let mut out = format!(
+            "{} scripts, {} passed{}, {} failed ({}ms)\n",
+            self.results.len(),
+            self.results.len() - self.failed(),
+            flaky,
+            self.failed(),
+            total.as_millis()
+        );
+        for (heading, filter) in [
+            ("flaky", ScriptResult::flaky as fn(&ScriptResult) -> bool),
+            ("failed", |r: &ScriptResult| !r.passed()),
+        ] {
+            let matching: Vec<_> = self.results.iter().filter(|r| filter(r)).collect();
+            if !matching.is_empty() {
+                let _ = writeln!(out, "{}:", heading);
+                for result in matching {
+                    let _ = writeln!(out, "  {}", result.path.display());
+                }
+            }
+        }
+        out
+    }
+
+    /// The results as a JUnit XML test suite, one test case per script.
+    ///
+    /// The failed attempts of a flaky script are listed as `flakyFailure`
+    /// elements, as Maven Surefire writes them.
+    pub fn junit(&self) -> String {
+        let total: Duration = self.results.iter().map(|r| r.duration).sum();
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            out,
+            "<testsuite name=\"scriptty\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">",
+            self.results.len(),
+            self.failed(),
+            total.as_secs_f64()
+        );
+        for result in &self.results {
+            let _ = write!(
+                out,
+                "  <testcase name=\"{}\" classname=\"scriptty\" time=\"{:.3}\"",
+                xml_text(&result.path.display().to_string()),
+                result.duration.as_secs_f64()
+            );
+            match &result.result {
+                Ok(()) if !result.flaky() => out.push_str("/>\n"),
+                Ok(()) => {
+                    out.push_str(">\n");
+                    for error in &result.retried {
+                        let _ = writeln!(
+                            out,
+                            "    <flakyFailure message=\"{}\">{}</flakyFailure>",
+                            xml_text(error.lines().next().unwrap_or_default()),
+                            xml_text(error)
+                        );
+                    }
+                    out.push_str("  </testcase>\n");
+                }
+                Err(e) => {
+                    let error = format!("{:#}", e);
+                    let _ = writeln!(
+                        out,
+                        ">\n    <failure message=\"{}\">{}</failure>\n  </testcase>",
+                        xml_text(error.lines().next().unwrap_or_default()),
+                        xml_text(&error)
+                    );
+                }
+            }
+        }
+        out.push_str("</testsuite>\n");
+        out
+    }
+
+    /// The results in the Test Anything Protocol, one test point per script
+    /// with a failing script's error as diagnostics. A flaky script's test
+    /// point passes, with a diagnostic naming the attempt it passed on.
+    pub fn tap(&self) -> String {
+        let mut out = format!("TAP version 13\n1..{}\n", self.results.len());
+        for (i, result) in self.results.iter().enumerate() {
+            let status = if result.passed() { "ok" } else { "not ok" };
+            let _ = writeln!(out, "{} {} - {}", status, i + 1, result.path.display());
+            if result.flaky() {
+                let _ = writeln!(out, "# flaky: passed on attempt {}", result.attempts());
+            }
+            if let Err(e) = &result.result {
+                for line in format!("{:#}", e).lines() {
+                    let _ = writeln!(out, "# {}", line);
+                }
+            }
+        }
+        out
+    }
+}
+
+/// `text` escaped for XML, without the control characters XML does not allow.
+fn xml_text(text: &str) -> String {
+    let allowed: String = text
+        .chars()
+        .filter(|c| !c.is_control() || matches!(c, '\n' | '\t'))
+        .collect();
+    crate::render::escape_xml(&allowed)
+}
+
+/// The `*.scriptty` files under `dir`, sorted by path.
+///
+/// # Errors
+///
+/// Returns an error if a directory cannot be read.
+pub fn discover(dir: &Path) -> Result<Vec<PathBuf>> {
+    find_files(dir, EXTENSION)
+}
+
+/// The `*.md` files under `dir`, sorted by path.
+///
+/// # Errors
+///
+/// Returns an error if a directory cannot be read.
+pub fn discover_markdown(dir: &Path) -> Result<Vec<PathBuf>> {
+    find_files(dir, "md")
+}
+
+/// The files with `extension` under `dir`, sorted by path.
+fn find_files(dir: &Path, extension: &str) -> Result<Vec<PathBuf>> {
+    let mut scripts = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = std::fs::read_dir(&dir)
+            .with_context(|| format!("Failed to read directory: {}", dir.display()))?;
+        for entry in entries {
+            let path = entry
+                .with_context(|| format!("Failed to read directory: {}", dir.display()))?
+                .path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == extension) {
+                scripts.push(path);
+            }
+        }
+    }
+    scripts.sort();
+    Ok(scripts)
+}
+
+/// Run every script found under `options.dir`, calling `on_result` as each
+/// one finishes.
+///
+/// `spawn` starts the program for each attempt, given the environment set
+/// by the script's `env` header, typically through [`Engine::builder`] with
+/// a handler that discards the output.
+///
+/// # Errors
+///
+/// Returns an error if the scripts cannot be discovered; failures of
+/// individual scripts are only recorded in the report.
+pub async fn run(
+    options: &SuiteOptions,
+    spawn: impl Fn(Vec<(String, String)>) -> Result<Engine>,
+    mut on_result: impl FnMut(&ScriptResult),
+) -> Result<SuiteReport> {
+    let mut report = SuiteReport::default();
+    for path in discover(&options.dir)? {
+        let result =
+            with_retries(options, path.clone(), || run_script(options, &spawn, &path)).await;
+        on_result(&result);
+        report.results.push(result);
+    }
+    Ok(report)
+}
+
+/// Run every `scriptty` block of the Markdown files under `options.dir`,
+/// calling `on_result` as each one finishes. Each block is reported as
+/// `path:line`, with its name if it has one. `spawn` starts the program, as
+/// for [`run`].
+///
+/// # Errors
+///
+/// Returns an error if the files cannot be discovered or read; failures of
+/// individual blocks are only recorded in the report.
+pub async fn run_markdown(
+    options: &SuiteOptions,
+    spawn: impl Fn(Vec<(String, String)>) -> Result<Engine>,
+    mut on_result: impl FnMut(&ScriptResult),
+) -> Result<SuiteReport> {
+    let mut report = SuiteReport::default();
+    for path in discover_markdown(&options.dir)? {
+        let document = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read Markdown file: {}", path.display()))?;
+        for block in markdown::blocks(&document) {
+            let label = match &block.name {
+                Some(name) => format!("{}:{} ({})", path.display(), block.line, name),
+                None => format!("{}:{}", path.display(), block.line),
+            };
+            let script = block.script();
+            let result = with_retries(options, label.into(), || {
+                run_source(options, &spawn, &script, &path)
+            })
+            .await;
+            on_result(&result);
+            report.results.push(result);
+        }
+    }
+    Ok(report)
+}
+
+/// Run `attempt` until it passes, at most `options.retries` more times after
+/// the first failure, and collect the outcome as the result for `path`.
+async fn with_retries<F, Fut>(options: &SuiteOptions, path: PathBuf, mut attempt: F) -> ScriptResult
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<()>>,
+{
+    let start = tokio::time::Instant::now();
+    let mut retried = Vec::new();
+    let mut result = attempt().await;
+    while let Err(e) = &result
+        && retried.len() < options.retries as usize
+    {
+        retried.push(format!("{:#}", e));
+        result = attempt().await;
+    }
+    ScriptResult {
+        path,
+        duration: start.elapsed(),
+        result,
+        retried,
+    }
+}
+
+/// Spawn the program, run one script against it, and stop the program.
+async fn run_script(
+    options: &SuiteOptions,
+    spawn: &impl Fn(Vec<(String, String)>) -> Result<Engine>,
+    path: &Path,
+) -> Result<()> {
+    let source = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read script file: {}", path.display()))?;
+    run_source(options, spawn, &source, path).await
+}
+
+/// Spawn the program, run the script `source` read from `path` against it,
+/// and stop the program.
+async fn run_source(
+    options: &SuiteOptions,
+    spawn: &impl Fn(Vec<(String, String)>) -> Result<Engine>,
+    source: &str,
+    path: &Path,
+) -> Result<()> {
+    let commands = parse_source(source, path)
+        .with_context(|| format!("Failed to parse script file: {}", path.display()))?;
+    let mut engine = spawn(script_env(source)?)?;
+    for (name, value) in &options.variables {
+        engine.set_var(name, value);
+    }
+    let result = engine.execute(commands).await;
+    engine.stop();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_discover() {
+        let dir = std::env::temp_dir().join(format!("scriptty-suite-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("menu")).unwrap();
+        for name in [
+            "login.scriptty",
+            "menu/open.scriptty",
+            "notes.txt",
+            "b.script",
+        ] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        assert_eq!(
+            discover(&dir).unwrap(),
+            [dir.join("login.scriptty"), dir.join("menu/open.scriptty")]
+        );
+        assert!(discover(&dir.join("missing")).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_report() {
+        let report = SuiteReport {
+            results: vec![
+                ScriptResult {
+                    path: "a.scriptty".into(),
+                    duration: Duration::from_millis(120),
+                    result: Ok(()),
+                    retried: Vec::new(),
+                },
+                ScriptResult {
+                    path: "b.scriptty".into(),
+                    duration: Duration::from_millis(80),
+                    result: Err(anyhow!("Timeout waiting for pattern: 'ok'")),
+                    retried: Vec::new(),
+                },
+            ],
+        };
+        assert_eq!(report.failed(), 1);
+        assert_eq!(report.results[0].summary(), "PASS  a.scriptty (120ms)\n");
+        assert_eq!(
+            report.results[1].summary(),
+            "FAIL  b.scriptty (80ms)\n      Timeout waiting for pattern: 'ok'\n"
+        );
+        assert_eq!(
+            report.report(),
+            "2 scripts, 1 passed, 1 failed (200ms)\nfailed:\n  b.scriptty\n"
+        );
+        assert_eq!(
+            report.junit(),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <testsuite name=\"scriptty\" tests=\"2\" failures=\"1\" time=\"0.200\">\n\
+             \x20 <testcase name=\"a.scriptty\" classname=\"scriptty\" time=\"0.120\"/>\n\
+             \x20 <testcase name=\"b.scriptty\" classname=\"scriptty\" time=\"0.080\">\n\
+             \x20   <failure message=\"Timeout waiting for pattern: 'ok'\">Timeout waiting for pattern: 'ok'</failure>\n\
+             \x20 </testcase>\n\
+             </testsuite>\n"
+        );
+        assert_eq!(
+            report.tap(),
+            "TAP version 13\n1..2\nok 1 - a.scriptty\nnot ok 2 - b.scriptty\n# Timeout waiting for pattern: 'ok'\n"
+        );
+        assert_eq!(xml_text("<a & \x1b[1mb\n"), "&lt;a &amp; [1mb\n");
+    }
+
+    #[test]
+    fn test_report_flaky() {
+        let report = SuiteReport {
+            results: vec![ScriptResult {
+                path: "a.scriptty".into(),
+                duration: Duration::from_millis(300),
+                result: Ok(()),
+                retried: vec!["Timeout waiting for pattern: 'ok'".to_string()],
+            }],
+        };
+        assert_eq!(report.flaky(), 1);
+        assert_eq!(
+            report.results[0].summary(),
+            "FLAKY a.scriptty (300ms, passed on attempt 2)\n"
+        );
+        assert_eq!(
+            report.report(),
+            "1 scripts, 1 passed (1 flaky), 0 failed (300ms)\nflaky:\n  a.scriptty\n"
+        );
+        assert!(report.junit().contains(
+            "<testcase name=\"a.scriptty\" classname=\"scriptty\" time=\"0.300\">\n\
+             \x20   <flakyFailure message=\"Timeout waiting for pattern: 'ok'\">"
+        ));
+        assert_eq!(
+            report.tap(),
+            "TAP version 13\n1..1\nok 1 - a.scriptty\n# flaky: passed on attempt 2\n"
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//! Crash-loop detection for respawned programs.
+//!
+//! A script that restarts its program whenever it dies (e.g. `respawn` in a
+//! `while` loop) would otherwise restart a program that crashes on startup
+//! forever. The supervisor remembers when the program exited on its own and
+//! refuses to restart it once too many exits fall within a short window.
+
+use anyhow::{Result, anyhow};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How many exits within how long count as a crash loop.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CrashLoopLimit {
+    /// Number of exits that trigger the limit.
+    pub max_exits: usize,
+    /// Window in which those exits must happen.
+    pub window: Duration,
+}
+
+impl Default for CrashLoopLimit {
+    /// 5 exits within 10 seconds.
+    fn default() -> Self {
+        Self {
+            max_exits: 5,
+            window: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Recent exits of the program, checked against a [`CrashLoopLimit`].
+#[derive(Debug, Clone)]
+pub(crate) struct Supervisor {
+    limit: Option<CrashLoopLimit>,
+    exits: VecDeque<(Instant, u32)>,
+}
+
+impl Supervisor {
+    pub(crate) fn new(limit: Option<CrashLoopLimit>) -> Self {
+        Self {
+            limit,
+            exits: VecDeque::new(),
+        }
+    }
+
+    /// Change the limit; `None` disables detection.
+    pub(crate) fn set_limit(&mut self, limit: Option<CrashLoopLimit>) {
+        self.limit = limit;
+    }
+
+    /// Record that the program exited on its own with `code` at `at`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing the crash loop if this exit reaches the
+    /// limit; the program should not be restarted.
+    pub(crate) fn record_exit(&mut self, code: u32, at: Instant) -> Result<()> {
+        let Some(limit) = self.limit else {
+            return Ok(());
+        };
+        self.exits.push_back((at, code));
+        while let Some((first, _)) = self.exits.front()
+            && at.duration_since(*first) > limit.window
+        {
+            self.exits.pop_front();
+        }
+        if self.exits.len() < limit.max_exits {
+            return Ok(());
+        }
+        let codes: Vec<String> = self.exits.iter().map(|(_, c)| c.to_string()).collect();
+        Err(anyhow!(
+            "Crash loop detected: the program exited {} times within {:?} (exit codes: {}); not restarting it",
+            self.exits.len(),
+            limit.window,
+            codes.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit() -> Option<CrashLoopLimit> {
+        Some(CrashLoopLimit {
+            max_exits: 3,
+            window: Duration::from_secs(10),
+        })
+    }
+
+    #[test]
+    fn test_detects_loop() {
+        let mut supervisor = Supervisor::new(limit());
+        let start = Instant::now();
+        supervisor.record_exit(1, start).unwrap();
+        supervisor
+            .record_exit(1, start + Duration::from_secs(1))
+            .unwrap();
+        let err = supervisor
+            .record_exit(139, start + Duration::from_secs(2))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("3 times"), "got: {err}");
+        assert!(err.contains("1, 1, 139"), "got: {err}");
+    }
+
+    #[test]
+    fn test_old_exits_expire() {
+        let mut supervisor = Supervisor::new(limit());
+        let start = Instant::now();
+        for i in 0..10 {
+            supervisor
+                .record_exit(1, start + Duration::from_secs(6 * i))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut supervisor = Supervisor::new(None);
+        let start = Instant::now();
+        for _ in 0..10 {
+            supervisor.record_exit(1, start).unwrap();
+        }
+    }
+}
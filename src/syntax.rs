@@ -0,0 +1,165 @@
+//! Syntax highlighting definitions for script files, generated from the
+//! command registry.
+//!
+//! Because the keyword lists come from the parser's registries, a command
+//! added there is highlighted without touching the grammars by hand:
+//!
+//! - [`tm_language`] — a TextMate grammar (`.tmLanguage` plist), understood
+//!   by VS Code, Sublime Text, and most other editors,
+//! - [`tree_sitter_grammar`] and [`tree_sitter_highlights`] — a tree-sitter
+//!   `grammar.js` and its `queries/highlights.scm`, for Neovim, Helix, Zed, etc.
+
+use crate::commands::KEY_NAMES;
+use crate::parser::{command_keywords, control_keywords, keywords};
+use std::fmt::Write as _;
+
+/// The TextMate scope name of script files.
+pub const SCOPE_NAME: &str = "source.scriptty";
+
+/// Generate a TextMate grammar in XML property-list form.
+pub fn tm_language() -> String {
+    let commands = alternation(command_keywords());
+    let controls = alternation(control_keywords().chain(["else", "end"]));
+    let keys = alternation(KEY_NAMES.iter().copied());
+    let patterns = [
+        (
+            "comment.line.number-sign.scriptty",
+            r"(?:^|\s)#.*$".to_string(),
+        ),
+        (
+            "keyword.control.scriptty",
+            format!(r"^\s*(?:{})\b", controls),
+        ),
+        (
+            "keyword.other.command.scriptty",
+            format!(r"^\s*(?:{})\b", commands),
+        ),
+        (
+            "constant.numeric.duration.scriptty",
+            r"\b\d+(?:\.\d+)?(?:ms|s)\b".to_string(),
+        ),
+        (
+            "constant.language.modifier.scriptty",
+            r"\b(?:Ctrl|Alt|Shift)\+".to_string(),
+        ),
+        (
+            "constant.language.key.scriptty",
+            format!(r"\b(?:{})\b", keys),
+        ),
+    ];
+
+    let mut out = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" ",
+        "\"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+        "<plist version=\"1.0\">\n<dict>\n",
+        "  <key>name</key><string>scriptty</string>\n",
+        "  <key>fileTypes</key><array><string>script</string><string>scriptty</string></array>\n",
+    ));
+    let _ = writeln!(out, "  <key>scopeName</key><string>{}</string>", SCOPE_NAME);
+    out.push_str("  <key>patterns</key>\n  <array>\n");
+    out.push_str(concat!(
+        "    <dict>\n",
+        "      <key>name</key><string>string.quoted.double.scriptty</string>\n",
+        "      <key>begin</key><string>\"</string>\n",
+        "      <key>end</key><string>\"</string>\n",
+        "      <key>patterns</key>\n      <array>\n",
+        "        <dict><key>name</key><string>constant.character.escape.scriptty</string>",
+        "<key>match</key><string>\\\\.</string></dict>\n",
+        "        <dict><key>name</key><string>variable.other.scriptty</string>",
+        "<key>match</key><string>\\$\\{[^}]*\\}</string></dict>\n",
+        "      </array>\n",
+        "    </dict>\n",
+    ));
+    for (scope, regex) in patterns {
+        let _ = writeln!(
+            out,
+            "    <dict><key>name</key><string>{}</string><key>match</key><string>{}</string></dict>",
+            scope,
+            xml_escape(&regex)
+        );
+    }
+    out.push_str("  </array>\n</dict>\n</plist>\n");
+    out
+}
+
+/// Generate a tree-sitter `grammar.js` for script files.
+pub fn tree_sitter_grammar() -> String {
+    let keywords: Vec<String> = keywords()
+        .chain(["else", "end"])
+        .map(|k| format!("'{}'", k))
+        .collect();
+    let mut out = String::from(
+        "// Generated by `scriptty emit-syntax --format tree-sitter`.\n\
+         module.exports = grammar({\n  \
+         name: 'scriptty',\n  \
+         extras: $ => [/[ \\t]/],\n  \
+         rules: {\n    \
+         source_file: $ => repeat(choice($.statement, $.comment, $._newline)),\n    \
+         statement: $ => seq(field('keyword', $.keyword), repeat($._argument), optional($.comment), $._newline),\n",
+    );
+    let _ = writeln!(out, "    keyword: $ => choice({}),", keywords.join(", "));
+    out.push_str(
+        "    _argument: $ => choice($.string, $.duration, $.word),\n    \
+         string: $ => seq('\"', repeat(choice($.escape, $.variable, /[^\"\\\\$]+/, '$')), '\"'),\n    \
+         escape: $ => /\\\\./,\n    \
+         variable: $ => /\\$\\{[^}]*\\}/,\n    \
+         duration: $ => token(prec(1, /\\d+(\\.\\d+)?(ms|s)/)),\n    \
+         word: $ => /[^\\s\"#]+/,\n    \
+         comment: $ => /#[^\\n]*/,\n    \
+         _newline: $ => /\\r?\\n/,\n  \
+         },\n\
+         });\n",
+    );
+    out
+}
+
+/// The tree-sitter highlight query matching [`tree_sitter_grammar`].
+pub fn tree_sitter_highlights() -> &'static str {
+    concat!(
+        "(keyword) @keyword\n",
+        "(string) @string\n",
+        "(escape) @string.escape\n",
+        "(variable) @variable\n",
+        "(duration) @number\n",
+        "(comment) @comment\n",
+    )
+}
+
+fn alternation<'a>(words: impl Iterator<Item = &'a str>) -> String {
+    words.collect::<Vec<_>>().join("|")
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tm_language_covers_registry() {
+        let grammar = tm_language();
+        assert!(grammar.starts_with("<?xml"));
+        assert!(grammar.contains(SCOPE_NAME));
+        for keyword in keywords() {
+            assert!(grammar.contains(keyword), "missing keyword '{keyword}'");
+        }
+        assert!(grammar.contains("PageDown"));
+    }
+
+    #[test]
+    fn test_tree_sitter_covers_registry() {
+        let grammar = tree_sitter_grammar();
+        for keyword in keywords() {
+            assert!(
+                grammar.contains(&format!("'{keyword}'")),
+                "missing keyword '{keyword}'"
+            );
+        }
+        assert!(tree_sitter_highlights().contains("@keyword"));
+    }
+}
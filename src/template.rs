@@ -0,0 +1,91 @@
+//! Pre-parse templating of scripts (requires the `templates` feature).
+//!
+//! A script can be written as a [MiniJinja](https://docs.rs/minijinja) template
+//! and rendered with values from a YAML data file before it is parsed, so one
+//! template can generate per-environment scripts:
+//!
+//! ```text
+//! {% for host in hosts %}
+//! send "ssh {{ host }}"
+//! key Enter
+//! expect "$ "
+//! {% endfor %}
+//! ```
+//!
+//! Template expressions (`{{ ... }}`, `{% ... %}`) are evaluated once, before
+//! parsing; script variables (`${NAME}`) are still expanded at run time.
+
+use anyhow::{Context, Result, anyhow};
+use minijinja::{Environment, UndefinedBehavior};
+use std::path::Path;
+
+/// Read template data from a YAML (or JSON) file.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or is not valid YAML.
+pub fn load_data(path: impl AsRef<Path>) -> Result<serde_yaml::Value> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read template data: {}", path.display()))?;
+    serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse template data: {}", path.display()))
+}
+
+/// Render a script template with `data`.
+///
+/// Block tags are stripped together with their line, so loops and conditionals
+/// do not leave blank lines behind. Referencing a value missing from `data`
+/// is an error rather than an empty string.
+///
+/// # Errors
+///
+/// Returns an error if the template is malformed or references undefined values.
+///
+/// # Example
+///
+/// ```
+/// use scriptty::template::render;
+///
+/// let data = serde_yaml::from_str("hosts: [web1, web2]").unwrap();
+/// let script = render("{% for h in hosts %}\nsend \"ssh {{ h }}\"\n{% endfor %}\n", &data).unwrap();
+/// assert_eq!(script, "send \"ssh web1\"\nsend \"ssh web2\"\n");
+/// ```
+pub fn render(template: &str, data: &serde_yaml::Value) -> Result<String> {
+    let mut env = Environment::new();
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.set_trim_blocks(true);
+    env.set_lstrip_blocks(true);
+    env.set_keep_trailing_newline(true);
+    env.render_str(template, data)
+        .map_err(|e| anyhow!("{:#}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(yaml: &str) -> serde_yaml::Value {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_render_conditionals() {
+        let template = "{% if env == \"prod\" %}\nexpect \"Are you sure?\"\n{% endif %}\ntype \"{{ host }}\"\n";
+        let rendered = render(template, &data("env: prod\nhost: db1")).unwrap();
+        assert_eq!(rendered, "expect \"Are you sure?\"\ntype \"db1\"\n");
+        let rendered = render(template, &data("env: dev\nhost: db2")).unwrap();
+        assert_eq!(rendered, "type \"db2\"\n");
+    }
+
+    #[test]
+    fn test_runtime_variables_untouched() {
+        let rendered = render("send \"${USER}\"\n", &data("{}")).unwrap();
+        assert_eq!(rendered, "send \"${USER}\"\n");
+    }
+
+    #[test]
+    fn test_undefined_value() {
+        assert!(render("type \"{{ missing }}\"\n", &data("{}")).is_err());
+    }
+}
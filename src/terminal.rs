@@ -0,0 +1,213 @@
+//! How the program's terminal is set up before it starts.
+//!
+//! Some programs look at more than the terminal's size in rows and columns.
+//! Programs that draw images (sixel, the kitty graphics protocol) size them
+//! from the window's size in pixels, which `TIOCGWINSZ` reports alongside
+//! the rows and columns and is zero unless set. Others behave differently
+//! depending on the line settings they start with, e.g. whether the terminal
+//! echoes input. [`TerminalSettings`] sets both up before the program starts;
+//! the pixel size follows later resizes.
+//!
+//! Line settings are written as for `stty`: a flag name turns the flag on,
+//! a leading `-` turns it off, e.g. `-echo -onlcr`. `raw` switches to raw
+//! mode (no line editing, echo, signals, or output processing).
+
+use anyhow::{Context as _, Result, anyhow};
+
+/// Terminal settings for the program; the defaults leave the terminal as
+/// the operating system sets it up.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TerminalSettings {
+    /// Line settings applied in order before the program starts.
+    pub termios: Vec<TermiosSetting>,
+    /// Size of one character cell as `(width, height)` in pixels; the window
+    /// size in pixels is reported as this times the rows and columns.
+    pub cell_size: Option<(u16, u16)>,
+}
+
+/// One `stty`-style line setting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TermiosSetting {
+    /// Raw mode, as set by `cfmakeraw`.
+    Raw,
+    /// Turn a flag, named as for `stty`, on or off.
+    Flag { name: &'static str, on: bool },
+}
+
+/// Which flag word of `termios` a flag is in.
+#[derive(Debug, Clone, Copy)]
+enum FlagWord {
+    Input,
+    Output,
+    Local,
+}
+
+/// The flags that can be set, with their `stty` names.
+const FLAGS: &[(&str, FlagWord, libc::tcflag_t)] = &[
+    ("icrnl", FlagWord::Input, libc::ICRNL),
+    ("inlcr", FlagWord::Input, libc::INLCR),
+    ("igncr", FlagWord::Input, libc::IGNCR),
+    ("ixon", FlagWord::Input, libc::IXON),
+    ("ixoff", FlagWord::Input, libc::IXOFF),
+    ("istrip", FlagWord::Input, libc::ISTRIP),
+    ("iutf8", FlagWord::Input, libc::IUTF8),
+    ("opost", FlagWord::Output, libc::OPOST),
+    ("onlcr", FlagWord::Output, libc::ONLCR),
+    ("ocrnl", FlagWord::Output, libc::OCRNL),
+    ("echo", FlagWord::Local, libc::ECHO),
+    ("echoe", FlagWord::Local, libc::ECHOE),
+    ("echok", FlagWord::Local, libc::ECHOK),
+    ("echonl", FlagWord::Local, libc::ECHONL),
+    ("icanon", FlagWord::Local, libc::ICANON),
+    ("isig", FlagWord::Local, libc::ISIG),
+    ("iexten", FlagWord::Local, libc::IEXTEN),
+];
+
+impl TerminalSettings {
+    /// Whether the settings change nothing.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The window size in pixels, as `(width, height)`, of a `rows` x `cols`
+    /// terminal; zero when no cell size is set.
+    pub(crate) fn pixel_size(&self, rows: u16, cols: u16) -> (u16, u16) {
+        self.cell_size.map_or((0, 0), |(width, height)| {
+            (cols.saturating_mul(width), rows.saturating_mul(height))
+        })
+    }
+
+    /// Apply the line settings to the terminal open at `fd`.
+    pub(crate) fn apply_termios(&self, fd: libc::c_int) -> Result<()> {
+        if self.termios.is_empty() {
+            return Ok(());
+        }
+        // SAFETY: termios is plain data; tcgetattr fills it in.
+        let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+        // SAFETY: termios is a valid, writable termios struct.
+        if unsafe { libc::tcgetattr(fd, &mut termios) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .context("Failed to read the terminal settings");
+        }
+        for setting in &self.termios {
+            match *setting {
+                // SAFETY: termios is a valid termios struct.
+                TermiosSetting::Raw => unsafe { libc::cfmakeraw(&mut termios) },
+                TermiosSetting::Flag { name, on } => {
+                    let (_, word, bit) = FLAGS
+                        .iter()
+                        .find(|(flag, _, _)| *flag == name)
+                        .expect("flag names come from FLAGS");
+                    let flags = match word {
+                        FlagWord::Input => &mut termios.c_iflag,
+                        FlagWord::Output => &mut termios.c_oflag,
+                        FlagWord::Local => &mut termios.c_lflag,
+                    };
+                    if on {
+                        *flags |= bit;
+                    } else {
+                        *flags &= !bit;
+                    }
+                }
+            }
+        }
+        // SAFETY: termios is a valid termios struct.
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &termios) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .context("Failed to change the terminal settings");
+        }
+        Ok(())
+    }
+}
+
+/// Parse `stty`-style line settings separated by spaces, e.g. `-echo raw`.
+///
+/// # Errors
+///
+/// Returns an error naming the first setting that is not supported.
+pub fn parse_termios(spec: &str) -> Result<Vec<TermiosSetting>> {
+    spec.split_whitespace()
+        .map(|word| {
+            if word == "raw" {
+                return Ok(TermiosSetting::Raw);
+            }
+            let (name, on) = match word.strip_prefix('-') {
+                Some(name) => (name, false),
+                None => (word, true),
+            };
+            FLAGS
+                .iter()
+                .find(|(flag, _, _)| *flag == name)
+                .map(|(name, _, _)| TermiosSetting::Flag { name, on })
+                .ok_or_else(|| {
+                    let names: Vec<&str> = FLAGS.iter().map(|(name, _, _)| *name).collect();
+                    anyhow!(
+                        "Unsupported terminal setting: {} (supported: raw, {})",
+                        word,
+                        names.join(", ")
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Parse a cell size in pixels written as `WIDTHxHEIGHT`, e.g. `10x20`.
+///
+/// # Errors
+///
+/// Returns an error if `s` is not two positive numbers separated by `x`.
+pub fn parse_cell_size(s: &str) -> Result<(u16, u16)> {
+    let invalid = || anyhow!("Invalid cell size, expected WIDTHxHEIGHT in pixels: {}", s);
+    let (width, height) = s.trim().split_once('x').ok_or_else(invalid)?;
+    let width: u16 = width.parse().map_err(|_| invalid())?;
+    let height: u16 = height.parse().map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    Ok((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_termios() {
+        assert_eq!(
+            parse_termios(" -echo raw  onlcr").unwrap(),
+            [
+                TermiosSetting::Flag {
+                    name: "echo",
+                    on: false
+                },
+                TermiosSetting::Raw,
+                TermiosSetting::Flag {
+                    name: "onlcr",
+                    on: true
+                },
+            ]
+        );
+        assert!(parse_termios("").unwrap().is_empty());
+        let err = parse_termios("-echo -cbreak").unwrap_err().to_string();
+        assert!(err.contains("-cbreak"), "got: {err}");
+    }
+
+    #[test]
+    fn test_parse_cell_size() {
+        assert_eq!(parse_cell_size("10x20").unwrap(), (10, 20));
+        assert!(parse_cell_size("10").is_err());
+        assert!(parse_cell_size("0x20").is_err());
+        assert!(parse_cell_size("tenx20").is_err());
+    }
+
+    #[test]
+    fn test_pixel_size() {
+        let settings = TerminalSettings {
+            cell_size: Some((10, 20)),
+            ..TerminalSettings::default()
+        };
+        assert_eq!(settings.pixel_size(24, 80), (800, 480));
+        assert_eq!(settings.pixel_size(u16::MAX, 80), (800, u16::MAX));
+        assert_eq!(TerminalSettings::default().pixel_size(24, 80), (0, 0));
+    }
+}
@@ -0,0 +1,261 @@
+//! Golden-file assertions for tests of terminal programs.
+//!
+//! A golden test runs a program, then compares what it printed with a file
+//! checked in next to the test:
+//!
+//! ```no_run
+//! use scriptty::{Engine, parse_str};
+//! use scriptty::testing::assert_matches_golden;
+//!
+//! #[tokio::test]
+//! async fn login() -> anyhow::Result<()> {
+//!     let mut engine = Engine::spawn_with_handler("./app", &[] as &[&str], |_| {})?;
+//!     engine.execute(parse_str(r#"expect "login: ""#)?).await?;
+//!     assert_matches_golden(engine.output(), "tests/golden/login.txt");
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Output is normalized before it is compared, so the golden file holds
+//! readable text: escape sequences are removed and line endings become `\n`.
+//! Output that changes from run to run (timestamps, process ids) can be
+//! masked with a [`Golden`] built with more normalizers.
+//!
+//! When the program's output changes on purpose, run the tests with
+//! `SCRIPTTY_UPDATE_SNAPSHOTS=1` to rewrite the golden files (and create
+//! missing ones) instead of comparing against them.
+//!
+//! The `snapshot` script command stores its screens the same way, with the
+//! same normalization and the same switch (`--update-snapshots` on the
+//! command line).
+
+use crate::commands::SnapshotMode;
+use crate::diff::{ScriptLine, diff_lines};
+use anyhow::{Context as _, Result, anyhow};
+use regex::Regex;
+use std::path::Path;
+
+/// Set this environment variable (to anything but `0`) to write golden
+/// files and `snapshot` screens instead of comparing against them.
+pub const UPDATE_ENV: &str = "SCRIPTTY_UPDATE_SNAPSHOTS";
+
+type Normalizer = Box<dyn Fn(&str) -> String>;
+
+/// A golden-file comparison with its normalizers, applied in the order they
+/// were added after the default ones (escape sequences and line endings).
+#[derive(Default)]
+pub struct Golden {
+    normalizers: Vec<Normalizer>,
+}
+
+impl Golden {
+    /// A comparison with only the default normalization.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace dates and times of day (`2024-05-01 12:30:00`,
+    /// `2024-05-01T12:30:00.123Z`, `12:30:00`) with `<TIME>`.
+    pub fn mask_timestamps(self) -> Self {
+        self.replace(
+            r"\d{4}-\d{2}-\d{2}([T ]\d{2}:\d{2}:\d{2}(\.\d+)?(Z|[+-]\d{2}:?\d{2})?)?|\b\d{1,2}:\d{2}:\d{2}(\.\d+)?\b",
+            "<TIME>",
+        )
+        .expect("the timestamp pattern is valid")
+    }
+
+    /// Replace process ids with `<PID>`: numbers after `pid` (`pid 42`,
+    /// `PID: 42`, `pid=42`) and in syslog-style `name[42]` tags.
+    pub fn mask_pids(self) -> Self {
+        self.replace(r"(?i)\b(pid[ =:]*)\d+", "${1}<PID>")
+            .expect("the pid pattern is valid")
+            .replace(r"(\w)\[\d+\]", "${1}[<PID>]")
+            .expect("the pid pattern is valid")
+    }
+
+    /// Replace every match of the regex `pattern` with `replacement`, which
+    /// may refer to groups as `$1` or `${name}`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `pattern` is not a valid regex.
+    pub fn replace(mut self, pattern: &str, replacement: &str) -> Result<Self> {
+        let regex = Regex::new(pattern).with_context(|| format!("Invalid pattern: {}", pattern))?;
+        let replacement = replacement.to_string();
+        self.normalizers.push(Box::new(move |text| {
+            regex.replace_all(text, replacement.as_str()).into_owned()
+        }));
+        Ok(self)
+    }
+
+    /// Add a custom normalizer.
+    pub fn normalize(mut self, normalizer: impl Fn(&str) -> String + 'static) -> Self {
+        self.normalizers.push(Box::new(normalizer));
+        self
+    }
+
+    /// `output` as it is compared with a golden file.
+    pub fn normalized(&self, output: &str) -> String {
+        let mut text = plain_text(output);
+        for normalizer in &self.normalizers {
+            text = normalizer(&text);
+        }
+        text
+    }
+
+    /// Compare `output`, normalized, with the golden file at `path`, or
+    /// with [`UPDATE_ENV`] set, write it there.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error with a line diff if the output does not match, or
+    /// if the golden file cannot be read or written.
+    pub fn check(&self, output: &str, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let name = format!("golden file {}", path.display());
+        self.compare(output, path, SnapshotMode::from_env(), &name)
+    }
+
+    /// Compare `output`, normalized, with the file at `path`, or write it
+    /// there, as `mode` says. `name` describes the file in errors.
+    pub(crate) fn compare(
+        &self,
+        output: &str,
+        path: &Path,
+        mode: SnapshotMode,
+        name: &str,
+    ) -> Result<()> {
+        let actual = self.normalized(output);
+        if mode == SnapshotMode::Record {
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+            }
+            return std::fs::write(path, actual)
+                .with_context(|| format!("Failed to write {}", name));
+        }
+        let golden = std::fs::read_to_string(path).with_context(|| {
+            format!(
+                "Failed to read {} (record it with --update-snapshots or {}=1)",
+                name, UPDATE_ENV
+            )
+        })?;
+        let changes = diff_lines(&lines(&golden), &lines(&actual));
+        if !changes.is_empty() {
+            return Err(anyhow!(
+                "Output does not match {} (update it with --update-snapshots or {}=1); - stored, + actual:\n{}",
+                name,
+                UPDATE_ENV,
+                changes
+            ));
+        }
+        Ok(())
+    }
+
+    /// Like [`Golden::check`], but panic on a mismatch, for use in tests.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the output does not match or the golden file cannot be
+    /// read or written.
+    #[track_caller]
+    pub fn assert(&self, output: impl AsRef<str>, path: impl AsRef<Path>) {
+        if let Err(e) = self.check(output.as_ref(), path) {
+            panic!("{:#}", e);
+        }
+    }
+}
+
+/// Assert that `output` matches the golden file at `path`, after removing
+/// escape sequences and normalizing line endings; see the [module
+/// docs](self).
+///
+/// # Panics
+///
+/// Panics if the output does not match or the golden file cannot be read or
+/// written.
+#[track_caller]
+pub fn assert_matches_golden(output: impl AsRef<str>, path: impl AsRef<Path>) {
+    Golden::new().assert(output, path);
+}
+
+/// `output` without escape sequences and with `\n` line endings; a bare
+/// carriage return starts the line over, as on a terminal. Trailing blanks
+/// are removed from each line.
+pub(crate) fn plain_text(output: &str) -> String {
+    let (plain, _) = crate::ansi::strip(output);
+    let mut text = String::with_capacity(plain.len());
+    for (i, line) in plain.split('\n').enumerate() {
+        if i > 0 {
+            text.push('\n');
+        }
+        let line = line
+            .trim_end_matches('\r')
+            .rsplit('\r')
+            .next()
+            .unwrap_or_default();
+        text.push_str(line.trim_end());
+    }
+    text
+}
+
+/// Lines numbered from 1, for diffing.
+fn lines(text: &str) -> Vec<ScriptLine> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| ScriptLine {
+            line: i + 1,
+            text: line.to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalized() {
+        let golden = Golden::new().mask_timestamps().mask_pids();
+        assert_eq!(
+            golden.normalized(
+                "\x1b[32mok\x1b[0m  \r\nloading\rdone\r\n2024-05-01T12:30:00.5Z sshd[4321]: started, pid=77 at 9:05:01\r\n"
+            ),
+            "ok\ndone\n<TIME> sshd[<PID>]: started, pid=<PID> at <TIME>\n"
+        );
+        let custom = Golden::new()
+            .replace(r"v\d+\.\d+", "vX")
+            .unwrap()
+            .normalize(|text| text.to_uppercase());
+        assert_eq!(custom.normalized("tool v1.2\n"), "TOOL VX\n");
+        assert!(Golden::new().replace("(", "").is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let dir = std::env::temp_dir().join(format!("scriptty-golden-{}", std::process::id()));
+        let path = dir.join("nested/login.txt");
+        let _ = std::fs::remove_dir_all(&dir);
+        let err = Golden::new().check("login: ", &path).unwrap_err();
+        assert!(format!("{:#}", err).contains(UPDATE_ENV));
+
+        Golden::new()
+            .compare("Welcome\r\nlogin: ", &path, SnapshotMode::Record, "login")
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "Welcome\nlogin:");
+        assert!(
+            Golden::new()
+                .check("\x1b[1mWelcome\x1b[0m\r\nlogin: ", &path)
+                .is_ok()
+        );
+        let err = Golden::new()
+            .check("Welcome back\r\nlogin: ", &path)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("-    1: Welcome\n+    1: Welcome back"),
+            "got: {err}"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,169 @@
+//! Spans for the commands and waits of a run.
+//!
+//! When tracing is enabled (see [`crate::Engine::enable_tracing`]), each
+//! top-level script command becomes a span, and each wait of an
+//! `expect`-style command becomes a child span of its command, with the
+//! pattern, the wait time, and whether the pattern was found. All spans
+//! belong to one trace under a root span for the whole run. With the `otlp`
+//! cargo feature, [`crate::otlp`] sends the trace to an OpenTelemetry
+//! collector.
+
+use std::time::{Duration, SystemTime};
+
+/// A value of a span attribute.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValue {
+    String(String),
+    Int(i64),
+}
+
+/// A finished span.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub span_id: [u8; 8],
+    /// The parent span; `None` for the root span of the run.
+    pub parent_id: Option<[u8; 8]>,
+    pub name: String,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub attributes: Vec<(String, AttributeValue)>,
+    /// The error message, if the command failed or the wait timed out.
+    pub error: Option<String>,
+}
+
+/// The spans of one run.
+#[derive(Debug, Clone)]
+pub struct Trace {
+    pub trace_id: [u8; 16],
+    /// Id of the root span covering the whole run.
+    pub root_id: [u8; 8],
+    /// When tracing was enabled; the start of the root span.
+    pub start: SystemTime,
+    spans: Vec<Span>,
+    /// The command running now, the parent of waits.
+    current: Option<[u8; 8]>,
+}
+
+impl Trace {
+    pub(crate) fn new() -> Self {
+        Self {
+            trace_id: rand::random(),
+            root_id: rand::random(),
+            start: SystemTime::now(),
+            spans: Vec::new(),
+            current: None,
+        }
+    }
+
+    /// The spans finished so far, in the order they finished; the root span
+    /// is the last one once the run is over.
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    /// Start the span of a command and return its id.
+    pub(crate) fn begin_command(&mut self) -> [u8; 8] {
+        let id = rand::random();
+        self.current = Some(id);
+        id
+    }
+
+    /// Finish the span of the command begun last, `name`, which ran for
+    /// `duration` and failed with `error`, if any.
+    pub(crate) fn end_command(&mut self, name: &str, duration: Duration, error: Option<String>) {
+        let Some(id) = self.current.take() else {
+            return;
+        };
+        let end = SystemTime::now();
+        self.spans.push(Span {
+            span_id: id,
+            parent_id: Some(self.root_id),
+            name: name.to_string(),
+            start: end - duration,
+            end,
+            attributes: vec![(
+                "scriptty.command".to_string(),
+                AttributeValue::String(name.to_string()),
+            )],
+            error,
+        });
+    }
+
+    /// Record a wait for `pattern` that took `wait` and found it if `matched`.
+    pub(crate) fn record_wait(&mut self, pattern: &str, wait: Duration, matched: bool) {
+        let end = SystemTime::now();
+        self.spans.push(Span {
+            span_id: rand::random(),
+            parent_id: Some(self.current.unwrap_or(self.root_id)),
+            name: "expect".to_string(),
+            start: end - wait,
+            end,
+            attributes: vec![
+                (
+                    "scriptty.pattern".to_string(),
+                    AttributeValue::String(pattern.to_string()),
+                ),
+                (
+                    "scriptty.wait_ms".to_string(),
+                    AttributeValue::Int(wait.as_millis() as i64),
+                ),
+                (
+                    "scriptty.outcome".to_string(),
+                    AttributeValue::String(if matched { "matched" } else { "timeout" }.into()),
+                ),
+            ],
+            error: (!matched).then(|| format!("Timeout waiting for '{}'", pattern)),
+        });
+    }
+
+    /// Finish the root span of the run, named `name`, which failed with
+    /// `error`, if any.
+    pub(crate) fn end_run(&mut self, name: &str, command_line: &str, error: Option<String>) {
+        self.spans.push(Span {
+            span_id: self.root_id,
+            parent_id: None,
+            name: name.to_string(),
+            start: self.start,
+            end: SystemTime::now(),
+            attributes: vec![(
+                "scriptty.command_line".to_string(),
+                AttributeValue::String(command_line.to_string()),
+            )],
+            error,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spans() {
+        let mut trace = Trace::new();
+        trace.record_wait("early", Duration::from_millis(5), true);
+        let id = trace.begin_command();
+        trace.record_wait("$ ", Duration::from_millis(20), false);
+        trace.end_command("expect", Duration::from_millis(30), Some("boom".into()));
+
+        let spans = trace.spans();
+        assert_eq!(spans.len(), 3);
+        assert_eq!(spans[0].parent_id, Some(trace.root_id));
+        assert_eq!(spans[1].parent_id, Some(id));
+        assert_eq!(spans[1].name, "expect");
+        assert!(spans[1].attributes.contains(&(
+            "scriptty.outcome".to_string(),
+            AttributeValue::String("timeout".into())
+        )));
+        assert!(
+            spans[1]
+                .attributes
+                .contains(&("scriptty.wait_ms".to_string(), AttributeValue::Int(20)))
+        );
+        assert!(spans[1].error.is_some());
+        assert_eq!(spans[2].span_id, id);
+        assert_eq!(spans[2].parent_id, Some(trace.root_id));
+        assert_eq!(spans[2].error.as_deref(), Some("boom"));
+        assert!(spans[2].end.duration_since(spans[2].start).unwrap() >= Duration::from_millis(30));
+    }
+}
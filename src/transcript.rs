@@ -0,0 +1,659 @@
+//! Plain-text transcripts with timestamps.
+//!
+//! A [`TranscriptWriter`] writes the program's output line by line, without
+//! escape sequences, each line prefixed with the time since the transcript
+//! started:
+//!
+//! ```text
+//! [00:00:00.112] Welcome to the server
+//! [00:00:00.113] login:
+//! [00:00:01.530] > alice
+//! ```
+//!
+//! Lines starting with `> ` after the timestamp are input sent to the
+//! program, when input is included. Unlike a cast, a transcript is meant to
+//! be read (or searched) as is, e.g. as a CI artifact.
+//!
+//! A [`Transcript`] is the replayable form: the session's output, input,
+//! resizes, and markers as timestamped events, as kept by the engine
+//! ([`Engine::transcript`](crate::Engine::transcript)) and saved to disk as
+//! an asciinema v2 cast. It can be cut at a point in time or between two
+//! markers, and rendered to a screen.
+
+use crate::recorder::take_complete_utf8;
+use anyhow::{Context as _, Result, anyhow};
+use serde_json::{Value, json};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Bytes of output the engine keeps in its [`Transcript`]; older events are
+/// dropped, as from [`Engine::output`](crate::Engine::output).
+const LOG_LIMIT: usize = 1 << 20;
+
+/// Most cells a replayed terminal may have, so a damaged cast can't make
+/// rendering it allocate gigabytes.
+const MAX_CELLS: u32 = 1 << 20;
+
+/// Whether a `cols` x `rows` terminal can be replayed: it has at least one
+/// row and column, and at most [`MAX_CELLS`] cells.
+pub(crate) fn is_valid_size(cols: u16, rows: u16) -> bool {
+    cols > 0 && rows > 0 && u32::from(cols) * u32::from(rows) <= MAX_CELLS
+}
+
+/// Writes output (and optionally input) as timestamped plain-text lines.
+pub struct TranscriptWriter {
+    out: Box<dyn Write + Send>,
+    start: Instant,
+    include_input: bool,
+    /// Output of the line not yet finished, and when it started.
+    output: Option<(Vec<u8>, Duration)>,
+    /// Input of the line not yet submitted, and when it started.
+    input: Option<(Vec<u8>, Duration)>,
+}
+
+impl TranscriptWriter {
+    /// Create the transcript file at `path`, including the program's input
+    /// if `include_input` is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be created.
+    pub fn create(path: impl AsRef<Path>, include_input: bool) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create transcript: {}", path.display()))?;
+        Ok(Self::new(BufWriter::new(file), include_input))
+    }
+
+    /// Write a transcript to `out`, including the program's input if
+    /// `include_input` is set.
+    pub fn new(out: impl Write + Send + 'static, include_input: bool) -> Self {
+        Self {
+            out: Box::new(out),
+            start: Instant::now(),
+            include_input,
+            output: None,
+            input: None,
+        }
+    }
+
+    /// Record a chunk of program output.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a finished line cannot be written.
+    pub fn output(&mut self, data: &[u8]) -> Result<()> {
+        for &byte in data {
+            if byte == b'\n' {
+                let (line, at) = self.output.take().unwrap_or((Vec::new(), self.elapsed()));
+                self.write_line(at, "", &line)?;
+            } else {
+                let elapsed = self.elapsed();
+                self.output
+                    .get_or_insert_with(|| (Vec::new(), elapsed))
+                    .0
+                    .push(byte);
+            }
+        }
+        self.out.flush().context("Failed to write transcript")
+    }
+
+    /// Record bytes sent to the program; a line is written once it is
+    /// submitted with Enter.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a finished line cannot be written.
+    pub fn input(&mut self, data: &[u8]) -> Result<()> {
+        if !self.include_input {
+            return Ok(());
+        }
+        for &byte in data {
+            if byte == b'\r' || byte == b'\n' {
+                if let Some((line, at)) = self.input.take() {
+                    self.write_line(at, "> ", &line)?;
+                }
+            } else {
+                let elapsed = self.elapsed();
+                self.input
+                    .get_or_insert_with(|| (Vec::new(), elapsed))
+                    .0
+                    .push(byte);
+            }
+        }
+        self.out.flush().context("Failed to write transcript")
+    }
+
+    /// Write a line about the run that is neither output nor input, e.g.
+    /// where an image the program drew was saved.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the line cannot be written.
+    pub fn note(&mut self, text: &str) -> Result<()> {
+        let at = self.elapsed();
+        self.write_line(at, "", text.as_bytes())?;
+        self.out.flush().context("Failed to write transcript")
+    }
+
+    /// Write out unfinished lines, e.g. a prompt the program is waiting at.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the lines cannot be written.
+    pub fn finish(&mut self) -> Result<()> {
+        if let Some((line, at)) = self.output.take() {
+            self.write_line(at, "", &line)?;
+        }
+        if let Some((line, at)) = self.input.take() {
+            self.write_line(at, "> ", &line)?;
+        }
+        self.out.flush().context("Failed to write transcript")
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn write_line(&mut self, at: Duration, prefix: &str, line: &[u8]) -> Result<()> {
+        let text = String::from_utf8_lossy(line);
+        let (plain, _) = crate::ansi::strip(&text);
+        // A carriage return starts the line over; keep what is left visible.
+        let plain = plain
+            .trim_end_matches('\r')
+            .rsplit('\r')
+            .next()
+            .unwrap_or_default();
+        let millis = at.as_millis();
+        writeln!(
+            self.out,
+            "[{:02}:{:02}:{:02}.{:03}] {}{}",
+            millis / 3_600_000,
+            millis / 60_000 % 60,
+            millis / 1000 % 60,
+            millis % 1000,
+            prefix,
+            plain.trim_end()
+        )
+        .context("Failed to write transcript")
+    }
+}
+
+impl Drop for TranscriptWriter {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// An event of a [`Transcript`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum TranscriptEvent {
+    /// The program printed `data` at `time` from the start.
+    Output { time: Duration, data: String },
+    /// The script sent `data` to the program at `time`.
+    Input { time: Duration, data: String },
+    /// The terminal was resized to `cols` x `rows` at `time`.
+    Resize {
+        time: Duration,
+        cols: u16,
+        rows: u16,
+    },
+    /// The script reached the `marker` called `name` at `time`.
+    Marker { time: Duration, name: String },
+}
+
+impl TranscriptEvent {
+    /// When the event happened, from the start of the transcript.
+    pub fn time(&self) -> Duration {
+        match self {
+            Self::Output { time, .. }
+            | Self::Input { time, .. }
+            | Self::Resize { time, .. }
+            | Self::Marker { time, .. } => *time,
+        }
+    }
+
+    fn with_time(&self, time: Duration) -> Self {
+        let mut event = self.clone();
+        match &mut event {
+            Self::Output { time: t, .. }
+            | Self::Input { time: t, .. }
+            | Self::Resize { time: t, .. }
+            | Self::Marker { time: t, .. } => *t = time,
+        }
+        event
+    }
+}
+
+/// A session as timestamped events, on a terminal of `width` x `height` at
+/// the start.
+///
+/// On disk this is an asciinema v2 cast with `"o"` (output), `"i"` (input),
+/// `"r"` (resize), and `"m"` (marker) events, so it plays with `asciinema
+/// play` and loads as a [`Cast`](crate::recorder::Cast) as well.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transcript {
+    pub width: u16,
+    pub height: u16,
+    pub events: Vec<TranscriptEvent>,
+}
+
+impl Transcript {
+    /// An empty transcript of a `cols` x `rows` terminal.
+    pub fn new(cols: u16, rows: u16) -> Self {
+        Self {
+            width: cols,
+            height: rows,
+            events: Vec::new(),
+        }
+    }
+
+    /// Read the transcript saved at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a v2 cast.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read transcript: {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid transcript: {}", path.display()))
+    }
+
+    /// Parse an asciinema v2 cast. Events of other kinds are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header is missing or not version 2, or an
+    /// event line is malformed. Sizes without a row or column, or too large
+    /// to replay, count as malformed.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+        let (_, header) = lines.next().ok_or_else(|| anyhow!("Empty cast file"))?;
+        let header: Value = serde_json::from_str(header).context("Line 1: invalid JSON")?;
+        if header["version"] != 2 {
+            return Err(anyhow!("Only asciinema v2 casts are supported"));
+        }
+        let size = |name: &str| {
+            header[name]
+                .as_u64()
+                .and_then(|n| u16::try_from(n).ok())
+                .ok_or_else(|| anyhow!("Line 1: \"{}\" must be a number", name))
+        };
+        let (width, height) = (size("width")?, size("height")?);
+        if !is_valid_size(width, height) {
+            return Err(anyhow!("Line 1: invalid size: {}x{}", width, height));
+        }
+        let mut transcript = Self::new(width, height);
+        for (i, line) in lines {
+            let invalid = || anyhow!("Line {}: expected [time, code, data]", i + 1);
+            let event: Value = serde_json::from_str(line)
+                .with_context(|| format!("Line {}: invalid JSON", i + 1))?;
+            let time = event[0]
+                .as_f64()
+                .and_then(|t| Duration::try_from_secs_f64(t).ok())
+                .ok_or_else(invalid)?;
+            let data = event[2].as_str().ok_or_else(invalid)?.to_string();
+            let event = match event[1].as_str().ok_or_else(invalid)? {
+                "o" => TranscriptEvent::Output { time, data },
+                "i" => TranscriptEvent::Input { time, data },
+                "m" => TranscriptEvent::Marker { time, name: data },
+                "r" => {
+                    let (cols, rows) = data
+                        .split_once('x')
+                        .and_then(|(c, r)| Some((c.parse().ok()?, r.parse().ok()?)))
+                        .filter(|(cols, rows)| is_valid_size(*cols, *rows))
+                        .ok_or_else(|| anyhow!("Line {}: invalid size: {}", i + 1, data))?;
+                    TranscriptEvent::Resize { time, cols, rows }
+                }
+                _ => continue,
+            };
+            transcript.events.push(event);
+        }
+        Ok(transcript)
+    }
+
+    /// Save the transcript to `path` as an asciinema v2 cast.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_cast())
+            .with_context(|| format!("Failed to write transcript: {}", path.display()))
+    }
+
+    /// The text of the asciinema v2 cast [`save`](Self::save) writes.
+    pub fn to_cast(&self) -> String {
+        let mut cast = json!({
+            "version": 2,
+            "width": self.width,
+            "height": self.height,
+        })
+        .to_string();
+        for event in &self.events {
+            let time = event.time().as_micros() as f64 / 1e6;
+            let line = match event {
+                TranscriptEvent::Output { data, .. } => json!([time, "o", data]),
+                TranscriptEvent::Input { data, .. } => json!([time, "i", data]),
+                TranscriptEvent::Resize { cols, rows, .. } => {
+                    json!([time, "r", format!("{}x{}", cols, rows)])
+                }
+                TranscriptEvent::Marker { name, .. } => json!([time, "m", name]),
+            };
+            cast.push('\n');
+            cast.push_str(&line.to_string());
+        }
+        cast.push('\n');
+        cast
+    }
+
+    /// Index in `events` of the first event after `at`: where playback
+    /// resumes after seeking to `at`. The events before it make up the
+    /// screen at `at`.
+    pub fn seek(&self, at: Duration) -> usize {
+        self.events.partition_point(|event| event.time() <= at)
+    }
+
+    /// The part of the session from the marker `from` to the marker `to`,
+    /// both included, with times counted from `from`. It starts with the
+    /// screen as it was at `from`, so it renders and plays on its own.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either marker is missing, or `to` does not come
+    /// after `from`.
+    pub fn slice(&self, from: &str, to: &str) -> Result<Transcript> {
+        let marker = |name: &str, skip: usize| {
+            self.events
+                .iter()
+                .skip(skip)
+                .position(|e| matches!(e, TranscriptEvent::Marker { name: n, .. } if n == name))
+                .map(|i| i + skip)
+                .ok_or_else(|| anyhow!("Transcript has no marker '{}'", name))
+        };
+        let start = marker(from, 0)?;
+        let end = marker(to, start + 1).with_context(|| format!("after marker '{}'", from))?;
+        let (rows, cols, screen) = self.replay(&self.events[..start], self.height, self.width);
+        let offset = self.events[start].time();
+        let mut slice = Transcript::new(cols, rows);
+        slice.events.push(TranscriptEvent::Output {
+            time: Duration::ZERO,
+            data: String::from_utf8_lossy(&screen.contents_formatted()).into_owned(),
+        });
+        slice.events.extend(
+            self.events[start..=end]
+                .iter()
+                .map(|event| event.with_time(event.time() - offset)),
+        );
+        Ok(slice)
+    }
+
+    /// The screen after every event, on a terminal that starts out `rows` x
+    /// `cols`; resize events still apply, except to a size without a row or
+    /// column, or too large to replay, which is ignored. To render the screen at a point in
+    /// time, render the events before [`seek`](Self::seek) returns.
+    pub fn render(&self, rows: u16, cols: u16) -> vt100::Screen {
+        self.replay(&self.events, rows, cols).2
+    }
+
+    /// Run `events` through a terminal of `rows` x `cols`; returns its final
+    /// size and screen.
+    fn replay(
+        &self,
+        events: &[TranscriptEvent],
+        rows: u16,
+        cols: u16,
+    ) -> (u16, u16, vt100::Screen) {
+        let mut parser = vt100::Parser::new(rows, cols, 0);
+        for event in events {
+            match event {
+                TranscriptEvent::Output { data, .. } => parser.process(data.as_bytes()),
+                TranscriptEvent::Resize { cols, rows, .. } if is_valid_size(*cols, *rows) => {
+                    parser.screen_mut().set_size(*rows, *cols)
+                }
+                TranscriptEvent::Resize { .. } => {}
+                TranscriptEvent::Input { .. } | TranscriptEvent::Marker { .. } => {}
+            }
+        }
+        let (rows, cols) = parser.screen().size();
+        (rows, cols, parser.screen().clone())
+    }
+}
+
+/// The [`Transcript`] the engine keeps of the current session.
+pub(crate) struct TranscriptLog {
+    start: Instant,
+    transcript: Transcript,
+    /// Bytes of a UTF-8 character split across output chunks.
+    pending: Vec<u8>,
+    output_bytes: usize,
+}
+
+impl TranscriptLog {
+    pub(crate) fn new(rows: u16, cols: u16) -> Self {
+        Self {
+            start: Instant::now(),
+            transcript: Transcript::new(cols, rows),
+            pending: Vec::new(),
+            output_bytes: 0,
+        }
+    }
+
+    pub(crate) fn output(&mut self, data: &[u8]) {
+        let Some(data) = take_complete_utf8(&mut self.pending, data) else {
+            return;
+        };
+        self.output_bytes += data.len();
+        let time = self.start.elapsed();
+        self.transcript
+            .events
+            .push(TranscriptEvent::Output { time, data });
+        if self.output_bytes > LOG_LIMIT {
+            let mut dropped = 0;
+            for event in &self.transcript.events {
+                if self.output_bytes <= LOG_LIMIT {
+                    break;
+                }
+                if let TranscriptEvent::Output { data, .. } = event {
+                    self.output_bytes -= data.len();
+                }
+                dropped += 1;
+            }
+            self.transcript.events.drain(..dropped);
+        }
+    }
+
+    pub(crate) fn input(&mut self, data: &[u8]) {
+        let time = self.start.elapsed();
+        let data = String::from_utf8_lossy(data).into_owned();
+        self.transcript
+            .events
+            .push(TranscriptEvent::Input { time, data });
+    }
+
+    pub(crate) fn resize(&mut self, rows: u16, cols: u16) {
+        let time = self.start.elapsed();
+        self.transcript
+            .events
+            .push(TranscriptEvent::Resize { time, cols, rows });
+    }
+
+    pub(crate) fn marker(&mut self, name: &str) {
+        let time = self.start.elapsed();
+        let name = name.to_string();
+        self.transcript
+            .events
+            .push(TranscriptEvent::Marker { time, name });
+    }
+
+    pub(crate) fn transcript(&self) -> Transcript {
+        self.transcript.clone()
+    }
+}
+
+impl Default for TranscriptLog {
+    fn default() -> Self {
+        Self::new(24, 80)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn contents(out: &Shared) -> Vec<String> {
+        String::from_utf8(out.0.lock().unwrap().clone())
+            .unwrap()
+            .lines()
+            .map(|line| line[15..].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_output_lines() {
+        let out = Shared::default();
+        let mut writer = TranscriptWriter::new(out.clone(), false);
+        writer.output(b"\x1b[1mWel").unwrap();
+        writer
+            .output(b"come\x1b[0m\r\n\r\nloading...\rdone\r\n$ ")
+            .unwrap();
+        writer.input(b"ls\r").unwrap();
+        writer.finish().unwrap();
+        assert_eq!(contents(&out), ["Welcome", "", "done", "$"]);
+        let text = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        assert!(text.starts_with("[00:00:00."), "got: {text}");
+    }
+
+    #[test]
+    fn test_input_lines() {
+        let out = Shared::default();
+        let mut writer = TranscriptWriter::new(out.clone(), true);
+        writer.output(b"login: ").unwrap();
+        writer.input(b"al").unwrap();
+        writer.input(b"ice\r").unwrap();
+        writer.output(b"alice\r\n").unwrap();
+        writer.finish().unwrap();
+        assert_eq!(contents(&out), ["> alice", "login: alice"]);
+    }
+
+    fn sample() -> Transcript {
+        let at = Duration::from_millis;
+        let mut transcript = Transcript::new(20, 3);
+        transcript.events = vec![
+            TranscriptEvent::Output {
+                time: at(0),
+                data: "\x1b[1m$ \x1b[0m".into(),
+            },
+            TranscriptEvent::Marker {
+                time: at(100),
+                name: "ls".into(),
+            },
+            TranscriptEvent::Input {
+                time: at(200),
+                data: "ls\r".into(),
+            },
+            TranscriptEvent::Output {
+                time: at(300),
+                data: "ls\r\na.txt\r\n$ ".into(),
+            },
+            TranscriptEvent::Marker {
+                time: at(400),
+                name: "done".into(),
+            },
+            TranscriptEvent::Resize {
+                time: at(500),
+                cols: 30,
+                rows: 4,
+            },
+        ];
+        transcript
+    }
+
+    #[test]
+    fn test_transcript_cast() {
+        let transcript = sample();
+        let cast = transcript.to_cast();
+        assert!(cast.contains(r#"[0.2,"i","ls\r"]"#), "got: {cast}");
+        assert!(cast.contains(r#"[0.4,"m","done"]"#), "got: {cast}");
+        assert_eq!(Transcript::parse(&cast).unwrap(), transcript);
+        let cast = crate::recorder::Cast::parse(&cast).unwrap();
+        assert_eq!(cast.events.len(), 3);
+        assert!(Transcript::parse(r#"{"version":1,"width":80,"height":24}"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_values() {
+        let header = r#"{"version":2,"width":80,"height":24}"#;
+        let parse = |event: &str| Transcript::parse(&format!("{}\n{}", header, event));
+        assert!(parse(r#"[0.5, "r", "100x30"]"#).is_ok());
+        assert!(parse(r#"[0.5, "r", "r"]"#).is_err());
+        assert!(parse(r#"[0.5, "r", "0x0"]"#).is_err());
+        assert!(parse(r#"[0.5, "r", "65535x65535"]"#).is_err());
+        assert!(parse(r#"[1e300, "o", "a"]"#).is_err());
+        assert!(parse(r#"[-1, "o", "a"]"#).is_err());
+        assert!(Transcript::parse(r#"{"version":2,"width":0,"height":24}"#).is_err());
+    }
+
+    #[test]
+    fn test_seek_and_render() {
+        let transcript = sample();
+        assert_eq!(transcript.seek(Duration::ZERO), 1);
+        assert_eq!(transcript.seek(Duration::from_millis(250)), 3);
+        assert_eq!(transcript.seek(Duration::from_secs(9)), 6);
+        let screen = transcript.render(3, 20);
+        assert_eq!(screen.size(), (4, 30));
+        assert_eq!(screen.contents(), "$ ls\na.txt\n$ ");
+        let before = Transcript {
+            events: transcript.events[..transcript.seek(Duration::from_millis(250))].to_vec(),
+            ..transcript
+        };
+        assert_eq!(before.render(3, 20).contents(), "$ ");
+        let mut resized = transcript.clone();
+        resized.events.push(TranscriptEvent::Resize {
+            time: Duration::from_secs(1),
+            cols: 0,
+            rows: 0,
+        });
+        assert_eq!(resized.render(3, 20).size(), (4, 30));
+    }
+
+    #[test]
+    fn test_slice() {
+        let transcript = sample();
+        let slice = transcript.slice("ls", "done").unwrap();
+        assert_eq!(slice.events.len(), 5);
+        assert_eq!(slice.events[4].time(), Duration::from_millis(300));
+        // The slice starts from the screen at the first marker, bold prompt included.
+        let screen = slice.render(slice.height, slice.width);
+        assert_eq!(screen.contents(), "$ ls\na.txt\n$ ");
+        assert!(screen.cell(0, 0).unwrap().bold());
+
+        let err = transcript.slice("done", "ls").unwrap_err();
+        assert!(
+            format!("{err:#}").contains("no marker 'ls'"),
+            "got: {err:#}"
+        );
+        assert!(transcript.slice("start", "done").is_err());
+    }
+}
@@ -0,0 +1,79 @@
+//! Per-run temporary workspaces.
+//!
+//! A workspace is a fresh directory that the program under test starts in and
+//! that scripts can refer to as `${WORKSPACE}`. It replaces the usual
+//! `mkdir`/`cd`/`rm -rf` fixture boilerplate at the top and bottom of a
+//! script. The directory is removed when the engine is dropped, unless the run
+//! failed and the engine was asked to keep it for inspection.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// A temporary directory owned by one engine run.
+#[derive(Debug)]
+pub struct Workspace {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl Workspace {
+    /// Create a new, empty directory under the system temp directory.
+    pub(crate) fn create() -> Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "scriptty-{}-{:08x}",
+            std::process::id(),
+            rand::random::<u32>()
+        ));
+        std::fs::create_dir(&path)
+            .with_context(|| format!("Failed to create workspace: {}", path.display()))?;
+        Ok(Self { path, keep: false })
+    }
+
+    /// The workspace directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Leave the directory in place when the workspace is dropped.
+    pub(crate) fn keep(&mut self) {
+        self.keep = true;
+    }
+
+    /// Whether the directory will be left in place when dropped.
+    pub fn is_kept(&self) -> bool {
+        self.keep
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_removed_on_drop() {
+        let workspace = Workspace::create().unwrap();
+        let path = workspace.path().to_path_buf();
+        std::fs::write(path.join("fixture"), "data").unwrap();
+        assert!(path.is_dir());
+        drop(workspace);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_kept() {
+        let mut workspace = Workspace::create().unwrap();
+        workspace.keep();
+        let path = workspace.path().to_path_buf();
+        drop(workspace);
+        assert!(path.is_dir());
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -224,3 +224,1342 @@ type "exit"
     // Clean up
     let _ = fs::remove_file(script_path);
 }
+
+#[test]
+fn test_expect_any_command() {
+    // Only one of the patterns ever appears
+    let script = r#"
+expect_any "this_will_never_appear" "$"
+type "echo second"
+expect_any "second" "never" 2s
+wait 200ms
+type "exit"
+"#;
+
+    let script_path = "/tmp/test_expect_any.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    // Run scriptty
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--command")
+        .arg("sh")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    // Check that it ran successfully
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // Clean up
+    let _ = fs::remove_file(script_path);
+}
+
+#[test]
+fn test_capture_and_interpolation() {
+    // Capture a value from the output and reuse it in a later command
+    let script = r#"
+expect "$"
+send "echo version 4.2"
+key Enter
+capture "version (\d+\.\d+)" into VER
+send "echo got ${VER}"
+key Enter
+expect "got 4.2"
+wait 200ms
+type "exit"
+"#;
+
+    let script_path = "/tmp/test_capture.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    // Run scriptty
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--command")
+        .arg("sh")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    // Check that it ran successfully
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // Clean up
+    let _ = fs::remove_file(script_path);
+}
+
+#[test]
+fn test_if_block() {
+    // The first condition times out and takes the else branch
+    let script = r#"
+expect "$"
+if expect "this_will_never_appear" 300ms
+  show "wrong branch"
+else
+  send "echo else-branch"
+  key Enter
+end
+if expect "else-branch" 2s
+  show "matched"
+end
+wait 200ms
+type "exit"
+"#;
+
+    let script_path = "/tmp/test_if_block.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    // Run scriptty
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--command")
+        .arg("sh")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    // Check that it ran successfully
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("matched"), "Then branch should run");
+    assert!(
+        !stdout.contains("wrong branch"),
+        "Then branch should be skipped on timeout"
+    );
+
+    // Clean up
+    let _ = fs::remove_file(script_path);
+}
+
+#[test]
+fn test_workspace() {
+    let script = r#"
+expect "$"
+send "pwd"
+key Enter
+expect "${WORKSPACE}"
+send "touch marker && ls"
+key Enter
+expect "marker"
+wait 200ms
+type "exit"
+"#;
+
+    let script_path = "/tmp/test_workspace.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--command")
+        .arg("sh")
+        .arg("--workspace")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let dir = stdout
+        .lines()
+        .find(|l| l.contains("/scriptty-") && !l.contains("pwd"))
+        .expect("pwd should print the workspace")
+        .trim();
+    assert!(
+        !std::path::Path::new(dir).exists(),
+        "workspace {dir} should be removed after a successful run"
+    );
+
+    let _ = fs::remove_file(script_path);
+}
+
+#[test]
+fn test_env() {
+    let script = r#"
+env GREETING=hello
+env LANG="en_US.UTF-8"
+expect "hello C"
+"#;
+
+    let script_path = "/tmp/test_env.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--env")
+        .arg("LANG=C")
+        .arg("--command")
+        .arg("sh")
+        .arg("--")
+        .arg("-c")
+        .arg("echo \"$GREETING $LANG\"; sleep 1")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let _ = fs::remove_file(script_path);
+}
+
+#[test]
+fn test_banner() {
+    let script = r#"
+send "${banner}"
+key Enter
+expect "got demo v1.2"
+"#;
+
+    let script_path = "/tmp/test_banner.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--ready-idle")
+        .arg("300ms")
+        .arg("--command")
+        .arg("sh")
+        .arg("--")
+        .arg("-c")
+        .arg(r#"printf '\033[1mdemo v1.2\033[0m\n'; read line; echo "got $line"; sleep 1"#)
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let _ = fs::remove_file(script_path);
+}
+
+#[test]
+fn test_cwd() {
+    let dir = std::env::temp_dir().join(format!("scriptty-cwd-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("fixture.txt"), "").unwrap();
+    let script = r#"
+expect "fixture.txt"
+"#;
+
+    let script_path = "/tmp/test_cwd.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--cwd")
+        .arg(&dir)
+        .arg("--command")
+        .arg("sh")
+        .arg("--")
+        .arg("-c")
+        .arg("ls; sleep 1")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let _ = fs::remove_file(script_path);
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn test_debug_state() {
+    let script = r#"
+capture "version (\d+)" into VERSION
+debug_state
+"#;
+
+    let script_path = "/tmp/test_debug_state.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--command")
+        .arg("sh")
+        .arg("--")
+        .arg("-c")
+        .arg("echo version 7; echo leftover; sleep 1")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    // The report goes to stderr, not into the program's output.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("[debug_state]"), "got: {stdout}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("[debug_state]   VERSION = \"7\""),
+        "got: {stderr}"
+    );
+    assert!(stderr.contains("[debug_state] buffer ("), "got: {stderr}");
+
+    let _ = fs::remove_file(script_path);
+}
+
+#[test]
+fn test_kill() {
+    let script = r#"
+expect "ready"
+kill 5s
+expect "got-term"
+kill 200ms
+"#;
+
+    let script_path = "/tmp/test_kill.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let start = std::time::Instant::now();
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--command")
+        .arg("sh")
+        .arg("--")
+        .arg("-c")
+        .arg("trap 'echo got-term; exit 0' TERM; echo ready; while true; do sleep 0.1; done")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(start.elapsed() < std::time::Duration::from_secs(4));
+
+    let _ = fs::remove_file(script_path);
+}
+
+#[test]
+fn test_expect_eof() {
+    let script = r#"
+expect "$"
+send "exit 3"
+key Enter
+expect_eof 5s
+show "exit code ${EXIT_CODE}"
+"#;
+
+    let script_path = "/tmp/test_expect_eof.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--command")
+        .arg("sh")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("exit code 3"),
+        "Output should report the exit code, got: {stdout}"
+    );
+
+    let _ = fs::remove_file(script_path);
+}
+
+#[test]
+fn test_assert_exit_code_failure() {
+    let script = r#"
+expect "$"
+send "exit 4"
+key Enter
+assert_exit_code 0 5s
+"#;
+
+    let script_path = "/tmp/test_assert_exit_code.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--command")
+        .arg("sh")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(
+        !output.status.success(),
+        "scriptty should fail when the program exits non-zero"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("exited with code 4, expected 0"),
+        "got stderr: {stderr}"
+    );
+
+    let _ = fs::remove_file(script_path);
+}
+
+#[test]
+fn test_respawn_snapshot() {
+    let script = r#"
+expect "$"
+send "echo first-$$-session"
+key Enter
+expect "first-"
+expect "$"
+respawn snapshot before
+expect "$"
+send "echo second"
+key Enter
+expect "second"
+assert_snapshot before "first-"
+"#;
+
+    let script_path = "/tmp/test_respawn_snapshot.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--command")
+        .arg("sh")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_resize() {
+    let script = r#"
+expect "$"
+resize 40 120
+send "stty size"
+key Enter
+expect "40 120"
+respawn
+expect "$"
+send "stty size"
+key Enter
+expect "40 120"
+"#;
+
+    let script_path = "/tmp/test_resize.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--command")
+        .arg("sh")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_signal() {
+    let script = r#"
+expect "ready"
+signal INT
+expect "got-int"
+assert_exit_code 3
+"#;
+
+    let script_path = "/tmp/test_signal.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--command")
+        .arg("sh")
+        .arg("--")
+        .arg("-c")
+        .arg("trap 'echo got-int; exit 3' INT; echo ready; while true; do sleep 0.1; done")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_snapshot() {
+    let script = r#"
+expect "menu"
+expect_idle 200ms
+snapshot "menu"
+"#;
+
+    let script_path = "/tmp/test_snapshot.script";
+    let snapshot_dir = "/tmp/test_snapshot_dir";
+    fs::write(script_path, script).expect("Failed to write test script");
+    let _ = fs::remove_dir_all(snapshot_dir);
+
+    let run = |update: bool| {
+        let mut command = Command::new(get_scriptty_bin());
+        command
+            .arg("--script")
+            .arg(script_path)
+            .arg("--snapshot-dir")
+            .arg(snapshot_dir);
+        if update {
+            command.arg("--update-snapshots");
+        }
+        command
+            .arg("--command")
+            .arg("sh")
+            .arg("--")
+            .arg("-c")
+            .arg("printf 'menu\\n> Open\\n'; sleep 2")
+            .output()
+            .expect("Failed to execute scriptty")
+    };
+
+    let output = run(false);
+    assert!(!output.status.success(), "a missing snapshot must fail");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--update-snapshots"), "stderr: {}", stderr);
+
+    let output = run(true);
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let golden = format!("{}/menu.txt", snapshot_dir);
+    let recorded = fs::read_to_string(&golden).expect("Snapshot not written");
+    assert!(recorded.starts_with("menu\n> Open\n"));
+
+    assert!(run(false).status.success());
+
+    fs::write(&golden, recorded.replace("> Open", "> Save")).unwrap();
+    let output = run(false);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("-    2: > Save"), "stderr: {}", stderr);
+    assert!(stderr.contains("+    2: > Open"), "stderr: {}", stderr);
+}
+
+#[test]
+fn test_expect_plain() {
+    let script = r#"
+expect --plain "build ok" 2s
+set plain_expect on
+expect "all 3 passed" 2s
+"#;
+
+    let script_path = "/tmp/test_expect_plain.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--command")
+        .arg("sh")
+        .arg("--")
+        .arg("-c")
+        .arg(r"printf 'build \033[1;32mok\033[0m\nall \033[1m3\033[0m passed\n'; sleep 1")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_expect_not() {
+    let script = r#"
+expect "step done" 2s
+expect_not "panic" 200ms
+expect "next step" 2s
+expect_not "panic" 2s
+"#;
+
+    let script_path = "/tmp/test_expect_not.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--command")
+        .arg("sh")
+        .arg("--")
+        .arg("-c")
+        .arg("echo step done; sleep 1; echo next step; echo 'panic: boom'; sleep 1")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Unexpected pattern in output: 'panic'"),
+        "unexpected stderr: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_record_cast() {
+    let script = r#"
+expect "ready" 2s
+show "overlay"
+"#;
+
+    let script_path = "/tmp/test_record_cast.script";
+    let cast_path = "/tmp/test_record_cast.cast";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--record")
+        .arg(cast_path)
+        .arg("--command")
+        .arg("sh")
+        .arg("--")
+        .arg("-c")
+        .arg("echo ready; sleep 1")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let cast = fs::read_to_string(cast_path).expect("Failed to read cast");
+    let mut lines = cast.lines();
+    assert!(lines.next().unwrap().contains("\"version\":2"));
+    let events: Vec<&str> = lines.collect();
+    assert!(
+        events
+            .iter()
+            .any(|e| e.contains("\"o\"") && e.contains("ready"))
+    );
+    assert!(events.iter().any(|e| e.contains("overlay")));
+}
+
+#[test]
+fn test_transcript() {
+    let script = r#"
+expect "ready" 2s
+type "hello"
+key Enter
+expect "got hello" 2s
+"#;
+
+    let script_path = "/tmp/test_transcript.script";
+    let transcript_path = "/tmp/test_transcript.log";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--transcript")
+        .arg(transcript_path)
+        .arg("--transcript-input")
+        .arg("--command")
+        .arg("sh")
+        .arg("--")
+        .arg("-c")
+        .arg("stty -echo; printf '\\033[1mready\\033[0m\\n'; read line; echo got $line")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let transcript = fs::read_to_string(transcript_path).expect("Failed to read transcript");
+    let lines: Vec<&str> = transcript.lines().map(|line| &line[15..]).collect();
+    assert!(lines.contains(&"ready"), "got: {transcript}");
+    assert!(lines.contains(&"> hello"), "got: {transcript}");
+    assert!(lines.contains(&"got hello"), "got: {transcript}");
+}
+
+#[test]
+fn test_output_quota() {
+    let script = r#"
+wait 10s
+"#;
+
+    let script_path = "/tmp/test_output_quota.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let started = std::time::Instant::now();
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--max-output")
+        .arg("64K")
+        .arg("--command")
+        .arg("yes")
+        .stdout(std::process::Stdio::null())
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(!output.status.success());
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Quota exceeded during 'wait': the program printed more than 65536 bytes"),
+        "unexpected stderr: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_sandbox_limits() {
+    let script = r#"
+expect "files: 32" 2s
+"#;
+
+    let script_path = "/tmp/test_sandbox_limits.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--limit-files")
+        .arg("32")
+        .arg("--command")
+        .arg("sh")
+        .arg("--")
+        .arg("-c")
+        .arg("echo files: $(ulimit -n); sleep 1")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_stopped_program_timeout() {
+    let script = r#"
+expect "ready" 2s
+signal STOP
+expect "never" 500ms
+"#;
+
+    let script_path = "/tmp/test_stopped_program_timeout.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--command")
+        .arg("sh")
+        .arg("--")
+        .arg("-c")
+        .arg("echo ready; sleep 5")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("the program is stopped by a signal"),
+        "unexpected stderr: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_run_as_user() {
+    // SAFETY: geteuid has no preconditions and cannot fail.
+    if unsafe { libc::geteuid() } != 0 {
+        eprintln!("skipping test_run_as_user: needs root");
+        return;
+    }
+    let script = r#"
+expect "user: nobody" 2s
+"#;
+
+    let script_path = "/tmp/test_run_as_user.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--user")
+        .arg("nobody")
+        .arg("--command")
+        .arg("sh")
+        .arg("--")
+        .arg("-c")
+        .arg("echo user: $(id -un); sleep 1")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_verify_against_cast() {
+    let script = r#"
+expect "done" 2s
+"#;
+
+    let script_path = "/tmp/test_verify_against_cast.script";
+    let cast_path = "/tmp/test_verify_against_cast.cast";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let run = |extra: &[&str], words: &str| {
+        Command::new(get_scriptty_bin())
+            .arg("--script")
+            .arg(script_path)
+            .args(extra)
+            .arg("--command")
+            .arg("sh")
+            .arg("--")
+            .arg("-c")
+            .arg(format!("for w in {words}; do echo $w; done; echo done"))
+            .output()
+            .expect("Failed to execute scriptty")
+    };
+
+    let output = run(&["--record", cast_path], "alpha beta");
+    assert!(
+        output.status.success(),
+        "recording failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let output = run(&["--verify", cast_path], "alpha beta");
+    assert!(
+        output.status.success(),
+        "verification failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let output = run(&["--verify", cast_path], "alpha gamma");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("-    2: beta") && stderr.contains("+    2: gamma"),
+        "unexpected stderr: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_termios_settings() {
+    let script = r#"
+expect "echo is off" 2s
+"#;
+
+    let script_path = "/tmp/test_termios_settings.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--termios")
+        .arg("-echo")
+        .arg("--cell-size")
+        .arg("10x20")
+        .arg("--command")
+        .arg("sh")
+        .arg("--")
+        .arg("-c")
+        .arg("stty -a | grep -q -- ' -echo ' && echo echo is off; sleep 1")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_save_images() {
+    let script = r#"
+expect "done" 2s
+"#;
+
+    let script_path = "/tmp/test_save_images.script";
+    let images_dir = "/tmp/test_save_images";
+    let _ = fs::remove_dir_all(images_dir);
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--images")
+        .arg(images_dir)
+        .arg("--command")
+        .arg("sh")
+        .arg("--")
+        .arg("-c")
+        .arg(r#"printf '\033Pq#1;2;100;0;0#1!20~\033\\\n'; echo done; sleep 1"#)
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let png = fs::read(format!("{}/image-001.png", images_dir)).expect("image not saved");
+    assert!(png.starts_with(b"\x89PNG"));
+}
+
+#[test]
+fn test_assert_image_similar() {
+    let images_dir = "/tmp/test_assert_image_similar";
+    let script_path = "/tmp/test_assert_image_similar.script";
+    let _ = fs::remove_dir_all(images_dir);
+    let run = |script: &str, color: &str| {
+        fs::write(script_path, script).expect("Failed to write test script");
+        Command::new(get_scriptty_bin())
+            .arg("--script")
+            .arg(script_path)
+            .arg("--images")
+            .arg(images_dir)
+            .arg("--command")
+            .arg("sh")
+            .arg("--")
+            .arg("-c")
+            .arg(format!(
+                r#"sleep 0.2; printf '\033Pq#1;2;{}#1!20~\033\\\n'; sleep 1"#,
+                color
+            ))
+            .output()
+            .expect("Failed to execute scriptty")
+    };
+
+    let output = run("expect_image 2s\n", "100;0;0");
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let golden = format!("{}/golden.png", images_dir);
+    fs::rename(format!("{}/image-001.png", images_dir), &golden).unwrap();
+
+    let script = format!(
+        "expect_image 2s\nassert_image_similar \"{}\" 0.98\n",
+        golden
+    );
+    let output = run(&script, "100;0;0");
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let output = run(&script, "0;0;100");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("similarity"), "stderr: {}", stderr);
+}
+
+#[test]
+fn test_exec() {
+    let output = Command::new(get_scriptty_bin())
+        .arg("exec")
+        .arg("-c")
+        .arg("sh")
+        .arg("-e")
+        .arg("expect \"$\"")
+        .arg("-e")
+        .arg("type \"echo ${GREETING}-$((40 + 2))\"")
+        .arg("-e")
+        .arg("key Enter")
+        .arg("-e")
+        .arg("expect \"hi-42\"")
+        .arg("-D")
+        .arg("GREETING=hi")
+        .output()
+        .expect("Failed to execute scriptty");
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let mut child = Command::new(get_scriptty_bin())
+        .arg("exec")
+        .arg("-c")
+        .arg("sh")
+        .arg("--")
+        .arg("-c")
+        .arg("echo one")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to execute scriptty");
+    use std::io::Write;
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"expect \"two\" 500ms\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        !output.status.success(),
+        "a failing line should fail the run"
+    );
+}
+
+#[test]
+fn test_script_from_stdin() {
+    let mut child = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg("-")
+        .arg("--retries")
+        .arg("1")
+        .arg("--command")
+        .arg("sh")
+        .arg("--")
+        .arg("-c")
+        .arg("echo from-stdin; sleep 1")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to execute scriptty");
+    use std::io::Write;
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"expect \"from-stdin\"\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_markdown_block() {
+    let readme = "# Demo\n\n```scriptty other\nexpect \"never\" 100ms\n```\n\n```scriptty greet\nexpect \"hello\"\n```\n";
+    let path = "/tmp/test_markdown_block.md";
+    fs::write(path, readme).expect("Failed to write Markdown file");
+
+    let run = |block: &str| {
+        Command::new(get_scriptty_bin())
+            .arg("--script")
+            .arg(path)
+            .arg("--block")
+            .arg(block)
+            .arg("--command")
+            .arg("sh")
+            .arg("--")
+            .arg("-c")
+            .arg("echo hello; sleep 1")
+            .output()
+            .expect("Failed to execute scriptty")
+    };
+    let output = run("greet");
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let output = run("missing");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("found: other, greet"));
+
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn test_doctest() {
+    let dir = std::env::temp_dir().join(format!("scriptty-doctest-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("guide")).unwrap();
+    fs::write(
+        dir.join("intro.md"),
+        "# Intro\n\n```scriptty greet\nwait 10s\nexpect \"hello\"\n```\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("guide/broken.md"),
+        "Text\n\n```scriptty\nexpect \"goodbye\" 200ms\n```\n",
+    )
+    .unwrap();
+
+    let start = std::time::Instant::now();
+    let output = Command::new(get_scriptty_bin())
+        .arg("doctest")
+        .arg(&dir)
+        .arg("-c")
+        .arg("sh")
+        .arg("--")
+        .arg("-c")
+        .arg("echo hello; sleep 1")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        !output.status.success(),
+        "a failing block should fail the run"
+    );
+    assert!(
+        stderr.contains("PASS") && stderr.contains("intro.md:4 (greet)"),
+        "got: {stderr}"
+    );
+    assert!(
+        stderr.contains("FAIL") && stderr.contains("broken.md:4"),
+        "got: {stderr}"
+    );
+    assert!(
+        stderr.contains("2 scripts, 1 passed, 1 failed"),
+        "got: {stderr}"
+    );
+    assert!(
+        start.elapsed() < std::time::Duration::from_secs(8),
+        "waits should be skipped"
+    );
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn test_suite_runner() {
+    let dir = "/tmp/test_suite_runner";
+    let _ = fs::remove_dir_all(dir);
+    fs::create_dir_all(format!("{}/menu", dir)).unwrap();
+    fs::write(
+        format!("{}/greet.scriptty", dir),
+        "type \"echo hi ${NAME}\"\nkey Enter\nexpect \"hi ci\"\n",
+    )
+    .unwrap();
+    fs::write(
+        format!("{}/menu/broken.scriptty", dir),
+        "expect \"never printed\" 300ms\n",
+    )
+    .unwrap();
+    fs::write(format!("{}/notes.txt", dir), "not a script").unwrap();
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("test")
+        .arg(dir)
+        .arg("--command")
+        .arg("sh")
+        .arg("-D")
+        .arg("NAME=ci")
+        .arg("--junit")
+        .arg(format!("{}/report.xml", dir))
+        .arg("--tap")
+        .arg(format!("{}/report.tap", dir))
+        .env("PS1", "$ ")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!("PASS  {}/greet.scriptty", dir)),
+        "stderr: {}",
+        stderr
+    );
+    assert!(
+        stderr.contains(&format!("FAIL  {}/menu/broken.scriptty", dir)),
+        "stderr: {}",
+        stderr
+    );
+    assert!(
+        stderr.contains("2 scripts, 1 passed, 1 failed"),
+        "stderr: {}",
+        stderr
+    );
+    let junit = fs::read_to_string(format!("{}/report.xml", dir)).unwrap();
+    assert!(
+        junit.contains(r#"tests="2" failures="1""#),
+        "junit: {}",
+        junit
+    );
+    let tap = fs::read_to_string(format!("{}/report.tap", dir)).unwrap();
+    assert!(
+        tap.contains(&format!("not ok 2 - {}/menu/broken.scriptty", dir)),
+        "tap: {}",
+        tap
+    );
+}
+
+#[test]
+fn test_on_event_hook() {
+    let script = r#"
+marker "halfway"
+expect "ready" 2s
+"#;
+
+    let script_path = "/tmp/test_on_event_hook.script";
+    let log_path = "/tmp/test_on_event_hook.log";
+    let _ = fs::remove_file(log_path);
+    fs::write(script_path, script).expect("Failed to write test script");
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--on-event")
+        .arg(format!(
+            r#"echo "$SCRIPTTY_EVENT $SCRIPTTY_MESSAGE" >> {}"#,
+            log_path
+        ))
+        .arg("--command")
+        .arg("sh")
+        .arg("--")
+        .arg("-c")
+        .arg("echo ready; sleep 1")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let log = fs::read_to_string(log_path).expect("hook did not run");
+    assert_eq!(log, "marker halfway\nfinished Script finished\n");
+}
+
+#[test]
+fn test_on_fail_hook() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let script = r#"
+expect "ready" 2s
+expect "never printed" 300ms
+"#;
+
+    let script_path = "/tmp/test_on_fail_hook.script";
+    let hook_path = "/tmp/test_on_fail_hook.sh";
+    let report_path = "/tmp/test_on_fail_hook.report";
+    let _ = fs::remove_file(report_path);
+    fs::write(script_path, script).expect("Failed to write test script");
+    fs::write(hook_path, format!("#!/bin/sh\nmv \"$1\" {}\n", report_path)).unwrap();
+    fs::set_permissions(hook_path, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = Command::new(get_scriptty_bin())
+        .arg("--script")
+        .arg(script_path)
+        .arg("--on-fail")
+        .arg(hook_path)
+        .arg("--command")
+        .arg("sh")
+        .arg("--")
+        .arg("-c")
+        .arg("echo ready; sleep 2")
+        .output()
+        .expect("Failed to execute scriptty");
+
+    assert!(!output.status.success());
+    let report = fs::read_to_string(report_path).expect("hook did not run");
+    assert!(
+        report.contains("error: Timeout waiting for pattern"),
+        "report: {}",
+        report
+    );
+    assert!(report.contains("command: expect"), "report: {}", report);
+    assert!(report.contains("screen:\nready"), "report: {}", report);
+}
+
+#[test]
+fn test_ready_pattern() {
+    let script = r#"
+expect "ready>" 100ms
+"#;
+
+    let script_path = "/tmp/test_ready_pattern.script";
+    fs::write(script_path, script).expect("Failed to write test script");
+    let run = |ready: &[&str]| {
+        Command::new(get_scriptty_bin())
+            .arg("--script")
+            .arg(script_path)
+            .args(ready)
+            .arg("--command")
+            .arg("sh")
+            .arg("--")
+            .arg("-c")
+            .arg("sleep 0.5; echo banner; echo 'ready>'; sleep 1")
+            .output()
+            .expect("Failed to execute scriptty")
+    };
+
+    // Without a startup policy, the short expect runs before the program is up.
+    assert!(!run(&[]).status.success());
+    let output = run(&["--ready-pattern", "ready>"]);
+    assert!(
+        output.status.success(),
+        "scriptty failed with stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("banner"));
+    let output = run(&["--ready-idle", "300ms"]);
+    assert!(!output.status.success());
+    let output = run(&["--ready-pattern", "never", "--timeout-scale", "0.2"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("at startup"), "stderr: {}", stderr);
+}